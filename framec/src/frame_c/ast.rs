@@ -6,6 +6,7 @@ use super::symbol_table::{ActionDeclSymbol, SymbolType,EventSymbol};
 use downcast_rs::*;
 use downcast_rs::__std::cell::RefCell;
 use std::rc::Rc;
+use std::fmt;
 use std::collections::VecDeque;
 use crate::frame_c::visitors::*;
 use crate::frame_c::ast::OperatorType::{Plus, Minus, Multiply, Divide, Greater, GreaterEqual, LessEqual};
@@ -90,6 +91,9 @@ impl CallChainLiteralNodeType {
 
 //-----------------------------------------------------//
 
+// Attributes are either flags, e.g. #[final], or name/value pairs, e.g.
+// #[language="python_3"]. Flags have an empty value.
+
 pub struct AttributeNode {
     pub name:String,
     pub value:String,
@@ -108,36 +112,104 @@ impl AttributeNode {
 
 pub struct SystemNode {
     pub name: String,
+    pub type_params:Vec<String>,
     pub header:String,
     pub attributes_opt:Option<HashMap<String,AttributeNode>>,
+    pub start_state_state_params_opt:Option<Vec<ParameterNode>>,
+    pub start_state_enter_params_opt:Option<Vec<ParameterNode>>,
+    pub domain_params_opt:Option<Vec<ParameterNode>>,
     pub interface_block_node_opt:Option<InterfaceBlockNode>,
     pub machine_block_node_opt:Option<MachineBlockNode>,
     pub actions_block_node_opt:Option<ActionsBlockNode>,
+    pub operations_block_node_opt:Option<OperationsBlockNode>,
     pub domain_block_node_opt:Option<DomainBlockNode>,
     pub line:usize,
 }
 
 impl SystemNode {
     pub fn new(name:String,
+               type_params:Vec<String>,
                header:String,
                attributes_opt:Option<HashMap<String,AttributeNode>>,
+               start_state_state_params_opt:Option<Vec<ParameterNode>>,
+               start_state_enter_params_opt:Option<Vec<ParameterNode>>,
+               domain_params_opt:Option<Vec<ParameterNode>>,
                interface_block_node_opt:Option<InterfaceBlockNode>,
                machine_block_node_opt:Option<MachineBlockNode>,
                actions_block_node_opt:Option<ActionsBlockNode>,
+               operations_block_node_opt:Option<OperationsBlockNode>,
                domain_block_node_opt:Option<DomainBlockNode>,
                line:usize) -> SystemNode {
         SystemNode {
             name,
+            type_params,
             header,
             attributes_opt,
+            start_state_state_params_opt,
+            start_state_enter_params_opt,
+            domain_params_opt,
             interface_block_node_opt,
             machine_block_node_opt,
             actions_block_node_opt,
+            operations_block_node_opt,
             domain_block_node_opt,
             line,
         }
     }
 
+    // The system parameters in constructor order: the start state's
+    // parameters, then its enter parameters, then the domain parameters.
+
+    pub fn get_system_params(&self) -> Vec<ParameterNode> {
+        let mut params = Vec::new();
+        for params_opt in [&self.start_state_state_params_opt, &self.start_state_enter_params_opt, &self.domain_params_opt] {
+            if let Some(system_params) = params_opt {
+                params.extend(system_params.iter().cloned());
+            }
+        }
+        params
+    }
+
+    // Domain variables with these names are initialized from the
+    // system parameter of the same name.
+
+    pub fn get_domain_param_names(&self) -> Vec<String> {
+        match &self.domain_params_opt {
+            Some(domain_params) => domain_params.iter().map(|param| param.param_name.clone()).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    // True if any action has no Frame body. The generated system is then
+    // abstract and the user implements those actions in a subclass.
+
+    pub fn has_external_actions(&self) -> bool {
+        match &self.actions_block_node_opt {
+            Some(actions_block_node) => {
+                actions_block_node.actions.iter().any(|action_node_rcref| !action_node_rcref.borrow().has_frame_body())
+            },
+            None => false,
+        }
+    }
+
+    // All states in declaration order.
+
+    pub fn get_states(&self) -> Vec<Rc<RefCell<StateNode>>> {
+        match &self.machine_block_node_opt {
+            Some(mb) => mb.states.clone(),
+            None => Vec::new(),
+        }
+    }
+
+    // The domain variables in declaration order.
+
+    pub fn get_domain_vars(&self) -> Vec<Rc<RefCell<VariableDeclNode>>> {
+        match &self.domain_block_node_opt {
+            Some(domain_block_node) => domain_block_node.member_variables.clone(),
+            None => Vec::new(),
+        }
+    }
+
     pub fn get_first_state(&self) -> Option<&Rc<RefCell<StateNode>>> {
         match &self.machine_block_node_opt {
             Some(mb) => {
@@ -146,6 +218,174 @@ impl SystemNode {
             None => None,
         }
     }
+
+    // True if any state declares a timeout, in which case the
+    // timer machinery needs to be generated.
+
+    pub fn has_timeouts(&self) -> bool {
+        match &self.machine_block_node_opt {
+            Some(mb) => {
+                mb.states.iter().any(|state_node_rcref| state_node_rcref.borrow().timeout_opt.is_some())
+            },
+            None => false,
+        }
+    }
+
+    // True if any state has completion transitions.
+
+    pub fn has_completions(&self) -> bool {
+        match &self.machine_block_node_opt {
+            Some(mb) => {
+                mb.states.iter().any(|state_node_rcref| state_node_rcref.borrow().completions.len() > 0)
+            },
+            None => false,
+        }
+    }
+
+    // True if any state defers events, in which case the deferred
+    // event queue needs to be generated.
+
+    pub fn has_deferred_events(&self) -> bool {
+        match &self.machine_block_node_opt {
+            Some(mb) => {
+                mb.states.iter().any(|state_node_rcref| state_node_rcref.borrow().deferred_events_opt.is_some())
+            },
+            None => false,
+        }
+    }
+
+    // True if any state records history, in which case the history
+    // machinery needs to be generated.
+
+    pub fn has_history(&self) -> bool {
+        match &self.machine_block_node_opt {
+            Some(mb) => {
+                mb.states.iter().any(|state_node_rcref| state_node_rcref.borrow().history_records.len() > 0)
+            },
+            None => false,
+        }
+    }
+
+    // The states declared #[final], in declaration order.
+
+    pub fn get_final_state_names(&self) -> Vec<String> {
+        match &self.machine_block_node_opt {
+            Some(mb) => {
+                mb.states.iter()
+                    .filter(|state_node_rcref| state_node_rcref.borrow().is_final())
+                    .map(|state_node_rcref| state_node_rcref.borrow().name.clone())
+                    .collect()
+            },
+            None => Vec::new(),
+        }
+    }
+
+    // The states that push themselves on the state stack and so can
+    // be the target of a state stack pop.
+
+    pub fn get_pushed_state_names(&self) -> Vec<String> {
+        match &self.machine_block_node_opt {
+            Some(mb) => mb.pushed_state_names.clone(),
+            None => Vec::new(),
+        }
+    }
+
+    // True if the machine is split into orthogonal regions.
+
+    pub fn has_regions(&self) -> bool {
+        match &self.machine_block_node_opt {
+            Some(mb) => mb.regions.len() > 0,
+            None => false,
+        }
+    }
+
+    // The state each region starts in, in region order.
+
+    pub fn get_region_initial_state_names(&self) -> Vec<String> {
+        match &self.machine_block_node_opt {
+            Some(mb) => {
+                mb.regions.iter().map(|region_node| region_node.state_names[0].clone()).collect()
+            },
+            None => Vec::new(),
+        }
+    }
+
+    // #[run_to_completion] queues events sent to the system while it is
+    // still handling an event rather than dispatching them recursively.
+
+    pub fn is_run_to_completion(&self) -> bool {
+        match &self.attributes_opt {
+            Some(attributes) => attributes.contains_key("run_to_completion"),
+            None => false,
+        }
+    }
+
+    // #![state_stack_max="N"] bounds the depth of the state stack.
+
+    pub fn get_state_stack_max(&self) -> Option<usize> {
+        match &self.attributes_opt {
+            Some(attributes) => attributes.get("state_stack_max").and_then(|attribute_node| attribute_node.value.parse::<usize>().ok()),
+            None => None,
+        }
+    }
+
+    // #![state_stack_underflow] says what $$[-] does when the state stack
+    // is empty. Defaults to sending an error event once the stack is
+    // bounded.
+
+    pub fn get_state_stack_underflow(&self) -> Option<StateStackUnderflowPolicy> {
+        let attributes = match &self.attributes_opt {
+            Some(attributes) => attributes,
+            None => return None,
+        };
+        match attributes.get("state_stack_underflow") {
+            Some(attribute_node) => match attribute_node.value.as_str() {
+                "error" => Some(StateStackUnderflowPolicy::Error),
+                "stay" => Some(StateStackUnderflowPolicy::Stay),
+                value => Some(StateStackUnderflowPolicy::State {state_name:value.trim_start_matches('$').to_string()}),
+            },
+            None if attributes.contains_key("state_stack_max") => Some(StateStackUnderflowPolicy::Error),
+            None => None,
+        }
+    }
+
+    // #![snapshot_version="N"] is the model version written into
+    // snapshots. Systems without one are version 1.
+
+    pub fn get_snapshot_version(&self) -> u32 {
+        match &self.attributes_opt {
+            Some(attributes) => attributes.get("snapshot_version").and_then(|attribute_node| attribute_node.value.parse::<u32>().ok()).unwrap_or(1),
+            None => 1,
+        }
+    }
+
+    // A snapshot records a hash of every state with its state parameters
+    // and variables. Any change to them changes the hash so a snapshot
+    // from a different model is detected on restore.
+
+    pub fn get_snapshot_hash(&self) -> String {
+        let mut signatures:Vec<String> = self.get_states().iter()
+            .map(|state_node_rcref| {
+                let state_node = state_node_rcref.borrow();
+                format!("{}[{}]{{{}}}", state_node.name, state_node.get_param_names().join(","), state_node.get_var_names().join(","))
+            })
+            .collect();
+        signatures.sort();
+        // FNV-1a so the hash is the same on every platform and release.
+        let mut hash:u64 = 0xcbf29ce484222325;
+        for byte in signatures.join(";").bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        format!("{:016x}", hash)
+    }
+}
+
+#[derive(Clone)]
+pub enum StateStackUnderflowPolicy {
+    Error,
+    Stay,
+    State {state_name:String},
 }
 
 impl NodeElement for SystemNode {
@@ -186,16 +426,21 @@ pub struct InterfaceMethodNode {
     pub name:String,
     pub params:Option<Vec<ParameterNode>>,
     pub return_type_opt:Option<TypeNode>,
+    // returned when no handler sets a return value
+    pub default_return_expr_opt:Option<ExprType>,
     pub alias:Option<MessageNode>,
+    pub attributes_opt:Option<HashMap<String,AttributeNode>>,
 }
 
 impl InterfaceMethodNode {
-    pub fn new(name:String,params:Option<Vec<ParameterNode>>,return_type:Option<TypeNode>,alias:Option<MessageNode>) -> InterfaceMethodNode {
+    pub fn new(name:String,params:Option<Vec<ParameterNode>>,return_type:Option<TypeNode>,default_return_expr_opt:Option<ExprType>,alias:Option<MessageNode>,attributes_opt:Option<HashMap<String,AttributeNode>>) -> InterfaceMethodNode {
         InterfaceMethodNode {
             name,
             params,
             return_type_opt: return_type,
+            default_return_expr_opt,
             alias,
+            attributes_opt,
         }
     }
 }
@@ -236,21 +481,49 @@ impl NodeElement for ParameterNode {
 
 //-----------------------------------------------------//
 
+// An action body is either a superstring in the target language or
+// Frame statements. Actions with neither are implemented by the user.
+
 pub struct ActionNode {
     pub name:String,
     pub params:Option<Vec<ParameterNode>>,
     pub type_opt:Option<TypeNode>,
     pub code_opt:Option<String>,
+    pub statements:Vec<DeclOrStmtType>,
+    pub terminator_expr_opt:Option<TerminatorExpr>,
+    // True if the Frame body assigns domain variables, directly or
+    // through the actions it calls.
+    pub mutates_domain:bool,
+    pub attributes_opt:Option<HashMap<String,AttributeNode>>,
 }
 
 impl ActionNode {
     pub fn new(name:String,params:Option<Vec<ParameterNode>>,
-               type_opt:Option<TypeNode>,code_opt:Option<String>) -> ActionNode {
+               type_opt:Option<TypeNode>,code_opt:Option<String>,
+               statements:Vec<DeclOrStmtType>,
+               terminator_expr_opt:Option<TerminatorExpr>,
+               attributes_opt:Option<HashMap<String,AttributeNode>>) -> ActionNode {
         ActionNode {
             name,
             params,
             type_opt,
             code_opt,
+            statements,
+            terminator_expr_opt,
+            mutates_domain:false,
+            attributes_opt,
+        }
+    }
+
+    pub fn has_frame_body(&self) -> bool {
+        !self.statements.is_empty() || self.terminator_expr_opt.is_some()
+    }
+
+    // The return value expression of a Frame body, if any.
+    pub fn get_return_expr_opt(&self) -> Option<&ExprType> {
+        match &self.terminator_expr_opt {
+            Some(terminator_expr) => terminator_expr.return_expr_t_opt.as_ref(),
+            None => None,
         }
     }
 }
@@ -264,6 +537,54 @@ impl NodeElement for ActionNode {
     }
 }
 
+//-----------------------------------------------------//
+
+// An operation is a public method that runs directly against the domain
+// without going through the state machine. Its body is either a
+// superstring in the target language or Frame statements.
+
+pub struct OperationNode {
+    pub name:String,
+    pub params:Option<Vec<ParameterNode>>,
+    pub type_opt:Option<TypeNode>,
+    pub code_opt:Option<String>,
+    pub statements:Vec<DeclOrStmtType>,
+    pub terminator_expr_opt:Option<TerminatorExpr>,
+    pub attributes_opt:Option<HashMap<String,AttributeNode>>,
+}
+
+impl OperationNode {
+    pub fn new(name:String,params:Option<Vec<ParameterNode>>,
+               type_opt:Option<TypeNode>,code_opt:Option<String>,
+               statements:Vec<DeclOrStmtType>,
+               terminator_expr_opt:Option<TerminatorExpr>,
+               attributes_opt:Option<HashMap<String,AttributeNode>>) -> OperationNode {
+        OperationNode {
+            name,
+            params,
+            type_opt,
+            code_opt,
+            statements,
+            terminator_expr_opt,
+            attributes_opt,
+        }
+    }
+
+    // The return value expression of a Frame body, if any.
+    pub fn get_return_expr_opt(&self) -> Option<&ExprType> {
+        match &self.terminator_expr_opt {
+            Some(terminator_expr) => terminator_expr.return_expr_t_opt.as_ref(),
+            None => None,
+        }
+    }
+}
+
+impl NodeElement for OperationNode {
+    fn accept(&self, ast_visitor: &mut dyn AstVisitor) {
+        ast_visitor.visit_operation_node(self);
+    }
+}
+
 
 //-----------------------------------------------------//
 
@@ -273,16 +594,28 @@ pub struct VariableDeclNode {
     pub is_constant:bool,
     pub initializer_expr_t_opt:Option<ExprType>,
     pub identifier_decl_scope:IdentifierDeclScope,
+    pub attributes_opt:Option<HashMap<String,AttributeNode>>,
 }
 
 impl VariableDeclNode {
-    pub fn new(name:String, type_opt:Option<TypeNode>, is_constant:bool, initializer_expr_t_opt:Option<ExprType>,identifier_decl_scope:IdentifierDeclScope) -> VariableDeclNode {
+    pub fn new(name:String, type_opt:Option<TypeNode>, is_constant:bool, initializer_expr_t_opt:Option<ExprType>,identifier_decl_scope:IdentifierDeclScope,attributes_opt:Option<HashMap<String,AttributeNode>>) -> VariableDeclNode {
         VariableDeclNode {
             name,
             type_opt,
             is_constant,
             initializer_expr_t_opt,
             identifier_decl_scope,
+            attributes_opt,
+        }
+    }
+
+    // #[snapshot_default] domain variables keep their initial value when
+    // restoring a snapshot taken before they were added.
+
+    pub fn has_snapshot_default(&self) -> bool {
+        match &self.attributes_opt {
+            Some(attributes) => attributes.contains_key("snapshot_default"),
+            None => false,
         }
     }
 }
@@ -333,12 +666,36 @@ impl NodeElement for VariableNode {
 
 pub struct MachineBlockNode {
     pub states:Vec<Rc<RefCell<StateNode>>>,
+    pub regions:Vec<RegionNode>,
+    pub pushed_state_names:Vec<String>,
 }
 
 impl MachineBlockNode {
-    pub fn new(states:Vec<Rc<RefCell<StateNode>>>) -> MachineBlockNode {
+    pub fn new(states:Vec<Rc<RefCell<StateNode>>>, regions:Vec<RegionNode>, pushed_state_names:Vec<String>) -> MachineBlockNode {
         MachineBlockNode {
             states,
+            regions,
+            pushed_state_names,
+        }
+    }
+}
+
+//-----------------------------------------------------//
+
+// A region is a group of states with its own active state. Events
+// sent to the system are dispatched to every region in the order the
+// regions are declared. The first state of a region is its start state.
+
+pub struct RegionNode {
+    pub name:String,
+    pub state_names:Vec<String>,
+}
+
+impl RegionNode {
+    pub fn new(name:String) -> RegionNode {
+        RegionNode {
+            name,
+            state_names:Vec::new(),
         }
     }
 }
@@ -375,6 +732,26 @@ impl NodeElement for ActionsBlockNode {
     }
 }
 
+//-----------------------------------------------------//
+
+pub struct OperationsBlockNode {
+    pub operations:Vec<Rc<RefCell<OperationNode>>>,
+}
+
+impl OperationsBlockNode {
+    pub fn new(operations:Vec<Rc<RefCell<OperationNode>>>) -> OperationsBlockNode {
+        OperationsBlockNode {
+            operations,
+        }
+    }
+}
+
+impl NodeElement for OperationsBlockNode {
+    fn accept(&self, ast_visitor: &mut dyn AstVisitor) {
+        ast_visitor.visit_operations_block_node(self);
+    }
+}
+
 
 //-----------------------------------------------------//
 
@@ -408,6 +785,11 @@ pub struct StateNode {
     pub exit_event_handler_opt:Option<Rc<RefCell<EventHandlerNode>>>,
     // pub transitions:Vec<Rc<RefCell<TransitionStatementNode>>>,
     pub dispatch_opt:Option<DispatchNode>,
+    pub timeout_opt:Option<TimeoutNode>,
+    pub deferred_events_opt:Option<Vec<String>>,
+    pub completions:Vec<CompletionNode>,
+    pub history_records:Vec<HistoryRecordNode>,
+    pub attributes_opt:Option<HashMap<String,AttributeNode>>,
     pub line:usize,
 }
 
@@ -420,6 +802,10 @@ impl StateNode {
                enter_event_handler_opt:Option<Rc<RefCell<EventHandlerNode>>>,
                exit_event_handler_opt:Option<Rc<RefCell<EventHandlerNode>>>,
                dispatch_opt:Option<DispatchNode>,
+               timeout_opt:Option<TimeoutNode>,
+               deferred_events_opt:Option<Vec<String>>,
+               completions:Vec<CompletionNode>,
+               attributes_opt:Option<HashMap<String,AttributeNode>>,
                line:usize,) -> StateNode {
         StateNode {
             name,
@@ -431,9 +817,57 @@ impl StateNode {
             exit_event_handler_opt,
             // transitions:Vec::new(),
             dispatch_opt,
+            timeout_opt,
+            deferred_events_opt,
+            completions,
+            history_records:Vec::new(),
+            attributes_opt,
             line,
         }
     }
+
+    // #[final] states are entered but never left.
+
+    pub fn is_final(&self) -> bool {
+        match &self.attributes_opt {
+            Some(attributes) => attributes.contains_key("final"),
+            None => false,
+        }
+    }
+
+    // The state parameter names in declaration order.
+
+    pub fn get_param_names(&self) -> Vec<String> {
+        match &self.params_opt {
+            Some(params) => params.iter().map(|param| param.param_name.clone()).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    // The state variable names in declaration order.
+
+    pub fn get_var_names(&self) -> Vec<String> {
+        match &self.vars_opt {
+            Some(vars) => vars.iter().map(|var_rcref| var_rcref.borrow().name.clone()).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    // #[renamed_from="Old, Older"] restores snapshots taken in states
+    // that have since been renamed or merged into this one.
+
+    pub fn get_renamed_from(&self) -> Vec<String> {
+        match &self.attributes_opt {
+            Some(attributes) => match attributes.get("renamed_from") {
+                Some(attribute_node) => attribute_node.value.split(',')
+                    .map(|state_name| state_name.trim().trim_start_matches('$').to_string())
+                    .filter(|state_name| state_name.len() > 0)
+                    .collect(),
+                None => Vec::new(),
+            },
+            None => Vec::new(),
+        }
+    }
 }
 
 impl NodeElement for StateNode {
@@ -444,11 +878,33 @@ impl NodeElement for StateNode {
 
 //-----------------------------------------------------//
 
+// When a state is exited it records itself as the history of each
+// ancestor that is the target of a history transition. Shallow history
+// resumes the child of the ancestor on the path to the state, deep
+// history resumes the state itself.
+
+pub struct HistoryRecordNode {
+    pub parent_state_name:String,
+    pub child_state_name:String,
+}
+
+impl HistoryRecordNode {
+    pub fn new(parent_state_name:String, child_state_name:String) -> HistoryRecordNode {
+        HistoryRecordNode {
+            parent_state_name,
+            child_state_name,
+        }
+    }
+}
+
+//-----------------------------------------------------//
+
 // TODO: Dead code?
 
 pub enum StateContextType {
     StateRef { state_context_node:StateContextNode },
-    StateStackPop {},
+    StateStackPop { enter_args_opt:Option<ExprListNode> },
+    StateHistory { state_ref_node:StateRefNode, is_deep:bool },
     // MethodCall { state_context_node:StateContextNode }, // TODO
 }
 
@@ -520,6 +976,57 @@ impl NodeElement for DispatchNode {
 
 //-----------------------------------------------------//
 
+// A state timeout, e.g. 'after(5000) -> $Timeout'. The timer is started
+// (in milliseconds) when the state is entered and cancelled when the state
+// is exited. If it expires the transition is taken.
+
+pub struct TimeoutNode {
+    pub duration_expr_t:ExprType,
+    pub transition_statement_node:TransitionStatementNode,
+    pub line:usize,
+}
+
+impl TimeoutNode {
+    pub fn new(duration_expr_t:ExprType,
+               transition_statement_node:TransitionStatementNode,
+               line:usize) -> TimeoutNode {
+        TimeoutNode {
+            duration_expr_t,
+            transition_statement_node,
+            line,
+        }
+    }
+}
+
+impl NodeElement for TimeoutNode {
+    fn accept(&self, ast_visitor: &mut dyn AstVisitor) {
+        ast_visitor.visit_timeout_node(self);
+    }
+}
+
+//-----------------------------------------------------//
+
+// A completion transition, e.g. 'done(count > 3) -> $Next'. Completion
+// transitions are tried in order once the state's enter handler has
+// finished and the first one whose guard holds is taken.
+
+pub struct CompletionNode {
+    pub guard_expr_t_opt:Option<ExprType>,
+    pub transition_statement_node:TransitionStatementNode,
+}
+
+impl CompletionNode {
+    pub fn new(guard_expr_t_opt:Option<ExprType>,
+               transition_statement_node:TransitionStatementNode) -> CompletionNode {
+        CompletionNode {
+            guard_expr_t_opt,
+            transition_statement_node,
+        }
+    }
+}
+
+//-----------------------------------------------------//
+
 pub struct EventHandlerNode {
 //    pub event_handler_type:EventHandlerType,
     pub state_name:String,
@@ -530,6 +1037,7 @@ pub struct EventHandlerNode {
     // this is so we can know to declare a StateContext at the
     // top of the event handler.
     pub event_handler_has_transition:bool,
+    pub attributes_opt:Option<HashMap<String,AttributeNode>>,
     pub line:usize,
 }
 
@@ -541,6 +1049,7 @@ impl EventHandlerNode {
                  terminator_node: TerminatorExpr,
                  event_symbol_rcref: Rc<RefCell<EventSymbol>>,
                  event_handler_has_transition:bool,
+                 attributes_opt:Option<HashMap<String,AttributeNode>>,
                  line:usize ) -> EventHandlerNode {
         EventHandlerNode {
           //  event_handler_type,
@@ -550,6 +1059,7 @@ impl EventHandlerNode {
             terminator_node,
             event_symbol_rcref,
             event_handler_has_transition,
+            attributes_opt,
             line,
         }
     }
@@ -560,6 +1070,10 @@ impl EventHandlerNode {
             None => String::new(),
         }
     }
+
+    pub fn get_event_ret_type_node_opt(&self) -> Option<TypeNode> {
+        self.event_symbol_rcref.borrow().ret_type_opt.clone()
+    }
 }
 
 impl NodeElement for EventHandlerNode {
@@ -699,8 +1213,30 @@ pub enum ExprType {
     BinaryExprT {
         binary_expr_node:BinaryExprNode
     },
+    ConditionalExprT {
+        conditional_expr_node:ConditionalExprNode
+    },
+    ListLiteralExprT {
+        list_literal_node:ListLiteralNode
+    },
+    MapLiteralExprT {
+        map_literal_node:MapLiteralNode
+    },
 }
 
+impl ExprType {
+
+    // Returns the operator for unary and binary expressions. Visitors use
+    // this to decide if an operand needs parentheses in the target language.
+
+    pub fn get_operator_opt(&self) -> Option<OperatorType> {
+        match self {
+            ExprType::UnaryExprT { unary_expr_node } => Some(unary_expr_node.operator.clone()),
+            ExprType::BinaryExprT { binary_expr_node } => Some(binary_expr_node.operator.clone()),
+            _ => None,
+        }
+    }
+}
 
 impl NodeElement for ExprType {
     fn accept(&self, ast_visitor: &mut dyn AstVisitor) {
@@ -741,6 +1277,15 @@ impl NodeElement for ExprType {
             ExprType::BinaryExprT {binary_expr_node} => {
                 ast_visitor.visit_binary_expr_node(binary_expr_node);
             },
+            ExprType::ConditionalExprT {conditional_expr_node} => {
+                ast_visitor.visit_conditional_expr_node(conditional_expr_node);
+            },
+            ExprType::ListLiteralExprT {list_literal_node} => {
+                ast_visitor.visit_list_literal_node(list_literal_node);
+            },
+            ExprType::MapLiteralExprT {map_literal_node} => {
+                ast_visitor.visit_map_literal_node(map_literal_node);
+            },
         }
     }
 
@@ -783,6 +1328,15 @@ impl NodeElement for ExprType {
             ExprType::UnaryExprT {unary_expr_node} => {
                 ast_visitor.visit_unary_expr_node_to_string(unary_expr_node,output);
             },
+            ExprType::ConditionalExprT {conditional_expr_node} => {
+                ast_visitor.visit_conditional_expr_node_to_string(conditional_expr_node,output);
+            },
+            ExprType::ListLiteralExprT {list_literal_node} => {
+                ast_visitor.visit_list_literal_node_to_string(list_literal_node,output);
+            },
+            ExprType::MapLiteralExprT {map_literal_node} => {
+                ast_visitor.visit_map_literal_node_to_string(map_literal_node,output);
+            },
         }
     }
 
@@ -825,6 +1379,12 @@ pub enum StatementType {
     StateStackStmt {
         state_stack_operation_statement_node:StateStackOperationStatementNode,
     },
+    RaiseStmt {
+        raise_statement_node:RaiseStatementNode,
+    },
+    ForwardStmt {
+        forward_statement_node:ForwardStatementNode,
+    },
     #[allow(dead_code)] // is used, don't know why I need this
     NoStmt,
 }
@@ -1062,6 +1622,56 @@ impl NodeElement for StateStackOperationStatementNode {
     }
 }
 
+//-----------------------------------------------------//
+
+// Raises an interface message from inside the machine, e.g.
+// 'raise |open| (x)'. The event is queued until the current event
+// has run to completion.
+
+pub struct RaiseStatementNode {
+    pub interface_method_name:String,
+    pub call_expr_list_node:CallExprListNode,
+}
+
+impl RaiseStatementNode {
+    pub fn new(interface_method_name:String,
+               call_expr_list_node:CallExprListNode) -> RaiseStatementNode {
+        RaiseStatementNode {
+            interface_method_name,
+            call_expr_list_node,
+        }
+    }
+}
+
+impl NodeElement for RaiseStatementNode {
+    fn accept(&self, ast_visitor: &mut dyn AstVisitor) {
+        ast_visitor.visit_raise_statement_node(self);
+    }
+}
+
+//-----------------------------------------------------//
+
+// Forwards the current event to the parent state, '=> $^', or to a
+// named ancestor, '=> $Base', from inside an event handler.
+
+pub struct ForwardStatementNode {
+    pub target_state_ref:StateRefNode,
+}
+
+impl ForwardStatementNode {
+    pub fn new(target_state_ref:StateRefNode) -> ForwardStatementNode {
+        ForwardStatementNode {
+            target_state_ref,
+        }
+    }
+}
+
+impl NodeElement for ForwardStatementNode {
+    fn accept(&self, ast_visitor: &mut dyn AstVisitor) {
+        ast_visitor.visit_forward_statement_node(self);
+    }
+}
+
 
 //-----------------------------------------------------//
 
@@ -1124,7 +1734,7 @@ impl NodeElement for CallChainLiteralExprNode {
 }
 
 //-----------------------------------------------------//
-#[derive(PartialEq)]
+#[derive(Clone, PartialEq)]
 pub enum OperatorType {
     Plus,
     Minus,
@@ -1235,6 +1845,36 @@ impl NodeElement for BinaryExprNode {
 
 //-----------------------------------------------------//
 
+// condition ? true_expr : false_expr
+
+pub struct ConditionalExprNode {
+    pub condition_rcref:Rc<RefCell<ExprType>>,
+    pub true_expr_rcref:Rc<RefCell<ExprType>>,
+    pub false_expr_rcref:Rc<RefCell<ExprType>>,
+}
+
+impl ConditionalExprNode {
+    pub fn new(condition:ExprType,true_expr:ExprType,false_expr:ExprType) -> ConditionalExprNode {
+        ConditionalExprNode {
+            condition_rcref:Rc::new(RefCell::new(condition)),
+            true_expr_rcref:Rc::new(RefCell::new(true_expr)),
+            false_expr_rcref:Rc::new(RefCell::new(false_expr)),
+        }
+    }
+}
+
+impl NodeElement for ConditionalExprNode {
+    fn accept(&self, ast_visitor: &mut dyn AstVisitor) {
+        ast_visitor.visit_conditional_expr_node(self);
+    }
+
+    fn accept_to_string(&self, ast_visitor:&mut dyn AstVisitor, output:&mut String) {
+        ast_visitor.visit_conditional_expr_node_to_string(self, output);
+    }
+}
+
+//-----------------------------------------------------//
+
 pub struct CallExprNode {
     pub identifier:IdentifierNode,
     pub call_expr_list: CallExprListNode,
@@ -1325,6 +1965,72 @@ impl NodeElement for ExprListNode {
 
 //-----------------------------------------------------//
 
+// '[' expr* ']'
+//
+// element_type_opt is the Frame type (int, float, string, bool) shared by
+// all elements when they are literals. type_opt is the declared type of
+// the variable the literal initializes, if any.
+
+pub struct ListLiteralNode {
+    pub exprs_t:Vec<ExprType>,
+    pub element_type_opt:Option<FrameType>,
+    pub type_opt:Option<TypeNode>,
+}
+
+impl ListLiteralNode {
+    pub fn new(exprs_t:Vec<ExprType>, element_type_opt:Option<FrameType>) -> ListLiteralNode {
+        ListLiteralNode {
+            exprs_t,
+            element_type_opt,
+            type_opt:None,
+        }
+    }
+}
+
+impl NodeElement for ListLiteralNode {
+    fn accept(&self, ast_visitor: &mut dyn AstVisitor) {
+        ast_visitor.visit_list_literal_node(self);
+    }
+
+    fn accept_to_string(&self, ast_visitor:&mut dyn AstVisitor, output:&mut String) {
+        ast_visitor.visit_list_literal_node_to_string(self, output);
+    }
+}
+
+//-----------------------------------------------------//
+
+// '{' (expr ':' expr)* '}'
+
+pub struct MapLiteralNode {
+    pub entries:Vec<(ExprType,ExprType)>,
+    pub key_type_opt:Option<FrameType>,
+    pub value_type_opt:Option<FrameType>,
+    pub type_opt:Option<TypeNode>,
+}
+
+impl MapLiteralNode {
+    pub fn new(entries:Vec<(ExprType,ExprType)>, key_type_opt:Option<FrameType>, value_type_opt:Option<FrameType>) -> MapLiteralNode {
+        MapLiteralNode {
+            entries,
+            key_type_opt,
+            value_type_opt,
+            type_opt:None,
+        }
+    }
+}
+
+impl NodeElement for MapLiteralNode {
+    fn accept(&self, ast_visitor: &mut dyn AstVisitor) {
+        ast_visitor.visit_map_literal_node(self);
+    }
+
+    fn accept_to_string(&self, ast_visitor:&mut dyn AstVisitor, output:&mut String) {
+        ast_visitor.visit_map_literal_node_to_string(self, output);
+    }
+}
+
+//-----------------------------------------------------//
+
 // TODO!!: why aren't there MachineBlock, InterfaceBlock etc?
 // there is a misalighment of ParseScope vs IdentiferDeclScope?
 // for instance States have a IdentiferDeclScope of None. Should be machine
@@ -1414,12 +2120,17 @@ impl NodeElement for LiteralExprNode {
 
 
 // &String | &str | Widget<int> | `& mut String` | &`mut String` | *x
+//
+// Types written with Frame's portable type names (int, float, bool, string,
+// list<T>, map<K V>) carry a FrameType that each visitor maps to its native
+// type. All other types are pasted verbatim into the generated code.
 
 #[derive(Clone)]
 pub struct TypeNode {
     is_superstring:bool,
     is_reference:bool,
     type_str:String,
+    frame_type_opt:Option<FrameType>,
 }
 
 impl TypeNode {
@@ -1428,6 +2139,16 @@ impl TypeNode {
             is_superstring,
             is_reference,
             type_str,
+            frame_type_opt:None,
+        }
+    }
+
+    pub fn new_frame_type(is_reference:bool, frame_type:FrameType) -> TypeNode {
+        TypeNode {
+            is_superstring:false,
+            is_reference,
+            type_str:frame_type.to_string(),
+            frame_type_opt:Some(frame_type),
         }
     }
 
@@ -1441,6 +2162,24 @@ impl TypeNode {
         s.push_str(&*self.type_str);
         s
     }
+
+    pub fn get_frame_type_opt(&self) -> Option<&FrameType> {
+        self.frame_type_opt.as_ref()
+    }
+
+    pub fn is_reference(&self) -> bool {
+        self.is_reference
+    }
+
+    pub fn is_type_param(&self) -> bool {
+        matches!(self.frame_type_opt, Some(FrameType::TypeParam {..}))
+    }
+
+    pub fn collect_type_params(&self, type_params:&mut Vec<String>) {
+        if let Some(frame_type) = &self.frame_type_opt {
+            frame_type.collect_type_params(type_params);
+        }
+    }
 }
 
 impl NodeElement for TypeNode {
@@ -1455,6 +2194,64 @@ impl NodeElement for TypeNode {
 }
 
 
+//-----------------------------------------------------//
+
+#[derive(Clone, PartialEq)]
+pub enum FrameType {
+    Int,
+    Float,
+    Bool,
+    String,
+    List {element_type:Box<FrameType>},
+    Map {key_type:Box<FrameType>, value_type:Box<FrameType>},
+    TypeParam {name:String},
+}
+
+impl FrameType {
+    pub fn get_primitive_type(type_name:&str) -> Option<FrameType> {
+        match type_name {
+            "int" => Some(FrameType::Int),
+            "float" => Some(FrameType::Float),
+            "bool" => Some(FrameType::Bool),
+            "string" => Some(FrameType::String),
+            _ => None,
+        }
+    }
+
+    // Adds the system type parameters this type uses, including those
+    // of collection elements.
+
+    pub fn collect_type_params(&self, type_params:&mut Vec<String>) {
+        match self {
+            FrameType::List {element_type} => element_type.collect_type_params(type_params),
+            FrameType::Map {key_type, value_type} => {
+                key_type.collect_type_params(type_params);
+                value_type.collect_type_params(type_params);
+            },
+            FrameType::TypeParam {name} => {
+                if !type_params.contains(name) {
+                    type_params.push(name.clone());
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+impl fmt::Display for FrameType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrameType::Int => write!(f, "int"),
+            FrameType::Float => write!(f, "float"),
+            FrameType::Bool => write!(f, "bool"),
+            FrameType::String => write!(f, "string"),
+            FrameType::List {element_type} => write!(f, "list<{}>", element_type),
+            FrameType::Map {key_type, value_type} => write!(f, "map<{} {}>", key_type, value_type),
+            FrameType::TypeParam {name} => write!(f, "{}", name),
+        }
+    }
+}
+
 //-----------------------------------------------------//
 
 pub enum TestType {
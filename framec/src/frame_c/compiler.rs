@@ -10,6 +10,7 @@ use crate::frame_c::visitors::python_visitor::PythonVisitor;
 use crate::frame_c::visitors::gdscript_3_2_visitor::GdScript32Visitor;
 use crate::frame_c::visitors::java_8_visitor::Java8Visitor;
 use crate::frame_c::visitors::rust_visitor::RustVisitor;
+use crate::frame_c::snapshot::SnapshotManifest;
 use crate::frame_c::utils::{RunError, frame_exitcode};
 use exitcode::USAGE;
 //use crate::frame_c::visitors::xtate_visitor::XStateVisitor;
@@ -46,8 +47,44 @@ impl Exe {
 
     /* --------------------------------------------------------------------- */
 
-    pub fn run(&self, contents:String, mut output_format:String) -> Result<String,RunError> {
+    // Returns a warning for each way the system can no longer restore
+    // snapshots described by the baseline manifest.
+
+    pub fn check_snapshot(&self, contents:String, baseline:&str) -> Result<Vec<String>,RunError> {
+        let baseline_manifest = match SnapshotManifest::parse(baseline) {
+            Ok(manifest) => manifest,
+            Err(error) => {
+                let run_error = RunError::new(frame_exitcode::PARSE_ERR, &format!("Invalid snapshot baseline - {}", error));
+                return Err(run_error);
+            }
+        };
+        let manifest_text = self.run(contents, String::from("snapshot"))?;
+        let manifest = match SnapshotManifest::parse(&manifest_text) {
+            Ok(manifest) => manifest,
+            Err(error) => {
+                let run_error = RunError::new(frame_exitcode::PARSE_ERR, &error);
+                return Err(run_error);
+            }
+        };
+        Ok(manifest.check(&baseline_manifest))
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    pub fn run(&self, contents:String, output_format:String) -> Result<String,RunError> {
+        let (output, _actions_stub_opt) = self.run_with_actions_stub(contents, output_format, "")?;
+        Ok(output)
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    // Also returns the stub implementing the system's external actions for
+    // targets that generate them as abstract methods. A Python stub
+    // imports the system from module_name.
+
+    pub fn run_with_actions_stub(&self, contents:String, mut output_format:String, module_name:&str) -> Result<(String,Option<String>),RunError> {
         let output;
+        let mut actions_stub_opt = None;
 
         let scanner = Scanner::new(contents);
         let (has_errors,errors,tokens) = scanner.scan_tokens();
@@ -92,6 +129,11 @@ impl Exe {
         let generate_change_state = semantic_parser.generate_change_state;
         let generate_transition_state = semantic_parser.generate_transition_state;
 
+        // The snapshot manifest is the same for every language so a
+        // language attribute does not override it.
+        if output_format == "snapshot" {
+            return Ok((SnapshotManifest::new(&system_node).format(), None));
+        }
 
         match &system_node.attributes_opt {
             Some(attributes) => {
@@ -112,6 +154,7 @@ impl Exe {
                                                                                 , comments);
             visitor.run(&system_node);
             output = visitor.get_code();
+            actions_stub_opt = visitor.get_actions_stub();
         } else if output_format == "cpp" {
             let mut visitor = CppVisitor::new(semantic_parser.get_arcanum()
                                                   , generate_exit_args
@@ -123,6 +166,7 @@ impl Exe {
                                                   , comments);
             visitor.run(&system_node);
             output = visitor.get_code();
+            actions_stub_opt = visitor.get_actions_stub();
         } else if output_format == "c_sharp_bob" {
             let mut visitor = CsVisitorForBob::new(semantic_parser.get_arcanum()
                                                    , generate_exit_args
@@ -145,6 +189,7 @@ impl Exe {
                                              , comments);
             visitor.run(&system_node);
             output = visitor.get_code();
+            actions_stub_opt = visitor.get_actions_stub();
         } else if output_format == "gdscript" {
             let mut visitor = GdScript32Visitor::new(semantic_parser.get_arcanum()
                                                      , generate_exit_args
@@ -167,6 +212,7 @@ impl Exe {
                                                    , comments);
             visitor.run(&system_node);
             output = visitor.get_code();
+            actions_stub_opt = visitor.get_actions_stub();
         } else if output_format == "python_3" {
             let mut visitor = PythonVisitor::new(semantic_parser.get_arcanum()
                                                    , generate_exit_args
@@ -178,6 +224,7 @@ impl Exe {
                                                    , comments);
             visitor.run(&system_node);
             output = visitor.get_code();
+            actions_stub_opt = visitor.get_actions_stub(module_name);
         } else if output_format == "plantuml" {
             // let x = (&semantic_parser).get_arcanum();
             // semantic_parser = semantic_parser.into_inner();
@@ -206,6 +253,7 @@ impl Exe {
                                              , comments);
             visitor.run(&system_node);
             output = visitor.get_code();
+            actions_stub_opt = visitor.get_actions_stub();
         // } else if output_format == "xstate" {
         //     let mut visitor = XStateVisitor::new(semantic_parser.get_arcanum()
         //                                        , generate_exit_args
@@ -223,7 +271,7 @@ impl Exe {
             return Err(run_error);
         }
 
-        Ok(output)
+        Ok((output, actions_stub_opt))
 
         // let mut graphviz_visitor = GraphVizVisitor::new(semantic_parser.get_arcanum(), comments);
         // graphviz_visitor.run(&system_node);
@@ -4,6 +4,7 @@ mod symbol_table;
 mod scanner;
 mod visitors;
 mod parser;
+mod snapshot;
 pub mod utils;
 
 
@@ -16,6 +16,16 @@ use super::symbol_table::SymbolType::*;
 use super::ast::AssignmentExprNode;
 use crate::frame_c::utils::{SystemHierarchy};
 use std::collections::HashMap;
+use std::collections::HashSet;
+
+// The attributes each kind of declaration accepts. Any declaration can
+// also be documented with #[doc] and #[deprecated].
+
+const DOC_ATTRIBUTES:&[&str] = &["doc", "deprecated"];
+const SYSTEM_ATTRIBUTES:&[&str] = &["language", "run_to_completion", "state_stack_max", "state_stack_underflow", "snapshot_version"];
+const INTERFACE_METHOD_ATTRIBUTES:&[&str] = &["query"];
+const STATE_ATTRIBUTES:&[&str] = &["final", "renamed_from"];
+const DOMAIN_VARIABLE_ATTRIBUTES:&[&str] = &["snapshot_default"];
 
 pub struct ParseError {
     // TODO:
@@ -100,7 +110,29 @@ pub struct Parser<'a> {
     last_sync_token_idx:usize,
     system_hierarchy_opt:Option<SystemHierarchy>,
     is_parsing_rhs:bool,
+    pending_type_closers:usize,
     event_handler_has_transition:bool,
+    is_run_to_completion:bool,
+    history_targets:Vec<(String,usize)>,
+    region_name_opt:Option<String>,
+    region_token_idx_opt:Option<usize>,
+    is_final_state:bool,
+    always_completions:Vec<(String,String,usize)>,
+    parent_state_name_opt:Option<String>,
+    forward_targets:Vec<(String,String,usize)>,
+    pushed_state_names:Vec<String>,
+    state_stack_pop_enter_args:Vec<(usize,usize)>,
+    attribute_token_idxs:HashMap<String,usize>,
+    renamed_states:HashMap<String,String>,
+    is_parsing_operation:bool,
+    is_parsing_action:bool,
+    action_mutates_domain:bool,
+    called_action_names:Vec<String>,
+    action_calls:HashMap<String,Vec<String>>,
+    query_messages:HashSet<String>,
+    query_handler_msg_opt:Option<String>,
+    query_forwards:Vec<(String,String,usize)>,
+    system_type_params:Vec<String>,
     pub generate_exit_args:bool,
     pub generate_state_context:bool,
     pub generate_state_stack:bool,
@@ -130,7 +162,29 @@ impl<'a> Parser<'a> {
             current_tok_ref:&tokens[0],
             system_hierarchy_opt:None,
             is_parsing_rhs:false,
+            pending_type_closers:0,
             event_handler_has_transition:false,
+            is_run_to_completion:false,
+            history_targets:Vec::new(),
+            region_name_opt:None,
+            region_token_idx_opt:None,
+            is_final_state:false,
+            always_completions:Vec::new(),
+            parent_state_name_opt:None,
+            forward_targets:Vec::new(),
+            pushed_state_names:Vec::new(),
+            state_stack_pop_enter_args:Vec::new(),
+            attribute_token_idxs:HashMap::new(),
+            renamed_states:HashMap::new(),
+            is_parsing_operation:false,
+            is_parsing_action:false,
+            action_mutates_domain:false,
+            called_action_names:Vec::new(),
+            action_calls:HashMap::new(),
+            query_messages:HashSet::new(),
+            query_handler_msg_opt:None,
+            query_forwards:Vec::new(),
+            system_type_params:Vec::new(),
             generate_exit_args:false,
             generate_state_context:false,
             generate_state_stack:false,
@@ -334,6 +388,27 @@ impl<'a> Parser<'a> {
 
     /* --------------------------------------------------------------------- */
 
+    // Returns the first token following any outer attributes at the
+    // current position. Used where attributes could precede more than one
+    // kind of declaration.
+
+    fn peek_past_attributes(&self) -> &Token {
+        let mut idx = self.current;
+        while self.tokens[idx].token_type == OuterAttributeTok {
+            while self.tokens[idx].token_type != RBracketTok
+                && self.tokens[idx].token_type != EofTok {
+                idx += 1;
+            }
+            if self.tokens[idx].token_type == RBracketTok {
+                idx += 1;
+            }
+        }
+
+        &self.tokens[idx]
+    }
+
+    /* --------------------------------------------------------------------- */
+
     fn follows(&self,token:&Token,follows_vec:&Vec<TokenType>) -> bool {
         for follows_token_type in follows_vec {
             if *follows_token_type == token.token_type {
@@ -358,17 +433,23 @@ impl<'a> Parser<'a> {
         let mut interface_block_node_opt = Option::None;
         let mut machine_block_node_opt = Option::None;
         let mut actions_block_node_opt = Option::None;
+        let mut operations_block_node_opt = Option::None;
         let mut domain_block_node_opt = Option::None;
 
         if self.match_token(&vec![EofTok]) {
             self.error_at_current("Empty system.");
             return SystemNode::new(String::from("error"),
+                                   Vec::new(),
                                    header,
                                    None,
                                    None,
                                    None,
                                    None,
                                    None,
+                                   None,
+                                   None,
+                                   None,
+                                   None,
                                    0)
         }
 
@@ -400,12 +481,56 @@ impl<'a> Parser<'a> {
         }
         if !self.match_token(&vec![IdentifierTok]) {
             self.error_at_current("Expected system identifer.");
-            let sync_tokens = &vec![InterfaceBlockTok, MachineBlockTok, ActionsBlockTok, DomainBlockTok, SystemEndTok];
+            let sync_tokens = &vec![InterfaceBlockTok, MachineBlockTok, ActionsBlockTok, OperationsBlockTok, DomainBlockTok, SystemEndTok];
             self.synchronize(sync_tokens);
         }
 
         let id = self.previous();
         let system_name = id.lexeme.clone();
+        let system_name_token = id.clone();
+
+        self.system_type_params = Vec::new();
+        if self.match_token(&vec![LTTok]) {
+            match self.type_params() {
+                Ok(type_params) => self.system_type_params = type_params,
+                Err(_parse_error) => {
+                    let sync_tokens = &vec![LBracketTok, InterfaceBlockTok, MachineBlockTok, ActionsBlockTok, OperationsBlockTok, DomainBlockTok, SystemEndTok];
+                    self.synchronize(sync_tokens);
+                }
+            }
+        }
+
+        let mut start_state_state_params_opt = None;
+        let mut start_state_enter_params_opt = None;
+        let mut domain_params_opt = None;
+        if self.match_token(&vec![LBracketTok]) {
+            match self.system_params() {
+                Ok((state_params_opt, enter_params_opt, params_opt)) => {
+                    start_state_state_params_opt = state_params_opt;
+                    start_state_enter_params_opt = enter_params_opt;
+                    domain_params_opt = params_opt;
+                },
+                Err(_parse_error) => {
+                    let sync_tokens = &vec![InterfaceBlockTok, MachineBlockTok, ActionsBlockTok, OperationsBlockTok, DomainBlockTok, SystemEndTok];
+                    self.synchronize(sync_tokens);
+                }
+            }
+        }
+
+        // Inner attributes apply to the system they are declared in.
+        let attributes_opt = match self.inner_attributes() {
+            Ok(Some(inner_attributes)) => {
+                let mut attributes = attributes_opt.unwrap_or_default();
+                attributes.extend(inner_attributes);
+                Some(attributes)
+            },
+            Ok(None) => attributes_opt,
+            Err(_parse_error) => attributes_opt,
+        };
+
+        if let Some(attributes) = &attributes_opt {
+            self.is_run_to_completion = attributes.contains_key("run_to_completion");
+        }
 
         self.system_hierarchy_opt = Some(SystemHierarchy::new(system_name.clone()));
 
@@ -430,10 +555,36 @@ impl<'a> Parser<'a> {
             self.arcanum.debug_print_current_symbols(self.arcanum.get_current_symtab());
         }
 
+        // The hierarchy is only complete once all states are parsed.
+        if !self.is_building_symbol_table {
+            if let Some(machine_block_node) = &machine_block_node_opt {
+                self.resolve_history(machine_block_node);
+                self.check_regions(machine_block_node);
+                self.check_query_forwards(machine_block_node);
+            }
+            self.check_completion_cycles();
+            self.check_forward_targets();
+            self.check_state_stack_pop_enter_args();
+            if let Some(attributes) = &attributes_opt {
+                self.check_state_stack_attributes(attributes);
+                self.check_snapshot_version(attributes);
+            }
+        }
+
         if self.match_token(&vec![ActionsBlockTok]) {
             actions_block_node_opt = Option::Some(self.actions_block());
         }
 
+        if self.match_token(&vec![OperationsBlockTok]) {
+            let interface_names = match &interface_block_node_opt {
+                Some(interface_block_node) => interface_block_node.interface_methods.iter()
+                    .map(|interface_method_node| interface_method_node.name.clone())
+                    .collect(),
+                None => Vec::new(),
+            };
+            operations_block_node_opt = Option::Some(self.operations_block(&interface_names));
+        }
+
         if self.match_token(&vec![DomainBlockTok]) {
             self.arcanum.debug_print_current_symbols(self.arcanum.get_current_symtab());
             domain_block_node_opt = Option::Some(self.domain_block());
@@ -444,237 +595,884 @@ impl<'a> Parser<'a> {
             self.error_at_current("Expected ##.");
         }
 
+        if !self.is_building_symbol_table {
+            self.check_system_params(&system_name_token, &machine_block_node_opt, &domain_block_node_opt
+                                     , &start_state_state_params_opt, &start_state_enter_params_opt, &domain_params_opt);
+        }
+
         let line = self.previous().line;
 
         self.arcanum.exit_parse_scope();
 
-        SystemNode::new(system_name,
+        let system_node = SystemNode::new(system_name,
+                        self.system_type_params.clone(),
                         header,
                         attributes_opt,
+                        start_state_state_params_opt,
+                        start_state_enter_params_opt,
+                        domain_params_opt,
                         interface_block_node_opt,
                         machine_block_node_opt,
                         actions_block_node_opt,
+                        operations_block_node_opt,
                         domain_block_node_opt,
-                        line)
-    }
+                        line);
 
-    /* --------------------------------------------------------------------- */
+        if !self.is_building_symbol_table {
+            self.check_attribute_names(&system_node);
+        }
 
+        system_node
+    }
 
-    fn attributes(&mut self) -> Result<Option<HashMap<String,AttributeNode>>,ParseError> {
-        let mut attributes:HashMap<String,AttributeNode> = HashMap::new();
+    /* --------------------------------------------------------------------- */
 
-        loop {
-            if self.match_token(&vec![InnerAttributeTok]) {
-                // not supported yet
-                let parse_error = ParseError::new("Found '#![' token - inner attribute syntax not currently supported.");
-                return Err(parse_error);
-            } else if self.match_token(&vec![OuterAttributeTok]) {
-                let attribute_node = match self.attribute() {
-                    Ok(attribute_node) => {
-                        attribute_node
-                    },
-                    Err(err) => {
-                        return Err(err);
-                    },
-                };
-                attributes.insert(attribute_node.name.clone(),attribute_node);
-                if let Err(parse_error) =  self.consume(RBracketTok, "Expected ']'.") {
-                    return Err(parse_error);
+    fn check_attribute_names(&mut self, system_node:&SystemNode) {
+        self.check_declaration_attributes(&system_node.attributes_opt, SYSTEM_ATTRIBUTES, "systems");
+        if let Some(interface_block_node) = &system_node.interface_block_node_opt {
+            for interface_method_node in &interface_block_node.interface_methods {
+                self.check_declaration_attributes(&interface_method_node.attributes_opt, INTERFACE_METHOD_ATTRIBUTES, "interface methods");
+            }
+        }
+        if let Some(machine_block_node) = &system_node.machine_block_node_opt {
+            for state_node_rcref in &machine_block_node.states {
+                let state_node = state_node_rcref.borrow();
+                self.check_declaration_attributes(&state_node.attributes_opt, STATE_ATTRIBUTES, "states");
+                for evt_handler_rcref in &state_node.evt_handlers_rcref {
+                    self.check_declaration_attributes(&evt_handler_rcref.borrow().attributes_opt, &[], "event handlers");
                 }
-            } else {
-                break;
             }
         }
+        if let Some(actions_block_node) = &system_node.actions_block_node_opt {
+            for action_node_rcref in &actions_block_node.actions {
+                self.check_declaration_attributes(&action_node_rcref.borrow().attributes_opt, &[], "actions");
+            }
+        }
+        if let Some(operations_block_node) = &system_node.operations_block_node_opt {
+            for operation_node_rcref in &operations_block_node.operations {
+                self.check_declaration_attributes(&operation_node_rcref.borrow().attributes_opt, &[], "operations");
+            }
+        }
+        if let Some(domain_block_node) = &system_node.domain_block_node_opt {
+            for variable_decl_node_rcref in &domain_block_node.member_variables {
+                self.check_declaration_attributes(&variable_decl_node_rcref.borrow().attributes_opt, DOMAIN_VARIABLE_ATTRIBUTES, "domain variables");
+            }
+        }
+    }
 
-        if attributes.len() == 0 {
-            Ok(None)
-        } else {
-            Ok(Some(attributes))
+    /* --------------------------------------------------------------------- */
+
+    // Rejects misspelled attributes, and known ones on a declaration they
+    // don't apply to, rather than silently ignoring them.
+
+    fn check_declaration_attributes(&mut self, attributes_opt:&Option<HashMap<String,AttributeNode>>, accepted:&[&str], declaration_kind:&str) {
+        let attributes = match attributes_opt {
+            Some(attributes) => attributes,
+            None => return,
+        };
+        let mut names:Vec<&String> = attributes.keys().collect();
+        names.sort();
+        for name in names {
+            if DOC_ATTRIBUTES.contains(&name.as_str()) || accepted.contains(&name.as_str()) {
+                continue;
+            }
+            let token = self.tokens[self.attribute_token_idxs[name]].clone();
+            let is_known = [SYSTEM_ATTRIBUTES, INTERFACE_METHOD_ATTRIBUTES, STATE_ATTRIBUTES, DOMAIN_VARIABLE_ATTRIBUTES].iter()
+                .any(|known| known.contains(&name.as_str()));
+            if is_known {
+                self.error_at(&token, &format!("#[{}] can't be used on {}.", name, declaration_kind));
+            } else {
+                self.error_at(&token, &format!("Unknown attribute #[{}].", name));
+            }
+            return;
         }
     }
 
     /* --------------------------------------------------------------------- */
 
-    fn attribute(&mut self) -> Result<AttributeNode,ParseError> {
-        let name ;
-        let value;
+    // type_params -> '<' identifier+ '>'
+    //
+    // The type parameters of a generic system. They can be used wherever
+    // a Frame type can.
 
-        if self.match_token(&vec![IdentifierTok]) {
-           name = self.previous().lexeme.clone();
-        } else {
-            self.error_at_current("Expected attribute name.");
-            let parse_error = ParseError::new("TODO");
-            return Err(parse_error);
+    fn type_params(&mut self) -> Result<Vec<String>,ParseError> {
+        let mut type_params:Vec<String> = Vec::new();
 
+        while self.match_token(&vec![IdentifierTok]) {
+            let type_param = self.previous().lexeme.clone();
+            if FrameType::get_primitive_type(&type_param).is_some() || type_param == "list" || type_param == "map" {
+                self.error_at_previous(&format!("'{}' is a Frame type and can't be a type parameter.", type_param));
+            } else if type_params.contains(&type_param) {
+                self.error_at_previous(&format!("Duplicate type parameter '{}'.", type_param));
+            } else {
+                type_params.push(type_param);
+            }
         }
-        if let Err(_) = self.consume(TokenType::EqualsTok, "Expected '('") {
-            self.error_at_current("Expected '='.");
-            let parse_error = ParseError::new("TODO");
-            return Err(parse_error);
+
+        if type_params.is_empty() {
+            self.error_at_current("Expected type parameter name.");
         }
-        if self.match_token(&vec![StringTok]) {
-            value = self.previous().lexeme.clone();
-        } else {
-            self.error_at_current("Expected attribute value.");
-            let parse_error = ParseError::new("TODO");
+        if let Err(parse_error) = self.consume(GTTok, "Expected '>'.") {
             return Err(parse_error);
-
         }
-        return Ok(AttributeNode::new(name,value));
+
+        Ok(type_params)
     }
 
     /* --------------------------------------------------------------------- */
 
-    fn interface_block(&mut self) -> InterfaceBlockNode {
-
-        let mut interface_methods = Vec::new();
+    // system_params -> '[' ( '$' '[' parameters | '>' '[' parameters | parameter )* ']'
 
-        // NOTE: this loop peeks() ahead and then interface_method() consumes
-        // the identifier. Not sure if this is the best way.
+    fn system_params(&mut self) -> Result<(Option<Vec<ParameterNode>>,Option<Vec<ParameterNode>>,Option<Vec<ParameterNode>>),ParseError> {
+        let mut start_state_state_params_opt = None;
+        let mut start_state_enter_params_opt = None;
+        let mut domain_params = Vec::new();
 
-        while self.match_token(&vec![TokenType::IdentifierTok]) {
-            match self.interface_method() {
-                Ok(interface_method_node) => {
-                    interface_methods.push(interface_method_node);
-                },
-                Err(_parse_error) => {
-                    let sync_tokens = &vec![IdentifierTok, MachineBlockTok, ActionsBlockTok, DomainBlockTok, SystemEndTok];
-                    self.synchronize(sync_tokens);
+        while !self.match_token(&vec![RBracketTok]) {
+            if self.match_token(&vec![StateTok]) {
+                if start_state_state_params_opt.is_some() {
+                    self.error_at_previous("Duplicate start state parameters.");
+                }
+                if let Err(parse_error) = self.consume(LBracketTok, "Expected '['.") {
+                    return Err(parse_error);
+                }
+                start_state_state_params_opt = self.parameters()?;
+            } else if self.match_token(&vec![GTTok]) {
+                if start_state_enter_params_opt.is_some() {
+                    self.error_at_previous("Duplicate start state enter parameters.");
+                }
+                if let Err(parse_error) = self.consume(LBracketTok, "Expected '['.") {
+                    return Err(parse_error);
+                }
+                start_state_enter_params_opt = self.parameters()?;
+            } else {
+                match self.parameter()? {
+                    Some(parameter_node) => domain_params.push(parameter_node),
+                    None => break,
                 }
             }
         }
 
-
-        InterfaceBlockNode::new(interface_methods)
+        let domain_params_opt = if domain_params.len() > 0 { Some(domain_params) } else { None };
+        Ok((start_state_state_params_opt, start_state_enter_params_opt, domain_params_opt))
     }
 
     /* --------------------------------------------------------------------- */
 
-    // interface_method -> identifier ('[' parameters ']')? (':' return_type)?
-
-    fn interface_method(&mut self) -> Result<InterfaceMethodNode,ParseError> {
-
-        let name = self.previous().lexeme.clone();
-
-        let mut params_opt:Option<Vec<ParameterNode>> = Option::None;
-        let mut return_type_opt:Option<TypeNode> = Option::None;
-        let mut alias_opt:Option<MessageNode> = Option::None;
-
-        if self.match_token(&vec![TokenType::LBracketTok]) {
-            match self.parameters() {
-                Ok(Some(parameters)) => params_opt = Some(parameters),
-                Ok(None) => return Err(ParseError::new("TODO")),
-                Err(parse_error) => return Err(parse_error),
+    // System parameters are passed to the start state or initialize
+    // domain variables so they must match what they are passed to.
+
+    fn check_system_params(&mut self, id:&Token
+                           , machine_block_node_opt:&Option<MachineBlockNode>
+                           , domain_block_node_opt:&Option<DomainBlockNode>
+                           , start_state_state_params_opt:&Option<Vec<ParameterNode>>
+                           , start_state_enter_params_opt:&Option<Vec<ParameterNode>>
+                           , domain_params_opt:&Option<Vec<ParameterNode>>) {
+        let param_names = |params_opt:&Option<Vec<ParameterNode>>| -> Vec<String> {
+            match params_opt {
+                Some(params) => params.iter().map(|param| param.param_name.clone()).collect(),
+                None => Vec::new(),
+            }
+        };
+        if start_state_state_params_opt.is_some() || start_state_enter_params_opt.is_some() {
+            let start_state_node_rcref = match machine_block_node_opt.as_ref().and_then(|machine_block_node| machine_block_node.states.first()) {
+                Some(state_node_rcref) => state_node_rcref.clone(),
+                None => {
+                    self.error_at(id, "Start state parameters require a start state.");
+                    return;
+                }
+            };
+            let start_state_name = start_state_node_rcref.borrow().name.clone();
+            if start_state_state_params_opt.is_some() {
+                let state_param_names = start_state_node_rcref.borrow().get_param_names();
+                let system_param_names = param_names(start_state_state_params_opt);
+                if system_param_names != state_param_names {
+                    self.error_at(id, &format!("System parameters $[{}] must match the parameters [{}] of ${}.", system_param_names.join(" "), state_param_names.join(" "), start_state_name));
+                }
+            }
+            if start_state_enter_params_opt.is_some() {
+                let enter_param_names:Vec<String> = self.get_enter_params(&start_state_name).into_iter().map(|(name, _)| name).collect();
+                let system_param_names = param_names(start_state_enter_params_opt);
+                if system_param_names != enter_param_names {
+                    self.error_at(id, &format!("System parameters >[{}] must match the enter parameters [{}] of ${}.", system_param_names.join(" "), enter_param_names.join(" "), start_state_name));
+                }
             }
         }
-
-        // Parse return type
-        if self.match_token(&vec![TokenType::ColonTok]) {
-
-            match self.type_decl() {
-                Ok(type_node) => return_type_opt = Some(type_node),
-                Err(parse_error) => return Err(parse_error),
+        let domain_var_names:Vec<String> = match domain_block_node_opt {
+            Some(domain_block_node) => domain_block_node.member_variables.iter().map(|var_rcref| var_rcref.borrow().name.clone()).collect(),
+            None => Vec::new(),
+        };
+        for param_name in param_names(domain_params_opt) {
+            if !domain_var_names.contains(&param_name) {
+                self.error_at(id, &format!("System parameter {} does not match a domain variable.", param_name));
             }
         }
+    }
 
-        // Parse alias
-        if self.match_token(&vec![TokenType::AtTok]) {
-            if let Err(_) = self.consume(TokenType::LParenTok, "Expected '('") {
-                self.error_at_current("Expected '('.");
-                let sync_tokens = &vec![PipeTok];
-                self.synchronize(sync_tokens);
-            }
+    /* --------------------------------------------------------------------- */
 
-            match self.message() {
-                Ok(MessageType::CustomMessage { message_node }) => alias_opt = Some(message_node),
-                Ok(AnyMessage {..}) => {
-                    self.error_at_previous("Expected message, found '||*");
-                    let sync_tokens = &vec![RParenTok, MachineBlockTok, ActionsBlockTok, DomainBlockTok, SystemEndTok];
-                    self.synchronize(sync_tokens);
+    // Adds a history record to every state below a history target so
+    // the generated code knows what to remember when the state is exited.
+
+    fn resolve_history(&mut self, machine_block_node:&MachineBlockNode) {
+        if self.history_targets.len() == 0 {
+            return;
+        }
+
+        let history_targets = self.history_targets.clone();
+        for (target_state_name, token_idx) in &history_targets {
+            let has_children = match &self.system_hierarchy_opt {
+                Some(system_hierarchy) => match system_hierarchy.get_node(target_state_name) {
+                    Some(node) => node.children.len() > 0,
+                    None => false,
                 },
-                Err(err) => return Err(err),
+                None => false,
+            };
+            if !has_children {
+                let token = self.tokens[*token_idx].clone();
+                self.error_at(&token, &format!("${} has no substates so it has no history.", target_state_name));
+                return;
             }
+        }
 
-            if let Err(_) = self.consume(TokenType::RParenTok, "Expected ')'") {
-                let sync_tokens = &vec![IdentifierTok, MachineBlockTok, ActionsBlockTok, DomainBlockTok, SystemEndTok];
-                self.synchronize(sync_tokens);
+        // History only remembers a state, so resuming gives that state a
+        // new StateContext. The parent and its substates can't carry data.
+        let mut subtree_state_names:Vec<(String,String,usize)> = Vec::new();
+        if let Some(system_hierarchy) = &self.system_hierarchy_opt {
+            for state_node_rcref in &machine_block_node.states {
+                let state_node = state_node_rcref.borrow();
+                let ancestors = system_hierarchy.get_ancestors(&state_node.name);
+                for (target_state_name, token_idx) in &history_targets {
+                    if state_node.name == *target_state_name || ancestors.contains(target_state_name) {
+                        if state_node.params_opt.is_some() || state_node.vars_opt.is_some() {
+                            let token = self.tokens[*token_idx].clone();
+                            self.error_at(&token, &format!("History transitions are not supported when ${} or its substates have state parameters, state variables or enter arguments.", target_state_name));
+                            return;
+                        }
+                        subtree_state_names.push((state_node.name.clone(), target_state_name.clone(), *token_idx));
+                    }
+                }
+            }
+        }
+        for (state_name, target_state_name, token_idx) in &subtree_state_names {
+            if self.get_enter_params(state_name).len() > 0 {
+                let token = self.tokens[*token_idx].clone();
+                self.error_at(&token, &format!("History transitions are not supported when ${} or its substates have state parameters, state variables or enter arguments.", target_state_name));
+                return;
             }
         }
 
-        let mut param_symbols_opt = None;
-        match &params_opt {
-            Some(param_nodes) => {
-                let mut vec = Vec::new();
-                for param_node in param_nodes {
-                    let param_symbol = ParameterSymbol::new(param_node.param_name.clone(),param_node.param_type_opt.clone(),IdentifierDeclScope::None);
-                    vec.push(param_symbol);
+        let system_hierarchy = match &self.system_hierarchy_opt {
+            Some(system_hierarchy) => system_hierarchy,
+            None => return,
+        };
+        for state_node_rcref in &machine_block_node.states {
+            let mut state_node = state_node_rcref.borrow_mut();
+            let ancestors = system_hierarchy.get_ancestors(&state_node.name);
+            let mut child_state_name = state_node.name.clone();
+            for ancestor in ancestors {
+                if history_targets.iter().any(|(target, _)| *target == ancestor) {
+                    state_node.history_records.push(HistoryRecordNode::new(ancestor.clone(), child_state_name.clone()));
                 }
-                param_symbols_opt = Some(vec);
-            },
-            None => {},
+                child_state_name = ancestor;
+            }
         }
+    }
 
-        // if the alias exists, that is the name of the event message.
-        // if not, the interface method name becomes the event message name.
+    /* --------------------------------------------------------------------- */
 
-        let msg = match &alias_opt {
-            Some(alias) => alias.name.clone(),
-            None => name.clone(),
+    // Each region keeps a single state reference while its events are
+    // dispatched, so per state data, the shared state stack, the single
+    // timer and the single deferred event queue can't be used with regions.
+
+    fn check_regions(&mut self, machine_block_node:&MachineBlockNode) {
+        let region_token_idx = match self.region_token_idx_opt {
+            Some(region_token_idx) => region_token_idx,
+            None => return,
         };
+        let has_timeouts = machine_block_node.states.iter().any(|state_node_rcref| state_node_rcref.borrow().timeout_opt.is_some());
+        let has_deferred_events = machine_block_node.states.iter().any(|state_node_rcref| state_node_rcref.borrow().deferred_events_opt.is_some());
+        if self.generate_state_context || self.generate_state_stack || has_timeouts || has_deferred_events {
+            let token = self.tokens[region_token_idx].clone();
+            self.error_at(&token, "Regions are not supported with state parameters, state variables, enter arguments, the state stack, timeouts or deferred events.");
+        }
+    }
 
-        // get or create the event symbol for the message we found
-        let event_symbol_rcref ;
-        match self.arcanum.get_event(&*msg,&self.state_name_opt) {
-            Some(_existing_event_symbol_rc_ref) => {
-                // found message
-                // event_symbol_rcref = existing_event_symbol_rc_ref.clone();
-            },
-            None => {
-                let event_symbol = EventSymbol::new(&self.arcanum.symbol_config
-                                                    ,&msg
-                                                    ,Some(name.clone())
-                                                    ,param_symbols_opt
-                                                    ,return_type_opt.clone()
-                                                    ,self.state_name_opt.clone());
-                event_symbol_rcref = Rc::new(RefCell::new(event_symbol));
-                self.arcanum.declare_event(Rc::clone(&event_symbol_rcref));
+    /* --------------------------------------------------------------------- */
 
-                // This is the first time we are seeing this event.
-                // Set flag so parameters and return type are added to event symbol
-                // during this parse.
- //               is_declaring_event = true;
+    // A completion transition that is always taken can't lead back to a
+    // state it has already passed through or the chain never ends.
+
+    fn check_completion_cycles(&mut self) {
+        let mut always_completions:HashMap<String,(String,usize)> = HashMap::new();
+        for (source_state_name, target_state_name, token_idx) in &self.always_completions {
+            always_completions.insert(source_state_name.clone(), (target_state_name.clone(), *token_idx));
+        }
+        let mut checked:HashSet<String> = HashSet::new();
+        for (start_state_name, _, _) in self.always_completions.clone() {
+            let mut path:Vec<String> = Vec::new();
+            let mut path_map:HashMap<String,usize> = HashMap::new();
+            let mut state_name = start_state_name;
+            while !checked.contains(&state_name) {
+                if let Some(cycle_start) = path_map.get(&state_name) {
+                    let mut cycle:Vec<String> = path[*cycle_start..].iter().map(|state_name| format!("${}", state_name)).collect();
+                    cycle.push(format!("${}", state_name));
+                    let token = self.tokens[always_completions[&state_name].1].clone();
+                    self.error_at(&token, &format!("Completion transitions loop forever: {}.", cycle.join(" -> ")));
+                    return;
+                }
+                path_map.insert(state_name.clone(), path.len());
+                path.push(state_name.clone());
+                match always_completions.get(&state_name) {
+                    Some((target_state_name, _)) => state_name = target_state_name.clone(),
+                    None => break,
+                }
             }
+            checked.extend(path);
         }
+    }
 
-        // let event_symbol = EventSymbol::new(&self.arcanum.symbol_config
-        //                                                 ,&name
-        //                                                 ,Some(name.clone()),
-        //                                                 ,param_symbols_opt
-        //                                                 ,return_type_opt.clone()
-        //                                                 ,None);
+    /* --------------------------------------------------------------------- */
 
-        let interface_method_node = InterfaceMethodNode::new(name, params_opt, return_type_opt, alias_opt);
+    // An event can only be forwarded to an ancestor of the state handling
+    // it.
 
-        Ok(interface_method_node)
+    fn check_forward_targets(&mut self) {
+        let forward_targets = self.forward_targets.clone();
+        for (state_name, target_state_name, token_idx) in &forward_targets {
+            let is_ancestor = match &self.system_hierarchy_opt {
+                Some(system_hierarchy) => system_hierarchy.get_ancestors(state_name).contains(target_state_name),
+                None => false,
+            };
+            if !is_ancestor {
+                let token = self.tokens[*token_idx].clone();
+                self.error_at(&token, &format!("${} is not an ancestor of ${}.", target_state_name, state_name));
+                return;
+            }
+        }
     }
 
-
     /* --------------------------------------------------------------------- */
 
-
-    fn type_decl(&mut self) -> Result<TypeNode,ParseError> {
-        let mut is_reference = false;
-
-        if self.match_token(&vec![TokenType::SuperStringTok]) {
-            let id = self.previous();
-            let type_str = id.lexeme.clone();
-            Ok(TypeNode::new(true,false, type_str))
-        } else {
-            if self.match_token(&vec![TokenType::AndTok]) {
-                is_reference = true
+    // An event forwarded from a #[query] handler must reach another
+    // handler for the same message. Walking up from the target, the first
+    // state with a |*| handler and no handler for the message would run
+    // a handler that isn't checked as a query.
+
+    fn check_query_forwards(&mut self, machine_block_node:&MachineBlockNode) {
+        let query_forwards = self.query_forwards.clone();
+        for (target_state_name, msg, token_idx) in &query_forwards {
+            let mut state_names = vec![target_state_name.clone()];
+            if let Some(system_hierarchy) = &self.system_hierarchy_opt {
+                state_names.extend(system_hierarchy.get_ancestors(target_state_name));
             }
-            if !self.match_token(&vec![TokenType::IdentifierTok]) {
+            for state_name in &state_names {
+                let state_node_rcref = match machine_block_node.states.iter().find(|state_node_rcref| &state_node_rcref.borrow().name == state_name) {
+                    Some(state_node_rcref) => state_node_rcref.clone(),
+                    None => break,
+                };
+                let state_node = state_node_rcref.borrow();
+                let mut handles_msg = false;
+                let mut handles_any = false;
+                for evt_handler_rcref in &state_node.evt_handlers_rcref {
+                    match &evt_handler_rcref.borrow().msg_t {
+                        MessageType::CustomMessage {message_node} if &message_node.name == msg => handles_msg = true,
+                        MessageType::AnyMessage {..} => handles_any = true,
+                        _ => {},
+                    }
+                }
+                if handles_msg {
+                    break;
+                }
+                if handles_any {
+                    let token = self.tokens[*token_idx].clone();
+                    self.error_at(&token, &format!("|{}| is a #[query] but is forwarded to the |*| handler in ${}.", msg, state_name));
+                    return;
+                }
+            }
+        }
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    // Enter arguments passed to $$[-] go to whichever pushed state is
+    // popped, so every pushed state must take exactly those parameters.
+    // A pop without arguments can only restore states without any.
+
+    fn check_state_stack_pop_enter_args(&mut self) {
+        let state_stack_pop_enter_args = self.state_stack_pop_enter_args.clone();
+        for (token_idx, arg_count) in &state_stack_pop_enter_args {
+            let token = self.tokens[*token_idx].clone();
+            let pushed_state_names = self.pushed_state_names.clone();
+            if pushed_state_names.is_empty() {
+                if *arg_count == 0 {
+                    continue;
+                }
+                self.error_at(&token, "Enter arguments can not be passed to $$[-] as no state is pushed on the state stack.");
+                return;
+            }
+            let first_enter_params = self.get_enter_params(&pushed_state_names[0]);
+            for pushed_state_name in &pushed_state_names {
+                let enter_params = self.get_enter_params(pushed_state_name);
+                if enter_params.len() != *arg_count {
+                    self.error_at(&token, &format!("Enter arguments to $$[-] don't match the enter parameters of ${}.", pushed_state_name));
+                    return;
+                }
+                if enter_params != first_enter_params {
+                    self.error_at(&token, &format!("${} and ${} are pushed on the state stack but have different enter parameters.", pushed_state_names[0], pushed_state_name));
+                    return;
+                }
+            }
+        }
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    // #![state_stack_max] must be a positive depth and
+    // #![state_stack_underflow] must be "error", "stay" or a state that
+    // can be entered without arguments.
+
+    fn check_state_stack_attributes(&mut self, attributes:&HashMap<String,AttributeNode>) {
+        let has_state_stack_attribute = attributes.contains_key("state_stack_max") || attributes.contains_key("state_stack_underflow");
+        if has_state_stack_attribute && !self.generate_state_stack {
+            let attribute_name = if attributes.contains_key("state_stack_max") { "state_stack_max" } else { "state_stack_underflow" };
+            let token = self.tokens[self.attribute_token_idxs[attribute_name]].clone();
+            self.error_at(&token, &format!("#![{}] requires the state stack to be used.", attribute_name));
+            return;
+        }
+        if let Some(attribute_node) = attributes.get("state_stack_max") {
+            let is_valid = match attribute_node.value.parse::<usize>() {
+                Ok(max) => max > 0,
+                Err(_) => false,
+            };
+            if !is_valid {
+                let token = self.tokens[self.attribute_token_idxs["state_stack_max"]].clone();
+                self.error_at(&token, "#![state_stack_max] must be a positive number.");
+                return;
+            }
+        }
+        if let Some(attribute_node) = attributes.get("state_stack_underflow") {
+            let token = self.tokens[self.attribute_token_idxs["state_stack_underflow"]].clone();
+            let value = attribute_node.value.clone();
+            if value == "error" || value == "stay" {
+                return;
+            }
+            if !value.starts_with('$') {
+                self.error_at(&token, "#![state_stack_underflow] must be \"error\", \"stay\" or a state.");
+                return;
+            }
+            let state_name = value[1..].to_string();
+            let has_state_params = match self.arcanum.get_state(&state_name) {
+                Some(state_symbol_rcref) => state_symbol_rcref.borrow().params_opt.is_some(),
+                None => {
+                    self.error_at(&token, &format!("Unknown state {} in #![state_stack_underflow].", value));
+                    return;
+                }
+            };
+            if has_state_params || self.get_enter_params(&state_name).len() > 0 {
+                self.error_at(&token, &format!("{} can not take state or enter parameters as it is the #![state_stack_underflow] state.", value));
+            }
+        }
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    fn check_snapshot_version(&mut self, attributes:&HashMap<String,AttributeNode>) {
+        let attribute_node = match attributes.get("snapshot_version") {
+            Some(attribute_node) => attribute_node,
+            None => return,
+        };
+        let token = self.tokens[self.attribute_token_idxs["snapshot_version"]].clone();
+        if !self.arcanum.is_serializable() {
+            self.error_at(&token, "#![snapshot_version] requires a |>>>| or |<<<| interface method.");
+            return;
+        }
+        let is_valid = match attribute_node.value.parse::<u32>() {
+            Ok(version) => version > 0,
+            Err(_) => false,
+        };
+        if !is_valid {
+            self.error_at(&token, "#![snapshot_version] must be a positive number.");
+        }
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    // A state can take over the snapshots of states that no longer exist.
+    // Each old state can only be taken over by one state.
+
+    fn check_renamed_from(&mut self, state_name:&String, renamed_from:&String) {
+        let token = self.tokens[self.attribute_token_idxs["renamed_from"]].clone();
+        if !self.arcanum.is_serializable() {
+            self.error_at(&token, "#[renamed_from] requires a |>>>| or |<<<| interface method.");
+            return;
+        }
+        for old_state_name in renamed_from.split(',') {
+            let old_state_name = old_state_name.trim().trim_start_matches('$').to_string();
+            if old_state_name.len() == 0 {
+                self.error_at(&token, "#[renamed_from] must name the old states.");
+                return;
+            }
+            if self.arcanum.get_state(&old_state_name).is_some() {
+                self.error_at(&token, &format!("${} can not be renamed to ${} as ${} is still a state.", old_state_name, state_name, old_state_name));
+                return;
+            }
+            if let Some(new_state_name) = self.renamed_states.get(&old_state_name) {
+                let new_state_name = new_state_name.clone();
+                self.error_at(&token, &format!("${} is already renamed to ${}.", old_state_name, new_state_name));
+                return;
+            }
+            self.renamed_states.insert(old_state_name, state_name.clone());
+        }
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    // Returns the names and types of a state's enter event parameters.
+
+    fn get_enter_params(&mut self, state_name:&String) -> Vec<(String,String)> {
+        let enter_msg = self.arcanum.symbol_config.enter_msg_symbol.clone();
+        match self.arcanum.get_event(&enter_msg, &Some(state_name.clone())) {
+            Some(event_symbol_rcref) => match &event_symbol_rcref.borrow().params_opt {
+                Some(params) => params.iter()
+                    .map(|param| (param.name.clone(), param.param_type_opt.as_ref().map(|param_type| param_type.get_type_str()).unwrap_or_default()))
+                    .collect(),
+                None => Vec::new(),
+            },
+            None => Vec::new(),
+        }
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    // Returns the region of a state if it differs from the region
+    // currently being parsed.
+
+    fn get_other_region_opt(&mut self, state_name:&String) -> Option<String> {
+        if self.is_building_symbol_table || self.region_name_opt.is_none() {
+            return None;
+        }
+        let region_name_opt = match self.arcanum.get_state(state_name) {
+            Some(state_symbol_rcref) => state_symbol_rcref.borrow().region_name_opt.clone(),
+            None => return None,
+        };
+        if region_name_opt != self.region_name_opt {
+            region_name_opt
+        } else {
+            None
+        }
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    // A #[final] state has no outgoing transitions.
+
+    fn check_final_state_transition(&mut self) {
+        if self.is_final_state {
+            let state_name = self.state_name_opt.clone().unwrap_or_default();
+            self.error_at_previous(&format!("Final state ${} can not transition.", state_name));
+        }
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    // A transition can't leave the region of the state it is in.
+
+    fn check_region_transition(&mut self, target_state_name:&String) {
+        if let Some(region_name) = self.get_other_region_opt(target_state_name) {
+            self.error_at_previous(&format!("${} is in region {} and can not be reached from region {}.",
+                                            target_state_name, region_name, self.region_name_opt.clone().unwrap_or_default()));
+        }
+    }
+
+    /* --------------------------------------------------------------------- */
+
+
+    // attributes -> ('#[' attribute ']')*
+
+    fn attributes(&mut self) -> Result<Option<HashMap<String,AttributeNode>>,ParseError> {
+        if self.peek().token_type == InnerAttributeTok {
+            self.error_at_current("Inner attributes ('#![') are only allowed at the start of the system body.");
+            return Err(ParseError::new("TODO"));
+        }
+
+        self.attribute_list(OuterAttributeTok)
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    // inner_attributes -> ('#![' attribute ']')*
+
+    fn inner_attributes(&mut self) -> Result<Option<HashMap<String,AttributeNode>>,ParseError> {
+        self.attribute_list(InnerAttributeTok)
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    fn attribute_list(&mut self, attribute_token_type:TokenType) -> Result<Option<HashMap<String,AttributeNode>>,ParseError> {
+        let mut attributes:HashMap<String,AttributeNode> = HashMap::new();
+
+        loop {
+            if self.match_token(&vec![attribute_token_type]) {
+                let attribute_node = match self.attribute() {
+                    Ok(attribute_node) => {
+                        attribute_node
+                    },
+                    Err(err) => {
+                        return Err(err);
+                    },
+                };
+                attributes.insert(attribute_node.name.clone(),attribute_node);
+                if let Err(parse_error) =  self.consume(RBracketTok, "Expected ']'.") {
+                    return Err(parse_error);
+                }
+            } else {
+                break;
+            }
+        }
+
+        if attributes.len() == 0 {
+            Ok(None)
+        } else {
+            Ok(Some(attributes))
+        }
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    // attribute -> identifier ('=' string)?
+
+    fn attribute(&mut self) -> Result<AttributeNode,ParseError> {
+        let name ;
+        let mut value = String::new();
+
+        if self.match_token(&vec![IdentifierTok]) {
+           name = self.previous().lexeme.clone();
+           self.attribute_token_idxs.insert(name.clone(), self.current - 1);
+        } else {
+            self.error_at_current("Expected attribute name.");
+            let parse_error = ParseError::new("TODO");
+            return Err(parse_error);
+
+        }
+        if self.match_token(&vec![TokenType::EqualsTok]) {
+            if self.match_token(&vec![StringTok]) {
+                value = self.previous().lexeme.clone();
+            } else {
+                self.error_at_current("Expected attribute value.");
+                let parse_error = ParseError::new("TODO");
+                return Err(parse_error);
+            }
+        }
+        return Ok(AttributeNode::new(name,value));
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    fn interface_block(&mut self) -> InterfaceBlockNode {
+
+        let mut interface_methods = Vec::new();
+
+        // NOTE: this loop peeks() ahead and then interface_method() consumes
+        // the identifier. Not sure if this is the best way.
+
+        loop {
+            let attributes_opt = match self.attributes() {
+                Ok(attributes_opt) => attributes_opt,
+                Err(_parse_error) => None,
+            };
+            if !self.match_token(&vec![TokenType::IdentifierTok]) {
+                if attributes_opt.is_some() {
+                    self.error_at_current("Expected interface method after attributes.");
+                }
+                break;
+            }
+            match self.interface_method(attributes_opt) {
+                Ok(interface_method_node) => {
+                    interface_methods.push(interface_method_node);
+                },
+                Err(_parse_error) => {
+                    let sync_tokens = &vec![IdentifierTok, OuterAttributeTok, MachineBlockTok, ActionsBlockTok, OperationsBlockTok, DomainBlockTok, SystemEndTok];
+                    self.synchronize(sync_tokens);
+                }
+            }
+        }
+
+
+        InterfaceBlockNode::new(interface_methods)
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    // interface_method -> attributes identifier ('[' parameters ']')? (':' return_type)?
+
+    fn interface_method(&mut self, attributes_opt:Option<HashMap<String,AttributeNode>>) -> Result<InterfaceMethodNode,ParseError> {
+
+        let name = self.previous().lexeme.clone();
+
+        let mut params_opt:Option<Vec<ParameterNode>> = Option::None;
+        let mut return_type_opt:Option<TypeNode> = Option::None;
+        let mut alias_opt:Option<MessageNode> = Option::None;
+        let mut default_return_expr_opt:Option<ExprType> = Option::None;
+
+        if self.match_token(&vec![TokenType::LBracketTok]) {
+            match self.parameters() {
+                Ok(Some(parameters)) => params_opt = Some(parameters),
+                Ok(None) => return Err(ParseError::new("TODO")),
+                Err(parse_error) => return Err(parse_error),
+            }
+        }
+
+        // Parse return type
+        if self.match_token(&vec![TokenType::ColonTok]) {
+
+            match self.type_decl() {
+                Ok(type_node) => return_type_opt = Some(type_node),
+                Err(parse_error) => return Err(parse_error),
+            }
+        }
+
+        // Parse default return value
+        if self.match_token(&vec![TokenType::EqualsTok]) {
+            if return_type_opt.is_none() {
+                self.error_at_previous(&format!("Interface method {} has a default return value but no return type.", name));
+            }
+            match self.equality() {
+                Ok(Some(expr_t)) => default_return_expr_opt = Some(expr_t),
+                Ok(None) => {
+                    self.error_at_current("Expected default return value.");
+                    return Err(ParseError::new("TODO"));
+                },
+                Err(parse_error) => return Err(parse_error),
+            }
+        }
+
+        // Parse alias
+        if self.match_token(&vec![TokenType::AtTok]) {
+            if let Err(_) = self.consume(TokenType::LParenTok, "Expected '('") {
+                self.error_at_current("Expected '('.");
+                let sync_tokens = &vec![PipeTok];
+                self.synchronize(sync_tokens);
+            }
+
+            match self.message() {
+                Ok(MessageType::CustomMessage { message_node }) => alias_opt = Some(message_node),
+                Ok(AnyMessage {..}) => {
+                    self.error_at_previous("Expected message, found '||*");
+                    let sync_tokens = &vec![RParenTok, MachineBlockTok, ActionsBlockTok, OperationsBlockTok, DomainBlockTok, SystemEndTok];
+                    self.synchronize(sync_tokens);
+                },
+                Err(err) => return Err(err),
+            }
+
+            if let Err(_) = self.consume(TokenType::RParenTok, "Expected ')'") {
+                let sync_tokens = &vec![IdentifierTok, MachineBlockTok, ActionsBlockTok, OperationsBlockTok, DomainBlockTok, SystemEndTok];
+                self.synchronize(sync_tokens);
+            }
+        }
+
+        let mut param_symbols_opt = None;
+        match &params_opt {
+            Some(param_nodes) => {
+                let mut vec = Vec::new();
+                for param_node in param_nodes {
+                    let param_symbol = ParameterSymbol::new(param_node.param_name.clone(),param_node.param_type_opt.clone(),IdentifierDeclScope::None);
+                    vec.push(param_symbol);
+                }
+                param_symbols_opt = Some(vec);
+            },
+            None => {},
+        }
+
+        // if the alias exists, that is the name of the event message.
+        // if not, the interface method name becomes the event message name.
+
+        let msg = match &alias_opt {
+            Some(alias) => alias.name.clone(),
+            None => name.clone(),
+        };
+
+        // get or create the event symbol for the message we found
+        let event_symbol_rcref ;
+        match self.arcanum.get_event(&*msg,&self.state_name_opt) {
+            Some(_existing_event_symbol_rc_ref) => {
+                // found message
+                // event_symbol_rcref = existing_event_symbol_rc_ref.clone();
+            },
+            None => {
+                let event_symbol = EventSymbol::new(&self.arcanum.symbol_config
+                                                    ,&msg
+                                                    ,Some(name.clone())
+                                                    ,param_symbols_opt
+                                                    ,return_type_opt.clone()
+                                                    ,self.state_name_opt.clone());
+                event_symbol_rcref = Rc::new(RefCell::new(event_symbol));
+                self.arcanum.declare_event(Rc::clone(&event_symbol_rcref));
+
+                // This is the first time we are seeing this event.
+                // Set flag so parameters and return type are added to event symbol
+                // during this parse.
+ //               is_declaring_event = true;
+            }
+        }
+
+        // let event_symbol = EventSymbol::new(&self.arcanum.symbol_config
+        //                                                 ,&name
+        //                                                 ,Some(name.clone()),
+        //                                                 ,param_symbols_opt
+        //                                                 ,return_type_opt.clone()
+        //                                                 ,None);
+
+        // The |>>>| method returns the snapshot and the |<<<| method
+        // restores the one it is passed.
+        if let Some(alias) = &alias_opt {
+            if alias.name == self.arcanum.symbol_config.save_msg_symbol && return_type_opt.is_none() {
+                self.error_at_previous(&format!("The |{}| interface method must return the snapshot.", alias.name));
+            }
+            let param_count = params_opt.as_ref().map(|params| params.len()).unwrap_or(0);
+            if alias.name == self.arcanum.symbol_config.restore_msg_symbol && param_count != 1 {
+                self.error_at_previous(&format!("The |{}| interface method must take the snapshot as its only parameter.", alias.name));
+            }
+        }
+
+        // Handlers for #[query] methods are checked to be side effect free.
+        if let Some(attribute_node) = attributes_opt.as_ref().and_then(|attributes| attributes.get("query")) {
+            if attribute_node.value.len() > 0 {
+                let token = self.tokens[self.attribute_token_idxs["query"]].clone();
+                self.error_at(&token, "#[query] takes no value.");
+            }
+            self.query_messages.insert(msg.clone());
+        }
+
+        if default_return_expr_opt.is_some() {
+            if let Some(alias) = &alias_opt {
+                if alias.name == self.arcanum.symbol_config.save_msg_symbol {
+                    self.error_at_previous(&format!("The |{}| interface method can't have a default return value.", alias.name));
+                }
+            }
+        }
+
+        let interface_method_node = InterfaceMethodNode::new(name, params_opt, return_type_opt, default_return_expr_opt, alias_opt, attributes_opt);
+
+        Ok(interface_method_node)
+    }
+
+
+    /* --------------------------------------------------------------------- */
+
+
+    fn type_decl(&mut self) -> Result<TypeNode,ParseError> {
+        let mut is_reference = false;
+
+        if self.match_token(&vec![TokenType::SuperStringTok]) {
+            let id = self.previous();
+            let type_str = id.lexeme.clone();
+            Ok(TypeNode::new(true,false, type_str))
+        } else {
+            if self.match_token(&vec![TokenType::AndTok]) {
+                is_reference = true
+            }
+            if !self.match_token(&vec![TokenType::IdentifierTok]) {
                 self.error_at_current("Expected return type name.");
                 return Err(ParseError::new("TODO"));
             }
@@ -682,9 +1480,80 @@ impl<'a> Parser<'a> {
             let id = self.previous();
             let type_str = id.lexeme.clone();
 
-            Ok(TypeNode::new(false,is_reference, type_str))
+            match self.frame_type(&type_str) {
+                Ok(Some(frame_type)) => Ok(TypeNode::new_frame_type(is_reference, frame_type)),
+                Ok(None) => Ok(TypeNode::new(false,is_reference, type_str)),
+                Err(parse_error) => Err(parse_error),
+            }
+        }
+
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    // frame_type -> 'int' | 'float' | 'bool' | 'string' | type_param |
+    //               'list' '<' frame_type '>' | 'map' '<' frame_type frame_type '>'
+    //
+    // Returns None if type_name isn't a Frame type so that it is used as is.
+
+    fn frame_type(&mut self, type_name:&str) -> Result<Option<FrameType>,ParseError> {
+
+        if self.system_type_params.iter().any(|type_param| type_param == type_name) {
+            return Ok(Some(FrameType::TypeParam {name:type_name.to_string()}));
+        }
+
+        if let Some(frame_type) = FrameType::get_primitive_type(type_name) {
+            return Ok(Some(frame_type));
+        }
+
+        if type_name != "list" && type_name != "map" {
+            return Ok(None);
+        }
+
+        if let Err(parse_error) = self.consume(LTTok, "Expected '<'.") {
+            return Err(parse_error);
+        }
+
+        let mut type_params:Vec<FrameType> = Vec::new();
+        let type_param_cnt = if type_name == "list" { 1 } else { 2 };
+        for _ in 0..type_param_cnt {
+            if !self.match_token(&vec![IdentifierTok]) {
+                self.error_at_current("Expected type name.");
+                return Err(ParseError::new("TODO"));
+            }
+            let type_param_name = self.previous().lexeme.clone();
+            match self.frame_type(&type_param_name) {
+                Ok(Some(frame_type)) => type_params.push(frame_type),
+                Ok(None) => {
+                    self.error_at_previous(&format!("'{}' is not a Frame type. Use a superstring type for native collections.", type_param_name));
+                    return Err(ParseError::new("TODO"));
+                },
+                Err(parse_error) => return Err(parse_error),
+            }
+        }
+
+        // '>>' and '>>>' close several nested types at once.
+        if self.pending_type_closers > 0 {
+            self.pending_type_closers -= 1;
+        } else if self.match_token(&vec![GTx2Tok]) {
+            self.pending_type_closers = 1;
+        } else if self.match_token(&vec![GTx3Tok]) {
+            self.pending_type_closers = 2;
+        } else if let Err(parse_error) = self.consume(GTTok, "Expected '>'.") {
+            return Err(parse_error);
         }
 
+        let mut type_params_it = type_params.into_iter();
+        let frame_type = match type_params_it.next() {
+            Some(element_type) if type_name == "list" => FrameType::List {element_type:Box::new(element_type)},
+            Some(key_type) => FrameType::Map {
+                key_type:Box::new(key_type),
+                value_type:Box::new(type_params_it.next().unwrap()),
+            },
+            None => return Err(ParseError::new("TODO")),
+        };
+
+        Ok(Some(frame_type))
     }
 
     /* --------------------------------------------------------------------- */
@@ -764,7 +1633,7 @@ impl<'a> Parser<'a> {
                     }
                 },
                 Err(_parse_error) => {
-                    let sync_tokens = &vec![IdentifierTok, ColonTok, RBracketTok, MachineBlockTok, ActionsBlockTok, DomainBlockTok, SystemEndTok];
+                    let sync_tokens = &vec![IdentifierTok, ColonTok, RBracketTok, MachineBlockTok, ActionsBlockTok, OperationsBlockTok, DomainBlockTok, SystemEndTok];
                     self.synchronize(sync_tokens);
                     if !self.follows(self.peek(),&vec![IdentifierTok,ColonTok,RBracketTok]) {
                         break;
@@ -826,19 +1695,39 @@ impl<'a> Parser<'a> {
         }
 
         let mut states = Vec::new();
+        let mut regions:Vec<RegionNode> = Vec::new();
 
-        while self.match_token(&vec![TokenType::StateTok]) {
-            match self.state() {
+        loop {
+            if self.match_token(&vec![TokenType::DashDashTok]) {
+                if let Ok(region_node) = self.region(&states, &regions) {
+                    regions.push(region_node);
+                }
+                continue;
+            }
+            let attributes_opt = match self.attributes() {
+                Ok(attributes_opt) => attributes_opt,
+                Err(_parse_error) => None,
+            };
+            if !self.match_token(&vec![TokenType::StateTok]) {
+                if attributes_opt.is_some() {
+                    self.error_at_current("Expected state after attributes.");
+                }
+                break;
+            }
+            match self.state(attributes_opt) {
                 Ok(state_rcref) => {
+                    if let Some(region_node) = regions.last_mut() {
+                        region_node.state_names.push(state_rcref.borrow().name.clone());
+                    }
                     states.push(state_rcref);
                 },
                 Err(_) => {
                     self.error_at_current("Error parsing Machine Block.");
-                    let sync_tokens = &vec![StateTok];
+                    let sync_tokens = &vec![StateTok, OuterAttributeTok, DashDashTok];
                     if self.synchronize(sync_tokens) {
                         continue;
                     } else {
-                        let sync_tokens = &vec![ActionsBlockTok, DomainBlockTok, SystemEndTok];
+                        let sync_tokens = &vec![ActionsBlockTok, OperationsBlockTok, DomainBlockTok, SystemEndTok];
                         self.synchronize(sync_tokens);
                         break;
                     }
@@ -846,108 +1735,466 @@ impl<'a> Parser<'a> {
             }
         }
 
-        self.arcanum.exit_parse_scope();
-
-        MachineBlockNode::new(states)
+        if let Some(region_node) = regions.last() {
+            if region_node.state_names.len() == 0 {
+                self.error_at_current(&format!("Region {} has no states.", region_node.name));
+            }
+        }
+
+        self.arcanum.exit_parse_scope();
+
+        MachineBlockNode::new(states, regions, self.pushed_state_names.clone())
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    // region -> '--' region_name
+
+    fn region(&mut self, states:&Vec<Rc<RefCell<StateNode>>>, regions:&Vec<RegionNode>) -> Result<RegionNode,ParseError> {
+        let region_token_idx = self.current - 1;
+        if !self.match_token(&vec![TokenType::IdentifierTok]) {
+            self.error_at_current("Expected region name.");
+            return Err(ParseError::new("TODO"));
+        }
+        let region_name = self.previous().lexeme.clone();
+
+        if regions.len() == 0 && states.len() > 0 {
+            self.error_at_previous("States must be declared in a region once the machine has regions.");
+        }
+        if let Some(region_node) = regions.last() {
+            if region_node.state_names.len() == 0 {
+                self.error_at_previous(&format!("Region {} has no states.", region_node.name));
+            }
+        }
+        if regions.iter().any(|region_node| region_node.name == region_name) {
+            self.error_at_previous(&format!("Duplicate region name {}.", region_name));
+        }
+
+        if self.region_token_idx_opt.is_none() {
+            self.region_token_idx_opt = Some(region_token_idx);
+        }
+        self.region_name_opt = Some(region_name.clone());
+
+        Ok(RegionNode::new(region_name))
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    // TODO: Return result
+    fn actions_block(&mut self) -> ActionsBlockNode {
+
+        if self.is_building_symbol_table {
+            let actions_block_scope_symbol = Rc::new(RefCell::new(ActionsBlockScopeSymbol::new()));
+            self.arcanum.enter_scope(ParseScopeType::ActionsBlockScope { actions_block_scope_symbol_rcref: actions_block_scope_symbol });
+        } else {
+            self.arcanum.set_parse_scope(ActionsBlockScopeSymbol::scope_name());
+        }
+
+        let mut actions = Vec::new();
+
+        loop {
+            let attributes_opt = match self.attributes() {
+                Ok(attributes_opt) => attributes_opt,
+                Err(_parse_error) => None,
+            };
+            if !self.match_token(&vec![IdentifierTok]) {
+                if attributes_opt.is_some() {
+                    self.error_at_current("Expected action after attributes.");
+                }
+                break;
+            }
+            match self.action_decl(attributes_opt) {
+                Ok(action_decl_node) =>  actions.push(action_decl_node),
+                Err(_) => {
+
+                }
+            }
+        }
+
+        if !self.is_building_symbol_table {
+            self.propagate_domain_mutation(&actions);
+        }
+
+        self.arcanum.exit_parse_scope();
+
+        ActionsBlockNode::new(actions)
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    // An action that calls an action which assigns domain variables
+    // assigns them too.
+
+    fn propagate_domain_mutation(&mut self, actions:&Vec<Rc<RefCell<ActionNode>>>) {
+        loop {
+            let mutating_action_names:Vec<String> = actions.iter()
+                .filter(|action_rcref| action_rcref.borrow().mutates_domain)
+                .map(|action_rcref| action_rcref.borrow().name.clone())
+                .collect();
+            let mut changed = false;
+            for action_rcref in actions {
+                let mut action_node = action_rcref.borrow_mut();
+                if action_node.mutates_domain {
+                    continue;
+                }
+                let calls_mutating_action = match self.action_calls.get(&action_node.name) {
+                    Some(called_names) => called_names.iter().any(|called_name| mutating_action_names.contains(called_name)),
+                    None => false,
+                };
+                if calls_mutating_action {
+                    action_node.mutates_domain = true;
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    fn action_decl(&mut self, attributes_opt:Option<HashMap<String,AttributeNode>>) -> Result<Rc<RefCell<ActionNode>>,ParseError> {
+
+        let action_name = self.previous().lexeme.clone();
+
+        if self.is_building_symbol_table {
+            let action_scope_symbol_rcref = Rc::new(RefCell::new(ActionScopeSymbol::new(&action_name)));
+            self.arcanum.enter_scope(ParseScopeType::ActionScope { action_scope_symbol_rcref });
+        } else {
+            self.arcanum.set_parse_scope(&ActionScopeSymbol::scope_name(&action_name));
+        }
+
+        let result = self.action_body(action_name.clone(), attributes_opt);
+
+        self.arcanum.exit_parse_scope();
+
+        let action_decl_node = result?;
+        let action_decl_rcref = Rc::new(RefCell::new(action_decl_node));
+
+        if self.is_building_symbol_table {
+            let s = action_name.clone();
+            let mut action_decl_symbol = ActionDeclSymbol::new(s);
+            // TODO: note what is being done. We are linking to the AST node generated in the syntax pass.
+            // This AST tree is otherwise disposed of. This may be fine but feels wrong. Alternatively
+            // we could copy this information out of the node and into the symbol.
+            action_decl_symbol.set_ast_node(Rc::clone(&action_decl_rcref));
+            let action_decl_symbol_rcref = Rc::new(RefCell::new(action_decl_symbol));
+            let action_decl_symbol_t = ActionDeclSymbolT { action_decl_symbol_rcref };
+            // TOOD: just insert into arcanum directly
+            self.arcanum.current_symtab.borrow_mut().insert_symbol(&action_decl_symbol_t);
+        } else {
+            // link action symbol to action declaration node
+        }
+
+        Ok(action_decl_rcref)
+
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    // action_body -> ('[' parameters ']')? (':' type)?
+    //                ('{' (super_string | statement* ('^' | '^' '(' expression ')')?) '}')?
+
+    fn action_body(&mut self, action_name:String, attributes_opt:Option<HashMap<String,AttributeNode>>) -> Result<ActionNode,ParseError> {
+
+        let mut params:Option<Vec<ParameterNode>> = Option::None;
+
+        if self.match_token(&vec![LBracketTok]) {
+            params = match self.parameters() {
+                Ok(Some(parameters)) =>  Some(parameters),
+                Ok(None) =>  None,
+                Err(parse_error) => return Err(parse_error),
+            };
+            self.declare_method_params(&params);
+        }
+
+        let mut type_opt:Option<TypeNode> = None;
+
+        if self.match_token(&vec![ColonTok]) {
+            // if !self.match_token(&vec![TokenType::IdentifierTok]) {
+            //     self.error_at_previous("Expected parameter type.");
+            //     return Err(ParseError::new("TODO"));
+            // }
+            //
+            // let id = self.previous();
+            // let type_name = id.lexeme.clone();
+            //
+            // type_opt = Some(type_name);
+
+            match self.type_decl() {
+                Ok(type_node) => type_opt = Some(type_node),
+                Err(parse_error) => return Err(parse_error),
+            }
+        }
+
+        let mut code_opt:Option<String> = None;
+        let mut statements = Vec::new();
+        let mut terminator_expr_opt = None;
+
+        if self.match_token(&vec![OpenBraceTok]) {
+
+            self.is_parsing_action = true;
+            self.action_mutates_domain = false;
+            self.called_action_names.clear();
+            let body = self.method_body();
+            self.is_parsing_action = false;
+            self.action_calls.insert(action_name.clone(), self.called_action_names.clone());
+            (code_opt, statements, terminator_expr_opt) = body?;
+
+            if let Err(parse_error) =  self.consume(CloseBraceTok, "Expected '}'.") {
+                return Err(parse_error);
+            }
+        }
+
+        let mut action_node = ActionNode::new(action_name, params, type_opt, code_opt, statements, terminator_expr_opt, attributes_opt);
+        action_node.mutates_domain = self.action_mutates_domain;
+
+        // Actions without a Frame body are implemented elsewhere so
+        // there is nothing to check.
+        if action_node.has_frame_body() {
+            self.check_method_body_return("Action", &action_node.name, &action_node.type_opt, &action_node.terminator_expr_opt);
+        }
+
+        Ok(action_node)
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    fn operations_block(&mut self, interface_names:&Vec<String>) -> OperationsBlockNode {
+
+        let mut operations:Vec<Rc<RefCell<OperationNode>>> = Vec::new();
+
+        loop {
+            let attributes_opt = match self.attributes() {
+                Ok(attributes_opt) => attributes_opt,
+                Err(_parse_error) => None,
+            };
+            if !self.match_token(&vec![IdentifierTok]) {
+                if attributes_opt.is_some() {
+                    self.error_at_current("Expected operation after attributes.");
+                }
+                break;
+            }
+            if !self.is_building_symbol_table {
+                let operation_name = self.previous().lexeme.clone();
+                if operations.iter().any(|operation_rcref| operation_rcref.borrow().name == operation_name) {
+                    self.error_at_previous(&format!("Operation {} is already declared.", operation_name));
+                } else if interface_names.contains(&operation_name) {
+                    self.error_at_previous(&format!("Operation {} has the same name as an interface method.", operation_name));
+                }
+            }
+            match self.operation(attributes_opt) {
+                Ok(operation_node) => operations.push(Rc::new(RefCell::new(operation_node))),
+                Err(_) => {
+                    let sync_tokens = &vec![IdentifierTok, DomainBlockTok, SystemEndTok];
+                    self.synchronize(sync_tokens);
+                }
+            }
+        }
+
+        OperationsBlockNode::new(operations)
     }
 
     /* --------------------------------------------------------------------- */
 
-    // TODO: Return result
-    fn actions_block(&mut self) -> ActionsBlockNode {
-
-        if self.is_building_symbol_table {
-            let actions_block_scope_symbol = Rc::new(RefCell::new(ActionsBlockScopeSymbol::new()));
-            self.arcanum.enter_scope(ParseScopeType::ActionsBlockScope { actions_block_scope_symbol_rcref: actions_block_scope_symbol });
-        }
-
-        let mut actions = Vec::new();
+    // operation -> identifier ('[' parameters ']')? (':' type)?
+    //              '{' (super_string | statement* ('^' | '^' '(' expression ')')?) '}'
 
-        while self.match_token(&vec![IdentifierTok]) {
-            match self.action_decl() {
-                Ok(action_decl_node) =>  actions.push(action_decl_node),
-                Err(_) => {
+    fn operation(&mut self, attributes_opt:Option<HashMap<String,AttributeNode>>) -> Result<OperationNode,ParseError> {
 
-                }
-            }
-        }
+        let operation_name = self.previous().lexeme.clone();
 
         if self.is_building_symbol_table {
-            self.arcanum.exit_parse_scope();
+            let operation_scope_symbol_rcref = Rc::new(RefCell::new(OperationScopeSymbol::new(&operation_name)));
+            self.arcanum.enter_scope(ParseScopeType::OperationScope { operation_scope_symbol_rcref });
+        } else {
+            self.arcanum.set_parse_scope(&operation_name);
         }
 
-        ActionsBlockNode::new(actions)
+        let result = self.operation_body(operation_name, attributes_opt);
+
+        self.arcanum.exit_parse_scope();
+
+        result
     }
 
     /* --------------------------------------------------------------------- */
 
-    fn action_decl(&mut self) -> Result<Rc<RefCell<ActionNode>>,ParseError> {
-
-        let action_name = self.previous().lexeme.clone();
+    fn operation_body(&mut self, operation_name:String, attributes_opt:Option<HashMap<String,AttributeNode>>) -> Result<OperationNode,ParseError> {
 
-        let mut params:Option<Vec<ParameterNode>> = Option::None;
+        let mut params:Option<Vec<ParameterNode>> = None;
 
         if self.match_token(&vec![LBracketTok]) {
-            params = match self.parameters() {
-                Ok(Some(parameters)) =>  Some(parameters),
-                Ok(None) =>  None,
-                Err(parse_error) => return Err(parse_error),
-            }
+            params = self.parameters()?;
+            self.declare_method_params(&params);
         }
 
         let mut type_opt:Option<TypeNode> = None;
 
         if self.match_token(&vec![ColonTok]) {
-            // if !self.match_token(&vec![TokenType::IdentifierTok]) {
-            //     self.error_at_previous("Expected parameter type.");
-            //     return Err(ParseError::new("TODO"));
-            // }
-            //
-            // let id = self.previous();
-            // let type_name = id.lexeme.clone();
-            //
-            // type_opt = Some(type_name);
+            type_opt = Some(self.type_decl()?);
+        }
 
-            match self.type_decl() {
-                Ok(type_node) => type_opt = Some(type_node),
-                Err(parse_error) => return Err(parse_error),
-            }
+        self.consume(OpenBraceTok, "Expected '{'.")?;
+
+        self.is_parsing_operation = true;
+        let body = self.method_body();
+        self.is_parsing_operation = false;
+        let (code_opt, statements, terminator_expr_opt) = body?;
+
+        self.consume(CloseBraceTok, "Expected '}'.")?;
+
+        if code_opt.is_none() {
+            self.check_method_body_return("Operation", &operation_name, &type_opt, &terminator_expr_opt);
         }
 
-        let mut code_opt:Option<String> = None;
+        Ok(OperationNode::new(operation_name, params, type_opt, code_opt, statements, terminator_expr_opt, attributes_opt))
+    }
 
-        if self.match_token(&vec![OpenBraceTok]) {
+    /* --------------------------------------------------------------------- */
 
-            if self.match_token(&vec![SuperStringTok]) {
-                let token = self.previous();
-                code_opt = Some(token.lexeme.clone());
-            }
+    // Operation and action parameters are plain local names in the body.
 
-            if let Err(parse_error) =  self.consume(CloseBraceTok, "Expected '}'.") {
-                return Err(parse_error);
+    fn declare_method_params(&mut self, params:&Option<Vec<ParameterNode>>) {
+        if !self.is_building_symbol_table {
+            return;
+        }
+        for param in params.iter().flatten() {
+            let variable_symbol = VariableSymbol::new(param.param_name.clone(), param.param_type_opt.clone(), IdentifierDeclScope::EventHandlerVar);
+            let variable_symbol_t = SymbolType::EventHandlerVariableSymbolT { event_handler_variable_symbol_rcref: Rc::new(RefCell::new(variable_symbol)) };
+            self.arcanum.current_symtab.borrow_mut().insert_symbol(&variable_symbol_t);
+        }
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    // method_body -> super_string | statement* ('^' | '^' '(' expression ')')?
+
+    fn method_body(&mut self) -> Result<(Option<String>,Vec<DeclOrStmtType>,Option<TerminatorExpr>),ParseError> {
+
+        if self.match_token(&vec![SuperStringTok]) {
+            return Ok((Some(self.previous().lexeme.clone()), Vec::new(), None));
+        }
+
+        let statements = self.statements();
+        let mut terminator_expr_opt = None;
+
+        if self.match_token(&vec![CaretTok]) {
+            if self.match_token(&vec![LParenTok]) {
+                let expr_t = match self.expression() {
+                    Ok(Some(expr_t)) => expr_t,
+                    _ => {
+                        self.error_at_current("Expected expression as return value.");
+                        return Err(ParseError::new("TODO"));
+                    }
+                };
+                self.consume(RParenTok, "Expected ')'.")?;
+                terminator_expr_opt = Some(TerminatorExpr::new(Return, Some(expr_t), self.previous().line));
+            } else {
+                terminator_expr_opt = Some(TerminatorExpr::new(Return, None, self.previous().line));
             }
         }
 
-        let action_decl_node = ActionNode::new(action_name.clone(), params, type_opt, code_opt);
-        let action_decl_rcref = Rc::new(RefCell::new(action_decl_node));
+        Ok((None, statements, terminator_expr_opt))
+    }
 
+    /* --------------------------------------------------------------------- */
+
+    fn check_method_body_return(&mut self, method_kind:&str, method_name:&str, type_opt:&Option<TypeNode>, terminator_expr_opt:&Option<TerminatorExpr>) {
         if self.is_building_symbol_table {
-            let s = action_name.clone();
-            let mut action_decl_symbol = ActionDeclSymbol::new(s);
-            // TODO: note what is being done. We are linking to the AST node generated in the syntax pass.
-            // This AST tree is otherwise disposed of. This may be fine but feels wrong. Alternatively
-            // we could copy this information out of the node and into the symbol.
-            action_decl_symbol.set_ast_node(Rc::clone(&action_decl_rcref));
-            let action_decl_symbol_rcref = Rc::new(RefCell::new(action_decl_symbol));
-            let action_decl_symbol_t = ActionDeclSymbolT { action_decl_symbol_rcref };
-            // TOOD: just insert into arcanum directly
-            self.arcanum.current_symtab.borrow_mut().insert_symbol(&action_decl_symbol_t);
-        } else {
-            // link action symbol to action declaration node
+            return;
+        }
+        let has_return_value = terminator_expr_opt.as_ref()
+            .map_or(false, |terminator_expr| terminator_expr.return_expr_t_opt.is_some());
+        if type_opt.is_some() && !has_return_value {
+            self.error_at_previous(&format!("{} {} must end with ^(value) to return its value.", method_kind, method_name));
+        } else if type_opt.is_none() && has_return_value {
+            self.error_at_previous(&format!("{} {} returns a value but does not declare a return type.", method_kind, method_name));
         }
+    }
 
-        Ok(action_decl_rcref)
+    /* --------------------------------------------------------------------- */
+
+    // Operations and actions run outside of the state machine so they
+    // can't use statements that need a current state or event.
+
+    fn check_method_body_statement(&mut self, stmt_t:&StatementType) {
+        let statement_name = match stmt_t {
+            StatementType::TransitionStmt {..} => "Transitions",
+            StatementType::ChangeStateStmt {..} => "State changes",
+            StatementType::StateStackStmt {..} => "State stack operations",
+            StatementType::ForwardStmt {..} => "Forwards",
+            StatementType::RaiseStmt {..} => "Raised events",
+            _ => return,
+        };
+        self.error_at_previous(&format!("{} are not allowed in {}.", statement_name, self.method_body_name().to_lowercase()));
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    fn method_body_name(&self) -> &'static str {
+        if self.is_parsing_operation { "Operations" } else { "Actions" }
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    // Handlers for #[query] interface methods may only read the system.
+
+    fn check_query_statement(&mut self, stmt_t:&StatementType) {
+        let statement_name = match stmt_t {
+            StatementType::TransitionStmt {..} => "Transitions",
+            StatementType::ChangeStateStmt {..} => "State changes",
+            StatementType::StateStackStmt {..} => "State stack operations",
+            StatementType::RaiseStmt {..} => "Raised events",
+            StatementType::ForwardStmt {forward_statement_node} => {
+                // The handler the event is forwarded to is checked once
+                // every state has been parsed.
+                if !self.is_building_symbol_table {
+                    if let Some(msg) = self.query_handler_msg_opt.clone() {
+                        let target_state_name = forward_statement_node.target_state_ref.name.clone();
+                        self.query_forwards.push((target_state_name, msg, self.current - 1));
+                    }
+                }
+                return;
+            },
+            _ => return,
+        };
+        self.error_at_previous(&format!("{} are not allowed in handlers for #[query] interface methods.", statement_name));
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    fn check_query_assignment(&mut self, l_value:&ExprType) {
+        let var_node = match Parser::get_assigned_variable(l_value) {
+            Some(var_node) => var_node,
+            None => return,
+        };
+        let variable_kind = match var_node.scope {
+            IdentifierDeclScope::DomainBlock => "domain variable",
+            IdentifierDeclScope::StateVar => "state variable",
+            IdentifierDeclScope::StateParam => "state parameter",
+            _ => return,
+        };
+        self.error_at_previous(&format!("Handlers for #[query] interface methods can't assign to {} {}.",
+                                        variable_kind, var_node.id_node.name.lexeme));
+    }
 
+    /* --------------------------------------------------------------------- */
+
+    // The variable an assignment writes to, if it is one.
+
+    fn get_assigned_variable(l_value:&ExprType) -> Option<&VariableNode> {
+        match l_value {
+            VariableExprT { var_node } => Some(var_node),
+            CallChainLiteralExprT { call_chain_expr_node } => {
+                match call_chain_expr_node.call_chain.get(0) {
+                    Some(CallChainLiteralNodeType::VariableNodeT { var_node }) => Some(var_node),
+                    _ => None,
+                }
+            },
+            _ => None,
+        }
     }
 
     /* --------------------------------------------------------------------- */
@@ -966,11 +2213,31 @@ impl<'a> Parser<'a> {
 
         let mut domain_variables = Vec::new();
 
-        while self.match_token(&vec![VarTok, ConstTok]) {
-            match self.variable_decl(IdentifierDeclScope::DomainBlock) {
+        loop {
+            let attributes_opt = match self.attributes() {
+                Ok(attributes_opt) => attributes_opt,
+                Err(_parse_error) => None,
+            };
+            if !self.match_token(&vec![VarTok, ConstTok]) {
+                if attributes_opt.is_some() {
+                    self.error_at_current("Expected domain variable after attributes.");
+                }
+                break;
+            }
+            if !self.is_building_symbol_table {
+                if let Some(attribute_node) = attributes_opt.as_ref().and_then(|attributes| attributes.get("snapshot_default")) {
+                    let token = self.tokens[self.attribute_token_idxs["snapshot_default"]].clone();
+                    if !self.arcanum.is_serializable() {
+                        self.error_at(&token, "#[snapshot_default] requires a |>>>| or |<<<| interface method.");
+                    } else if attribute_node.value.len() > 0 {
+                        self.error_at(&token, "#[snapshot_default] takes no value as the variable's initializer is the default.");
+                    }
+                }
+            }
+            match self.variable_decl(IdentifierDeclScope::DomainBlock, attributes_opt) {
                 Ok(domain_variable_node) =>  domain_variables.push(domain_variable_node),
                 Err(_parse_err) => {
-                    let sync_tokens = &vec![VarTok, ConstTok, SystemEndTok];
+                    let sync_tokens = &vec![VarTok, ConstTok, OuterAttributeTok, SystemEndTok];
                     self.synchronize(sync_tokens);
                 },
             }
@@ -984,7 +2251,7 @@ impl<'a> Parser<'a> {
 
     //* --------------------------------------------------------------------- *//
 
-    fn variable_decl(&mut self,identifier_decl_scope:IdentifierDeclScope) -> Result<Rc<RefCell<VariableDeclNode>>,ParseError> {
+    fn variable_decl(&mut self,identifier_decl_scope:IdentifierDeclScope,attributes_opt:Option<HashMap<String,AttributeNode>>) -> Result<Rc<RefCell<VariableDeclNode>>,ParseError> {
 
         let is_constant = match self.previous().token_type {
             VarTok => false,
@@ -1039,6 +2306,27 @@ impl<'a> Parser<'a> {
                     => initializer_expr_t_opt = Some(BinaryExprT {binary_expr_node}),
                 Ok(Some(FrameEventExprT { frame_event_part }))
                     => initializer_expr_t_opt = Some(FrameEventExprT {frame_event_part}),
+                Ok(Some(ListLiteralExprT { mut list_literal_node })) => {
+                    // An untyped declaration takes the inferred type.
+                    if type_node_opt.is_none() {
+                        if let Some(element_type) = &list_literal_node.element_type_opt {
+                            let frame_type = FrameType::List {element_type:Box::new(element_type.clone())};
+                            type_node_opt = Some(TypeNode::new_frame_type(false, frame_type));
+                        }
+                    }
+                    list_literal_node.type_opt = type_node_opt.clone();
+                    initializer_expr_t_opt = Some(ListLiteralExprT {list_literal_node})
+                },
+                Ok(Some(MapLiteralExprT { mut map_literal_node })) => {
+                    if type_node_opt.is_none() {
+                        if let (Some(key_type), Some(value_type)) = (&map_literal_node.key_type_opt, &map_literal_node.value_type_opt) {
+                            let frame_type = FrameType::Map {key_type:Box::new(key_type.clone()), value_type:Box::new(value_type.clone())};
+                            type_node_opt = Some(TypeNode::new_frame_type(false, frame_type));
+                        }
+                    }
+                    map_literal_node.type_opt = type_node_opt.clone();
+                    initializer_expr_t_opt = Some(MapLiteralExprT {map_literal_node})
+                },
                 _ => {
                     self.error_at_current("Unexpected assignment expression value.");
                     return Err(ParseError::new("TODO"))
@@ -1050,7 +2338,7 @@ impl<'a> Parser<'a> {
             return Err(ParseError::new("TODO"));
         }
 
-        let variable_decl_node = VariableDeclNode::new(name.clone(), type_node_opt.clone(), is_constant, initializer_expr_t_opt,identifier_decl_scope.clone());
+        let variable_decl_node = VariableDeclNode::new(name.clone(), type_node_opt.clone(), is_constant, initializer_expr_t_opt,identifier_decl_scope.clone(),attributes_opt);
         let variable_decl_node_rcref = Rc::new(RefCell::new(variable_decl_node));
 
         if self.is_building_symbol_table { // syntactic pass
@@ -1105,7 +2393,7 @@ impl<'a> Parser<'a> {
 
     // TODO return result
 //    fn state(&mut self) -> Rc<RefCell<StateNode>> {
-    fn state(&mut self) -> Result<Rc<RefCell<StateNode>>,ParseError> {
+    fn state(&mut self, attributes_opt:Option<HashMap<String,AttributeNode>>) -> Result<Rc<RefCell<StateNode>>,ParseError> {
 
         let line = self.previous().line;
 
@@ -1114,7 +2402,7 @@ impl<'a> Parser<'a> {
 
             // error message and synchronize
             self.error_at_current("Expected state name.");
-            let sync_tokens = &vec![StateTok, ActionsBlockTok, DomainBlockTok, SystemEndTok];
+            let sync_tokens = &vec![StateTok, ActionsBlockTok, OperationsBlockTok, DomainBlockTok, SystemEndTok];
             self.synchronize(sync_tokens);
 
             let state_node = StateNode::new(String::from("error"),
@@ -1125,6 +2413,10 @@ impl<'a> Parser<'a> {
                                             Option::None,
                                             Option::None,
                                             None,
+                                            None,
+                                            None,
+                                            Vec::new(),
+                                            attributes_opt,
                                             0);
             let state_node_rcref = Rc::new(RefCell::new(state_node));
             return Ok(state_node_rcref);
@@ -1134,17 +2426,30 @@ impl<'a> Parser<'a> {
 
         self.state_name_opt = Some(state_name.clone());
 
+        // A final state is entered but never left.
+        self.is_final_state = match &attributes_opt {
+            Some(attributes) => attributes.contains_key("final"),
+            None => false,
+        };
+
         let state_symbol_rcref;
         if self.is_building_symbol_table {
             if self.arcanum.get_state(&state_name).is_some() {
                 self.error_at_previous(&format!("Duplicate state name {}.",&state_name));
             }
-            let state_symbol = StateSymbol::new(&state_name, self.arcanum.get_current_symtab());
+            let mut state_symbol = StateSymbol::new(&state_name, self.arcanum.get_current_symtab());
+            state_symbol.region_name_opt = self.region_name_opt.clone();
             state_symbol_rcref = Rc::new(RefCell::new(state_symbol));
             self.arcanum.enter_scope(ParseScopeType::StateScope{state_symbol: state_symbol_rcref.clone()});
         } else {
             self.arcanum.set_parse_scope(&state_name);
             state_symbol_rcref = self.arcanum.get_state(&state_name).unwrap();
+            if let Some(attributes) = &attributes_opt {
+                if let Some(attribute_node) = attributes.get("renamed_from") {
+                    let renamed_from = attribute_node.value.clone();
+                    self.check_renamed_from(&state_name, &renamed_from);
+                }
+            }
         }
 
         // parse state parameters e.g. $S1[x]
@@ -1193,26 +2498,36 @@ impl<'a> Parser<'a> {
             match self.consume(TokenType::StateTok, "Expected '$'") {
                 Ok(_) => {
                     if self.match_token(&vec![TokenType::IdentifierTok]) {
-                        let id = self.previous();
+                        let id = self.previous().clone();
                         let target_state_name = id.lexeme.clone();
+                        if let Some(region_name) = self.get_other_region_opt(&target_state_name) {
+                            self.error_at_previous(&format!("${} is in region {} and can not be the parent of a state in region {}.",
+                                                            target_state_name, region_name, self.region_name_opt.clone().unwrap_or_default()));
+                        }
+
+                        if self.is_final_state {
+                            self.error_at_previous(&format!("Final state ${} can not have a parent state.", &state_name));
+                        }
 
                         let target_state_ref = StateRefNode::new(target_state_name);
                         dispatch_opt = Some(DispatchNode::new(target_state_ref,id.line));
                     } else {
                         self.error_at_current("Expected dispatch target state identifier.");
-                        let sync_tokens = &vec![PipeTok, StateTok, ActionsBlockTok, DomainBlockTok, SystemEndTok];
+                        let sync_tokens = &vec![PipeTok, StateTok, ActionsBlockTok, OperationsBlockTok, DomainBlockTok, SystemEndTok];
                         self.synchronize(sync_tokens);
                     }
 
                 },
                 Err(_) => {
                     // synchronize to next event handler, state, remaining blocks or the end token
-                    let sync_tokens = &vec![PipeTok, StateTok, ActionsBlockTok, DomainBlockTok, SystemEndTok];
+                    let sync_tokens = &vec![PipeTok, StateTok, ActionsBlockTok, OperationsBlockTok, DomainBlockTok, SystemEndTok];
                     self.synchronize(sync_tokens);
                 }
             }
         }
 
+        self.parent_state_name_opt = dispatch_opt.as_ref().map(|dispatch_node| dispatch_node.target_state_ref.name.clone());
+
         // add to hierarchy
 
         match &dispatch_opt {
@@ -1258,7 +2573,7 @@ impl<'a> Parser<'a> {
         // const c   (immutable)
         while self.match_token(&vec![VarTok, ConstTok]) {
             self.generate_state_context = true;
-            match self.variable_decl(IdentifierDeclScope::StateVar) {
+            match self.variable_decl(IdentifierDeclScope::StateVar, None) {
                 Ok(variable_node) =>  {
                     vars.push(variable_node);
                 },
@@ -1272,6 +2587,60 @@ impl<'a> Parser<'a> {
             vars_opt = Some(vars);
         }
 
+        // Timeout clause.
+        // 'after' '(' expr ')' '->' transition
+        let mut timeout_opt = None;
+        if self.peek().token_type == IdentifierTok
+            && self.peek().lexeme == "after"
+            && self.tokens[self.current + 1].token_type == LParenTok {
+            if self.is_final_state {
+                self.error_at_current(&format!("Final state ${} can only handle the enter event.", &state_name));
+            }
+            match self.timeout() {
+                Ok(timeout_node) => timeout_opt = Some(timeout_node),
+                Err(parse_error) => return Err(parse_error),
+            }
+        }
+
+        // Deferral clause.
+        // 'defer' '(' message+ ')'
+        let mut deferred_events_opt = None;
+        if self.peek().token_type == IdentifierTok
+            && self.peek().lexeme == "defer"
+            && self.tokens[self.current + 1].token_type == LParenTok {
+            if self.is_final_state {
+                self.error_at_current(&format!("Final state ${} can only handle the enter event.", &state_name));
+            }
+            match self.deferred_events() {
+                Ok(deferred_events) => deferred_events_opt = Some(deferred_events),
+                Err(parse_error) => return Err(parse_error),
+            }
+        }
+
+        // Completion clauses.
+        // ('done' ('(' expr ')')? '->' transition)*
+        let mut completions:Vec<CompletionNode> = Vec::new();
+        while self.peek().token_type == IdentifierTok
+            && self.peek().lexeme == "done"
+            && (self.tokens[self.current + 1].token_type == LParenTok
+                || self.tokens[self.current + 1].token_type == TransitionTok) {
+            if completions.iter().any(|completion_node| completion_node.guard_expr_t_opt.is_none()) {
+                self.error_at_current("Completion transition after an unguarded one is never taken.");
+            }
+            let token_idx = self.current;
+            match self.completion() {
+                Ok(completion_node) => {
+                    if completions.len() == 0 && completion_node.guard_expr_t_opt.is_none() && !self.is_building_symbol_table {
+                        if let StateContextType::StateRef {state_context_node} = &completion_node.transition_statement_node.target_state_context_t {
+                            self.always_completions.push((state_name.clone(), state_context_node.state_ref_node.name.clone(), token_idx));
+                        }
+                    }
+                    completions.push(completion_node);
+                },
+                Err(parse_error) => return Err(parse_error),
+            }
+        }
+
         // State Calls
         let mut calls_opt = None;
         let mut calls = Vec::new();
@@ -1326,15 +2695,22 @@ impl<'a> Parser<'a> {
             }
 
 
-            if self.peek().token_type == AtTok ||
-                self.peek().token_type == PipeTok ||
-                self.peek().token_type == AnyMessageTok {
+            // Attributes may precede either an event handler or the next state.
+            let next_token_type = self.peek_past_attributes().token_type;
+            if next_token_type == AtTok ||
+                next_token_type == PipeTok ||
+                next_token_type == AnyMessageTok {
 
-                while self.peek().token_type == AtTok ||
-                    self.peek().token_type == PipeTok ||
-                    self.peek().token_type == AnyMessageTok {
+                while self.peek_past_attributes().token_type == AtTok ||
+                    self.peek_past_attributes().token_type == PipeTok ||
+                    self.peek_past_attributes().token_type == AnyMessageTok {
 
-                    match self.event_handler() {
+                    let attributes_opt = match self.attributes() {
+                        Ok(attributes_opt) => attributes_opt,
+                        Err(_parse_error) => None,
+                    };
+
+                    match self.event_handler(attributes_opt) {
                         Ok(eh_opt) => {
                             match eh_opt {
                                 Some(eh) => {
@@ -1359,6 +2735,18 @@ impl<'a> Parser<'a> {
                                                 exit_event_handler = Some(eh_rcref.clone());
                                             }
                                         }
+
+                                        if self.is_final_state && !evt.is_enter_msg {
+                                            self.error_at_previous(&format!("Final state ${} can only handle the enter event.", &state_name));
+                                        }
+
+                                        if let MessageType::CustomMessage {message_node} = &eh_ref.msg_t {
+                                            if let Some(deferred_events) = &deferred_events_opt {
+                                                if deferred_events.contains(&message_node.name) {
+                                                    self.error_at_previous(&format!("|{}| is deferred in ${} and can not also be handled there.", &message_node.name, &state_name));
+                                                }
+                                            }
+                                        }
                                     }
 
                                     evt_handlers.push(eh_rcref);
@@ -1367,20 +2755,20 @@ impl<'a> Parser<'a> {
                             }
                         },
                         Err(_) => {
-                            let sync_tokens = &vec![PipeTok, StateTok, ActionsBlockTok, DomainBlockTok, SystemEndTok];
+                            let sync_tokens = &vec![PipeTok, StateTok, ActionsBlockTok, OperationsBlockTok, DomainBlockTok, SystemEndTok];
                             self.synchronize(sync_tokens);
                         }
                     }
                 }
 
             } else {
-                let follows_vec = &vec![StateTok, ActionsBlockTok, DomainBlockTok, SystemEndTok];
-                if self.follows(self.peek(),follows_vec) {
+                let follows_vec = &vec![StateTok, DashDashTok, ActionsBlockTok, OperationsBlockTok, DomainBlockTok, SystemEndTok];
+                if self.follows(self.peek_past_attributes(),follows_vec) {
                     // next token is expected
                     break;
                 } else {
                     self.error_at_current("Unexpected token in event handler message");
-                    let sync_tokens = &vec![PipeTok,AnyMessageTok,StateTok,ActionsBlockTok,DomainBlockTok];
+                    let sync_tokens = &vec![PipeTok,AnyMessageTok,OuterAttributeTok,StateTok,ActionsBlockTok,OperationsBlockTok,DomainBlockTok];
                     if !self.synchronize(sync_tokens) {
                         return Err(ParseError::new("TODO"));
                     }
@@ -1399,34 +2787,178 @@ impl<'a> Parser<'a> {
                                         enter_event_handler,
                                         exit_event_handler,
                                         dispatch_opt,
+                                        timeout_opt,
+                                        deferred_events_opt,
+                                        completions,
+                                        attributes_opt,
                                         line);
         let state_node_rcref = Rc::new(RefCell::new(state_node));
 
-        // If this is the 2nd pass, set the reference to the AST state node.
-        if !self.is_building_symbol_table {
-            // let state_validator = StateSemanticValidator::new();
-            // if !state_validator.has_valid_exit_semantics(&state_node_rcref.borrow()) {
-            //     return Err(ParseError::new("TODO"));
-            // }
-            state_symbol_rcref.borrow_mut().set_state_node(Rc::clone(&state_node_rcref));
+        // If this is the 2nd pass, set the reference to the AST state node.
+        if !self.is_building_symbol_table {
+            // let state_validator = StateSemanticValidator::new();
+            // if !state_validator.has_valid_exit_semantics(&state_node_rcref.borrow()) {
+            //     return Err(ParseError::new("TODO"));
+            // }
+            state_symbol_rcref.borrow_mut().set_state_node(Rc::clone(&state_node_rcref));
+        }
+
+        self.state_name_opt = None;
+        self.is_final_state = false;
+        self.parent_state_name_opt = None;
+
+        if pop_state_params_scope {
+            self.arcanum.exit_parse_scope(); // state params scope
+        }
+        self.arcanum.exit_parse_scope(); // state scope
+
+        Ok(state_node_rcref)
+    }
+
+
+    /* --------------------------------------------------------------------- */
+
+    // timeout -> 'after' '(' expression ')' '->' transition
+
+    fn timeout(&mut self) -> Result<TimeoutNode,ParseError> {
+
+        // 'after'
+        self.advance();
+        let line = self.previous().line;
+
+        if let Err(parse_error) = self.consume(LParenTok, "Expected '('.") {
+            return Err(parse_error);
+        }
+
+        let duration_expr_t = match self.expression() {
+            Ok(Some(expr_t)) => expr_t,
+            Ok(None) => {
+                self.error_at_current("Expected timeout duration.");
+                return Err(ParseError::new("TODO"));
+            },
+            Err(parse_error) => return Err(parse_error),
+        };
+
+        if let Err(parse_error) = self.consume(RParenTok, "Expected ')'.") {
+            return Err(parse_error);
+        }
+
+        if let Err(parse_error) = self.consume(TransitionTok, "Expected '->' after timeout.") {
+            return Err(parse_error);
+        }
+
+        let transition_statement_node = match self.transition(None) {
+            Ok(Some(StatementType::TransitionStmt {transition_statement})) => transition_statement,
+            Ok(_) => return Err(ParseError::new("TODO")),
+            Err(parse_error) => return Err(parse_error),
+        };
+
+        // transition() flags the enclosing event handler, which doesn't
+        // apply here.
+        self.event_handler_has_transition = false;
+
+        Ok(TimeoutNode::new(duration_expr_t, transition_statement_node, line))
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    // completion -> 'done' ('(' expr ')')? '->' transition
+
+    fn completion(&mut self) -> Result<CompletionNode,ParseError> {
+
+        // 'done'
+        self.advance();
+
+        let mut guard_expr_t_opt = None;
+        if self.match_token(&vec![LParenTok]) {
+            match self.expression() {
+                Ok(Some(expr_t)) => guard_expr_t_opt = Some(expr_t),
+                Ok(None) => {
+                    self.error_at_current("Expected completion guard.");
+                    return Err(ParseError::new("TODO"));
+                },
+                Err(parse_error) => return Err(parse_error),
+            }
+
+            if let Err(parse_error) = self.consume(RParenTok, "Expected ')'.") {
+                return Err(parse_error);
+            }
+        }
+
+        if let Err(parse_error) = self.consume(TransitionTok, "Expected '->' after done.") {
+            return Err(parse_error);
+        }
+
+        let transition_statement_node = match self.transition(None) {
+            Ok(Some(StatementType::TransitionStmt {transition_statement})) => transition_statement,
+            Ok(_) => return Err(ParseError::new("TODO")),
+            Err(parse_error) => return Err(parse_error),
+        };
+
+        // transition() flags the enclosing event handler, which doesn't
+        // apply here.
+        self.event_handler_has_transition = false;
+
+        Ok(CompletionNode::new(guard_expr_t_opt, transition_statement_node))
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    // deferred_events -> 'defer' '(' message+ ')'
+
+    fn deferred_events(&mut self) -> Result<Vec<String>,ParseError> {
+
+        // 'defer'
+        self.advance();
+
+        if let Err(parse_error) = self.consume(LParenTok, "Expected '('.") {
+            return Err(parse_error);
         }
 
-        self.state_name_opt = None;
+        let mut deferred_events = Vec::new();
+        while self.peek().token_type == PipeTok {
+            let msg = match self.message() {
+                Ok(MessageType::CustomMessage {message_node}) => message_node.name,
+                Ok(MessageType::AnyMessage {..}) => {
+                    self.error_at_previous("Expected message, found '||*'.");
+                    return Err(ParseError::new("TODO"));
+                },
+                Err(parse_error) => return Err(parse_error),
+            };
 
-        if pop_state_params_scope {
-            self.arcanum.exit_parse_scope(); // state params scope
+            // Only events sent through the interface can be held back
+            // and replayed. The caller has already returned by then so
+            // there is nowhere to deliver a return value.
+            let (is_interface_msg, has_return) = match self.arcanum.get_event(&msg, &None) {
+                Some(event_symbol_rcref) => {
+                    let event_symbol = event_symbol_rcref.borrow();
+                    (event_symbol.interface_name_opt.is_some(), event_symbol.ret_type_opt.is_some())
+                },
+                None => (false, false),
+            };
+            if !is_interface_msg {
+                self.error_at_previous(&format!("Only interface messages can be deferred, found |{}|.", msg));
+            } else if has_return {
+                self.error_at_previous(&format!("|{}| returns a value and can not be deferred.", msg));
+            } else if deferred_events.contains(&msg) {
+                self.error_at_previous(&format!("|{}| is deferred more than once.", msg));
+            } else {
+                deferred_events.push(msg);
+            }
         }
-        self.arcanum.exit_parse_scope(); // state scope
 
-        Ok(state_node_rcref)
-    }
+        if let Err(parse_error) = self.consume(RParenTok, "Expected ')'.") {
+            return Err(parse_error);
+        }
 
+        Ok(deferred_events)
+    }
 
     /* --------------------------------------------------------------------- */
 
-    // event_handler -> '|' Identifier '|' event_handler_terminator
+    // event_handler -> attributes '|' Identifier '|' event_handler_terminator
 
-    fn event_handler(&mut self) -> Result<Option<EventHandlerNode>,ParseError> {
+    fn event_handler(&mut self, attributes_opt:Option<HashMap<String,AttributeNode>>) -> Result<Option<EventHandlerNode>,ParseError> {
 
         let message_type:MessageType;
         // Hack - there is a weird bug w/ Clion that doesn't let msg be uninitialized.
@@ -1454,6 +2986,8 @@ impl<'a> Parser<'a> {
             },
         }
 
+        self.query_handler_msg_opt = if self.query_messages.contains(&msg) { Some(msg.clone()) } else { None };
+
         let mut is_declaring_event = false;
         if self.is_building_symbol_table {
             let event_symbol_rcref;
@@ -1558,7 +3092,7 @@ impl<'a> Parser<'a> {
                                                     // TODO
                                                     self.error_at_current("Bad parameter to event handler");
 
-                                                    let sync_tokens = &vec![PipeTok, CaretTok, StateTok, ActionsBlockTok, DomainBlockTok, SystemEndTok];
+                                                    let sync_tokens = &vec![PipeTok, CaretTok, StateTok, ActionsBlockTok, OperationsBlockTok, DomainBlockTok, SystemEndTok];
                                                     self.synchronize(sync_tokens);
 
                                                 }
@@ -1647,7 +3181,7 @@ impl<'a> Parser<'a> {
             Ok(terminator_node) => terminator_node,
             Err(_parse_error) => {
                 // TODO: this vec keeps the parser from hanging. don't know why
-                let sync_tokens = &vec![PipeTok, StateTok, ActionsBlockTok, DomainBlockTok, SystemEndTok];
+                let sync_tokens = &vec![PipeTok, StateTok, ActionsBlockTok, OperationsBlockTok, DomainBlockTok, SystemEndTok];
                 self.synchronize(sync_tokens);
                 // create "dummy" node to keep processing
                 // TODO: 1) make line # an int so as to set it to -1 when it is a dummy node and 2) confirm this is the best way
@@ -1674,6 +3208,7 @@ impl<'a> Parser<'a> {
             self.arcanum.exit_parse_scope(); // event handler params scope (EventHandlerParamsScopeSymbol)
         }
         self.arcanum.exit_parse_scope(); // event handler lscope (EventHandlerScopeSymbol)
+        self.query_handler_msg_opt = None;
 
         if self.panic_mode {
             return Err(ParseError::new("TODO"));
@@ -1684,7 +3219,9 @@ impl<'a> Parser<'a> {
                                       statements,
                                       terminator_node,
                                       ret_event_symbol_rcref,
-                                      self.event_handler_has_transition,line_number)))
+                                      self.event_handler_has_transition,
+                                      attributes_opt,
+                                      line_number)))
     }
 
     /* --------------------------------------------------------------------- */
@@ -1703,7 +3240,7 @@ impl<'a> Parser<'a> {
         if self.match_token(&vec![TokenType::CaretTok]) {
             if self.match_token(&vec![TokenType::LParenTok]) {
 
-                let expr_t = match self.unary_expression() {
+                let expr_t = match self.expression() {
                     Ok(Some(expr_t)) => expr_t,
                     _ => {
                         self.error_at_current("Expected expression as return value.");
@@ -1749,7 +3286,7 @@ impl<'a> Parser<'a> {
                     }
                 },
                 Err(_err) => {
-                    let sync_tokens = &vec![IdentifierTok, LParenTok, CaretTok, GTTok, SystemTok, StateTok, PipePipeTok, DotTok, ColonTok, PipeTok, ActionsBlockTok, DomainBlockTok, SystemEndTok];
+                    let sync_tokens = &vec![IdentifierTok, LParenTok, CaretTok, GTTok, SystemTok, StateTok, PipePipeTok, DotTok, ColonTok, PipeTok, ActionsBlockTok, OperationsBlockTok, DomainBlockTok, SystemEndTok];
                     self.synchronize(sync_tokens);
                 },
 
@@ -1763,7 +3300,7 @@ impl<'a> Parser<'a> {
 
     fn decl_or_stmt(&mut self) -> Result<Option<DeclOrStmtType>,ParseError> {
         if self.match_token(&vec![VarTok, ConstTok]) {
-            match self.variable_decl(IdentifierDeclScope::EventHandlerVar) {
+            match self.variable_decl(IdentifierDeclScope::EventHandlerVar, None) {
                 Ok(var_decl_t_rc_ref) => {
                     return Ok(Some(DeclOrStmtType::VarDeclT { var_decl_t_rc_ref }));
                 },
@@ -1776,8 +3313,15 @@ impl<'a> Parser<'a> {
         match self.statement() {
             Ok(opt_smt) => {
                 match opt_smt {
-                    Some(stmt_t)
-                        => Ok(Some(DeclOrStmtType::StmtT { stmt_t })),
+                    Some(stmt_t) => {
+                        if self.is_parsing_operation || self.is_parsing_action {
+                            self.check_method_body_statement(&stmt_t);
+                        }
+                        if self.query_handler_msg_opt.is_some() {
+                            self.check_query_statement(&stmt_t);
+                        }
+                        Ok(Some(DeclOrStmtType::StmtT { stmt_t }))
+                    },
                     None => Ok(None),
                 }
             },
@@ -1791,11 +3335,28 @@ impl<'a> Parser<'a> {
 
     fn statement(&mut self) -> Result<Option<StatementType>,ParseError> {
 
+        // 'raise' is only a keyword when followed by a message.
+        if self.peek().token_type == IdentifierTok
+            && self.peek().lexeme == "raise"
+            && self.tokens[self.current + 1].token_type == PipeTok {
+            return match self.raise_statement() {
+                Ok(raise_statement_node) => Ok(Some(StatementType::RaiseStmt {raise_statement_node})),
+                Err(parse_error) => Err(parse_error),
+            }
+        }
+
+        if self.peek().token_type == DispatchTok {
+            return match self.forward_statement() {
+                Ok(forward_statement_node) => Ok(Some(StatementType::ForwardStmt {forward_statement_node})),
+                Err(parse_error) => Err(parse_error),
+            }
+        }
+
         let mut expr_t_opt:Option<ExprType> = None;
         match self.expression() {
             Ok(et_opt) => expr_t_opt = et_opt,
             Err(_) => {
-                let sync_tokens = &vec![IdentifierTok, PipeTok, StateTok, ActionsBlockTok, DomainBlockTok, SystemEndTok];
+                let sync_tokens = &vec![IdentifierTok, PipeTok, StateTok, ActionsBlockTok, OperationsBlockTok, DomainBlockTok, SystemEndTok];
                 self.synchronize(sync_tokens);
             },
         }
@@ -1937,6 +3498,14 @@ impl<'a> Parser<'a> {
                         self.error_at_previous("Binary expression statements not allowed.");
                         return Err(ParseError::new("TODO"));
                     },
+                    ConditionalExprT {..} => {
+                        self.error_at_previous("Conditional expression statements not allowed.");
+                        return Err(ParseError::new("TODO"));
+                    },
+                    ListLiteralExprT {..} | MapLiteralExprT {..} => {
+                        self.error_at_previous("Collection literal statements not allowed.");
+                        return Err(ParseError::new("TODO"));
+                    },
                 }
             },
             None => {
@@ -1965,6 +3534,121 @@ impl<'a> Parser<'a> {
 
     /* --------------------------------------------------------------------- */
 
+    // forward_statement -> '=>' '$' ('^' | identifier)
+    //
+    // The ancestor may change state, so the handler has to return right
+    // after forwarding rather than carry on in a state it has left.
+
+    fn forward_statement(&mut self) -> Result<ForwardStatementNode,ParseError> {
+
+        // '=>'
+        self.advance();
+
+        let state_name = match &self.state_name_opt {
+            Some(state_name) => state_name.clone(),
+            None => {
+                self.error_at_previous("Events can only be forwarded from a state's event handlers.");
+                return Err(ParseError::new("TODO"));
+            },
+        };
+
+        if let Err(parse_error) = self.consume(StateTok, "Expected '$'.") {
+            return Err(parse_error);
+        }
+
+        let target_state_name = if self.match_token(&vec![CaretTok]) {
+            match &self.parent_state_name_opt {
+                Some(parent_state_name) => parent_state_name.clone(),
+                None => {
+                    self.error_at_previous(&format!("${} has no parent state to forward to.", state_name));
+                    return Err(ParseError::new("TODO"));
+                },
+            }
+        } else if self.match_token(&vec![IdentifierTok]) {
+            let target_state_name = self.previous().lexeme.clone();
+            if !self.is_building_symbol_table {
+                self.forward_targets.push((state_name, target_state_name.clone(), self.current - 1));
+            }
+            target_state_name
+        } else {
+            self.error_at_current("Expected '^' or ancestor state name.");
+            return Err(ParseError::new("TODO"));
+        };
+
+        if self.peek().token_type != CaretTok {
+            self.error_at_current("A forward must be the last statement before '^'.");
+        }
+
+        Ok(ForwardStatementNode::new(StateRefNode::new(target_state_name)))
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    // raise_statement -> 'raise' message ('(' expression* ')')?
+
+    fn raise_statement(&mut self) -> Result<RaiseStatementNode,ParseError> {
+
+        // 'raise'
+        self.advance();
+
+        // Report the error but keep parsing the statement.
+        if !self.is_run_to_completion {
+            self.error_at_previous("Raising events requires the #[run_to_completion] system attribute.");
+        }
+
+        let msg = match self.message() {
+            Ok(MessageType::CustomMessage {message_node}) => message_node.name,
+            Ok(MessageType::AnyMessage {..}) => {
+                self.error_at_previous("Expected message, found '||*'.");
+                return Err(ParseError::new("TODO"));
+            },
+            Err(parse_error) => return Err(parse_error),
+        };
+
+        // Raised events are sent through the interface so only interface
+        // messages can be raised. They are queued until the current event
+        // is handled so nothing can receive a return value.
+        let (interface_method_name, param_count, has_return) = match self.arcanum.get_event(&msg, &None) {
+            Some(event_symbol_rcref) => {
+                let event_symbol = event_symbol_rcref.borrow();
+                let param_count = match &event_symbol.params_opt {
+                    Some(params) => params.len(),
+                    None => 0,
+                };
+                (event_symbol.interface_name_opt.clone(), param_count, event_symbol.ret_type_opt.is_some())
+            },
+            None => (None, 0, false),
+        };
+        let interface_method_name = match interface_method_name {
+            Some(interface_method_name) => interface_method_name,
+            None => {
+                self.error_at_previous(&format!("Only interface messages can be raised, found |{}|.", msg));
+                return Err(ParseError::new("TODO"));
+            },
+        };
+        if has_return {
+            self.error_at_previous(&format!("|{}| returns a value and can not be raised.", msg));
+        }
+
+        let mut exprs_t = Vec::new();
+        if self.match_token(&vec![LParenTok]) {
+            match self.expr_list() {
+                Ok(Some(ExprListT {expr_list_node})) => exprs_t = expr_list_node.exprs_t,
+                Ok(_) => return Err(ParseError::new("TODO")),
+                Err(parse_error) => return Err(parse_error),
+            }
+        }
+
+        if exprs_t.len() != param_count {
+            self.error_at_previous(&format!("Expected {} argument(s) for |{}|, found {}.", param_count, msg, exprs_t.len()));
+            return Err(ParseError::new("TODO"));
+        }
+
+        Ok(RaiseStatementNode::new(interface_method_name, CallExprListNode::new(exprs_t)))
+    }
+
+    /* --------------------------------------------------------------------- */
+
     // This method detects if an expression can be tested:
     // (a = 1) ? --- not testable
     // (a + b) ? --- not testable (TODO: review but think not
@@ -2162,6 +3846,10 @@ impl<'a> Parser<'a> {
     // TODO: explore returning a TerminatorType rather than node
     fn branch_terminator(&mut self) -> Result<Option<TerminatorExpr>,ParseError> {
 
+        if (self.is_parsing_operation || self.is_parsing_action) && self.peek().token_type == CaretTok {
+            self.error_at_current(&format!("{} can only return at the end of their body.", self.method_body_name()));
+        }
+
         if self.match_token(&vec![TokenType::CaretTok]) {
             if self.match_token(&vec![TokenType::LParenTok]) {
 
@@ -2327,7 +4015,7 @@ impl<'a> Parser<'a> {
 
     fn assignment (&mut self) -> Result<Option<ExprType>,ParseError> {
 
-        let l_value = match self.equality() {
+        let l_value = match self.conditional() {
             Ok(Some(expr_type)) => expr_type,
             Ok(None) => return Ok(None),
             Err(parse_error) => return Err(parse_error),
@@ -2338,6 +4026,17 @@ impl<'a> Parser<'a> {
             // like (a) and (a b c)
             self.is_parsing_rhs = true;
 
+            if self.query_handler_msg_opt.is_some() && !self.is_building_symbol_table {
+                self.check_query_assignment(&l_value);
+            }
+            if self.is_parsing_action {
+                if let Some(var_node) = Parser::get_assigned_variable(&l_value) {
+                    if var_node.scope == IdentifierDeclScope::DomainBlock {
+                        self.action_mutates_domain = true;
+                    }
+                }
+            }
+
             let line = self.previous().line;
             let r_value = match self.equality() {
                 Ok(Some(expr_type)) => {
@@ -2363,6 +4062,89 @@ impl<'a> Parser<'a> {
 
     /* --------------------------------------------------------------------- */
 
+    // conditional -> equality ('?' conditional ':' conditional)?
+    //
+    // The scanner only produces '?' tokens inside of parentheses, which
+    // keeps conditionals from being confused with bool tests.
+
+    fn conditional(&mut self) -> Result<Option<ExprType>,ParseError> {
+
+        let condition = match self.equality() {
+            Ok(Some(expr_type)) => expr_type,
+            Ok(None) => return Ok(None),
+            Err(parse_error) => return Err(parse_error),
+        };
+
+        if !self.match_token(&vec![TokenType::QuestionTok]) {
+            return Ok(Some(condition));
+        }
+
+        if !self.is_testable_expression(&condition) {
+            self.error_at_previous("Not a testable expression.");
+            return Err(ParseError::new("TODO"));
+        }
+
+        let true_expr = match self.conditional() {
+            Ok(Some(expr_type)) => expr_type,
+            Ok(None) => {
+                self.error_at_current("Expected expression after '?'.");
+                return Err(ParseError::new("TODO"));
+            },
+            Err(parse_error) => return Err(parse_error),
+        };
+
+        if let Err(parse_error) = self.consume(TokenType::ColonTok, "Expected ':' in conditional expression.") {
+            return Err(parse_error);
+        }
+
+        let false_expr = match self.conditional() {
+            Ok(Some(expr_type)) => expr_type,
+            Ok(None) => {
+                self.error_at_current("Expected expression after ':'.");
+                return Err(ParseError::new("TODO"));
+            },
+            Err(parse_error) => return Err(parse_error),
+        };
+
+        if let (Some(true_type), Some(false_type)) = (self.get_literal_type_name(&true_expr),
+                                                      self.get_literal_type_name(&false_expr)) {
+            if true_type != false_type {
+                self.error_at_previous(&format!("Conditional expression branches have different types ({} and {}).", true_type, false_type));
+                return Err(ParseError::new("TODO"));
+            }
+        }
+
+        let conditional_expr_node = ConditionalExprNode::new(condition, true_expr, false_expr);
+        Ok(Some(ConditionalExprT {conditional_expr_node}))
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    // Returns the Frame type of a literal expression, looking through
+    // parentheses. Superstrings and null have no checkable type.
+
+    fn get_literal_type_name(&self, expr_t:&ExprType) -> Option<&'static str> {
+        match expr_t {
+            LiteralExprT {literal_expr_node} => {
+                match literal_expr_node.token_t {
+                    TokenType::NumberTok => Some("number"),
+                    TokenType::StringTok => Some("string"),
+                    TokenType::TrueTok | TokenType::FalseTok => Some("bool"),
+                    _ => None,
+                }
+            },
+            ExprListT {expr_list_node} => {
+                if expr_list_node.exprs_t.len() == 1 {
+                    self.get_literal_type_name(expr_list_node.exprs_t.first().unwrap())
+                } else {
+                    None
+                }
+            },
+            _ => None,
+        }
+    }
+
+    /* --------------------------------------------------------------------- */
 
     fn equality(&mut self) -> Result<Option<ExprType>,ParseError> {
 
@@ -2581,6 +4363,22 @@ impl<'a> Parser<'a> {
             }
         }
 
+        // '[' expr* ']'
+        if self.match_token(&vec![LBracketTok]) {
+            return match self.list_literal() {
+                Ok(list_literal_node) => Ok(Some(ListLiteralExprT {list_literal_node})),
+                Err(parse_error) => Err(parse_error),
+            }
+        }
+
+        // '{' (expr ':' expr)* '}'
+        if self.match_token(&vec![OpenBraceTok]) {
+            return match self.map_literal() {
+                Ok(map_literal_node) => Ok(Some(MapLiteralExprT {map_literal_node})),
+                Err(parse_error) => Err(parse_error),
+            }
+        }
+
         let mut scope = IdentifierDeclScope::None;
 
 //        let mut scope_override = false;
@@ -2730,6 +4528,11 @@ impl<'a> Parser<'a> {
 
         if self.match_token(&vec![StateStackOperationPushTok]) {
             self.generate_state_stack = true;
+            if let Some(state_name) = &self.state_name_opt {
+                if !self.pushed_state_names.contains(state_name) {
+                    self.pushed_state_names.push(state_name.clone());
+                }
+            }
             let ssot = StateStackOperationNode::new(StateStackOperationType::Push);
             return Ok(Some(ssot));
         } else if self.match_token(&vec![StateStackOperationPopTok]) {
@@ -2754,6 +4557,10 @@ impl<'a> Parser<'a> {
             return Ok(None);
         }
 
+        if self.is_parsing_operation || self.is_parsing_action {
+            self.error_at_previous(&format!("{} are not called with an event so '@' is not allowed.", self.method_body_name()));
+        }
+
         // '@' '||'
         if self.match_token(&vec![PipePipeTok]) {
             return Ok(Some(FrameEventPart::Message {is_reference}));
@@ -2808,6 +4615,119 @@ impl<'a> Parser<'a> {
 
     /* --------------------------------------------------------------------- */
 
+    // list_literal -> '[' expression* ']'
+
+    fn list_literal(&mut self) -> Result<ListLiteralNode,ParseError> {
+
+        let mut exprs_t:Vec<ExprType> = Vec::new();
+
+        while !self.match_token(&vec![RBracketTok]) {
+            match self.expression() {
+                Ok(Some(expr_t)) => exprs_t.push(expr_t),
+                Ok(None) => {
+                    self.error_at_current("Expected ']'.");
+                    return Err(ParseError::new("TODO"));
+                },
+                Err(parse_error) => return Err(parse_error),
+            }
+        }
+
+        let element_type_opt = self.infer_element_type(exprs_t.iter_mut().collect());
+        Ok(ListLiteralNode::new(exprs_t, element_type_opt))
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    // map_literal -> '{' (expression ':' expression)* '}'
+
+    fn map_literal(&mut self) -> Result<MapLiteralNode,ParseError> {
+
+        let mut entries:Vec<(ExprType,ExprType)> = Vec::new();
+
+        while !self.match_token(&vec![CloseBraceTok]) {
+            let key_expr_t = match self.expression() {
+                Ok(Some(expr_t)) => expr_t,
+                Ok(None) => {
+                    self.error_at_current("Expected '}'.");
+                    return Err(ParseError::new("TODO"));
+                },
+                Err(parse_error) => return Err(parse_error),
+            };
+
+            if let Err(parse_error) = self.consume(ColonTok, "Expected ':' after map key.") {
+                return Err(parse_error);
+            }
+
+            let value_expr_t = match self.expression() {
+                Ok(Some(expr_t)) => expr_t,
+                Ok(None) => {
+                    self.error_at_current("Expected map value.");
+                    return Err(ParseError::new("TODO"));
+                },
+                Err(parse_error) => return Err(parse_error),
+            };
+
+            entries.push((key_expr_t, value_expr_t));
+        }
+
+        let key_type_opt = self.infer_element_type(entries.iter_mut().map(|(k,_)| k).collect());
+        let value_type_opt = self.infer_element_type(entries.iter_mut().map(|(_,v)| v).collect());
+        Ok(MapLiteralNode::new(entries, key_type_opt, value_type_opt))
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    // Infers the Frame type of a collection's elements. This only succeeds
+    // when every element is a literal of the same type, with ints
+    // widening to float if the two are mixed. Widened int literals are
+    // rewritten as floats as typed targets don't convert them.
+
+    fn infer_element_type(&self, exprs_t:Vec<&mut ExprType>) -> Option<FrameType> {
+
+        let mut element_type_opt:Option<FrameType> = None;
+
+        for expr_t in &exprs_t {
+            let literal_type = match &**expr_t {
+                LiteralExprT {literal_expr_node} => {
+                    match literal_expr_node.token_t {
+                        TokenType::NumberTok => {
+                            if literal_expr_node.value.contains('.') {
+                                FrameType::Float
+                            } else {
+                                FrameType::Int
+                            }
+                        },
+                        TokenType::StringTok => FrameType::String,
+                        TokenType::TrueTok | TokenType::FalseTok => FrameType::Bool,
+                        _ => return None,
+                    }
+                },
+                _ => return None,
+            };
+            element_type_opt = match element_type_opt {
+                None => Some(literal_type),
+                Some(element_type) if element_type == literal_type => Some(element_type),
+                Some(FrameType::Int) if literal_type == FrameType::Float => Some(FrameType::Float),
+                Some(FrameType::Float) if literal_type == FrameType::Int => Some(FrameType::Float),
+                Some(_) => return None,
+            };
+        }
+
+        if element_type_opt == Some(FrameType::Float) {
+            for expr_t in exprs_t {
+                if let LiteralExprT {literal_expr_node} = expr_t {
+                    if !literal_expr_node.value.contains('.') {
+                        literal_expr_node.value.push_str(".0");
+                    }
+                }
+            }
+        }
+
+        element_type_opt
+    }
+
+    /* --------------------------------------------------------------------- */
+
     // TODO: create a new return type that is narrowed to just the types this method returns.
     // TODO: change the return type to be CallChainLiteralExprT as it doesn't return anything else.
     fn variable_or_call_expr(&mut self, explicit_scope: IdentifierDeclScope) -> Result<Option<ExprType>,ParseError> {
@@ -2840,6 +4760,9 @@ impl<'a> Parser<'a> {
                             match action_decl_symbol_opt {
                                 Some(ads) => {
                                     // action
+                                    if self.is_parsing_action {
+                                        self.called_action_names.push(s.clone());
+                                    }
                                     let mut action_call_expr_node = ActionCallExprNode::new(method_call_expr_node);
                                     action_call_expr_node.set_action_symbol(&Rc::clone(&ads));
                                     call_chain.push_back(CallChainLiteralNodeType::ActionCallT { action_call_expr_node });
@@ -2885,6 +4808,16 @@ impl<'a> Parser<'a> {
             is_first_node = false;
         }
 
+        // A method called on a domain variable may change it.
+        if self.is_parsing_action {
+            if let Some(CallChainLiteralNodeType::VariableNodeT {var_node}) = call_chain.front() {
+                let calls_method = call_chain.iter().any(|node| matches!(node, CallChainLiteralNodeType::CallT {..}));
+                if var_node.scope == IdentifierDeclScope::DomainBlock && calls_method {
+                    self.action_mutates_domain = true;
+                }
+            }
+        }
+
         let call_chain_literal_expr_node = CallChainLiteralExprNode::new(call_chain);
         return Ok(Some(CallChainLiteralExprT {call_chain_expr_node:call_chain_literal_expr_node}));
 
@@ -2986,7 +4919,11 @@ impl<'a> Parser<'a> {
     fn state_context(&mut self, enter_args_opt:Option<ExprListNode>) -> Result<Option<StateContextType>,ParseError> {
 
         if self.match_token(&vec![TokenType::StateStackOperationPopTok]) {
-            Ok(Some(StateContextType::StateStackPop {}))
+            if !self.is_building_symbol_table {
+                let arg_count = enter_args_opt.as_ref().map(|enter_args| enter_args.exprs_t.len()).unwrap_or(0);
+                self.state_stack_pop_enter_args.push((self.current - 1, arg_count));
+            }
+            Ok(Some(StateContextType::StateStackPop { enter_args_opt }))
         } else {
             // parse state ref e.g. '$S1'
             if !self.match_token(&vec![TokenType::StateTok]) {
@@ -2999,6 +4936,28 @@ impl<'a> Parser<'a> {
 
             let state_id = self.previous();
             let name = state_id.lexeme.clone();
+            self.check_region_transition(&name);
+
+            // history e.g. '$S1[H]' (shallow) or '$S1[H*]' (deep)
+            if self.match_token(&vec![LBracketTok]) {
+                let target_token_idx = self.current - 2;
+                if !self.match_token(&vec![TokenType::IdentifierTok]) || self.previous().lexeme != "H" {
+                    self.error_at_current("Expected 'H' or 'H*'.");
+                    return Err(ParseError::new("TODO"));
+                }
+                let is_deep = self.match_token(&vec![StarTok]);
+                if let Err(parse_error) = self.consume(RBracketTok, "Expected ']'.") {
+                    return Err(parse_error);
+                }
+                if enter_args_opt.is_some() {
+                    self.error_at_previous("History transitions can not pass enter arguments.");
+                    return Err(ParseError::new("TODO"));
+                }
+                if !self.history_targets.iter().any(|(target, _)| *target == name) {
+                    self.history_targets.push((name.clone(), target_token_idx));
+                }
+                return Ok(Some(StateContextType::StateHistory { state_ref_node:StateRefNode::new(name), is_deep }));
+            }
 
             // parse optional state ref expression list
             // '(' ')' | '(' expr ')'
@@ -3026,9 +4985,9 @@ impl<'a> Parser<'a> {
 
     /* --------------------------------------------------------------------- */
 
-    // state_context ->
+    // change_state_context -> '$' identifier ('(' expr_list ')')?
 
-    fn change_state_context(&mut self, _:Option<ExprListNode>) -> Result<Option<StateContextType>,ParseError> {
+    fn change_state_context(&mut self) -> Result<Option<StateContextType>,ParseError> {
 
 
         // parse state ref e.g. '$S1'
@@ -3042,10 +5001,24 @@ impl<'a> Parser<'a> {
 
         let state_id = self.previous();
         let name = state_id.lexeme.clone();
+        self.check_region_transition(&name);
+
+        // parse optional state ref expression list
+        // '(' ')' | '(' expr ')'
+        let mut state_ref_args_opt = None;
+        if self.match_token(&vec![LParenTok]) {
+            match self.expr_list() {
+                Ok(Some(ExprListT { expr_list_node }))
+                => state_ref_args_opt = Some(expr_list_node),
+                Ok(Some(_)) => return Err(ParseError::new("TODO")), // TODO
+                Err(parse_error) => return Err(parse_error),
+                Ok(None) => {}, // continue
+            }
+        }
 
         let state_context_node = StateContextNode::new(
             StateRefNode::new(name),
-            None,
+            state_ref_args_opt,
             None,
         );
 
@@ -3060,6 +5033,7 @@ impl<'a> Parser<'a> {
     fn transition(&mut self, exit_args_opt:Option<ExprListNode>) -> Result<Option<StatementType>,ParseError> {
 
         self.generate_transition_state = true;
+        self.check_final_state_transition();
 
         if exit_args_opt.is_some() {
             // need exit args generated
@@ -3114,6 +5088,8 @@ impl<'a> Parser<'a> {
     fn change_state(&mut self) -> Result<Option<StatementType>,ParseError> {
 
         self.generate_change_state = true;
+        self.event_handler_has_transition = true;
+        self.check_final_state_transition();
 
         let mut label_opt:Option<String> = None;
 
@@ -3123,7 +5099,7 @@ impl<'a> Parser<'a> {
         }
 
         let state_context_t;
-        match self.change_state_context(None) {
+        match self.change_state_context() {
             Ok(Some(scn)) => state_context_t = scn,
             Ok(None) => return Err(ParseError::new("TODO")),
             Err(parse_error) => return Err(parse_error),
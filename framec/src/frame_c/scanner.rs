@@ -25,6 +25,9 @@ pub(crate) struct Scanner {
     // what the current test type was in order to change the scanner
     // and pop it off when done with the test.
     test_t_stack:Vec<MatchType>,
+    // Tests only occur at the statement level so a '?' inside of
+    // parentheses starts a conditional expression instead.
+    paren_depth:usize,
     line:usize,
     keywords:HashMap<String,TokenType>,
 //    match_type:MatchType,
@@ -44,6 +47,7 @@ impl Scanner {
             ("-interface-".to_string(), TokenType::InterfaceBlockTok),
             ("-machine-".to_string(), TokenType::MachineBlockTok),
             ("-actions-".to_string(), TokenType::ActionsBlockTok),
+            ("-operations-".to_string(), TokenType::OperationsBlockTok),
             ("-domain-".to_string(), TokenType::DomainBlockTok),
         ].iter().cloned().collect();
 
@@ -56,6 +60,7 @@ impl Scanner {
             has_errors:false,
             errors:String::new(),
             test_t_stack:Vec::new(),
+            paren_depth:0,
             line:1,
             keywords,
        //     match_type:MatchType::None,
@@ -151,8 +156,16 @@ impl Scanner {
     fn scan_token(&mut self) {
         let c:char = self.advance();
         match c {
-            '(' => self.add_token(LParenTok),
-            ')' => self.add_token(RParenTok),
+            '(' => {
+                self.paren_depth += 1;
+                self.add_token(LParenTok);
+            },
+            ')' => {
+                if self.paren_depth > 0 {
+                    self.paren_depth -= 1;
+                }
+                self.add_token(RParenTok);
+            },
             '[' => self.add_token(LBracketTok),
             ']' => self.add_token(RBracketTok),
             '|' => {
@@ -246,7 +259,10 @@ impl Scanner {
                 }
             },
             '?' => {
-                if self.match_char('!') {
+                if self.paren_depth > 0 {
+                    // c ? a : b
+                    self.add_token(QuestionTok);
+                } else if self.match_char('!') {
                     self.add_token(BoolTestFalseTok);
                     // Store the context for the parse
                     self.test_t_stack.push(MatchType::BoolTok);
@@ -545,6 +561,7 @@ impl Scanner {
             ("interface-", InterfaceBlockTok),
             ("machine-", MachineBlockTok),
             ("actions-", ActionsBlockTok),
+            ("operations-", OperationsBlockTok),
             ("domain-", DomainBlockTok),
         ];
 
@@ -693,6 +710,7 @@ pub enum TokenType {
     InterfaceBlockTok,      // -interface-
     MachineBlockTok,        // -machine-
     ActionsBlockTok,        // -actions-
+    OperationsBlockTok,     // -operations-
     DomainBlockTok,         // -domain-
     LParenTok,
     RParenTok,
@@ -722,6 +740,7 @@ pub enum TokenType {
     BoolTestFalseTok,               // ?!
     StringTestTok,                  // ?~
     NumberTestTok,                  // ?#
+    QuestionTok,                    // ? - conditional expression
     ElseContinueTok,                // :>
     TestTerminatorTok,              // ::
     ForwardSlashTok,                // /
@@ -0,0 +1,187 @@
+use super::ast::*;
+
+// A snapshot manifest describes what a system puts into its snapshots.
+// Keeping the manifest of a released system lets framec warn when a
+// later version of the system can no longer restore those snapshots.
+
+pub struct SnapshotManifest {
+    pub system_name:String,
+    pub version:u32,
+    pub hash:String,
+    pub states:Vec<SnapshotState>,
+    pub domain_vars:Vec<SnapshotDomainVar>,
+}
+
+pub struct SnapshotState {
+    pub name:String,
+    pub args:Vec<String>,
+    pub vars:Vec<String>,
+    pub renamed_from:Vec<String>,
+}
+
+pub struct SnapshotDomainVar {
+    pub name:String,
+    pub has_default:bool,
+}
+
+impl SnapshotManifest {
+
+    pub fn new(system_node:&SystemNode) -> SnapshotManifest {
+        let states = system_node.get_states().iter()
+            .map(|state_node_rcref| {
+                let state_node = state_node_rcref.borrow();
+                SnapshotState {
+                    name:state_node.name.clone(),
+                    args:state_node.get_param_names(),
+                    vars:state_node.get_var_names(),
+                    renamed_from:state_node.get_renamed_from(),
+                }
+            })
+            .collect();
+        let domain_vars = system_node.get_domain_vars().iter()
+            .map(|var_rcref| {
+                let var = var_rcref.borrow();
+                SnapshotDomainVar {
+                    name:var.name.clone(),
+                    has_default:var.has_snapshot_default(),
+                }
+            })
+            .collect();
+        SnapshotManifest {
+            system_name:system_node.name.clone(),
+            version:system_node.get_snapshot_version(),
+            hash:system_node.get_snapshot_hash(),
+            states,
+            domain_vars,
+        }
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    pub fn format(&self) -> String {
+        let mut text = String::from("# Frame snapshot manifest\n");
+        text.push_str(&format!("system {}\n", self.system_name));
+        text.push_str(&format!("version {}\n", self.version));
+        text.push_str(&format!("hash {}\n", self.hash));
+        for state in &self.states {
+            text.push_str(&format!("state {}\n", state.name));
+            for arg in &state.args {
+                text.push_str(&format!("    arg {}\n", arg));
+            }
+            for var in &state.vars {
+                text.push_str(&format!("    var {}\n", var));
+            }
+            for renamed_from in &state.renamed_from {
+                text.push_str(&format!("    renamed_from {}\n", renamed_from));
+            }
+        }
+        for domain_var in &self.domain_vars {
+            if domain_var.has_default {
+                text.push_str(&format!("domain {} default\n", domain_var.name));
+            } else {
+                text.push_str(&format!("domain {}\n", domain_var.name));
+            }
+        }
+        text
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    pub fn parse(text:&str) -> Result<SnapshotManifest,String> {
+        let mut manifest = SnapshotManifest {
+            system_name:String::new(),
+            version:1,
+            hash:String::new(),
+            states:Vec::new(),
+            domain_vars:Vec::new(),
+        };
+        for (idx, line) in text.lines().enumerate() {
+            let words:Vec<&str> = line.split_whitespace().collect();
+            if words.is_empty() || words[0].starts_with('#') {
+                continue;
+            }
+            let value = match words.get(1) {
+                Some(value) => value.to_string(),
+                None => return Err(format!("line {}: '{}' requires a value.", idx + 1, words[0])),
+            };
+            match words[0] {
+                "system" => manifest.system_name = value,
+                "version" => manifest.version = match value.parse::<u32>() {
+                    Ok(version) => version,
+                    Err(_) => return Err(format!("line {}: invalid version '{}'.", idx + 1, value)),
+                },
+                "hash" => manifest.hash = value,
+                "state" => manifest.states.push(SnapshotState {
+                    name:value,
+                    args:Vec::new(),
+                    vars:Vec::new(),
+                    renamed_from:Vec::new(),
+                }),
+                "arg" | "var" | "renamed_from" => {
+                    let state = match manifest.states.last_mut() {
+                        Some(state) => state,
+                        None => return Err(format!("line {}: '{}' must follow a state.", idx + 1, words[0])),
+                    };
+                    match words[0] {
+                        "arg" => state.args.push(value),
+                        "var" => state.vars.push(value),
+                        _ => state.renamed_from.push(value),
+                    }
+                },
+                "domain" => manifest.domain_vars.push(SnapshotDomainVar {
+                    name:value,
+                    has_default:words.get(2) == Some(&"default"),
+                }),
+                _ => return Err(format!("line {}: unknown entry '{}'.", idx + 1, words[0])),
+            }
+        }
+        if manifest.system_name.is_empty() {
+            return Err(String::from("missing system entry."));
+        }
+        Ok(manifest)
+    }
+
+    /* --------------------------------------------------------------------- */
+
+    // Returns a warning for each way this system can no longer restore
+    // the snapshots described by the baseline manifest.
+
+    pub fn check(&self, baseline:&SnapshotManifest) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.system_name != baseline.system_name {
+            warnings.push(format!("Snapshot baseline is for system {} not {}.", baseline.system_name, self.system_name));
+            return warnings;
+        }
+        if self.version < baseline.version {
+            warnings.push(format!("#![snapshot_version] went back from {} to {}.", baseline.version, self.version));
+        }
+        for baseline_state in &baseline.states {
+            let state_opt = self.states.iter()
+                .find(|state| state.name == baseline_state.name)
+                .or_else(|| self.states.iter().find(|state| state.renamed_from.contains(&baseline_state.name)));
+            let state = match state_opt {
+                Some(state) => state,
+                None => {
+                    warnings.push(format!("State ${} is in version {} snapshots but no longer exists. Add #[renamed_from = \"{}\"] to the state that replaces it.", baseline_state.name, baseline.version, baseline_state.name));
+                    continue;
+                }
+            };
+            if state.args != baseline_state.args {
+                warnings.push(format!("State ${} parameters changed from [{}] to [{}] so version {} snapshots of it can not be restored.", state.name, baseline_state.args.join(","), state.args.join(","), baseline.version));
+            }
+            if state.vars != baseline_state.vars {
+                warnings.push(format!("State ${} variables changed from {{{}}} to {{{}}} so version {} snapshots of it can not be restored.", state.name, baseline_state.vars.join(","), state.vars.join(","), baseline.version));
+            }
+        }
+        for domain_var in &self.domain_vars {
+            if domain_var.has_default || baseline.domain_vars.iter().any(|baseline_var| baseline_var.name == domain_var.name) {
+                continue;
+            }
+            warnings.push(format!("Domain variable {} is not in version {} snapshots. Add #[snapshot_default] to keep its initializer when restoring them.", domain_var.name, baseline.version));
+        }
+        if self.hash != baseline.hash && self.version == baseline.version {
+            warnings.push(format!("The states in snapshots changed but #![snapshot_version] is still {}.", self.version));
+        }
+        warnings
+    }
+}
@@ -14,7 +14,9 @@ pub struct SymbolConfig {
     pub exit_msg_symbol:String,
     pub save_msg_symbol:String,
     pub restore_msg_symbol:String,
-
+    pub timeout_msg_symbol:String,
+    pub state_stack_overflow_msg_symbol:String,
+    pub state_stack_underflow_msg_symbol:String,
 }
 
 impl SymbolConfig {
@@ -26,6 +28,9 @@ impl SymbolConfig {
             exit_msg_symbol:String::from("<"),
             save_msg_symbol:String::from(">>>"),
             restore_msg_symbol:String::from("<<<"),
+            timeout_msg_symbol:String::from("$timeout"),
+            state_stack_overflow_msg_symbol:String::from("stateStackOverflow"),
+            state_stack_underflow_msg_symbol:String::from("stateStackUnderflow"),
         }
     }
 }
@@ -52,6 +57,8 @@ pub enum ParseScopeType {
     EventHandlerScope { event_handler_scope_symbol_rcref:Rc<RefCell<EventHandlerScopeSymbol>>},
     EventHandlerParamsScope { event_handler_params_scope_symbol_rcref:Rc<RefCell<EventHandlerParamsScopeSymbol>>},
     EventHandlerLocalScope { event_handler_local_scope_symbol_rcref:Rc<RefCell<EventHandlerLocalScopeSymbol>>},
+    OperationScope { operation_scope_symbol_rcref:Rc<RefCell<OperationScopeSymbol>>},
+    ActionScope { action_scope_symbol_rcref:Rc<RefCell<ActionScopeSymbol>>},
 }
 
 // This is what gets stored in the symbol tables
@@ -69,6 +76,8 @@ pub enum SymbolType {
     EventHandlerScopeSymbolT {event_handler_scope_symbol:Rc<RefCell<EventHandlerScopeSymbol>>},
     EventHandlerParamsScopeSymbolT { event_handler_params_scope_symbol_rcref:Rc<RefCell<EventHandlerParamsScopeSymbol>>},
     EventHandlerLocalScopeSymbolT { event_handler_local_scope_rcref:Rc<RefCell<EventHandlerLocalScopeSymbol>>},
+    OperationScopeSymbolT { operation_scope_symbol_rcref:Rc<RefCell<OperationScopeSymbol>>},
+    ActionScopeSymbolT { action_scope_symbol_rcref:Rc<RefCell<ActionScopeSymbol>>},
 
     // Variable Symbol types
     DomainVariableSymbolT {domain_variable_symbol_rcref:Rc<RefCell<VariableSymbol>>},
@@ -115,6 +124,10 @@ impl Symbol for SymbolType {
                 => state_param_symbol_rcref.borrow().get_name(),
             SymbolType::EventHandlerLocalScopeSymbolT { event_handler_local_scope_rcref }
                 => event_handler_local_scope_rcref.borrow().get_name(),
+            SymbolType::OperationScopeSymbolT { operation_scope_symbol_rcref }
+                => operation_scope_symbol_rcref.borrow().get_name(),
+            SymbolType::ActionScopeSymbolT { action_scope_symbol_rcref }
+                => action_scope_symbol_rcref.borrow().get_name(),
         }
     }
 }
@@ -154,6 +167,10 @@ impl ScopeSymbol for SymbolType {
                 => event_handler_params_scope_symbol_rcref.borrow().get_symbol_table(),
             SymbolType::EventHandlerLocalScopeSymbolT { event_handler_local_scope_rcref }
                 => event_handler_local_scope_rcref.borrow().get_symbol_table(),
+            SymbolType::OperationScopeSymbolT { operation_scope_symbol_rcref }
+                => operation_scope_symbol_rcref.borrow().get_symbol_table(),
+            SymbolType::ActionScopeSymbolT { action_scope_symbol_rcref }
+                => action_scope_symbol_rcref.borrow().get_symbol_table(),
             _ => panic!("TODO"),
         }
     }
@@ -176,6 +193,10 @@ impl ScopeSymbol for SymbolType {
                 => event_handler_params_symbol_rcref.borrow().get_symbol_table_for_symbol(symbol_name),
             SymbolType::EventHandlerLocalScopeSymbolT { event_handler_local_scope_rcref: event_handler_block_scope_struct_rcref }
                 => event_handler_block_scope_struct_rcref.borrow().get_symbol_table_for_symbol(symbol_name),
+            SymbolType::OperationScopeSymbolT { operation_scope_symbol_rcref }
+                => operation_scope_symbol_rcref.borrow().get_symbol_table_for_symbol(symbol_name),
+            SymbolType::ActionScopeSymbolT { action_scope_symbol_rcref }
+                => action_scope_symbol_rcref.borrow().get_symbol_table_for_symbol(symbol_name),
             _ => panic!("TODO"),
         }
     }
@@ -250,6 +271,18 @@ impl SymbolTable {
                 self.symbols.insert(name, st_ref);
                 ()
             },
+            ParseScopeType::OperationScope { operation_scope_symbol_rcref } => {
+                let name = operation_scope_symbol_rcref.borrow().name.clone();
+                let st_ref = Rc::new(RefCell::new(SymbolType::OperationScopeSymbolT { operation_scope_symbol_rcref }));
+                self.symbols.insert(name, st_ref);
+                ()
+            },
+            ParseScopeType::ActionScope { action_scope_symbol_rcref } => {
+                let name = action_scope_symbol_rcref.borrow().name.clone();
+                let st_ref = Rc::new(RefCell::new(SymbolType::ActionScopeSymbolT { action_scope_symbol_rcref }));
+                self.symbols.insert(name, st_ref);
+                ()
+            },
             ParseScopeType::ActionsBlockScope{ actions_block_scope_symbol_rcref: actions_block_scope_symbol } => {
                 let name = actions_block_scope_symbol.borrow().name.clone();
                 let st_ref = Rc::new(RefCell::new(SymbolType::ActionsBlockScopeSymbolT { actions_block_symbol_rcref:actions_block_scope_symbol }));
@@ -618,6 +651,36 @@ impl Arcanum {
                 // update current symbol table to new event_handler's symbol table
                 self.current_symtab = Rc::clone(&event_handler_block_scope_symbol_symtab_rcref);
             },
+            ParseScopeType::OperationScope { operation_scope_symbol_rcref } => {
+
+                // clone the Rc for the symbol table
+                let operation_scope_symbol_rcref_clone = Rc::clone(operation_scope_symbol_rcref);
+                let operation_scope_symbol_symtab_rcref = Rc::clone(&operation_scope_symbol_rcref_clone.borrow().symtab_rcref);
+
+                // current symtab should be the SystemSymbol
+                let current_symtab_rcref = Rc::clone(&self.current_symtab);
+                operation_scope_symbol_rcref.borrow_mut().set_parent_symtab(&current_symtab_rcref);
+
+                // add new scope symbol to previous symbol table
+                self.current_symtab.borrow_mut().insert_parse_scope(scope_t);
+                // update current symbol table to the operation's symbol table
+                self.current_symtab = Rc::clone(&operation_scope_symbol_symtab_rcref);
+            },
+            ParseScopeType::ActionScope { action_scope_symbol_rcref } => {
+
+                // clone the Rc for the symbol table
+                let action_scope_symbol_rcref_clone = Rc::clone(action_scope_symbol_rcref);
+                let action_scope_symbol_symtab_rcref = Rc::clone(&action_scope_symbol_rcref_clone.borrow().symtab_rcref);
+
+                // current symtab should be the ActionsBlockScopeSymbol
+                let current_symtab_rcref = Rc::clone(&self.current_symtab);
+                action_scope_symbol_rcref.borrow_mut().set_parent_symtab(&current_symtab_rcref);
+
+                // add new scope symbol to previous symbol table
+                self.current_symtab.borrow_mut().insert_parse_scope(scope_t);
+                // update current symbol table to the action's symbol table
+                self.current_symtab = Rc::clone(&action_scope_symbol_symtab_rcref);
+            },
             ParseScopeType::ActionsBlockScope { actions_block_scope_symbol_rcref: actions_block_scope_symbol } => {
 
                 {
@@ -1110,6 +1173,7 @@ pub struct StateSymbol {
     pub state_node:Option<Rc<RefCell<StateNode>>>,
 //    pub uses_enter_params:bool,
     requires_state_context:bool,
+    pub region_name_opt:Option<String>,
 }
 
 impl StateSymbol {
@@ -1124,6 +1188,7 @@ impl StateSymbol {
             symtab_rcref:Rc::new(RefCell::new(st_rcref)),
             state_node:None,
             requires_state_context:false,
+            region_name_opt:None,
         }
     }
 
@@ -1597,6 +1662,112 @@ impl ScopeSymbol for EventHandlerLocalScopeSymbol {
 
 // -----------------------
 
+// Operation parameters and local variables are plain local names in
+// every target language so they are declared as event handler variables.
+
+pub struct OperationScopeSymbol {
+    pub name:String,
+    pub symtab_rcref:Rc<RefCell<SymbolTable>>,
+}
+
+impl OperationScopeSymbol {
+
+    pub fn new(name:&str) -> OperationScopeSymbol {
+
+        OperationScopeSymbol {
+            name: name.to_string(),
+            symtab_rcref:Rc::new(RefCell::new(SymbolTable::new(name.to_string(), None, IdentifierDeclScope::EventHandlerVar, false))),
+        }
+    }
+
+    pub fn set_parent_symtab(&mut self, parent_symtab:&Rc<RefCell<SymbolTable>>) {
+        self.symtab_rcref.borrow_mut().parent_symtab_rcref_opt = Option::Some(Rc::clone(&parent_symtab));
+    }
+}
+
+impl Symbol for OperationScopeSymbol {
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl ScopeSymbol for OperationScopeSymbol {
+
+    fn get_symbol_table(&self) -> Rc<RefCell<SymbolTable>> {
+        Rc::clone(&self.symtab_rcref)
+    }
+
+    fn get_symbol_table_for_symbol(&self,symbol_name:&str) -> Rc<RefCell<SymbolTable>> {
+        let a = self.symtab_rcref.borrow();
+        let b = a.symbols.get(symbol_name);
+        if let Some(c) = b {
+            let d = c.borrow();
+            let e = d.get_symbol_table_for_symbol(symbol_name);
+            return Rc::clone(&e);
+        } else {
+            panic!("Fatal error - could not find symbol {} in operation scope.", symbol_name);
+        }
+    }
+}
+
+// -----------------------
+
+// Scope for the parameters and locals of an action with a Frame body.
+// It is keyed apart from the action's declaration symbol, which shares
+// the actions block symbol table.
+
+pub struct ActionScopeSymbol {
+    pub name:String,
+    pub symtab_rcref:Rc<RefCell<SymbolTable>>,
+}
+
+impl ActionScopeSymbol {
+
+    pub fn new(action_name:&str) -> ActionScopeSymbol {
+
+        let name = ActionScopeSymbol::scope_name(action_name);
+        ActionScopeSymbol {
+            name:name.clone(),
+            symtab_rcref:Rc::new(RefCell::new(SymbolTable::new(name, None, IdentifierDeclScope::EventHandlerVar, false))),
+        }
+    }
+
+    pub fn scope_name(action_name:&str) -> String {
+        format!("-action-{}-", action_name)
+    }
+
+    pub fn set_parent_symtab(&mut self, parent_symtab:&Rc<RefCell<SymbolTable>>) {
+        self.symtab_rcref.borrow_mut().parent_symtab_rcref_opt = Option::Some(Rc::clone(&parent_symtab));
+    }
+}
+
+impl Symbol for ActionScopeSymbol {
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl ScopeSymbol for ActionScopeSymbol {
+
+    fn get_symbol_table(&self) -> Rc<RefCell<SymbolTable>> {
+        Rc::clone(&self.symtab_rcref)
+    }
+
+    fn get_symbol_table_for_symbol(&self,symbol_name:&str) -> Rc<RefCell<SymbolTable>> {
+        let a = self.symtab_rcref.borrow();
+        let b = a.symbols.get(symbol_name);
+        if let Some(c) = b {
+            let d = c.borrow();
+            let e = d.get_symbol_table_for_symbol(symbol_name);
+            return Rc::clone(&e);
+        } else {
+            panic!("Fatal error - could not find symbol {} in action scope.", symbol_name);
+        }
+    }
+}
+
+// -----------------------
+
 const ACTIONS_BLOCK_SCOPE_NAME:&str = "-actions-block-";
 
 pub struct ActionsBlockScopeSymbol {
@@ -1606,6 +1777,11 @@ pub struct ActionsBlockScopeSymbol {
 
 impl ActionsBlockScopeSymbol {
 
+    #[inline]
+    pub fn scope_name() -> &'static str {
+        ACTIONS_BLOCK_SCOPE_NAME
+    }
+
     pub fn new() -> ActionsBlockScopeSymbol {
 
         let name = String::from(ACTIONS_BLOCK_SCOPE_NAME);
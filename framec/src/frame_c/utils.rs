@@ -137,13 +137,32 @@ impl SystemHierarchy {
         self.index.get(&self.system_name)
     }
 
+    // Returns the parent states of a state, nearest first. The system
+    // node is not included.
+
+    pub(crate) fn get_ancestors(&self, node_name:&String) -> Vec<String> {
+        let mut ancestors = Vec::new();
+        let mut current = node_name.clone();
+        while let Some(node) = self.index.get(&current) {
+            if node.parent_name == "" || node.parent_name == self.system_name {
+                break;
+            }
+            ancestors.push(node.parent_name.clone());
+            current = node.parent_name.clone();
+        }
+        ancestors
+    }
+
 }
 
-pub(crate) mod frame_exitcode {
+pub mod frame_exitcode {
     pub type FrameExitCode = i32;
 
     /// Framepiler parse error exit
     pub const PARSE_ERR: FrameExitCode = 1;
+
+    /// The code was generated but the language has no actions stub
+    pub const ACTIONS_STUB_ERR: FrameExitCode = 2;
 }
 
 pub struct RunError {
@@ -20,6 +20,8 @@ pub struct CppVisitor {
     first_event_handler:bool,
     system_name:String,
     first_state_name:String,
+    subclass_code:Vec<String>,
+    generate_abstract_actions:bool,
     warnings:Vec<String>,
     has_states:bool,
     errors:Vec<String>,
@@ -29,6 +31,18 @@ pub struct CppVisitor {
     generate_state_stack:bool,
     generate_change_state:bool,
     generate_transition_state:bool,
+    generate_timers:bool,
+    generate_event_queue:bool,
+    generate_deferred_events:bool,
+    generate_history:bool,
+    generate_regions:bool,
+    generate_final_states:bool,
+    generate_completions:bool,
+    pushed_state_names:Vec<String>,
+    domain_param_names:Vec<String>,
+    generate_start_enter:bool,
+    state_stack_max_opt:Option<usize>,
+    state_stack_underflow_opt:Option<StateStackUnderflowPolicy>,
 }
 
 impl CppVisitor {
@@ -64,6 +78,8 @@ impl CppVisitor {
             first_event_handler:true,
             system_name:String::new(),
             first_state_name:String::new(),
+            subclass_code:Vec::new(),
+            generate_abstract_actions:false,
             has_states:false,
             warnings:Vec::new(),
             visiting_call_chain_literal_variable:false,
@@ -73,11 +89,40 @@ impl CppVisitor {
             generate_state_stack,
             generate_change_state,
             generate_transition_state,
+            generate_timers:false,
+            generate_event_queue:false,
+            generate_deferred_events:false,
+            generate_history:false,
+            generate_regions:false,
+            generate_final_states:false,
+            generate_completions:false,
+            pushed_state_names:Vec::new(),
+            domain_param_names:Vec::new(),
+            generate_start_enter:false,
+            state_stack_max_opt:None,
+            state_stack_underflow_opt:None,
         }
     }
 
     //* --------------------------------------------------------------------- *//
 
+    // The subclass implementing the system's external actions, written
+    // once to its own file so regenerating the system keeps the user's code.
+
+    pub fn get_actions_stub(&self) -> Option<String> {
+        if !self.generate_abstract_actions {
+            return None;
+        }
+        let mut stub = String::new();
+        for line in self.subclass_code.iter() {
+            stub.push_str(line);
+            stub.push('\n');
+        }
+        Some(stub)
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     pub fn get_code(&self) -> String {
         if self.errors.len() > 0 {
             let mut error_list = String::new();
@@ -96,30 +141,30 @@ impl CppVisitor {
         let var_type = match &*symbol_type {
             DomainVariableSymbolT { domain_variable_symbol_rcref } => {
                 match &domain_variable_symbol_rcref.borrow().var_type {
-                    Some(x) => x.get_type_str(),
+                    Some(x) => self.format_type(x),
                     None => String::from("<?>"),
                 }
             },
             StateParamSymbolT { state_param_symbol_rcref } => {
                 match &state_param_symbol_rcref.borrow().param_type_opt {
-                    Some(x) => x.get_type_str(),
+                    Some(x) => self.format_type(x),
                     None => String::from("<?>"),
                 }
             },
             StateVariableSymbolT { state_variable_symbol_rcref } => {
                 match &state_variable_symbol_rcref.borrow().var_type {
-                    Some(x) => x.get_type_str(),
+                    Some(x) => self.format_type(x),
                     None => String::from("<?>"),
                 }                    },
             EventHandlerParamSymbolT { event_handler_param_symbol_rcref } => {
                 match &event_handler_param_symbol_rcref.borrow().param_type_opt {
-                    Some(x) => x.get_type_str(),
+                    Some(x) => self.format_type(x),
                     None => String::from("<?>"),
                 }
             },
             EventHandlerVariableSymbolT { event_handler_variable_symbol_rcref } => {
                 match &event_handler_variable_symbol_rcref.borrow().var_type {
-                    Some(x) => x.get_type_str(),
+                    Some(x) => self.format_type(x),
                     None => String::from("<?>"),
                 }
             },
@@ -210,10 +255,27 @@ impl CppVisitor {
         for param in params {
             self.add_code(&format!("{}", separator));
             let param_type: String = match &param.param_type_opt {
-                Some(ret_type) => ret_type.get_type_str(),
+                Some(param_type) => self.format_parameter_type(param_type),
+                None => String::from("<?>"),
+            };
+            self.add_code(&format!("{} {}", param_type, param.param_name));
+            separator = ",";
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn format_actions_parameter_list(&mut self, params:&Vec<ParameterNode>,subclass_actions:&mut String) {
+        let mut separator = "";
+        for param in params {
+            self.add_code(&format!("{}", separator));
+            subclass_actions.push_str(&format!("{}", separator));
+            let param_type: String = match &param.param_type_opt {
+                Some(param_type) => self.format_parameter_type(param_type),
                 None => String::from("<?>"),
             };
             self.add_code(&format!("{} {}", param_type, param.param_name));
+            subclass_actions.push_str(&format!("{} {}", param_type, param.param_name));
             separator = ",";
         }
     }
@@ -301,6 +363,12 @@ impl CppVisitor {
                         StatementType::StateStackStmt {state_stack_operation_statement_node} => {
                             state_stack_operation_statement_node.accept(self);
                         },
+                        StatementType::RaiseStmt {raise_statement_node} => {
+                            raise_statement_node.accept(self);
+                        },
+                        StatementType::ForwardStmt {forward_statement_node} => {
+                            forward_statement_node.accept(self);
+                        },
                         StatementType::ChangeStateStmt {change_state_stmt} => {
                             change_state_stmt.accept(self);
                         },
@@ -315,6 +383,20 @@ impl CppVisitor {
         }
     }
 
+    // Generates a Frame body for an operation or action.
+
+    fn generate_frame_body(&mut self, statements:&Vec<DeclOrStmtType>, return_expr_t_opt:Option<&ExprType>) {
+        self.visit_decl_stmts(statements);
+        if let Some(expr_t) = return_expr_t_opt {
+            self.newline();
+            self.add_code("return ");
+            expr_t.accept(self);
+            self.add_code(";");
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     //* --------------------------------------------------------------------- *//
 
     fn generate_machinery(&mut self, system_node: &SystemNode) {
@@ -342,21 +424,25 @@ impl CppVisitor {
 //            self.add_code(&format!("FrameState _state_ = &{}::_s{}_;",system_node.name, first_state.borrow().name ));
             self.add_code(&format!("FrameState _state_;"));
             self.newline();
-            self.add_code(&format!("StateContext* _pStateContext_;"));
+            self.newline();
+            self.generate_state_context_class();
+            self.newline();
+            self.newline();
+            self.add_code(&format!("StateContext* _pStateContext_ = nullptr;"));
             self.newline();
             self.newline();
             if self.generate_transition_state {
                 if self.generate_state_context {
                     if self.generate_exit_args {
-                        self.add_code(&format!("private void _transition_(FrameState newState,Dictionary<string,object> exitArgs, StateContext stateContext) {{"));
+                        self.add_code(&format!("void _transition_(FrameState newState,FrameMap* exitArgs, StateContext* pContext) {{"));
                     } else {
-                        self.add_code(&format!("private void _transition_(FrameState newState, StateContext stateContext) {{"));
+                        self.add_code(&format!("void _transition_(FrameState newState, StateContext* pContext) {{"));
                     }
                 } else {
                     if self.generate_exit_args {
-                        self.add_code(&format!("private void _transition_(FrameState newState,Dictionary<string,object> exitArgs) {{"));
+                        self.add_code(&format!("void _transition_(FrameState newState,FrameMap* exitArgs) {{"));
                     } else {
-                        self.add_code(&format!("private void _transition_(FrameState newState) {{"));
+                        self.add_code(&format!("void _transition_(FrameState newState) {{"));
                     }
                 }
                 self.indent();
@@ -367,6 +453,16 @@ impl CppVisitor {
                     self.add_code(&format!("FrameEvent exitEvent(\"<\",nullptr);"));
                 }
                 self.newline();
+                self.add_code(&format!("(this->*_state_)(exitEvent);"));
+                self.newline();
+                if self.generate_history {
+                    self.add_code(&format!("_recordHistory_();"));
+                    self.newline();
+                }
+                if self.generate_timers {
+                    self.add_code(&format!("_cancelTimer_();"));
+                    self.newline();
+                }
                 self.add_code(&format!("_state_ = newState;"));
                 self.newline();
                 self.add_code(&format!("if (_pStateContext_ && !_pStateContext_->isOnStateStack()) delete _pStateContext_;"));
@@ -374,13 +470,24 @@ impl CppVisitor {
                 if self.generate_state_context {
                     self.add_code(&format!("_pStateContext_ = pContext;"));
                     self.newline();
-                    self.add_code(&format!("FrameEvent enterEvent(\">\", pContext->enterArgs);"));
+                    self.add_code(&format!("FrameEvent enterEvent(\">\", &pContext->enterArgs);"));
                 } else {
                     self.add_code(&format!("FrameEvent enterEvent(\">\",nullptr);"));
                     self.newline();
                 }
                 self.newline();
                 self.add_code(&format!("(this->*_state_)(enterEvent);"));
+                if self.generate_deferred_events {
+                    self.newline();
+                    self.add_code(&format!("_replayDeferred_();"));
+                }
+                if self.generate_final_states && !self.generate_regions {
+                    self.generate_finished_check();
+                }
+                if self.generate_completions {
+                    self.newline();
+                    self.add_code("_complete_();");
+                }
                 self.outdent();
                 self.newline();
                 self.add_code(&format!("}}"));
@@ -405,7 +512,7 @@ impl CppVisitor {
                 self.add_code(&format!("StateContext* _stateStack_pop() {{"));
                 self.indent();
                 self.newline();
-                self.add_code(&format!("pStateContext =  _stateStack_.back();"));
+                self.add_code(&format!("StateContext* pStateContext = _stateStack_.back();"));
                 self.newline();
                 self.add_code(&format!("_stateStack_.pop_back();"));
                 self.newline();
@@ -420,132 +527,1004 @@ impl CppVisitor {
             if self.generate_change_state {
                 self.newline();
                 self.newline();
-                self.add_code(&format!("private void _changeState_(FrameState newState) {{"));
+                if self.generate_state_context {
+                    self.add_code(&format!("void _changeState_(FrameState newState, StateContext* pContext) {{"));
+                } else {
+                    self.add_code(&format!("void _changeState_(FrameState newState) {{"));
+                }
                 self.indent();
                 self.newline();
+                if self.generate_history {
+                    self.add_code(&format!("_recordHistory_();"));
+                    self.newline();
+                }
+                if self.generate_timers {
+                    self.add_code(&format!("_cancelTimer_();"));
+                    self.newline();
+                }
                 self.add_code(&format!("_state_ = newState;"));
+                if self.generate_state_context {
+                    self.newline();
+                    self.add_code(&format!("if (_pStateContext_ && !_pStateContext_->isOnStateStack()) delete _pStateContext_;"));
+                    self.newline();
+                    self.add_code(&format!("_pStateContext_ = pContext;"));
+                }
+                if self.generate_deferred_events {
+                    self.newline();
+                    self.add_code(&format!("_replayDeferred_();"));
+                }
+                if self.generate_final_states && !self.generate_regions {
+                    self.generate_finished_check();
+                }
+                if self.generate_completions {
+                    self.newline();
+                    self.add_code("_complete_();");
+                }
                 self.outdent();
                 self.newline();
                 self.add_code(&format!("}}"));
             }
+            if self.generate_timers {
+                self.generate_timers();
+            }
+            if self.generate_event_queue {
+                self.generate_event_queue();
+            }
+            if self.generate_deferred_events {
+                self.generate_deferred_events();
+            }
+            if self.generate_history {
+                self.generate_history(system_node);
+            }
+            if self.generate_regions {
+                self.generate_regions();
+            }
+            if self.generate_final_states {
+                self.generate_final_states(system_node);
+            }
+            if self.generate_completions {
+                self.generate_completions(system_node);
+            }
+            if self.generate_start_enter {
+                self.generate_start();
+            }
+            if self.arcanium.is_serializable() {
+                self.generate_serialization(system_node);
+            }
         }
     }
 
     //* --------------------------------------------------------------------- *//
 
-    fn generate_comment(&mut self,line:usize) {
-
-        // can't use self.newline() or self.add_code() due to double borrow.
-        let mut generated_comment = false;
-        while self.current_comment_idx < self.comments.len() &&
-            line >= self.comments[self.current_comment_idx].line {
-            let comment = &self.comments[self.current_comment_idx];
-            if comment.token_type == TokenType::SingleLineCommentTok {
-                self.code.push_str(&*format!("  // {}",&comment.lexeme[3..]));
-                self.code.push_str(&*format!("\n{}",(0..self.dent).map(|_| "\t").collect::<String>()));
-
-            } else {
-                let len = &comment.lexeme.len() - 3;
-                self.code.push_str(&*format!("/* {}",&comment.lexeme[3..len]));
-                self.code.push_str(&*format!("*/"));
-
-            }
+    // A StateContext holds a state with its arguments and variables. The
+    // values are type erased, their types are known by the code reading them.
 
-            self.current_comment_idx += 1;
-            generated_comment = true;
-        }
-        if generated_comment {
-//            self.code.push_str(&*format!("\n{}",(0..self.dent).map(|_| "\t").collect::<String>()));
-        }
+    fn generate_state_context_class(&mut self) {
+        self.add_code("class StateContext {");
+        self.indent();
+        self.newline();
+        self.add_code("public:");
+        self.newline();
+        self.add_code("StateContext(FrameState state) : state(state) {}");
+        self.newline();
+        self.newline();
+        self.add_code("FrameState state;");
+        self.newline();
+        self.add_code("FrameMap stateArgs;");
+        self.newline();
+        self.add_code("FrameMap stateVars;");
+        self.newline();
+        self.add_code("FrameMap enterArgs;");
+        self.newline();
+        self.newline();
+        self.add_code("void addStateArg(string name, string type, bool owned, void* value) { stateArgs[name] = value; }");
+        self.newline();
+        self.add_code("void* getStateArg(string name) { return stateArgs[name]; }");
+        self.newline();
+        self.add_code("void addStateVar(string name, string type, bool owned, void* value) { stateVars[name] = value; }");
+        self.newline();
+        self.add_code("void* getStateVar(string name) { return stateVars[name]; }");
+        self.newline();
+        self.add_code("void addEnterArg(string name, string type, bool owned, void* value) { enterArgs[name] = value; }");
+        self.newline();
+        self.add_code("bool isOnStateStack() { return _onStateStack_; }");
+        self.newline();
+        self.add_code("void setOnStateStack(bool onStateStack) { _onStateStack_ = onStateStack; }");
+        self.newline();
+        self.newline();
+        self.add_code("private:");
+        self.newline();
+        self.add_code("bool _onStateStack_ = false;");
+        self.outdent();
+        self.newline();
+        self.add_code("};");
     }
 
     //* --------------------------------------------------------------------- *//
 
-    // TODO
-    fn generate_state_ref_change_state(&mut self, change_state_stmt_node: &ChangeStateStatementNode) {
-
-        let target_state_name = match &change_state_stmt_node.state_context_t {
-            StateContextType::StateRef {state_context_node}
-            => &state_context_node.state_ref_node.name,
-            _ => {
-                self.errors.push("Change state target not found.".to_string());
-                "error"
-            },
-        };
+    // The system is given a FrameScheduler to run its timers so that
+    // the clock can be replaced, e.g. by a virtual clock in tests.
 
+    fn generate_scheduler_interface(&mut self) {
+        self.add_code("class FrameScheduler {");
+        self.indent();
+        self.newline();
+        self.add_code("public:");
+        self.newline();
+        self.add_code("virtual ~FrameScheduler() {}");
+        self.newline();
+        self.add_code("virtual void schedule(long delayMs, std::function<void()> callback) = 0;");
+        self.newline();
+        self.add_code("virtual void cancel() = 0;");
+        self.outdent();
+        self.newline();
+        self.add_code("};");
+        self.newline();
         self.newline();
-        self.add_code(&format!("_changeState_({});", self.format_target_state_name(target_state_name)));
-
     }
 
     //* --------------------------------------------------------------------- *//
 
-    // fn generate_state_ref_code(&self, target_state_name:&str) -> String {
-    //     format!("{}",self.format_target_state_name(target_state_name))
-    // }
+    // Only one timer is active at a time. It is cancelled when a state is
+    // exited so a timeout always belongs to the current state.
+
+    fn generate_timers(&mut self) {
+        self.newline();
+        self.newline();
+        self.add_code("FrameScheduler* _scheduler_;");
+        self.newline();
+        self.add_code("bool _timerActive_ = false;");
+        self.newline();
+        self.newline();
+        self.add_code("void _startTimer_(long delayMs) {");
+        self.indent();
+        self.newline();
+        self.add_code("if (_timerActive_) return;");
+        self.newline();
+        self.add_code("_timerActive_ = true;");
+        self.newline();
+        self.add_code("_scheduler_->schedule(delayMs, [this]() { _timeout_(); });");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        self.add_code("void _cancelTimer_() {");
+        self.indent();
+        self.newline();
+        self.add_code("if (!_timerActive_) return;");
+        self.newline();
+        self.add_code("_timerActive_ = false;");
+        self.newline();
+        self.add_code("_scheduler_->cancel();");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        self.add_code("void _timeout_() {");
+        self.indent();
+        self.newline();
+        self.add_code("if (!_timerActive_) return;");
+        self.newline();
+        self.add_code("_timerActive_ = false;");
+        self.newline();
+        self.add_code(&format!("FrameEvent timeoutEvent(\"{}\",nullptr);", self.symbol_config.timeout_msg_symbol));
+        self.newline();
+        if self.generate_event_queue {
+            self.add_code("_dispatch_(timeoutEvent);");
+        } else {
+            self.add_code("(this->*_state_)(timeoutEvent);");
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
 
     //* --------------------------------------------------------------------- *//
 
-    fn generate_state_ref_transition(&mut self, transition_statement: &TransitionStatementNode) {
+    // Events sent while the machine is handling an event are queued and
+    // dispatched in order once the current event has been fully handled.
+    // Event parameters point into the sender's stack frame, so interface
+    // methods with parameters queue a call that copies the arguments
+    // instead of the event itself.
 
+    fn generate_event_queue(&mut self) {
+        let state_dispatch = if self.generate_regions {"_dispatchRegions_"} else {"(this->*_state_)"};
         self.newline();
-        // this provides a block to segregate StateContext declarations which
-        // can overlap w/ other generated ones in child scopes which causes
-        // the compiler to complain.
-        // self.add_code("{");
-        // self.indent();
+        self.newline();
+        self.add_code("std::deque<std::function<void()>> _eventQueue_;");
+        self.newline();
+        self.add_code("bool _processing_ = false;");
+        self.newline();
+        self.newline();
+        self.add_code("void _dispatch_(FrameEvent& e) {");
+        self.indent();
+        self.newline();
+        self.add_code("if (_processing_) {");
+        self.indent();
+        self.newline();
+        self.add_code("_eventQueue_.push_back([this, e]() mutable { _dispatch_(e); });");
+        self.newline();
+        self.add_code("return;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.add_code("_processing_ = true;");
+        self.newline();
+        self.add_code(&format!("{}(e);", state_dispatch));
+        self.newline();
+        self.add_code("_processing_ = false;");
+        self.newline();
+        self.add_code("while (!_eventQueue_.empty()) {");
+        self.indent();
+        self.newline();
+        self.add_code("std::function<void()> queued = _eventQueue_.front();");
+        self.newline();
+        self.add_code("_eventQueue_.pop_front();");
+        self.newline();
+        self.add_code("queued();");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
 
-        let target_state_name = match &transition_statement.target_state_context_t {
-            StateContextType::StateRef {state_context_node} => {
-                &state_context_node.state_ref_node.name
-            },
-            _ => {
-                self.errors.push("Unknown error.".to_string());
-                ""
-            },
-        };
+    //* --------------------------------------------------------------------- *//
 
-        let state_ref_code = format!("&{}::_s{}_",self.system_name,&target_state_name);
+    // Queues a call of the interface method with copies of its arguments
+    // when an event is already being handled.
 
+    fn generate_queued_interface_call(&mut self, interface_method_node:&InterfaceMethodNode, params:&Vec<ParameterNode>) {
+        let param_names:Vec<String> = params.iter().map(|param| param.param_name.clone()).collect();
         self.newline();
-        match &transition_statement.label_opt {
-            Some(label) => {
-                self.add_code(&format!("// {}", label));
-                self.newline();
+        self.add_code("if (_processing_) {");
+        self.indent();
+        self.newline();
+        self.add_code(&format!("_eventQueue_.push_back([this, {}]() {{ {}({}); }});", param_names.join(", "), interface_method_node.name, param_names.join(", ")));
+        self.newline();
+        match (&interface_method_node.return_type_opt, &interface_method_node.default_return_expr_opt) {
+            (Some(_), Some(default_return_expr_t)) => {
+                let mut default_return = String::new();
+                default_return_expr_t.accept_to_string(self, &mut default_return);
+                self.add_code(&format!("return {};", default_return));
             },
-            None => {},
-        }
-
-        if self.generate_state_context {
-            self.add_code(&format!("pStateContext = new StateContext({});", state_ref_code));
-            self.newline();
+            (Some(_), None) => self.add_code("return {};"),
+            (None, _) => self.add_code("return;"),
         }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
 
-        // -- Exit Arguments --
-
-//        let mut has_exit_args = false;
-        if let Some(exit_args) = &transition_statement.exit_args_opt {
-            if exit_args.exprs_t.len() > 0 {
-                //has_exit_args = true;
-
-                // Note - searching for event keyed with "State:<"
-                // e.g. "S1:<"
+    //* --------------------------------------------------------------------- *//
 
-                let mut msg: String = String::new();
-                if let Some(state_name) = &self.current_state_name_opt {
-                    msg = state_name.clone();
-                }
-                msg.push_str(":");
-                msg.push_str(&self.symbol_config.exit_msg_symbol);
+    // The machine is finished once it is in a #[final] state, or once
+    // every region is.
 
-                if let Some(event_sym) = self.arcanium.get_event(&msg, &self.current_state_name_opt) {
-                    match &event_sym.borrow().params_opt {
-                        Some(event_params) => {
-                            if exit_args.exprs_t.len() != event_params.len() {
-                                panic!("Fatal error: misaligned parameters to arguments.")
-                            }
-                            let mut param_symbols_it = event_params.iter();
-                            self.add_code("map<string, Attr*> exitArg;");
+    fn generate_final_states(&mut self, system_node: &SystemNode) {
+        let final_states:Vec<String> = system_node.get_final_state_names().iter()
+            .map(|state_name| format!("state == &{}::_s{}_", system_node.name, state_name)).collect();
+        self.newline();
+        self.newline();
+        self.add_code("bool _isFinalState_(FrameState state) {");
+        self.indent();
+        self.newline();
+        self.add_code(&format!("return {};", final_states.join(" || ")));
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        if self.generate_regions {
+            self.newline();
+            self.add_code("public:");
+            self.newline();
+            self.add_code("bool isFinished() {");
+            self.indent();
+            self.newline();
+            self.add_code("for (FrameState state : _regions_) {");
+            self.indent();
+            self.newline();
+            self.add_code("if (!_isFinalState_(state)) {");
+            self.indent();
+            self.newline();
+            self.add_code("return false;");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+            self.newline();
+            self.add_code("return true;");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        } else {
+            self.newline();
+            self.add_code("public:");
+            self.newline();
+            self.add_code("bool isFinished() {");
+            self.indent();
+            self.newline();
+            self.add_code("return _isFinalState_(_state_);");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
+        self.newline();
+        self.newline();
+        self.add_code("protected:");
+        self.newline();
+        self.add_code("virtual void onFinished() {}");
+        self.newline();
+        self.newline();
+        self.add_code("private:");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Entering a final state completes the machine.
+
+    fn generate_finished_check(&mut self) {
+        self.newline();
+        self.add_code("if (isFinished()) {");
+        self.indent();
+        self.newline();
+        self.add_code("onFinished();");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Virtual actions called from the constructor would not reach a
+    // subclass so the start state is not entered there. The first
+    // interface call enters it with the system enter parameters.
+
+    fn generate_start(&mut self) {
+        self.newline();
+        self.newline();
+        self.add_code("private:");
+        self.newline();
+        self.newline();
+        self.add_code("bool _started_ = false;");
+        self.newline();
+        self.newline();
+        self.add_code("void _start_() {");
+        self.indent();
+        self.newline();
+        self.add_code("_started_ = true;");
+        self.newline();
+        self.add_code("FrameEvent enterEvent(\">\", &_pStateContext_->enterArgs);");
+        self.newline();
+        self.add_code("(this->*_state_)(enterEvent);");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Completion transitions are taken one after another in a loop rather
+    // than from inside the transition that entered the state, so long
+    // chains don't grow the stack.
+
+    fn generate_completions(&mut self, system_node: &SystemNode) {
+        self.newline();
+        self.newline();
+        self.add_code("bool _completing_ = false;");
+        self.newline();
+        self.newline();
+        self.add_code("void _complete_() {");
+        self.indent();
+        self.newline();
+        self.add_code("if (_completing_) {");
+        self.indent();
+        self.newline();
+        self.add_code("return;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.add_code("_completing_ = true;");
+        self.newline();
+        self.add_code("while (_completion_()) {}");
+        self.newline();
+        self.add_code("_completing_ = false;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        self.add_code("bool _completion_() {");
+        self.indent();
+        if let Some(machine_block_node) = &system_node.machine_block_node_opt {
+            for state_node_rcref in &machine_block_node.states {
+                let state_node = state_node_rcref.borrow();
+                if state_node.completions.len() == 0 {
+                    continue;
+                }
+                self.current_state_name_opt = Some(state_node.name.clone());
+                self.newline();
+                self.add_code(&format!("if (_state_ == &{}::_s{}_) {{", system_node.name, state_node.name));
+                self.indent();
+                for completion_node in &state_node.completions {
+                    if let Some(guard_expr_t) = &completion_node.guard_expr_t_opt {
+                        let mut guard = String::new();
+                        guard_expr_t.accept_to_string(self, &mut guard);
+                        self.newline();
+                        self.add_code(&format!("if ({}) {{", guard));
+                        self.indent();
+                    }
+                    completion_node.transition_statement_node.accept(self);
+                    self.newline();
+                    self.add_code("return true;");
+                    if completion_node.guard_expr_t_opt.is_some() {
+                        self.outdent();
+                        self.newline();
+                        self.add_code("}");
+                    }
+                }
+                self.outdent();
+                self.newline();
+                self.add_code("}");
+                self.current_state_name_opt = None;
+            }
+        }
+        self.newline();
+        self.add_code("return false;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // The snapshot is a JSON document written with nlohmann::json. State
+    // arguments and variables are held as void* so they are read and
+    // written with their declared types.
+
+    fn generate_serialization(&mut self, system_node: &SystemNode) {
+        let domain_vars = system_node.get_domain_vars();
+        let snapshot_version = system_node.get_snapshot_version();
+        let snapshot_hash = system_node.get_snapshot_hash();
+        self.newline();
+        self.newline();
+        self.add_code("public:");
+        self.newline();
+        self.newline();
+        self.add_code("std::string _serialize__do() {");
+        self.indent();
+        self.newline();
+        self.add_code("nlohmann::json bag;");
+        self.newline();
+        self.add_code(&format!("bag[\"version\"] = {};", snapshot_version));
+        self.newline();
+        self.add_code(&format!("bag[\"hash\"] = \"{}\";", snapshot_hash));
+        self.newline();
+        if self.generate_state_context {
+            self.add_code("bag[\"state\"] = _serializeState_(_pStateContext_);");
+        } else {
+            self.add_code("bag[\"state\"] = _serializeState_(_state_);");
+        }
+        if self.generate_state_stack {
+            self.newline();
+            self.add_code("bag[\"stateStack\"] = nlohmann::json::array();");
+            self.newline();
+            self.add_code("for (StateContext* pStateContext : _stateStack_) {");
+            self.indent();
+            self.newline();
+            if self.generate_state_context {
+                self.add_code("bag[\"stateStack\"].push_back(_serializeState_(pStateContext));");
+            } else {
+                self.add_code("bag[\"stateStack\"].push_back(_serializeState_(pStateContext->state));");
+            }
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
+        self.newline();
+        self.add_code("bag[\"domain\"] = nlohmann::json::object();");
+        for var_rcref in &domain_vars {
+            let var_name = var_rcref.borrow().name.clone();
+            self.newline();
+            self.add_code(&format!("bag[\"domain\"][\"{}\"] = this->{};", var_name, var_name));
+        }
+        self.newline();
+        self.add_code("return bag.dump();");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        self.add_code("void _deserialize__do(std::string data) {");
+        self.indent();
+        self.newline();
+        self.add_code("nlohmann::json bag = nlohmann::json::parse(data);");
+        self.newline();
+        self.add_code("int version = bag[\"version\"].get<int>();");
+        self.newline();
+        self.add_code(&format!("if (version > {}) {{", snapshot_version));
+        self.indent();
+        self.newline();
+        self.add_code(&format!("throw std::invalid_argument(\"Snapshot version \" + std::to_string(version) + \" is newer than version {} of {}\");", snapshot_version, system_node.name));
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.add_code(&format!("if (version == {} && bag[\"hash\"].get<std::string>() != \"{}\") {{", snapshot_version, snapshot_hash));
+        self.indent();
+        self.newline();
+        self.add_code(&format!("throw std::invalid_argument(\"Snapshot does not match version {} of {}\");", snapshot_version, system_node.name));
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        if self.generate_state_context {
+            self.add_code("if (_pStateContext_ && !_pStateContext_->isOnStateStack()) delete _pStateContext_;");
+            self.newline();
+            self.add_code("_pStateContext_ = _deserializeState_(bag[\"state\"]);");
+            self.newline();
+            self.add_code("_state_ = _pStateContext_->state;");
+        } else {
+            self.add_code("_state_ = _deserializeState_(bag[\"state\"]);");
+        }
+        if self.generate_state_stack {
+            self.newline();
+            self.add_code("for (StateContext* pStateContext : _stateStack_) {");
+            self.indent();
+            self.newline();
+            self.add_code("delete pStateContext;");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+            self.newline();
+            self.add_code("_stateStack_.clear();");
+            self.newline();
+            self.add_code("for (nlohmann::json& state : bag[\"stateStack\"]) {");
+            self.indent();
+            self.newline();
+            if self.generate_state_context {
+                self.add_code("StateContext* pStateContext = _deserializeState_(state);");
+            } else {
+                self.add_code("StateContext* pStateContext = new StateContext(_deserializeState_(state));");
+            }
+            self.newline();
+            self.add_code("pStateContext->setOnStateStack(true);");
+            self.newline();
+            self.add_code("_stateStack_.push_back(pStateContext);");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
+        if self.generate_start_enter {
+            self.newline();
+            self.add_code("_started_ = true;");
+        }
+        for var_rcref in &domain_vars {
+            let var = var_rcref.borrow();
+            let var_type = self.format_serialized_type(&var.type_opt);
+            self.newline();
+            if var.has_snapshot_default() {
+                self.add_code(&format!("if (bag[\"domain\"].contains(\"{}\")) {{", var.name));
+                self.indent();
+                self.newline();
+            }
+            self.add_code(&format!("this->{} = bag[\"domain\"][\"{}\"].get<{}>();", var.name, var.name, var_type));
+            if var.has_snapshot_default() {
+                self.outdent();
+                self.newline();
+                self.add_code("}");
+            }
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        self.add_code("private:");
+        self.newline();
+        self.newline();
+        if self.generate_state_context {
+            self.add_code("nlohmann::json _serializeState_(StateContext* pStateContext) {");
+        } else {
+            self.add_code("nlohmann::json _serializeState_(FrameState state) {");
+        }
+        self.indent();
+        self.newline();
+        self.add_code("nlohmann::json data;");
+        if self.generate_state_context {
+            self.newline();
+            self.add_code("data[\"stateArgs\"] = nlohmann::json::object();");
+            self.newline();
+            self.add_code("data[\"stateVars\"] = nlohmann::json::object();");
+        }
+        for state_node_rcref in system_node.get_states() {
+            let state_node = state_node_rcref.borrow();
+            let state_ref = self.format_target_state_name(&state_node.name);
+            self.newline();
+            if self.generate_state_context {
+                self.add_code(&format!("if (pStateContext->state == {}) {{", state_ref));
+            } else {
+                self.add_code(&format!("if (state == {}) {{", state_ref));
+            }
+            self.indent();
+            self.newline();
+            self.add_code(&format!("data[\"state\"] = \"{}\";", state_node.name));
+            if let Some(params) = &state_node.params_opt {
+                for param in params {
+                    let param_type = self.format_serialized_type(&param.param_type_opt);
+                    self.newline();
+                    self.add_code(&format!("data[\"stateArgs\"][\"{}\"] = *({}*) pStateContext->getStateArg(\"{}\");", param.param_name, param_type, param.param_name));
+                }
+            }
+            if let Some(vars) = &state_node.vars_opt {
+                for var_rcref in vars {
+                    let var = var_rcref.borrow();
+                    let var_type = self.format_serialized_type(&var.type_opt);
+                    self.newline();
+                    self.add_code(&format!("data[\"stateVars\"][\"{}\"] = *({}*) pStateContext->getStateVar(\"{}\");", var.name, var_type, var.name));
+                }
+            }
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
+        self.newline();
+        self.add_code("return data;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        if self.generate_state_context {
+            self.add_code("StateContext* _deserializeState_(nlohmann::json& data) {");
+        } else {
+            self.add_code("FrameState _deserializeState_(nlohmann::json& data) {");
+        }
+        self.indent();
+        self.newline();
+        self.add_code("std::string stateName = data[\"state\"].get<std::string>();");
+        for state_node_rcref in system_node.get_states() {
+            let state_node = state_node_rcref.borrow();
+            let state_ref = self.format_target_state_name(&state_node.name);
+            let conditions:Vec<String> = std::iter::once(state_node.name.clone()).chain(state_node.get_renamed_from())
+                .map(|name| format!("stateName == \"{}\"", name)).collect();
+            self.newline();
+            self.add_code(&format!("if ({}) {{", conditions.join(" || ")));
+            self.indent();
+            self.newline();
+            if self.generate_state_context {
+                self.add_code(&format!("StateContext* pStateContext = new StateContext({});", state_ref));
+                if let Some(params) = &state_node.params_opt {
+                    for param in params {
+                        let param_type = self.format_serialized_type(&param.param_type_opt);
+                        self.newline();
+                        self.add_code(&format!("pStateContext->addStateArg(string(\"{}\"),string(\"{}\"),true,new {}(data[\"stateArgs\"][\"{}\"].get<{}>()));", param.param_name, param_type, param_type, param.param_name, param_type));
+                    }
+                }
+                if let Some(vars) = &state_node.vars_opt {
+                    for var_rcref in vars {
+                        let var = var_rcref.borrow();
+                        let var_type = self.format_serialized_type(&var.type_opt);
+                        self.newline();
+                        self.add_code(&format!("pStateContext->addStateVar(string(\"{}\"),string(\"{}\"),true,new {}(data[\"stateVars\"][\"{}\"].get<{}>()));", var.name, var_type, var_type, var.name, var_type));
+                    }
+                }
+                self.newline();
+                self.add_code("return pStateContext;");
+            } else {
+                self.add_code(&format!("return {};", state_ref));
+            }
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
+        self.newline();
+        self.add_code("throw std::invalid_argument(\"Unknown state \" + stateName);");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Untyped values are written and read as JSON values.
+
+    fn format_serialized_type(&self, type_opt:&Option<TypeNode>) -> String {
+        match type_opt {
+            Some(type_node) => self.format_type(type_node),
+            None => String::from("nlohmann::json"),
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Each region has its own active state. An event is dispatched to
+    // every region in turn with the region's state made current.
+
+    fn generate_regions(&mut self) {
+        self.newline();
+        self.newline();
+        self.add_code("std::vector<FrameState> _regions_;");
+        self.newline();
+        self.newline();
+        self.add_code("void _dispatchRegions_(FrameEvent& e) {");
+        self.indent();
+        if self.generate_final_states {
+            self.newline();
+            self.add_code("bool wasFinished = isFinished();");
+        }
+        self.newline();
+        self.add_code("for (size_t i = 0; i < _regions_.size(); i++) {");
+        self.indent();
+        self.newline();
+        self.add_code("_state_ = _regions_[i];");
+        self.newline();
+        self.add_code("(this->*_state_)(e);");
+        self.newline();
+        self.add_code("_regions_[i] = _state_;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        if self.generate_final_states {
+            self.newline();
+            self.add_code("if (!wasFinished && isFinished()) {");
+            self.indent();
+            self.newline();
+            self.add_code("onFinished();");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // History is recorded on the way out of a state. Every ancestor that
+    // is a history target remembers the child it was left through and
+    // the leaf state that was active.
+
+    fn generate_history(&mut self, system_node: &SystemNode) {
+        self.newline();
+        self.newline();
+        self.add_code("std::map<std::string,FrameState> _shallowHistory_;");
+        self.newline();
+        self.add_code("std::map<std::string,FrameState> _deepHistory_;");
+        self.newline();
+        self.newline();
+        self.add_code("void _recordHistory_() {");
+        self.indent();
+        let mut is_first = true;
+        if let Some(machine_block_node) = &system_node.machine_block_node_opt {
+            for state_node_rcref in &machine_block_node.states {
+                let state_node = state_node_rcref.borrow();
+                if state_node.history_records.len() == 0 {
+                    continue;
+                }
+                let state_ref = self.format_target_state_name(&state_node.name);
+                self.newline();
+                if is_first {
+                    self.add_code(&format!("if (_state_ == {}) {{", state_ref));
+                } else {
+                    self.add_code(&format!("}} else if (_state_ == {}) {{", state_ref));
+                }
+                is_first = false;
+                self.indent();
+                for history_record in &state_node.history_records {
+                    let child_ref = self.format_target_state_name(&history_record.child_state_name);
+                    self.newline();
+                    self.add_code(&format!("_shallowHistory_[\"{}\"] = {};", history_record.parent_state_name, child_ref));
+                    self.newline();
+                    self.add_code(&format!("_deepHistory_[\"{}\"] = {};", history_record.parent_state_name, state_ref));
+                }
+                self.outdent();
+            }
+        }
+        if !is_first {
+            self.newline();
+            self.add_code("}");
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Deferred events are replayed in the order they arrived. Any the
+    // new state defers again go back on the queue.
+
+    fn generate_deferred_events(&mut self) {
+        self.newline();
+        self.newline();
+        self.add_code("std::vector<FrameEvent> _deferredQueue_;");
+        self.newline();
+        self.newline();
+        self.add_code("void _replayDeferred_() {");
+        self.indent();
+        self.newline();
+        self.add_code("std::vector<FrameEvent> deferred;");
+        self.newline();
+        self.add_code("deferred.swap(_deferredQueue_);");
+        self.newline();
+        self.add_code("for (FrameEvent& e : deferred) {");
+        self.indent();
+        self.newline();
+        self.add_code("(this->*_state_)(e);");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // A deferred event is put aside rather than handled and is replayed
+    // after the next transition.
+
+    fn generate_deferred_event_checks(&mut self, deferred_events:&Vec<String>) {
+        for msg in deferred_events {
+            self.newline();
+            self.add_code(&format!("if (e._message == \"{}\") {{", msg));
+            self.indent();
+            self.newline();
+            self.add_code("_deferredQueue_.push_back(e);");
+            self.newline();
+            self.add_code("return;");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn generate_timer_start(&mut self, timeout_node:&TimeoutNode) {
+        let mut duration = String::new();
+        timeout_node.duration_expr_t.accept_to_string(self, &mut duration);
+        self.newline();
+        self.add_code(&format!("_startTimer_({});", duration));
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn generate_comment(&mut self,line:usize) {
+
+        // can't use self.newline() or self.add_code() due to double borrow.
+        let mut generated_comment = false;
+        while self.current_comment_idx < self.comments.len() &&
+            line >= self.comments[self.current_comment_idx].line {
+            let comment = &self.comments[self.current_comment_idx];
+            if comment.token_type == TokenType::SingleLineCommentTok {
+                self.code.push_str(&*format!("  // {}",&comment.lexeme[3..]));
+                self.code.push_str(&*format!("\n{}",(0..self.dent).map(|_| "\t").collect::<String>()));
+
+            } else {
+                let len = &comment.lexeme.len() - 3;
+                self.code.push_str(&*format!("/* {}",&comment.lexeme[3..len]));
+                self.code.push_str(&*format!("*/"));
+
+            }
+
+            self.current_comment_idx += 1;
+            generated_comment = true;
+        }
+        if generated_comment {
+//            self.code.push_str(&*format!("\n{}",(0..self.dent).map(|_| "\t").collect::<String>()));
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // TODO
+    fn generate_state_ref_change_state(&mut self, change_state_stmt_node: &ChangeStateStatementNode) {
+
+        let target_state_name = match &change_state_stmt_node.state_context_t {
+            StateContextType::StateRef {state_context_node}
+            => &state_context_node.state_ref_node.name,
+            _ => {
+                self.errors.push("Change state target not found.".to_string());
+                "error"
+            },
+        };
+
+        let target_state_args_opt = match &change_state_stmt_node.state_context_t {
+            StateContextType::StateRef { state_context_node}
+                => &state_context_node.state_ref_args_opt,
+            _ => &Option::None,
+        };
+
+        self.newline();
+        if self.generate_state_context {
+            self.add_code(&format!("pStateContext = new StateContext(&{}::_s{}_);", self.system_name, target_state_name));
+            self.newline();
+            self.generate_state_args_and_vars(target_state_name, target_state_args_opt);
+            self.add_code(&format!("_changeState_({},pStateContext);", self.format_target_state_name(target_state_name)));
+        } else {
+            self.add_code(&format!("_changeState_({});", self.format_target_state_name(target_state_name)));
+        }
+
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // fn generate_state_ref_code(&self, target_state_name:&str) -> String {
+    //     format!("{}",self.format_target_state_name(target_state_name))
+    // }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn generate_state_ref_transition(&mut self, transition_statement: &TransitionStatementNode) {
+
+        self.newline();
+        // this provides a block to segregate StateContext declarations which
+        // can overlap w/ other generated ones in child scopes which causes
+        // the compiler to complain.
+        // self.add_code("{");
+        // self.indent();
+
+        let target_state_name = match &transition_statement.target_state_context_t {
+            StateContextType::StateRef {state_context_node} => {
+                &state_context_node.state_ref_node.name
+            },
+            _ => {
+                self.errors.push("Unknown error.".to_string());
+                ""
+            },
+        };
+
+        let state_ref_code = format!("&{}::_s{}_",self.system_name,&target_state_name);
+
+        self.newline();
+        match &transition_statement.label_opt {
+            Some(label) => {
+                self.add_code(&format!("// {}", label));
+                self.newline();
+            },
+            None => {},
+        }
+
+        if self.generate_state_context {
+            self.add_code(&format!("pStateContext = new StateContext({});", state_ref_code));
+            self.newline();
+        }
+
+        // -- Exit Arguments --
+
+//        let mut has_exit_args = false;
+        if let Some(exit_args) = &transition_statement.exit_args_opt {
+            if exit_args.exprs_t.len() > 0 {
+                //has_exit_args = true;
+
+                // Note - searching for event keyed with "State:<"
+                // e.g. "S1:<"
+
+                let mut msg: String = String::new();
+                if let Some(state_name) = &self.current_state_name_opt {
+                    msg = state_name.clone();
+                }
+                msg.push_str(":");
+                msg.push_str(&self.symbol_config.exit_msg_symbol);
+
+                if let Some(event_sym) = self.arcanium.get_event(&msg, &self.current_state_name_opt) {
+                    match &event_sym.borrow().params_opt {
+                        Some(event_params) => {
+                            if exit_args.exprs_t.len() != event_params.len() {
+                                panic!("Fatal error: misaligned parameters to arguments.")
+                            }
+                            let mut param_symbols_it = event_params.iter();
+                            self.add_code("map<string, Attr*> exitArg;");
                             self.newline();
                             // Loop through the ARGUMENTS...
                             for expr_t in &exit_args.exprs_t {
@@ -553,7 +1532,7 @@ impl CppVisitor {
                                 match param_symbols_it.next() {
                                     Some(p) => {
                                         let param_type = match &p.param_type_opt {
-                                            Some(param_type) => param_type.get_type_str(),
+                                            Some(param_type) => self.format_type(param_type),
                                             None => String::from("<?>"),
                                         };
                                         let mut expr = String::new();
@@ -578,7 +1557,7 @@ impl CppVisitor {
         let enter_args_opt = match &transition_statement.target_state_context_t {
             StateContextType::StateRef { state_context_node}
                 => &state_context_node.enter_args_opt,
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..} | StateContextType::StateHistory {..}
                 => &None,
         };
 
@@ -602,7 +1581,7 @@ impl CppVisitor {
                             match param_symbols_it.next() {
                                 Some(p) => {
                                     let param_type = match &p.param_type_opt {
-                                        Some(param_type) => param_type.get_type_str(),
+                                        Some(param_type) => self.format_type(param_type),
                                         None => String::from("<?>"),
                                     };
                                     let mut expr = String::new();
@@ -626,10 +1605,44 @@ impl CppVisitor {
         let target_state_args_opt = match &transition_statement.target_state_context_t {
             StateContextType::StateRef { state_context_node}
                 => &state_context_node.state_ref_args_opt,
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..} | StateContextType::StateHistory {..}
                 => &Option::None,
         };
 //
+        self.generate_state_args_and_vars(&target_state_name, target_state_args_opt);
+        // let exit_args = if has_exit_args {
+        //     "exitArgs"
+        // } else {
+        //     "null"
+        // };
+        if self.generate_state_context {
+            if self.generate_exit_args {
+                self.add_code(&format!("_transition_({},exitArgs,pStateContext);",self.format_target_state_name(&target_state_name)));
+            } else {
+                self.add_code(&format!("_transition_({},pStateContext);",self.format_target_state_name(&target_state_name)));
+            }
+        } else {
+            if self.generate_exit_args {
+                self.add_code(&format!("_transition_({},exitArgs);",self.format_target_state_name(&target_state_name)));
+            } else {
+                self.add_code(&format!("_transition_({});",self.format_target_state_name(&target_state_name)));
+            }
+        }
+
+        // see comment at top of method for purpose of this
+        // self.outdent();
+        // self.newline();
+        // self.add_code("}");
+
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Adds the target state's arguments and initialized
+    // state variables to the new state context.
+
+    fn generate_state_args_and_vars(&mut self, target_state_name:&str, target_state_args_opt:&Option<ExprListNode>) {
+
         if let Some(state_args) = target_state_args_opt {
 //            let mut params_copy = Vec::new();
             if let Some(state_sym) = self.arcanium.get_state(&target_state_name) {
@@ -644,7 +1657,7 @@ impl CppVisitor {
                                 Some(param_symbol_rcref) => {
                                     let param_symbol = param_symbol_rcref.borrow();
                                     let param_type = match &param_symbol.param_type_opt {
-                                        Some(param_type) => param_type.get_type_str(),
+                                        Some(param_type) => self.format_type(param_type),
                                         None => String::from("<?>"),
                                     };
                                     let mut expr = String::new();
@@ -680,7 +1693,7 @@ impl CppVisitor {
                         for var_rcref in state_node.vars_opt.as_ref().unwrap() {
                             let var = var_rcref.borrow();
                             let var_type = match &var.type_opt {
-                                Some(var_type) => var_type.get_type_str(),
+                                Some(var_type) => self.format_type(var_type),
                                 None => String::from("<?>"),
                             };
                             let expr_t = var.initializer_expr_t_opt.as_ref().unwrap();
@@ -697,30 +1710,6 @@ impl CppVisitor {
 //                code = target_state_vars.clone();
             },
         }
-        // let exit_args = if has_exit_args {
-        //     "exitArgs"
-        // } else {
-        //     "null"
-        // };
-        if self.generate_state_context {
-            if self.generate_exit_args {
-                self.add_code(&format!("_transition_({},exitArgs,pStateContext);",self.format_target_state_name(&target_state_name)));
-            } else {
-                self.add_code(&format!("_transition_({},pStateContext);",self.format_target_state_name(&target_state_name)));
-            }
-        } else {
-            if self.generate_exit_args {
-                self.add_code(&format!("_transition_({},exitArgs);",self.format_target_state_name(&target_state_name)));
-            } else {
-                self.add_code(&format!("_transition_({});",self.format_target_state_name(&target_state_name)));
-            }
-        }
-
-        // see comment at top of method for purpose of this
-        // self.outdent();
-        // self.newline();
-        // self.add_code("}");
-
     }
 
     //* --------------------------------------------------------------------- *//
@@ -794,25 +1783,246 @@ impl CppVisitor {
             }
         }
 
-        if self.generate_state_context {
-            self.add_code(&format!("StateContext* pStateContext = _stateStack_pop_();"));
-        } else {
-            self.add_code(&format!("FrameState* pState = _stateStack_pop_();"));
+        if let StateContextType::StateStackPop {..} = &transition_statement.target_state_context_t {
+            self.generate_state_stack_underflow_check();
+        }
+        if let StateContextType::StateHistory {state_ref_node, is_deep} = &transition_statement.target_state_context_t {
+            let history_map = if *is_deep {"_deepHistory_"} else {"_shallowHistory_"};
+            self.add_code(&format!("FrameState pState = {0}.count(\"{1}\") ? {0}[\"{1}\"] : {2};", history_map, state_ref_node.name, self.format_target_state_name(&state_ref_node.name)));
+            if self.generate_state_context {
+                self.newline();
+                self.add_code("pStateContext = new StateContext(pState);");
+            }
+        } else if self.generate_state_context {
+            self.add_code(&format!("pStateContext = _stateStack_pop_();"));
+        } else {
+            self.add_code(&format!("FrameState* pState = _stateStack_pop_();"));
+        }
+ //       self.add_code(&format!("StateContext* pStateContext = _stateStack_pop();"));
+        self.newline();
+        if let StateContextType::StateStackPop { enter_args_opt:Some(enter_args) } = &transition_statement.target_state_context_t {
+            self.generate_state_stack_pop_enter_args(enter_args);
+        }
+        if self.generate_exit_args {
+            if self.generate_state_context {
+                self.add_code(&format!("_transition_(pStateContext->state,exitArgs,pStateContext);"));
+            } else {
+                self.add_code(&format!("_transition_(pState,exitArgs);"));
+            }
+        } else {
+            if self.generate_state_context {
+                self.add_code(&format!("_transition_(pStateContext->state,pStateContext);"));
+            } else {
+                self.add_code(&format!("_transition_(pState);"));
+            }
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Refuses to push past #![state_stack_max], tells the current state
+    // with a stateStackOverflow event and ends the handler so the
+    // transition after the push does not happen.
+
+    fn generate_state_stack_overflow_check(&mut self) {
+        let max = match self.state_stack_max_opt {
+            Some(max) => max,
+            None => return,
+        };
+        self.add_code(&format!("if (_stateStack_.size() >= {}) {{", max));
+        self.indent();
+        self.newline();
+        let message = self.symbol_config.state_stack_overflow_msg_symbol.clone();
+        self.generate_state_stack_error_event(&message);
+        self.newline();
+        self.add_code("return;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Applies the #![state_stack_underflow] policy when $$[-] finds the
+    // state stack empty.
+
+    fn generate_state_stack_underflow_check(&mut self) {
+        let underflow = match &self.state_stack_underflow_opt {
+            Some(underflow) => underflow.clone(),
+            None => return,
+        };
+        self.add_code("if (_stateStack_.size() == 0) {");
+        self.indent();
+        self.newline();
+        match underflow {
+            StateStackUnderflowPolicy::Error => {
+                let message = self.symbol_config.state_stack_underflow_msg_symbol.clone();
+                self.generate_state_stack_error_event(&message);
+                self.newline();
+            },
+            StateStackUnderflowPolicy::Stay => {},
+            StateStackUnderflowPolicy::State {state_name} => {
+                if self.generate_state_context {
+                    self.add_code(&format!("pStateContext = new StateContext(&{}::_s{}_);", self.system_name, &state_name));
+                    self.newline();
+                    self.generate_state_args_and_vars(&state_name, &None);
+                    if self.generate_exit_args {
+                        self.add_code(&format!("_transition_({},exitArgs,pStateContext);", self.format_target_state_name(&state_name)));
+                    } else {
+                        self.add_code(&format!("_transition_({},pStateContext);", self.format_target_state_name(&state_name)));
+                    }
+                } else if self.generate_exit_args {
+                    self.add_code(&format!("_transition_({},exitArgs);", self.format_target_state_name(&state_name)));
+                } else {
+                    self.add_code(&format!("_transition_({});", self.format_target_state_name(&state_name)));
+                }
+                self.newline();
+            },
+        }
+        self.add_code("return;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Sends a state stack error event to the current state.
+
+    fn generate_state_stack_error_event(&mut self, message:&str) {
+        self.add_code(&format!("FrameEvent stateStackEvent(\"{}\",nullptr);", message));
+        self.newline();
+        if self.generate_event_queue {
+            self.add_code("_dispatch_(stateStackEvent);");
+        } else {
+            self.add_code("(this->*_state_)(stateStackEvent);");
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Adds the enter arguments of a state stack pop to the popped state
+    // context. Every pushed state takes the same enter parameters so the
+    // first one names them.
+
+    fn generate_state_stack_pop_enter_args(&mut self, enter_args:&ExprListNode) {
+        let mut msg = match self.pushed_state_names.first() {
+            Some(pushed_state_name) => pushed_state_name.clone(),
+            None => return,
+        };
+        msg.push_str(":");
+        msg.push_str(&self.symbol_config.enter_msg_symbol);
+
+        if let Some(event_sym) = self.arcanium.get_event(&msg,&self.current_state_name_opt) {
+            if let Some(event_params) = &event_sym.borrow().params_opt {
+                for (p, expr_t) in event_params.iter().zip(enter_args.exprs_t.iter()) {
+                    let param_type = match &p.param_type_opt {
+                        Some(param_type) => self.format_type(param_type),
+                        None => String::from("<?>"),
+                    };
+                    let mut expr = String::new();
+                    expr_t.accept_to_string(self,&mut expr);
+                    self.add_code(&format!("pStateContext->addEnterArg(string(\"{}\"),string(\"{}\"),true,new {}({}));",p.name,param_type,param_type,expr));
+                    self.newline();
+                }
+            }
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Element type for a collection literal whose elements couldn't be
+    // inferred.
+
+    fn format_element_type(&self, element_type_opt:&Option<FrameType>) -> String {
+        match element_type_opt {
+            Some(element_type) => self.format_frame_type(element_type),
+            None => String::from("void*"),
         }
- //       self.add_code(&format!("StateContext* pStateContext = _stateStack_pop();"));
-        self.newline();
-        if self.generate_exit_args {
-            if self.generate_state_context {
-                self.add_code(&format!("_transition_(pStateContext->state,exitArgs,pStateContext);"));
-            } else {
-                self.add_code(&format!("_transition_(pState,exitArgs);"));
-            }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Maps portable Frame types to C++. Other types are emitted as written.
+    // Frame references are only kept on parameters, see
+    // format_parameter_type().
+
+    fn format_type(&self, type_node:&TypeNode) -> String {
+        match type_node.get_frame_type_opt() {
+            Some(frame_type) => self.format_frame_type(frame_type),
+            None => type_node.get_type_str(),
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Frame references are passed as const references.
+
+    fn format_parameter_type(&self, type_node:&TypeNode) -> String {
+        let param_type = self.format_type(type_node);
+        if type_node.is_reference() && type_node.get_frame_type_opt().is_some() {
+            format!("const {}&", param_type)
         } else {
-            if self.generate_state_context {
-                self.add_code(&format!("_transition_(pStateContext->state,pStateContext);"));
-            } else {
-                self.add_code(&format!("_transition_(pState);"));
-            }
+            param_type
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // A generic system is a class template over the system's type parameters.
+
+    fn format_template_header(system_node:&SystemNode) -> String {
+        if system_node.type_params.is_empty() {
+            return String::new();
+        }
+        let type_params:Vec<String> = system_node.type_params.iter()
+            .map(|type_param| format!("typename {}", type_param)).collect();
+        format!("template<{}>", type_params.join(", "))
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn format_frame_type(&self, frame_type:&FrameType) -> String {
+        match frame_type {
+            FrameType::Int => String::from("int"),
+            FrameType::Float => String::from("double"),
+            FrameType::Bool => String::from("bool"),
+            FrameType::String => String::from("std::string"),
+            FrameType::List {element_type} => format!("std::vector<{}>", self.format_frame_type(element_type)),
+            FrameType::Map {key_type, value_type} => format!("std::map<{}, {}>", self.format_frame_type(key_type), self.format_frame_type(value_type)),
+            FrameType::TypeParam {name} => name.clone(),
+        }
+    }
+}
+
+//* --------------------------------------------------------------------- *//
+
+impl OperatorPrecedence for CppVisitor {
+
+    //* --------------------------------------------------------------------- *//
+
+    // Binding strength of each operator in C++, higher binds tighter.
+    // Xor is lowered to '!=' on bools ('^' would yield an int).
+
+    fn operator_precedence(&self, operator_type:&OperatorType) -> usize {
+        match operator_type {
+            OperatorType::LogicalOr => 1,
+            OperatorType::LogicalAnd => 2,
+            OperatorType::EqualEqual
+            | OperatorType::NotEqual
+            | OperatorType::LogicalXor => 3,
+            OperatorType::Greater
+            | OperatorType::GreaterEqual
+            | OperatorType::Less
+            | OperatorType::LessEqual => 4,
+            OperatorType::Plus
+            | OperatorType::Minus => 5,
+            OperatorType::Multiply
+            | OperatorType::Divide => 6,
+            OperatorType::Not
+            | OperatorType::Negated => 7,
         }
     }
 }
@@ -830,6 +2040,11 @@ impl AstVisitor for CppVisitor {
         self.add_code("// get include files at https://github.com/frame-lang/frame-ancillary-files");
         self.newline();
         self.newline();
+        let template_header = Self::format_template_header(system_node);
+        if !template_header.is_empty() {
+            self.add_code(&template_header);
+            self.newline();
+        }
         self.add_code(&format!("class {} {{", system_node.name));
         self.newline();
         self.indent();
@@ -841,28 +2056,86 @@ impl AstVisitor for CppVisitor {
         self.newline();
         self.newline();
 
-        let mut has_states = false;
+        self.generate_timers = system_node.has_timeouts();
+        self.generate_event_queue = system_node.is_run_to_completion();
+        self.generate_deferred_events = system_node.has_deferred_events();
+        self.generate_abstract_actions = system_node.has_external_actions();
+        self.generate_history = system_node.has_history();
+        self.generate_regions = system_node.has_regions();
+        self.generate_final_states = system_node.get_final_state_names().len() > 0;
+        self.generate_completions = system_node.has_completions();
+        self.pushed_state_names = system_node.get_pushed_state_names();
+        self.domain_param_names = system_node.get_domain_param_names();
+        self.generate_start_enter = system_node.start_state_enter_params_opt.is_some();
+        self.state_stack_max_opt = system_node.get_state_stack_max();
+        self.state_stack_underflow_opt = system_node.get_state_stack_underflow();
+        if self.generate_timers {
+            self.generate_scheduler_interface();
+        }
 
         // First state name needed for machinery.
         // Don't generate if there isn't at least one state.
         match (&system_node).get_first_state() {
             Some(x) => {
                 self.first_state_name = x.borrow().name.clone();
-                has_states = true;
+                self.has_states = true;
             },
             None => {},
         }
 
+        let type_args = if system_node.type_params.is_empty() { String::new() } else { format!("<{}>", system_node.type_params.join(", ")) };
+        if !template_header.is_empty() {
+            self.subclass_code.push(template_header);
+        }
+        self.subclass_code.push(format!("class {}Controller : public {}{} {{",system_node.name,system_node.name,type_args));
+        self.subclass_code.push(String::from("public:"));
+
         // TODO: initialize start state context.
         if self.has_states {
-            self.add_code(&format!("{}() {{", system_node.name));
+            let mut constructor_params:Vec<String> = system_node.get_system_params().iter()
+                .map(|param| {
+                    let param_type = match &param.param_type_opt {
+                        Some(param_type) => self.format_parameter_type(param_type),
+                        None => String::from("<?>"),
+                    };
+                    format!("{} {}", param_type, param.param_name)
+                })
+                .collect();
+            if self.generate_timers {
+                constructor_params.push(String::from("FrameScheduler* scheduler"));
+            }
+            if !constructor_params.is_empty() {
+                let mut base_args:Vec<String> = system_node.get_system_params().iter().map(|param| param.param_name.clone()).collect();
+                if self.generate_timers {
+                    base_args.push(String::from("scheduler"));
+                }
+                self.subclass_code.push(format!("    {}Controller({}) : {}{}({}) {{}}",system_node.name, constructor_params.join(", "), system_node.name, type_args, base_args.join(", ")));
+            }
+            self.add_code(&format!("{}({}) {{", system_node.name, constructor_params.join(", ")));
             self.indent();
             self.newline();
             self.add_code(&format!("_state_ = &{}::_s{}_;", system_node.name, self.first_state_name));
+            if self.generate_regions {
+                let region_states:Vec<String> = system_node.get_region_initial_state_names().iter()
+                    .map(|state_name| format!("&{}::_s{}_", system_node.name, state_name)).collect();
+                self.newline();
+                self.add_code(&format!("_regions_ = {{{}}};", region_states.join(", ")));
+            }
+            if self.generate_timers {
+                self.newline();
+                self.add_code(&format!("_scheduler_ = scheduler;"));
+            }
             if self.generate_state_context {
                 self.newline();
                 self.add_code(&format!("_pStateContext_ = new StateContext(_state_);"));
-                if has_states {
+                if let Some(params) = &system_node.start_state_state_params_opt {
+                    for param in params {
+                        let param_type = self.format_serialized_type(&param.param_type_opt);
+                        self.newline();
+                        self.add_code(&format!("_pStateContext_->addStateArg(string(\"{}\"),string(\"{}\"),true,new {}({}));", param.param_name, param_type, param_type, param.param_name));
+                    }
+                }
+                if self.has_states {
                     if let Some(state_symbol_rcref) = self.arcanium.get_state(&self.first_state_name) {
                         self.newline();
                         let state_symbol = state_symbol_rcref.borrow();
@@ -887,6 +2160,30 @@ impl AstVisitor for CppVisitor {
                 }
             }
 
+            // The start state is never entered so start its timer here.
+            if let Some(first_state_node_rcref) = system_node.get_first_state() {
+                let first_state_node = first_state_node_rcref.borrow();
+                if let Some(timeout_node) = &first_state_node.timeout_opt {
+                    self.current_state_name_opt = Some(first_state_node.name.clone());
+                    self.generate_timer_start(timeout_node);
+                    self.current_state_name_opt = None;
+                }
+            }
+
+            for var_name in &self.domain_param_names.clone() {
+                self.newline();
+                self.add_code(&format!("this->{} = {};", var_name, var_name));
+            }
+
+            // The start state is entered with these on the first event.
+            if let Some(params) = &system_node.start_state_enter_params_opt {
+                for param in params {
+                    let param_type = self.format_serialized_type(&param.param_type_opt);
+                    self.newline();
+                    self.add_code(&format!("_pStateContext_->addEnterArg(string(\"{}\"),string(\"{}\"),true,new {}({}));", param.param_name, param_type, param_type, param.param_name));
+                }
+            }
+
             self.outdent();
             self.newline();
             self.add_code(&format!("}}"));
@@ -897,6 +2194,10 @@ impl AstVisitor for CppVisitor {
             interface_block_node.accept(self);
         }
 
+        if let Some(operations_block_node) = &system_node.operations_block_node_opt {
+            operations_block_node.accept(self);
+        }
+
         if let Some(machine_block_node) = &system_node.machine_block_node_opt {
             machine_block_node.accept(self);
         }
@@ -909,6 +2210,8 @@ impl AstVisitor for CppVisitor {
             domain_block_node.accept(self);
         }
 
+        self.subclass_code.push(String::from("};"));
+
         if self.has_states {
             self.generate_machinery(system_node);
         }
@@ -920,7 +2223,7 @@ impl AstVisitor for CppVisitor {
         self.outdent();
  //       self.generate_comment(system_node.line);
         self.newline();
-        self.add_code("}");
+        self.add_code("};");
         self.newline();
 
 
@@ -963,7 +2266,7 @@ impl AstVisitor for CppVisitor {
 
         self.newline();
         let return_type = match &interface_method_node.return_type_opt {
-            Some(ret) => ret.get_type_str(),
+            Some(ret) => self.format_type(ret),
             None => "void".to_string(),
         };
 
@@ -989,6 +2292,11 @@ impl AstVisitor for CppVisitor {
 
         self.add_code(") {");
         self.indent();
+        if self.generate_event_queue {
+            if let Some(params) = &interface_method_node.params {
+                self.generate_queued_interface_call(interface_method_node, params);
+            }
+        }
         let params_param_code;
         if interface_method_node.params.is_some() {
             params_param_code = String::from("&params");
@@ -1010,15 +2318,41 @@ impl AstVisitor for CppVisitor {
             params_param_code = String::from("nullptr");
         }
 
+        if *method_name_or_alias == self.symbol_config.restore_msg_symbol {
+            if let Some(params) = &interface_method_node.params {
+                self.newline();
+                self.add_code(&format!("_deserialize__do({});", params[0].param_name));
+            }
+        }
+        if self.generate_start_enter {
+            self.newline();
+            self.add_code("if (!_started_) _start_();");
+        }
         self.newline();
         self.add_code(&format!("FrameEvent e(string(\"{}\"),{});", method_name_or_alias,params_param_code));
+        if let Some(default_return_expr_t) = &interface_method_node.default_return_expr_opt {
+            let mut default_return = String::new();
+            default_return_expr_t.accept_to_string(self, &mut default_return);
+            self.newline();
+            self.add_code(&format!("e._return = (void*) new {}({});", return_type, default_return));
+        }
         self.newline();
-        self.add_code(&format!("(this->*_state_)(e);"));
+        if self.generate_event_queue {
+            self.add_code(&format!("_dispatch_(e);"));
+        } else if self.generate_regions {
+            self.add_code(&format!("_dispatchRegions_(e);"));
+        } else {
+            self.add_code(&format!("(this->*_state_)(e);"));
+        }
 
         match &interface_method_node.return_type_opt {
+            Some(_) if *method_name_or_alias == self.symbol_config.save_msg_symbol => {
+                self.newline();
+                self.add_code("return _serialize__do();");
+            },
             Some(return_type) => {
                 self.newline();
-                self.add_code(&format!("return ({}) e.ret;",return_type.get_type_str()));
+                self.add_code(&format!("return ({}) e.ret;",self.format_type(return_type)));
             },
             None => {}
         }
@@ -1121,6 +2455,20 @@ impl AstVisitor for CppVisitor {
         //     None => panic!("TODO"),
         // };
 
+        if let Some(deferred_events) = &state_node.deferred_events_opt {
+            self.generate_deferred_event_checks(deferred_events);
+        }
+
+        if let Some(timeout_node) = &state_node.timeout_opt {
+            self.newline();
+            self.add_code(&format!("if (e._message == \"{}\") {{", self.symbol_config.enter_msg_symbol));
+            self.indent();
+            self.generate_timer_start(timeout_node);
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
+
         if let Some(calls) = &state_node.calls_opt {
             for call in calls {
                 self.newline();
@@ -1131,6 +2479,10 @@ impl AstVisitor for CppVisitor {
 
         self.first_event_handler = true; // context for formatting
 
+        if let Some(timeout_node) = &state_node.timeout_opt {
+            timeout_node.accept(self);
+        }
+
         if state_node.evt_handlers_rcref.len() > 0 {
             for evt_handler_node in &state_node.evt_handlers_rcref {
                 evt_handler_node.as_ref().borrow().accept(self);
@@ -1155,7 +2507,10 @@ impl AstVisitor for CppVisitor {
     //* --------------------------------------------------------------------- *//
 
     fn visit_event_handler_node<'b>(& mut self, evt_handler_node: &EventHandlerNode) -> AstVisitorReturnType {
-        self.current_event_ret_type = evt_handler_node.get_event_ret_type();
+        self.current_event_ret_type = match evt_handler_node.get_event_ret_type_node_opt() {
+            Some(ret_type) => self.format_type(&ret_type),
+            None => String::new(),
+        };
         self.newline();
         self.generate_comment(evt_handler_node.line);
 //        let mut generate_final_close_paren = true;
@@ -1179,7 +2534,7 @@ impl AstVisitor for CppVisitor {
         self.indent();
         if evt_handler_node.event_handler_has_transition && self.generate_state_context {
             self.newline();
-            self.add_code(&format!("auto pStateContext = null;"));
+            self.add_code(&format!("StateContext* pStateContext = nullptr;"));
         }
 
         match &evt_handler_node.msg_t {
@@ -1393,7 +2748,10 @@ impl AstVisitor for CppVisitor {
         match &transition_statement.target_state_context_t {
             StateContextType::StateRef {..}
                 => self.generate_state_ref_transition(transition_statement),
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..}
+                => self.generate_state_stack_pop_transition(transition_statement),
+            // a history target is restored like a popped state
+            StateContextType::StateHistory {..}
                 => self.generate_state_stack_pop_transition(transition_statement),
         };
 
@@ -1416,7 +2774,7 @@ impl AstVisitor for CppVisitor {
         match &change_state_stmt_node.state_context_t {
             StateContextType::StateRef {..}
                 => self.generate_state_ref_change_state(change_state_stmt_node),
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..} | StateContextType::StateHistory {..}
                 => panic!("TODO - not implemented"),
         };
 
@@ -1446,6 +2804,33 @@ impl AstVisitor for CppVisitor {
 
     //* --------------------------------------------------------------------- *//
 
+    fn visit_timeout_node(&mut self, timeout_node: &TimeoutNode) -> AstVisitorReturnType {
+        self.newline();
+        self.generate_comment(timeout_node.line);
+        if self.first_event_handler {
+            self.add_code(&format!("if (e._message == \"{}\") {{", self.symbol_config.timeout_msg_symbol));
+        } else {
+            self.add_code(&format!("else if (e._message == \"{}\") {{", self.symbol_config.timeout_msg_symbol));
+        }
+        self.indent();
+        if self.generate_state_context {
+            self.newline();
+            self.add_code(&format!("StateContext* pStateContext = nullptr;"));
+        }
+        timeout_node.transition_statement_node.accept(self);
+        self.newline();
+        self.add_code("return;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+
+        self.first_event_handler = false;
+
+        AstVisitorReturnType::TimeoutNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_test_statement_node(&mut self, test_stmt_node: &TestStatementNode) -> AstVisitorReturnType {
 
         match &test_stmt_node.test_t {
@@ -1948,6 +3333,84 @@ impl AstVisitor for CppVisitor {
 
     //* --------------------------------------------------------------------- *//
 
+    fn visit_list_literal_node(&mut self, list_literal_node: &ListLiteralNode) -> AstVisitorReturnType {
+
+        let mut output = String::new();
+        self.visit_list_literal_node_to_string(list_literal_node, &mut output);
+        self.add_code(&output);
+
+        AstVisitorReturnType::ListLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_list_literal_node_to_string(&mut self, list_literal_node: &ListLiteralNode, output:&mut String) -> AstVisitorReturnType {
+
+        // Without a known element type a bare initializer list is emitted
+        // and the target variable's type is relied upon.
+        match &list_literal_node.type_opt {
+            Some(type_node) => output.push_str(&self.format_type(type_node)),
+            None => {
+                if let Some(element_type) = &list_literal_node.element_type_opt {
+                    output.push_str(&format!("std::vector<{}>", self.format_frame_type(element_type)));
+                }
+            },
+        }
+        output.push_str("{");
+        let mut separator = "";
+        for expr_t in &list_literal_node.exprs_t {
+            output.push_str(separator);
+            expr_t.accept_to_string(self, output);
+            separator = ",";
+        }
+        output.push_str("}");
+
+        AstVisitorReturnType::ListLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_map_literal_node(&mut self, map_literal_node: &MapLiteralNode) -> AstVisitorReturnType {
+
+        let mut output = String::new();
+        self.visit_map_literal_node_to_string(map_literal_node, &mut output);
+        self.add_code(&output);
+
+        AstVisitorReturnType::MapLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_map_literal_node_to_string(&mut self, map_literal_node: &MapLiteralNode, output:&mut String) -> AstVisitorReturnType {
+
+        match &map_literal_node.type_opt {
+            Some(type_node) => output.push_str(&self.format_type(type_node)),
+            None => {
+                if map_literal_node.key_type_opt.is_some() && map_literal_node.value_type_opt.is_some() {
+                    output.push_str(&format!("std::map<{}, {}>"
+                                             , self.format_element_type(&map_literal_node.key_type_opt)
+                                             , self.format_element_type(&map_literal_node.value_type_opt)));
+                }
+            },
+        }
+        output.push_str("{");
+        let mut separator = "";
+        for (key_expr_t, value_expr_t) in &map_literal_node.entries {
+            output.push_str(separator);
+            output.push_str("{");
+            key_expr_t.accept_to_string(self, output);
+            output.push_str(",");
+            value_expr_t.accept_to_string(self, output);
+            output.push_str("}");
+            separator = ",";
+        }
+        output.push_str("}");
+
+        AstVisitorReturnType::MapLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_expression_list_node(&mut self, expr_list: &ExprListNode) -> AstVisitorReturnType {
 
         let mut separator = "";
@@ -1970,14 +3433,14 @@ impl AstVisitor for CppVisitor {
 //        self.add_code(&format!("{}(e);\n",dispatch_node.target_state_ref.name));
 
         let mut separator = "";
-        output.push_str(&format!("{{"));
+        output.push_str(&format!("("));
         for expr in &expr_list.exprs_t {
 
             output.push_str(&format!("{}",separator));
             expr.accept_to_string(self, output);
             separator = ",";
         }
-        output.push_str(&format!("}}"));
+        output.push_str(&format!(")"));
 
         AstVisitorReturnType::ParentheticalExpressionNode {}
     }
@@ -2085,6 +3548,7 @@ impl AstVisitor for CppVisitor {
         match state_stack_op_statement_node.state_stack_operation_node.operation_t {
             StateStackOperationType::Push => {
                 self.newline();
+                self.generate_state_stack_overflow_check();
                 if self.generate_state_context {
                     self.add_code(&format!("_stateStack_push_(_state_context_);"));
                 } else {
@@ -2103,6 +3567,26 @@ impl AstVisitor for CppVisitor {
     }
     //* --------------------------------------------------------------------- *//
 
+    fn visit_raise_statement_node(&mut self, raise_statement_node:&RaiseStatementNode) -> AstVisitorReturnType {
+        self.newline();
+        self.add_code(&format!("{}", raise_statement_node.interface_method_name));
+        raise_statement_node.call_expr_list_node.accept(self);
+        self.add_code(";");
+
+        AstVisitorReturnType::RaiseStatementNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_forward_statement_node(&mut self, forward_statement_node:&ForwardStatementNode) -> AstVisitorReturnType {
+        self.newline();
+        self.add_code(&format!("_s{}_(e);", forward_statement_node.target_state_ref.name));
+
+        AstVisitorReturnType::ForwardStatementNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_state_context_node(&mut self, _state_context_node:&StateContextNode) -> AstVisitorReturnType {
 
         // TODO
@@ -2149,20 +3633,39 @@ impl AstVisitor for CppVisitor {
 
         self.newline();
         let action_ret_type:String = match &action_decl_node.type_opt {
-            Some(ret_type) => ret_type.get_type_str(),
+            Some(ret_type) => self.format_type(ret_type),
             None => String::from("void"),
         };
 
         let action_name = self.format_action_name(&action_decl_node.name);
+        if action_decl_node.has_frame_body() {
+            self.add_code(&format!("virtual {} {}(",action_ret_type, action_name));
+            if let Some(params) = &action_decl_node.params {
+                self.format_parameter_list(params);
+            }
+            self.add_code(") {");
+            self.indent();
+            self.generate_frame_body(&action_decl_node.statements, action_decl_node.get_return_expr_opt());
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+            self.newline();
+            return AstVisitorReturnType::ActionDeclNode {};
+        }
+
+        let mut subclass_code = String::from("    ");
         self.add_code(&format!("virtual {} {}(",action_ret_type, action_name));
+        subclass_code.push_str(&format!("{} {}(",action_ret_type, action_name));
 
         match &action_decl_node.params {
             Some (params)
-                =>  self.format_parameter_list(params).clone(),
+                =>  self.format_actions_parameter_list(params,&mut subclass_code),
             None => {},
         }
+        subclass_code.push_str(") override {}");
+        self.subclass_code.push(subclass_code);
 
-        self.add_code(&format!(") {{}}"));
+        self.add_code(") = 0;");
 
         AstVisitorReturnType::ActionDeclNode {}
     }
@@ -2175,6 +3678,55 @@ impl AstVisitor for CppVisitor {
 
     //* --------------------------------------------------------------------- *//
 
+    fn visit_operations_block_node(&mut self, operations_block_node: &OperationsBlockNode) -> AstVisitorReturnType {
+        self.newline();
+        self.add_code("//===================== Operations Block ===================//");
+        self.newline();
+
+        for operation_node_rcref in &operations_block_node.operations {
+            operation_node_rcref.borrow().accept(self);
+        }
+
+        AstVisitorReturnType::OperationsBlockNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_operation_node(&mut self, operation_node: &OperationNode) -> AstVisitorReturnType {
+
+        self.newline();
+        let return_type = match &operation_node.type_opt {
+            Some(ret) => self.format_type(ret),
+            None => "void".to_string(),
+        };
+
+        self.add_code(&format!("{} {}(",return_type, operation_node.name));
+
+        match &operation_node.params {
+            Some (params)
+            =>  self.format_parameter_list(params),
+            None => {},
+        }
+
+        self.add_code(") {");
+        self.indent();
+        match &operation_node.code_opt {
+            Some(code) => {
+                self.newline();
+                self.add_code(code);
+            },
+            None => self.generate_frame_body(&operation_node.statements, operation_node.get_return_expr_opt()),
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+
+        AstVisitorReturnType::OperationNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_domain_variable_decl_node(&mut self, variable_decl_node: &VariableDeclNode) -> AstVisitorReturnType {
 
         self.visit_variable_decl_node(variable_decl_node);
@@ -2187,12 +3739,17 @@ impl AstVisitor for CppVisitor {
     fn visit_variable_decl_node(&mut self, variable_decl_node: &VariableDeclNode) -> AstVisitorReturnType {
 
         let var_type = match &variable_decl_node.type_opt {
-            Some(x) => x.get_type_str(),
+            Some(x) => self.format_type(x),
             None => String::from("<?>"),
         };
         let var_name =  &variable_decl_node.name;
         let var_init_expr = &variable_decl_node.initializer_expr_t_opt.as_ref().unwrap();
         self.newline();
+        // Domain parameters are assigned in the constructor.
+        if matches!(variable_decl_node.identifier_decl_scope, IdentifierDeclScope::DomainBlock) && self.domain_param_names.contains(var_name) {
+            self.add_code( &format!("{} {};",var_type,var_name));
+            return AstVisitorReturnType::VariableDeclNode {};
+        }
         let mut code = String::new();
         var_init_expr.accept_to_string(self, &mut code);
         self.add_code( &format!("{} {} = {};",var_type,var_name, code));
@@ -2274,10 +3831,9 @@ impl AstVisitor for CppVisitor {
 
     fn visit_unary_expr_node(&mut self, unary_expr_node: &UnaryExprNode) -> AstVisitorReturnType {
 
-        // TODO
-        //       self.generate_comment(assignment_expr_node.line);
-        unary_expr_node.operator.accept(self);
-        unary_expr_node.right_rcref.borrow().accept(self);
+        let mut output = String::new();
+        self.visit_unary_expr_node_to_string(unary_expr_node, &mut output);
+        self.add_code(&output);
 
         AstVisitorReturnType::UnaryExprNode {}
     }
@@ -2289,7 +3845,7 @@ impl AstVisitor for CppVisitor {
         // TODO
         //       self.generate_comment(assignment_expr_node.line);
         unary_expr_node.operator.accept_to_string(self, output);
-        unary_expr_node.right_rcref.borrow().accept_to_string(self,output);
+        self.visit_operand_to_string(&unary_expr_node.operator, &unary_expr_node.right_rcref.borrow(), false, output);
 
         AstVisitorReturnType::UnaryExprNode {}
     }
@@ -2298,23 +3854,9 @@ impl AstVisitor for CppVisitor {
 
     fn visit_binary_expr_node(&mut self, binary_expr_node: &BinaryExprNode) -> AstVisitorReturnType {
 
-        // TODO
-        //       self.generate_comment(assignment_expr_node.line);
-        if binary_expr_node.operator == OperatorType::LogicalXor {
-            self.add_code("((");
-            binary_expr_node.left_rcref.borrow().accept(self);
-            self.add_code(") && !(");
-            binary_expr_node.right_rcref.borrow().accept(self);
-            self.add_code(")) || (!(");
-            binary_expr_node.left_rcref.borrow().accept(self);
-            self.add_code(") && (");
-            binary_expr_node.right_rcref.borrow().accept(self);
-            self.add_code("))");
-        } else {
-            binary_expr_node.left_rcref.borrow().accept(self);
-            binary_expr_node.operator.accept(self);
-            binary_expr_node.right_rcref.borrow().accept(self);
-        }
+        let mut output = String::new();
+        self.visit_binary_expr_node_to_string(binary_expr_node, &mut output);
+        self.add_code(&output);
 
         AstVisitorReturnType::BinaryExprNode {}
     }
@@ -2323,26 +3865,41 @@ impl AstVisitor for CppVisitor {
 
     fn visit_binary_expr_node_to_string(&mut self, binary_expr_node: &BinaryExprNode, output:&mut String) -> AstVisitorReturnType {
 
-        if binary_expr_node.operator == OperatorType::LogicalXor {
-            output.push_str("((");
-            binary_expr_node.left_rcref.borrow().accept_to_string(self,output);
-            output.push_str(") && !(");
-            binary_expr_node.right_rcref.borrow().accept_to_string(self,output);
-            output.push_str(")) || (!(");
-            binary_expr_node.left_rcref.borrow().accept_to_string(self,output);
-            output.push_str(") && (");
-            binary_expr_node.right_rcref.borrow().accept_to_string(self,output);
-            output.push_str("))");
+        let operator_type = &binary_expr_node.operator;
+        self.visit_operand_to_string(operator_type, &binary_expr_node.left_rcref.borrow(), true, output);
+        operator_type.accept_to_string(self, output);
+        self.visit_operand_to_string(operator_type, &binary_expr_node.right_rcref.borrow(), false, output);
 
-        } else {
-            binary_expr_node.left_rcref.borrow().accept_to_string(self,output);
-            binary_expr_node.operator.accept_to_string(self, output);
-            binary_expr_node.right_rcref.borrow().accept_to_string(self,output);
-        }
         AstVisitorReturnType::BinaryExprNode {}
     }
 
 
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_conditional_expr_node(&mut self, conditional_expr_node: &ConditionalExprNode) -> AstVisitorReturnType {
+
+        conditional_expr_node.condition_rcref.borrow().accept(self);
+        self.add_code(" ? ");
+        conditional_expr_node.true_expr_rcref.borrow().accept(self);
+        self.add_code(" : ");
+        conditional_expr_node.false_expr_rcref.borrow().accept(self);
+
+        AstVisitorReturnType::ConditionalExprNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_conditional_expr_node_to_string(&mut self, conditional_expr_node: &ConditionalExprNode, output:&mut String) -> AstVisitorReturnType {
+
+        conditional_expr_node.condition_rcref.borrow().accept_to_string(self, output);
+        output.push_str(" ? ");
+        conditional_expr_node.true_expr_rcref.borrow().accept_to_string(self, output);
+        output.push_str(" : ");
+        conditional_expr_node.false_expr_rcref.borrow().accept_to_string(self, output);
+
+        AstVisitorReturnType::ConditionalExprNode {}
+    }
+
     //* --------------------------------------------------------------------- *//
 
     fn visit_operator_type(&mut self, operator_type: &OperatorType) -> AstVisitorReturnType {
@@ -2362,7 +3919,7 @@ impl AstVisitor for CppVisitor {
             OperatorType::NotEqual => self.add_code(" != "),
             OperatorType::LogicalAnd => self.add_code(" && "),
             OperatorType::LogicalOr => self.add_code(" || "),
-            OperatorType::LogicalXor => self.add_code(""),
+            OperatorType::LogicalXor => self.add_code(" != "),
         }
 
         AstVisitorReturnType::OperatorType {}
@@ -2387,7 +3944,7 @@ impl AstVisitor for CppVisitor {
             OperatorType::NotEqual => output.push_str(" != "),
             OperatorType::LogicalAnd => output.push_str(" && "),
             OperatorType::LogicalOr => output.push_str(" || "),
-            OperatorType::LogicalXor => output.push_str(""),
+            OperatorType::LogicalXor => output.push_str(" != "),
         }
 
         AstVisitorReturnType::OperatorType {}
@@ -19,8 +19,6 @@ pub struct CsVisitorForBob {
     first_event_handler:bool,
     system_name:String,
     first_state_name:String,
-    serialize:Vec<String>,
-    deserialize:Vec<String>,
     subclass_code:Vec<String>,
     warnings:Vec<String>,
     has_states:bool,
@@ -28,9 +26,21 @@ pub struct CsVisitorForBob {
     visiting_call_chain_literal_variable:bool,
     generate_exit_args:bool,
     generate_state_context:bool,
-    // generate_state_stack:bool,
+    generate_state_stack:bool,
     // generate_change_state:bool,
     // generate_transition_state:bool,
+    generate_timers:bool,
+    generate_event_queue:bool,
+    generate_deferred_events:bool,
+    generate_history:bool,
+    generate_regions:bool,
+    generate_final_states:bool,
+    generate_completions:bool,
+    pushed_state_names:Vec<String>,
+    domain_param_names:Vec<String>,
+    generate_start_enter:bool,
+    state_stack_max_opt:Option<usize>,
+    state_stack_underflow_opt:Option<StateStackUnderflowPolicy>,
 }
 
 impl CsVisitorForBob {
@@ -40,7 +50,7 @@ impl CsVisitorForBob {
     pub fn new(   arcanium:Arcanum
                 , generate_exit_args:bool
                 , generate_state_context:bool
-                , _generate_state_stack:bool
+                , generate_state_stack:bool
                 , _generate_change_state:bool
                 , _generate_transition_state:bool
                 , compiler_version:&str
@@ -66,8 +76,6 @@ impl CsVisitorForBob {
             first_event_handler:true,
             system_name:String::new(),
             first_state_name:String::new(),
-            serialize:Vec::new(),
-            deserialize:Vec::new(),
             has_states:false,
             errors:Vec::new(),
             subclass_code:Vec::new(),
@@ -75,9 +83,21 @@ impl CsVisitorForBob {
             visiting_call_chain_literal_variable:false,
             generate_exit_args,
             generate_state_context,
-            // generate_state_stack,
+            generate_state_stack,
             // generate_change_state,
             // generate_transition_state,
+            generate_timers:false,
+            generate_event_queue:false,
+            generate_deferred_events:false,
+            generate_history:false,
+            generate_regions:false,
+            generate_final_states:false,
+            generate_completions:false,
+            pushed_state_names:Vec::new(),
+            domain_param_names:Vec::new(),
+            generate_start_enter:false,
+            state_stack_max_opt:None,
+            state_stack_underflow_opt:None,
         }
     }
 
@@ -101,30 +121,30 @@ impl CsVisitorForBob {
         let var_type = match &*symbol_type {
             DomainVariableSymbolT { domain_variable_symbol_rcref } => {
                 match &domain_variable_symbol_rcref.borrow().var_type {
-                    Some(x) => x.get_type_str(),
+                    Some(x) => self.format_type(x),
                     None => String::from("<?>"),
                 }
             },
             StateParamSymbolT { state_param_symbol_rcref } => {
                 match &state_param_symbol_rcref.borrow().param_type_opt {
-                    Some(x) => x.get_type_str(),
+                    Some(x) => self.format_type(x),
                     None => String::from("<?>"),
                 }
             },
             StateVariableSymbolT { state_variable_symbol_rcref } => {
                 match &state_variable_symbol_rcref.borrow().var_type {
-                    Some(x) => x.get_type_str(),
+                    Some(x) => self.format_type(x),
                     None => String::from("<?>"),
                 }                    },
             EventHandlerParamSymbolT { event_handler_param_symbol_rcref } => {
                 match &event_handler_param_symbol_rcref.borrow().param_type_opt {
-                    Some(x) => x.get_type_str(),
+                    Some(x) => self.format_type(x),
                     None => String::from("<?>"),
                 }
             },
             EventHandlerVariableSymbolT { event_handler_variable_symbol_rcref } => {
                 match &event_handler_variable_symbol_rcref.borrow().var_type {
-                    Some(x) => x.get_type_str(),
+                    Some(x) => self.format_type(x),
                     None => String::from("<?>"),
                 }
             },
@@ -219,7 +239,7 @@ impl CsVisitorForBob {
         for param in params {
             self.add_code(&format!("{}", separator));
             let param_type: String = match &param.param_type_opt {
-                Some(ret_type) => ret_type.get_type_str(),
+                Some(ret_type) => self.format_type(ret_type),
                 None => String::from("<?>"),
             };
             self.add_code(&format!("{} {}", param_type, param.param_name));
@@ -234,7 +254,7 @@ impl CsVisitorForBob {
         for param in params {
             output.push_str(&format!("{}", separator));
             let param_type: String = match &param.param_type_opt {
-                Some(ret_type) => ret_type.get_type_str(),
+                Some(ret_type) => self.format_type(ret_type),
                 None => String::from("<?>"),
             };
             output.push_str(&format!("{} {}", param_type, param.param_name));
@@ -345,6 +365,12 @@ impl CsVisitorForBob {
                         StatementType::StateStackStmt {state_stack_operation_statement_node} => {
                             state_stack_operation_statement_node.accept(self);
                         },
+                        StatementType::RaiseStmt {raise_statement_node} => {
+                            raise_statement_node.accept(self);
+                        },
+                        StatementType::ForwardStmt {forward_statement_node} => {
+                            forward_statement_node.accept(self);
+                        },
                         StatementType::ChangeStateStmt {change_state_stmt} => {
                             change_state_stmt.accept(self);
                         },
@@ -487,6 +513,716 @@ impl CsVisitorForBob {
     //     }
     // }
 
+    // Generates a Frame body for an operation or action.
+
+    fn generate_frame_body(&mut self, statements:&Vec<DeclOrStmtType>, return_expr_t_opt:Option<&ExprType>) {
+        self.visit_decl_stmts(statements);
+        if let Some(expr_t) = return_expr_t_opt {
+            self.newline();
+            self.add_code("return ");
+            expr_t.accept(self);
+            self.add_code(";");
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    //* --------------------------------------------------------------------- *//
+
+    // The system is given an IFrameScheduler to run its timers so that
+    // the clock can be replaced, e.g. by a virtual clock in tests.
+
+    fn generate_scheduler_interface(&mut self) {
+        self.add_code("public interface IFrameScheduler {");
+        self.indent();
+        self.newline();
+        self.add_code("void Schedule(long delayMs, Action callback);");
+        self.newline();
+        self.add_code("void Cancel();");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Only one timer is active at a time. The transition machinery lives in
+    // FrameController so the timer is cancelled by the states on exit instead.
+
+    fn generate_timers(&mut self) {
+        self.newline();
+        self.newline();
+        self.add_code("private IFrameScheduler _scheduler_;");
+        self.newline();
+        self.add_code("private bool _timerActive_ = false;");
+        self.newline();
+        self.newline();
+        self.add_code("private void _startTimer_(long delayMs) {");
+        self.indent();
+        self.newline();
+        self.add_code("if (_timerActive_) return;");
+        self.newline();
+        self.add_code("_timerActive_ = true;");
+        self.newline();
+        self.add_code("_scheduler_.Schedule(delayMs, _timeout_);");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        self.add_code("private void _cancelTimer_() {");
+        self.indent();
+        self.newline();
+        self.add_code("if (!_timerActive_) return;");
+        self.newline();
+        self.add_code("_timerActive_ = false;");
+        self.newline();
+        self.add_code("_scheduler_.Cancel();");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        self.add_code("private void _timeout_() {");
+        self.indent();
+        self.newline();
+        self.add_code("if (!_timerActive_) return;");
+        self.newline();
+        self.add_code("_timerActive_ = false;");
+        self.newline();
+        if self.generate_event_queue {
+            self.add_code(&format!("_dispatch_(new FrameEvent(\"{}\",null));", self.symbol_config.timeout_msg_symbol));
+        } else {
+            self.add_code(&format!("_state_(new FrameEvent(\"{}\",null));", self.symbol_config.timeout_msg_symbol));
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Events sent while the machine is handling an event are queued and
+    // dispatched in order once the current event has been fully handled.
+
+    fn generate_event_queue(&mut self) {
+        let state_dispatch = if self.generate_regions {"_dispatchRegions_"} else {"_state_"};
+        self.newline();
+        self.newline();
+        self.add_code("private System.Collections.Generic.Queue<FrameEvent> _eventQueue_ = new System.Collections.Generic.Queue<FrameEvent>();");
+        self.newline();
+        self.add_code("private bool _processing_ = false;");
+        self.newline();
+        self.newline();
+        self.add_code("private void _dispatch_(FrameEvent e) {");
+        self.indent();
+        self.newline();
+        self.add_code("if (_processing_) {");
+        self.indent();
+        self.newline();
+        self.add_code("_eventQueue_.Enqueue(e);");
+        self.newline();
+        self.add_code("return;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.add_code("_processing_ = true;");
+        self.newline();
+        // Clear the flag even if a handler throws, otherwise every later
+        // event would be queued and never dispatched.
+        self.add_code("try {");
+        self.indent();
+        self.newline();
+        self.add_code(&format!("{}(e);", state_dispatch));
+        self.newline();
+        self.add_code("while (_eventQueue_.Count > 0) {");
+        self.indent();
+        self.newline();
+        self.add_code(&format!("{}(_eventQueue_.Dequeue());", state_dispatch));
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.outdent();
+        self.newline();
+        self.add_code("} finally {");
+        self.indent();
+        self.newline();
+        self.add_code("_processing_ = false;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // The machine is finished once it is in a #[final] state, or once
+    // every region is.
+
+    fn generate_final_states(&mut self, system_node: &SystemNode) {
+        let final_states:Vec<String> = system_node.get_final_state_names().iter()
+            .map(|state_name| format!("state == new FrameState(_s{}_)", state_name)).collect();
+        self.newline();
+        self.newline();
+        self.add_code("private bool _isFinalState_(FrameState state) {");
+        self.indent();
+        self.newline();
+        self.add_code(&format!("return {};", final_states.join(" || ")));
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        if self.generate_regions {
+            self.newline();
+            self.add_code("public bool isFinished() {");
+            self.indent();
+            self.newline();
+            self.add_code("foreach (FrameState state in _regions_) {");
+            self.indent();
+            self.newline();
+            self.add_code("if (!_isFinalState_(state)) {");
+            self.indent();
+            self.newline();
+            self.add_code("return false;");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+            self.newline();
+            self.add_code("return true;");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        } else {
+            self.newline();
+            self.add_code("public bool isFinished() {");
+            self.indent();
+            self.newline();
+            self.add_code("return _isFinalState_(_state_);");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
+        self.newline();
+        self.newline();
+        self.add_code("protected virtual void onFinished() {}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Entering a final state completes the machine.
+
+    fn generate_finished_check(&mut self) {
+        self.newline();
+        self.add_code("if (isFinished()) {");
+        self.indent();
+        self.newline();
+        self.add_code("onFinished();");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Actions called from the constructor would run before a subclass is
+    // initialized so the start state is not entered there. The first
+    // interface call enters it with the system enter parameters.
+
+    fn generate_start(&mut self) {
+        self.newline();
+        self.newline();
+        self.add_code("private bool _started_ = false;");
+        self.newline();
+        self.newline();
+        self.add_code("private void _start_() {");
+        self.indent();
+        self.newline();
+        self.add_code("_started_ = true;");
+        self.newline();
+        self.add_code("_state_(new FrameEvent(\">\",_stateContext_.getEnterArgs()));");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Completion transitions are taken one after another in a loop rather
+    // than from inside the transition that entered the state, so long
+    // chains don't grow the stack.
+
+    fn generate_completions(&mut self, system_node: &SystemNode) {
+        self.newline();
+        self.newline();
+        self.add_code("private bool _completing_ = false;");
+        self.newline();
+        self.newline();
+        self.add_code("private void _complete_() {");
+        self.indent();
+        self.newline();
+        self.add_code("if (_completing_) {");
+        self.indent();
+        self.newline();
+        self.add_code("return;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.add_code("_completing_ = true;");
+        self.newline();
+        self.add_code("while (_completion_()) {}");
+        self.newline();
+        self.add_code("_completing_ = false;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        self.add_code("private bool _completion_() {");
+        self.indent();
+        if let Some(machine_block_node) = &system_node.machine_block_node_opt {
+            for state_node_rcref in &machine_block_node.states {
+                let state_node = state_node_rcref.borrow();
+                if state_node.completions.len() == 0 {
+                    continue;
+                }
+                self.current_state_name_opt = Some(state_node.name.clone());
+                self.newline();
+                self.add_code(&format!("if (_state_ == new FrameState(_s{}_)) {{", state_node.name));
+                self.indent();
+                for completion_node in &state_node.completions {
+                    if let Some(guard_expr_t) = &completion_node.guard_expr_t_opt {
+                        let mut guard = String::new();
+                        guard_expr_t.accept_to_string(self, &mut guard);
+                        self.newline();
+                        self.add_code(&format!("if ({}) {{", guard));
+                        self.indent();
+                    }
+                    completion_node.transition_statement_node.accept(self);
+                    self.newline();
+                    self.add_code("return true;");
+                    if completion_node.guard_expr_t_opt.is_some() {
+                        self.outdent();
+                        self.newline();
+                        self.add_code("}");
+                    }
+                }
+                self.outdent();
+                self.newline();
+                self.add_code("}");
+                self.current_state_name_opt = None;
+            }
+        }
+        self.newline();
+        self.add_code("return false;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // The snapshot is a JSON document written with System.Text.Json. Values
+    // are read back with their declared types as the document only holds
+    // JsonElements.
+
+    fn generate_serialization(&mut self, system_node: &SystemNode) {
+        let domain_vars = system_node.get_domain_vars();
+        let state_type = if self.generate_state_context {"StateContext"} else {"FrameState"};
+        let snapshot_version = system_node.get_snapshot_version();
+        let snapshot_hash = system_node.get_snapshot_hash();
+        self.newline();
+        self.add_code("public string _serialize__do() {");
+        self.indent();
+        self.newline();
+        self.add_code("Dictionary<string,object> bag = new Dictionary<string,object>();");
+        self.newline();
+        self.add_code(&format!("bag[\"version\"] = {};", snapshot_version));
+        self.newline();
+        self.add_code(&format!("bag[\"hash\"] = \"{}\";", snapshot_hash));
+        self.newline();
+        if self.generate_state_context {
+            self.add_code("bag[\"state\"] = _serializeState_(_stateContext_);");
+        } else {
+            self.add_code("bag[\"state\"] = _serializeState_(_state_);");
+        }
+        if self.generate_state_stack {
+            self.newline();
+            self.add_code(&format!("{}[] states = _stateStack_.ToArray();", state_type));
+            self.newline();
+            self.add_code("System.Array.Reverse(states);");
+            self.newline();
+            self.add_code("List<Dictionary<string,object>> stateStack = new List<Dictionary<string,object>>();");
+            self.newline();
+            self.add_code(&format!("foreach ({} state in states) {{", state_type));
+            self.indent();
+            self.newline();
+            self.add_code("stateStack.Add(_serializeState_(state));");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+            self.newline();
+            self.add_code("bag[\"stateStack\"] = stateStack;");
+        }
+        self.newline();
+        self.add_code("Dictionary<string,object> domain = new Dictionary<string,object>();");
+        for var_rcref in &domain_vars {
+            let var_name = var_rcref.borrow().name.clone();
+            self.newline();
+            self.add_code(&format!("domain[\"{}\"] = {};", var_name, var_name));
+        }
+        self.newline();
+        self.add_code("bag[\"domain\"] = domain;");
+        self.newline();
+        self.add_code("return System.Text.Json.JsonSerializer.Serialize(bag);");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        self.add_code("public void _deserialize__do(string data) {");
+        self.indent();
+        self.newline();
+        self.add_code("System.Text.Json.JsonElement bag = System.Text.Json.JsonSerializer.Deserialize<System.Text.Json.JsonElement>(data);");
+        self.newline();
+        self.add_code("int version = bag.GetProperty(\"version\").GetInt32();");
+        self.newline();
+        self.add_code(&format!("if (version > {}) {{", snapshot_version));
+        self.indent();
+        self.newline();
+        self.add_code(&format!("throw new System.ArgumentException(\"Snapshot version \" + version + \" is newer than version {} of {}\");", snapshot_version, system_node.name));
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.add_code(&format!("if (version == {} && bag.GetProperty(\"hash\").GetString() != \"{}\") {{", snapshot_version, snapshot_hash));
+        self.indent();
+        self.newline();
+        self.add_code(&format!("throw new System.ArgumentException(\"Snapshot does not match version {} of {}\");", snapshot_version, system_node.name));
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        if self.generate_state_context {
+            self.add_code("_stateContext_ = _deserializeState_(bag.GetProperty(\"state\"));");
+            self.newline();
+            self.add_code("_state_ = _stateContext_.state;");
+        } else {
+            self.add_code("_state_ = _deserializeState_(bag.GetProperty(\"state\"));");
+        }
+        if self.generate_state_stack {
+            self.newline();
+            self.add_code("_stateStack_.Clear();");
+            self.newline();
+            self.add_code("foreach (System.Text.Json.JsonElement state in bag.GetProperty(\"stateStack\").EnumerateArray()) {");
+            self.indent();
+            self.newline();
+            self.add_code("_stateStack_.Push(_deserializeState_(state));");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
+        if self.generate_start_enter {
+            self.newline();
+            self.add_code("_started_ = true;");
+        }
+        self.newline();
+        self.add_code("System.Text.Json.JsonElement domain = bag.GetProperty(\"domain\");");
+        for var_rcref in &domain_vars {
+            let var = var_rcref.borrow();
+            let var_type = self.format_serialized_type(&var.type_opt);
+            self.newline();
+            if var.has_snapshot_default() {
+                self.add_code(&format!("if (domain.TryGetProperty(\"{}\", out _)) {{", var.name));
+                self.indent();
+                self.newline();
+            }
+            self.add_code(&format!("{} = System.Text.Json.JsonSerializer.Deserialize<{}>(domain.GetProperty(\"{}\").GetRawText());", var.name, var_type, var.name));
+            if var.has_snapshot_default() {
+                self.outdent();
+                self.newline();
+                self.add_code("}");
+            }
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        if self.generate_state_context {
+            self.add_code("private Dictionary<string,object> _serializeState_(StateContext stateContext) {");
+        } else {
+            self.add_code("private Dictionary<string,object> _serializeState_(FrameState state) {");
+        }
+        self.indent();
+        self.newline();
+        self.add_code("Dictionary<string,object> data = new Dictionary<string,object>();");
+        if self.generate_state_context {
+            self.newline();
+            self.add_code("Dictionary<string,object> stateArgs = new Dictionary<string,object>();");
+            self.newline();
+            self.add_code("Dictionary<string,object> stateVars = new Dictionary<string,object>();");
+        }
+        for state_node_rcref in system_node.get_states() {
+            let state_node = state_node_rcref.borrow();
+            self.newline();
+            if self.generate_state_context {
+                self.add_code(&format!("if (stateContext.state == new FrameState(_s{}_)) {{", state_node.name));
+            } else {
+                self.add_code(&format!("if (state == new FrameState(_s{}_)) {{", state_node.name));
+            }
+            self.indent();
+            self.newline();
+            self.add_code(&format!("data[\"state\"] = \"{}\";", state_node.name));
+            for param_name in state_node.get_param_names() {
+                self.newline();
+                self.add_code(&format!("stateArgs[\"{}\"] = stateContext.getStateArg(\"{}\");", param_name, param_name));
+            }
+            for var_name in state_node.get_var_names() {
+                self.newline();
+                self.add_code(&format!("stateVars[\"{}\"] = stateContext.getStateVar(\"{}\");", var_name, var_name));
+            }
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
+        if self.generate_state_context {
+            self.newline();
+            self.add_code("data[\"stateArgs\"] = stateArgs;");
+            self.newline();
+            self.add_code("data[\"stateVars\"] = stateVars;");
+        }
+        self.newline();
+        self.add_code("return data;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        self.add_code(&format!("private {} _deserializeState_(System.Text.Json.JsonElement data) {{", state_type));
+        self.indent();
+        self.newline();
+        self.add_code("switch (data.GetProperty(\"state\").GetString()) {");
+        self.indent();
+        for state_node_rcref in system_node.get_states() {
+            let state_node = state_node_rcref.borrow();
+            for renamed_from in state_node.get_renamed_from() {
+                self.newline();
+                self.add_code(&format!("case \"{}\":", renamed_from));
+            }
+            self.newline();
+            self.add_code(&format!("case \"{}\": {{", state_node.name));
+            self.indent();
+            self.newline();
+            if self.generate_state_context {
+                self.add_code(&format!("StateContext stateContext = new StateContext(_s{}_);", state_node.name));
+                if let Some(params) = &state_node.params_opt {
+                    for param in params {
+                        let param_type = self.format_serialized_type(&param.param_type_opt);
+                        self.newline();
+                        self.add_code(&format!("stateContext.addStateArg(\"{}\",System.Text.Json.JsonSerializer.Deserialize<{}>(data.GetProperty(\"stateArgs\").GetProperty(\"{}\").GetRawText()));", param.param_name, param_type, param.param_name));
+                    }
+                }
+                if let Some(vars) = &state_node.vars_opt {
+                    for var_rcref in vars {
+                        let var = var_rcref.borrow();
+                        let var_type = self.format_serialized_type(&var.type_opt);
+                        self.newline();
+                        self.add_code(&format!("stateContext.addStateVar(\"{}\",System.Text.Json.JsonSerializer.Deserialize<{}>(data.GetProperty(\"stateVars\").GetProperty(\"{}\").GetRawText()));", var.name, var_type, var.name));
+                    }
+                }
+                self.newline();
+                self.add_code("return stateContext;");
+            } else {
+                self.add_code(&format!("return _s{}_;", state_node.name));
+            }
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.add_code("throw new System.ArgumentException(\"Unknown state \" + data.GetProperty(\"state\").GetString());");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Untyped values are read back as JsonElements.
+
+    fn format_serialized_type(&self, type_opt:&Option<TypeNode>) -> String {
+        match type_opt {
+            Some(type_node) => self.format_type(type_node),
+            None => String::from("object"),
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Each region has its own active state. An event is dispatched to
+    // every region in turn with the region's state made current.
+
+    fn generate_regions(&mut self) {
+        self.newline();
+        self.newline();
+        self.add_code("private FrameState[] _regions_;");
+        self.newline();
+        self.newline();
+        self.add_code("private void _dispatchRegions_(FrameEvent e) {");
+        self.indent();
+        if self.generate_final_states {
+            self.newline();
+            self.add_code("bool wasFinished = isFinished();");
+        }
+        self.newline();
+        self.add_code("for (int i = 0; i < _regions_.Length; i++) {");
+        self.indent();
+        self.newline();
+        self.add_code("_state_ = _regions_[i];");
+        self.newline();
+        self.add_code("_state_(e);");
+        self.newline();
+        self.add_code("_regions_[i] = _state_;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        if self.generate_final_states {
+            self.newline();
+            self.add_code("if (!wasFinished && isFinished()) {");
+            self.indent();
+            self.newline();
+            self.add_code("onFinished();");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // History is recorded on the way out of a state. Every ancestor that
+    // is a history target remembers the child it was left through and
+    // the leaf state that was active.
+
+    fn generate_history(&mut self, system_node: &SystemNode) {
+        self.newline();
+        self.newline();
+        self.add_code("private System.Collections.Generic.Dictionary<string,FrameState> _shallowHistory_ = new System.Collections.Generic.Dictionary<string,FrameState>();");
+        self.newline();
+        self.add_code("private System.Collections.Generic.Dictionary<string,FrameState> _deepHistory_ = new System.Collections.Generic.Dictionary<string,FrameState>();");
+        self.newline();
+        self.newline();
+        self.add_code("private void _recordHistory_() {");
+        self.indent();
+        let mut is_first = true;
+        if let Some(machine_block_node) = &system_node.machine_block_node_opt {
+            for state_node_rcref in &machine_block_node.states {
+                let state_node = state_node_rcref.borrow();
+                if state_node.history_records.len() == 0 {
+                    continue;
+                }
+                let state_ref = self.format_target_state_name(&state_node.name);
+                self.newline();
+                if is_first {
+                    self.add_code(&format!("if (_state_ == new FrameState({})) {{", state_ref));
+                } else {
+                    self.add_code(&format!("}} else if (_state_ == new FrameState({})) {{", state_ref));
+                }
+                is_first = false;
+                self.indent();
+                for history_record in &state_node.history_records {
+                    let child_ref = self.format_target_state_name(&history_record.child_state_name);
+                    self.newline();
+                    self.add_code(&format!("_shallowHistory_[\"{}\"] = {};", history_record.parent_state_name, child_ref));
+                    self.newline();
+                    self.add_code(&format!("_deepHistory_[\"{}\"] = {};", history_record.parent_state_name, state_ref));
+                }
+                self.outdent();
+            }
+        }
+        if !is_first {
+            self.newline();
+            self.add_code("}");
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Deferred events are replayed in the order they arrived. Any the
+    // new state defers again go back on the queue.
+
+    fn generate_deferred_events(&mut self) {
+        self.newline();
+        self.newline();
+        self.add_code("private System.Collections.Generic.List<FrameEvent> _deferredQueue_ = new System.Collections.Generic.List<FrameEvent>();");
+        self.newline();
+        self.newline();
+        self.add_code("private void _replayDeferred_() {");
+        self.indent();
+        self.newline();
+        self.add_code("System.Collections.Generic.List<FrameEvent> deferred = _deferredQueue_;");
+        self.newline();
+        self.add_code("_deferredQueue_ = new System.Collections.Generic.List<FrameEvent>();");
+        self.newline();
+        self.add_code("foreach (FrameEvent e in deferred) {");
+        self.indent();
+        self.newline();
+        self.add_code("_state_(e);");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // A deferred event is put aside rather than handled and is replayed
+    // after the next transition.
+
+    fn generate_deferred_event_checks(&mut self, deferred_events:&Vec<String>) {
+        for msg in deferred_events {
+            self.newline();
+            self.add_code(&format!("if (e.Msg.EqualsEx(\"{}\")) {{", msg));
+            self.indent();
+            self.newline();
+            self.add_code("_deferredQueue_.Add(e);");
+            self.newline();
+            self.add_code("return;");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn generate_timer_start(&mut self, timeout_node:&TimeoutNode) {
+        let mut duration = String::new();
+        timeout_node.duration_expr_t.accept_to_string(self, &mut duration);
+        self.newline();
+        self.add_code(&format!("_startTimer_({});", duration));
+    }
+
     //* --------------------------------------------------------------------- *//
 
     fn generate_subclass(&mut self) {
@@ -538,8 +1274,21 @@ impl CsVisitorForBob {
             },
         };
 
+        let target_state_args_opt = match &change_state_stmt_node.state_context_t {
+            StateContextType::StateRef { state_context_node}
+                => &state_context_node.state_ref_args_opt,
+            _ => &Option::None,
+        };
+
         self.newline();
-        self.add_code(&format!("_changeState_({});", self.format_target_state_name(target_state_name)));
+        if self.generate_state_context {
+            self.add_code(&format!("stateContext = new StateContext({});", self.format_target_state_name(target_state_name)));
+            self.newline();
+            self.generate_state_args_and_vars(target_state_name, target_state_args_opt);
+            self.add_code(&format!("_changeState_({},stateContext);", self.format_target_state_name(target_state_name)));
+        } else {
+            self.add_code(&format!("_changeState_({});", self.format_target_state_name(target_state_name)));
+        }
 
     }
 
@@ -606,7 +1355,7 @@ impl CsVisitorForBob {
                                 match param_symbols_it.next() {
                                     Some(p) => {
                                         let _param_type = match &p.param_type_opt {
-                                            Some(param_type) => param_type.get_type_str(),
+                                            Some(param_type) => self.format_type(param_type),
                                             None => String::from("<?>"),
                                         };
                                         let mut expr = String::new();
@@ -633,7 +1382,7 @@ impl CsVisitorForBob {
         let enter_args_opt = match &transition_statement.target_state_context_t {
             StateContextType::StateRef { state_context_node}
                 => &state_context_node.enter_args_opt,
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..} | StateContextType::StateHistory {..}
                 => &None,
         };
 
@@ -657,7 +1406,7 @@ impl CsVisitorForBob {
                             match param_symbols_it.next() {
                                 Some(p) => {
                                     let _param_type = match &p.param_type_opt {
-                                        Some(param_type) => param_type.get_type_str(),
+                                        Some(param_type) => self.format_type(param_type),
                                         None => String::from("<?>"),
                                     };
                                     let mut expr = String::new();
@@ -681,10 +1430,38 @@ impl CsVisitorForBob {
         let target_state_args_opt = match &transition_statement.target_state_context_t {
             StateContextType::StateRef { state_context_node}
                 => &state_context_node.state_ref_args_opt,
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..} | StateContextType::StateHistory {..}
                 => &Option::None,
         };
 //
+        self.generate_state_args_and_vars(&target_state_name, target_state_args_opt);
+        let exit_args = if has_exit_args {
+            "exitArgs"
+        } else {
+            "null"
+        };
+        if self.generate_state_context {
+            if self.generate_exit_args {
+                self.add_code(&format!("_transition_({},{},stateContext);",self.format_target_state_name(target_state_name),exit_args));
+            } else {
+                self.add_code(&format!("_transition_({},stateContext);",self.format_target_state_name(target_state_name)));
+            }
+        } else {
+            if self.generate_exit_args {
+                self.add_code(&format!("_transition_({},exitArgs);",self.format_target_state_name(target_state_name)));
+            } else {
+                self.add_code(&format!("_transition_({});",self.format_target_state_name(target_state_name)));
+            }
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Adds the target state's arguments and initialized
+    // state variables to the new state context.
+
+    fn generate_state_args_and_vars(&mut self, target_state_name:&str, target_state_args_opt:&Option<ExprListNode>) {
+
         if let Some(state_args) = target_state_args_opt {
 //            let mut params_copy = Vec::new();
             if let Some(state_sym) = self.arcanium.get_state(&target_state_name) {
@@ -699,7 +1476,7 @@ impl CsVisitorForBob {
                                 Some(param_symbol_rcref) => {
                                     let param_symbol = param_symbol_rcref.borrow();
                                     let _param_type = match &param_symbol.param_type_opt {
-                                        Some(param_type) => param_type.get_type_str(),
+                                        Some(param_type) => self.format_type(param_type),
                                         None => String::from("<?>"),
                                     };
                                     let mut expr = String::new();
@@ -736,7 +1513,7 @@ impl CsVisitorForBob {
                         for var_rcref in state_node.vars_opt.as_ref().unwrap() {
                             let var = var_rcref.borrow();
                             let _var_type = match &var.type_opt {
-                                Some(var_type) => var_type.get_type_str(),
+                                Some(var_type) => self.format_type(var_type),
                                 None => String::from("<?>"),
                             };
                             let expr_t = var.initializer_expr_t_opt.as_ref().unwrap();
@@ -752,24 +1529,6 @@ impl CsVisitorForBob {
 //                code = target_state_vars.clone();
             },
         }
-        let exit_args = if has_exit_args {
-            "exitArgs"
-        } else {
-            "null"
-        };
-        if self.generate_state_context {
-            if self.generate_exit_args {
-                self.add_code(&format!("_transition_({},{},stateContext);",self.format_target_state_name(target_state_name),exit_args));
-            } else {
-                self.add_code(&format!("_transition_({},stateContext);",self.format_target_state_name(target_state_name)));
-            }
-        } else {
-            if self.generate_exit_args {
-                self.add_code(&format!("_transition_({},exitArgs);",self.format_target_state_name(target_state_name)));
-            } else {
-                self.add_code(&format!("_transition_({});",self.format_target_state_name(target_state_name)));
-            }
-        }
     }
 
     //* --------------------------------------------------------------------- *//
@@ -825,7 +1584,7 @@ impl CsVisitorForBob {
                                 match param_symbols_it.next() {
                                     Some(p) => {
                                         let _param_type = match &p.param_type_opt {
-                                            Some(param_type) => param_type.get_type_str(),
+                                            Some(param_type) => self.format_type(param_type),
                                             None => String::from("<?>"),
                                         };
                                         let mut expr = String::new();
@@ -848,24 +1607,233 @@ impl CsVisitorForBob {
             }
         }
 
-        if self.generate_state_context {
-            self.add_code(&format!("StateContext stateContext = _stateStack_pop_();"));
+        if let StateContextType::StateStackPop {..} = &transition_statement.target_state_context_t {
+            let exit_args = match &transition_statement.exit_args_opt {
+                Some(exit_args) if exit_args.exprs_t.len() > 0 => "exitArgs",
+                _ => "null",
+            };
+            self.generate_state_stack_underflow_check(exit_args);
+        }
+        if let StateContextType::StateHistory {state_ref_node, is_deep} = &transition_statement.target_state_context_t {
+            let history_map = if *is_deep {"_deepHistory_"} else {"_shallowHistory_"};
+            self.add_code(&format!("FrameState state = {0}.ContainsKey(\"{1}\") ? {0}[\"{1}\"] : new FrameState({2});", history_map, state_ref_node.name, self.format_target_state_name(&state_ref_node.name)));
+            if self.generate_state_context {
+                self.newline();
+                self.add_code("stateContext = new StateContext(state);");
+            }
+        } else if self.generate_state_context {
+            self.add_code(&format!("stateContext = _stateStack_pop_();"));
+        } else {
+            self.add_code(&format!("FrameState state = _stateStack_pop_();"));
+        }
+        self.newline();
+        if let StateContextType::StateStackPop { enter_args_opt:Some(enter_args) } = &transition_statement.target_state_context_t {
+            self.generate_state_stack_pop_enter_args(enter_args);
+        }
+        if self.generate_exit_args {
+            if self.generate_state_context {
+                self.add_code(&format!("_transition_(stateContext.state,exitArgs,stateContext);"));
+            } else {
+                self.add_code(&format!("_transition_(state,exitArgs);"));
+            }
+        } else {
+            if self.generate_state_context {
+                self.add_code(&format!("_transition_(stateContext.state,stateContext);"));
+            } else {
+                self.add_code(&format!("_transition_(state);"));
+            }
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // The state stack lives in the runtime so the #![state_stack_max]
+    // check wraps each push. A refused push sends the current state a
+    // stateStackOverflow event and ends the handler so the transition
+    // after the push does not happen.
+
+    fn generate_state_stack_push(&mut self, push_code:&str) {
+        let max = match self.state_stack_max_opt {
+            Some(max) => max,
+            None => {
+                self.add_code(push_code);
+                return;
+            },
+        };
+        self.add_code(&format!("if (_stateStack_.Count >= {}) {{", max));
+        self.indent();
+        self.newline();
+        let message = self.symbol_config.state_stack_overflow_msg_symbol.clone();
+        self.generate_state_stack_error_event(&message);
+        self.newline();
+        self.add_code("return;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.add_code(push_code);
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Applies the #![state_stack_underflow] policy when $$[-] finds the
+    // state stack empty.
+
+    fn generate_state_stack_underflow_check(&mut self, exit_args:&str) {
+        let underflow = match &self.state_stack_underflow_opt {
+            Some(underflow) => underflow.clone(),
+            None => return,
+        };
+        self.add_code("if (_stateStack_.Count == 0) {");
+        self.indent();
+        self.newline();
+        match underflow {
+            StateStackUnderflowPolicy::Error => {
+                let message = self.symbol_config.state_stack_underflow_msg_symbol.clone();
+                self.generate_state_stack_error_event(&message);
+                self.newline();
+            },
+            StateStackUnderflowPolicy::Stay => {},
+            StateStackUnderflowPolicy::State {state_name} => {
+                if self.generate_state_context {
+                    self.add_code(&format!("stateContext = new StateContext({});", self.format_target_state_name(&state_name)));
+                    self.newline();
+                    self.generate_state_args_and_vars(&state_name, &None);
+                    if self.generate_exit_args {
+                        self.add_code(&format!("_transition_({},{},stateContext);", self.format_target_state_name(&state_name), exit_args));
+                    } else {
+                        self.add_code(&format!("_transition_({},stateContext);", self.format_target_state_name(&state_name)));
+                    }
+                } else if self.generate_exit_args {
+                    self.add_code(&format!("_transition_({},{});", self.format_target_state_name(&state_name), exit_args));
+                } else {
+                    self.add_code(&format!("_transition_({});", self.format_target_state_name(&state_name)));
+                }
+                self.newline();
+            },
+        }
+        self.add_code("return;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Sends a state stack error event to the current state.
+
+    fn generate_state_stack_error_event(&mut self, message:&str) {
+        if self.generate_event_queue {
+            self.add_code(&format!("_dispatch_(new FrameEvent(\"{}\",null));", message));
+        } else {
+            self.add_code(&format!("_state_(new FrameEvent(\"{}\",null));", message));
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Adds the enter arguments of a state stack pop to the popped state
+    // context. Every pushed state takes the same enter parameters so the
+    // first one names them.
+
+    fn generate_state_stack_pop_enter_args(&mut self, enter_args:&ExprListNode) {
+        let mut msg = match self.pushed_state_names.first() {
+            Some(pushed_state_name) => pushed_state_name.clone(),
+            None => return,
+        };
+        msg.push_str(":");
+        msg.push_str(&self.symbol_config.enter_msg_symbol);
+
+        if let Some(event_sym) = self.arcanium.get_event(&msg,&self.current_state_name_opt) {
+            if let Some(event_params) = &event_sym.borrow().params_opt {
+                for (p, expr_t) in event_params.iter().zip(enter_args.exprs_t.iter()) {
+                    let mut expr = String::new();
+                    expr_t.accept_to_string(self,&mut expr);
+                    self.add_code(&format!("stateContext.addEnterArg(\"{}\",{});", p.name, expr));
+                    self.newline();
+                }
+            }
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Element type for a collection literal whose elements couldn't be
+    // inferred.
+
+    fn format_element_type(&self, element_type_opt:&Option<FrameType>) -> String {
+        match element_type_opt {
+            Some(element_type) => self.format_frame_type(element_type),
+            None => String::from("object"),
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Maps portable Frame types to C#. Other types are emitted as written.
+    // Frame references are dropped, see CsVisitor::format_type().
+
+    fn format_type(&self, type_node:&TypeNode) -> String {
+        match type_node.get_frame_type_opt() {
+            Some(frame_type) => self.format_frame_type(frame_type),
+            None => type_node.get_type_str(),
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // A generic system is a generic class over the system's type parameters.
+
+    fn format_type_params(system_node:&SystemNode) -> String {
+        if system_node.type_params.is_empty() {
+            String::new()
         } else {
-            self.add_code(&format!("FrameState state = _stateStack_pop_();"));
+            format!("<{}>", system_node.type_params.join(", "))
         }
-        self.newline();
-        if self.generate_exit_args {
-            if self.generate_state_context {
-                self.add_code(&format!("_transition_(stateContext.state,exitArgs,stateContext);"));
-            } else {
-                self.add_code(&format!("_transition_(state,exitArgs);"));
-            }
-        } else {
-            if self.generate_state_context {
-                self.add_code(&format!("_transition_(stateContext.state,stateContext);"));
-            } else {
-                self.add_code(&format!("_transition_(state);"));
-            }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn format_frame_type(&self, frame_type:&FrameType) -> String {
+        match frame_type {
+            FrameType::Int => String::from("int"),
+            FrameType::Float => String::from("double"),
+            FrameType::Bool => String::from("bool"),
+            FrameType::String => String::from("string"),
+            FrameType::List {element_type} => format!("List<{}>", self.format_frame_type(element_type)),
+            FrameType::Map {key_type, value_type} => format!("Dictionary<{}, {}>", self.format_frame_type(key_type), self.format_frame_type(value_type)),
+            FrameType::TypeParam {name} => name.clone(),
+        }
+    }
+}
+
+//* --------------------------------------------------------------------- *//
+
+impl OperatorPrecedence for CsVisitorForBob {
+
+    //* --------------------------------------------------------------------- *//
+
+    // Binding strength of each operator in C#, higher binds tighter.
+    // C# has a native logical xor ('^') on bools.
+
+    fn operator_precedence(&self, operator_type:&OperatorType) -> usize {
+        match operator_type {
+            OperatorType::LogicalOr => 1,
+            OperatorType::LogicalAnd => 2,
+            OperatorType::LogicalXor => 3,
+            OperatorType::EqualEqual
+            | OperatorType::NotEqual => 4,
+            OperatorType::Greater
+            | OperatorType::GreaterEqual
+            | OperatorType::Less
+            | OperatorType::LessEqual => 5,
+            OperatorType::Plus
+            | OperatorType::Minus => 6,
+            OperatorType::Multiply
+            | OperatorType::Divide => 7,
+            OperatorType::Not
+            | OperatorType::Negated => 8,
         }
     }
 }
@@ -884,13 +1852,30 @@ impl AstVisitor for CsVisitorForBob {
         self.add_code("// get include files at https://github.com/frame-lang/frame-ancillary-files");
         self.newline();
         self.newline();
-        self.add_code(&format!("public partial class {}Base : FrameController {{", system_node.name));
+        let type_params = Self::format_type_params(system_node);
+        self.add_code(&format!("public partial class {}Base{} : FrameController {{", system_node.name, type_params));
         self.indent();
         self.newline();
         self.add_code(&format!("public FrameController self;"));
         self.newline();
         self.newline();
 
+        self.generate_timers = system_node.has_timeouts();
+        self.generate_event_queue = system_node.is_run_to_completion();
+        self.generate_deferred_events = system_node.has_deferred_events();
+        self.generate_history = system_node.has_history();
+        self.generate_regions = system_node.has_regions();
+        self.generate_final_states = system_node.get_final_state_names().len() > 0;
+        self.generate_completions = system_node.has_completions();
+        self.pushed_state_names = system_node.get_pushed_state_names();
+        self.domain_param_names = system_node.get_domain_param_names();
+        self.generate_start_enter = system_node.start_state_enter_params_opt.is_some();
+        self.state_stack_max_opt = system_node.get_state_stack_max();
+        self.state_stack_underflow_opt = system_node.get_state_stack_underflow();
+        if self.generate_timers {
+            self.generate_scheduler_interface();
+        }
+
         // First state name needed for machinery.
         // Don't generate if there isn't at least one state.
         match (&system_node).get_first_state() {
@@ -903,16 +1888,48 @@ impl AstVisitor for CsVisitorForBob {
 
         // generate constructor
 
+        let system_params = system_node.get_system_params();
+        let mut constructor_params:Vec<String> = system_params.iter()
+            .map(|param| {
+                let param_type = match &param.param_type_opt {
+                    Some(param_type) => self.format_type(param_type),
+                    None => String::from("<?>"),
+                };
+                format!("{} {}", param_type, param.param_name)
+            })
+            .collect();
+        let mut base_args:Vec<String> = system_params.iter().map(|param| param.param_name.clone()).collect();
+        if self.generate_timers {
+            constructor_params.push(String::from("IFrameScheduler scheduler"));
+            base_args.push(String::from("scheduler"));
+        }
+
         if self.has_states {
-            self.add_code(&format!("public {}Base() {{", system_node.name));
+            self.add_code(&format!("public {}Base({}) {{", system_node.name, constructor_params.join(", ")));
             self.indent();
             self.newline();
             self.add_code(&format!("self = this;"));
             self.newline();
             self.add_code(&format!("_state_ = _s{}_;", self.first_state_name));
+            if self.generate_regions {
+                let region_states:Vec<String> = system_node.get_region_initial_state_names().iter()
+                    .map(|state_name| format!("_s{}_", state_name)).collect();
+                self.newline();
+                self.add_code(&format!("_regions_ = new FrameState[] {{{}}};", region_states.join(", ")));
+            }
+            if self.generate_timers {
+                self.newline();
+                self.add_code(&format!("_scheduler_ = scheduler;"));
+            }
             if self.generate_state_context {
                 self.newline();
                 self.add_code(&format!("_stateContext_ = new StateContext(_s{}_);", self.first_state_name));
+                if let Some(params) = &system_node.start_state_state_params_opt {
+                    for param in params {
+                        self.newline();
+                        self.add_code(&format!("_stateContext_.addStateArg(\"{}\",{});", param.param_name, param.param_name));
+                    }
+                }
                 if let Some(state_symbol_rcref) = self.arcanium.get_state(&self.first_state_name) {
                     self.newline();
                     let state_symbol = state_symbol_rcref.borrow();
@@ -922,7 +1939,7 @@ impl AstVisitor for CsVisitorForBob {
                         for var_rcref in state_node.vars_opt.as_ref().unwrap() {
                             let var = var_rcref.borrow();
                             let _var_type = match &var.type_opt {
-                                Some(var_type) => var_type.get_type_str(),
+                                Some(var_type) => self.format_type(var_type),
                                 None => String::from("<?>"),
                             };
                             let expr_t = var.initializer_expr_t_opt.as_ref().unwrap();
@@ -936,6 +1953,29 @@ impl AstVisitor for CsVisitorForBob {
                 }
             }
 
+            // The start state is never entered so start its timer here.
+            if let Some(first_state_node_rcref) = system_node.get_first_state() {
+                let first_state_node = first_state_node_rcref.borrow();
+                if let Some(timeout_node) = &first_state_node.timeout_opt {
+                    self.current_state_name_opt = Some(first_state_node.name.clone());
+                    self.generate_timer_start(timeout_node);
+                    self.current_state_name_opt = None;
+                }
+            }
+
+            for var_name in &self.domain_param_names.clone() {
+                self.newline();
+                self.add_code(&format!("this.{} = {};", var_name, var_name));
+            }
+
+            // The start state is entered with these on the first event.
+            if let Some(params) = &system_node.start_state_enter_params_opt {
+                for param in params {
+                    self.newline();
+                    self.add_code(&format!("_stateContext_.addEnterArg(\"{}\",{});", param.param_name, param.param_name));
+                }
+            }
+
             self.outdent();
             self.newline();
             self.add_code(&format!("}}"));
@@ -944,24 +1984,23 @@ impl AstVisitor for CsVisitorForBob {
 
         // end of generate constructor
 
-        self.serialize.push("".to_string());
-        self.serialize.push("Bag _serialize__do() {".to_string());
-
-        self.deserialize.push("".to_string());
-
-        // @TODO: _do needs to be configurable.
-        self.deserialize.push("void _deserialize__do(Bag data) {".to_string());
-
 
         self.subclass_code.push("".to_string());
         self.subclass_code.push("/********************".to_string());
-        self.subclass_code.push(format!("public partial class {} : {}Base {{",system_node.name,system_node.name));
+        self.subclass_code.push(format!("public partial class {}{} : {}Base{} {{",system_node.name,type_params,system_node.name,type_params));
+        if !constructor_params.is_empty() {
+            self.subclass_code.push(format!("\tpublic {}({}) : base({}) {{}}",system_node.name, constructor_params.join(", "), base_args.join(", ")));
+        }
 //        self.subclass_code.push(format!("\tlet that = {}Base.call(this);", system_node.name));
 
         if let Some(interface_block_node) = &system_node.interface_block_node_opt {
             interface_block_node.accept(self);
         }
 
+        if let Some(operations_block_node) = &system_node.operations_block_node_opt {
+            operations_block_node.accept(self);
+        }
+
         if let Some(machine_block_node) = &system_node.machine_block_node_opt {
             machine_block_node.accept(self);
         }
@@ -980,18 +2019,38 @@ impl AstVisitor for CsVisitorForBob {
         self.subclass_code.push(format!("}}"));
         self.subclass_code.push("********************/".to_string());
 
-        self.serialize.push("".to_string());
-        self.serialize.push("\treturn JSON.stringify(bag);".to_string());
-        self.serialize.push("}".to_string());
-        self.serialize.push("".to_string());
-
-        self.deserialize.push("".to_string());
-        self.deserialize.push("}".to_string());
-
         if self.has_states {
 //            self.generate_machinery(system_node);
         }
 
+        if self.generate_timers {
+            self.generate_timers();
+        }
+        if self.generate_event_queue {
+            self.generate_event_queue();
+        }
+        if self.generate_deferred_events {
+            self.generate_deferred_events();
+        }
+        if self.generate_history {
+            self.generate_history(system_node);
+        }
+        if self.generate_regions {
+            self.generate_regions();
+        }
+        if self.generate_final_states {
+            self.generate_final_states(system_node);
+        }
+        if self.generate_completions {
+            self.generate_completions(system_node);
+        }
+        if self.generate_start_enter {
+            self.generate_start();
+        }
+        if self.arcanium.is_serializable() {
+            self.generate_serialization(system_node);
+        }
+
         // TODO: formatting
         // self.newline();
         // self.generate_comment(system_node.line);
@@ -1047,7 +2106,7 @@ impl AstVisitor for CsVisitorForBob {
 
         self.newline();
         let return_type = match &interface_method_node.return_type_opt {
-            Some(ret) => ret.get_type_str(),
+            Some(ret) => self.format_type(ret),
             None => "void".to_string(),
         };
 
@@ -1092,15 +2151,41 @@ impl AstVisitor for CsVisitorForBob {
             params_param_code = String::from("null");
         }
 
+        if *method_name_or_alias == self.symbol_config.restore_msg_symbol {
+            if let Some(params) = &interface_method_node.params {
+                self.newline();
+                self.add_code(&format!("_deserialize__do({});", params[0].param_name));
+            }
+        }
+        if self.generate_start_enter {
+            self.newline();
+            self.add_code("if (!_started_) _start_();");
+        }
         self.newline();
         self.add_code(&format!("FrameEvent e = new FrameEvent(\"{}\",{});", method_name_or_alias,params_param_code));
+        if let Some(default_return_expr_t) = &interface_method_node.default_return_expr_opt {
+            let mut default_return = String::new();
+            default_return_expr_t.accept_to_string(self, &mut default_return);
+            self.newline();
+            self.add_code(&format!("e._return = {};", default_return));
+        }
         self.newline();
-        self.add_code(&format!("_state_(e);"));
+        if self.generate_event_queue {
+            self.add_code(&format!("_dispatch_(e);"));
+        } else if self.generate_regions {
+            self.add_code(&format!("_dispatchRegions_(e);"));
+        } else {
+            self.add_code(&format!("_state_(e);"));
+        }
 
         match &interface_method_node.return_type_opt {
+            Some(_) if *method_name_or_alias == self.symbol_config.save_msg_symbol => {
+                self.newline();
+                self.add_code("return _serialize__do();");
+            },
             Some(return_type) => {
                 self.newline();
-                self.add_code(&format!("return ({}) e.Return;",return_type.get_type_str()));
+                self.add_code(&format!("return ({}) e.Return;",self.format_type(return_type)));
             },
             None => {}
         }
@@ -1119,29 +2204,11 @@ impl AstVisitor for CsVisitorForBob {
         self.newline();
         self.add_code("//===================== Machine Block ===================//");
 
-        self.serialize.push("".to_string());
-        self.serialize.push("\tvar stateName = null;".to_string());
-
-        self.deserialize.push("".to_string());
-        self.deserialize.push("\tconst bag = JSON.parse(data);".to_string());
-        self.deserialize.push("".to_string());
-        self.deserialize.push("\tswitch (bag.state) {".to_string());
-
 
         for state_node_rcref in &machine_block_node.states {
             state_node_rcref.borrow().accept(self);
         }
 
-        self.serialize.push("".to_string());
-        self.serialize.push("\tvar bag = {".to_string());
-        self.serialize.push("\t\tstate : stateName,".to_string());
-        self.serialize.push("\t\tdomain : {}".to_string());
-        self.serialize.push("\t};".to_string());
-        self.serialize.push("".to_string());
-
-        self.deserialize.push("\t}".to_string());
-        self.deserialize.push("".to_string());
-
 
         AstVisitorReturnType::MachineBlockNode {}
     }
@@ -1209,9 +2276,30 @@ impl AstVisitor for CsVisitorForBob {
         self.add_code(&format!("private void _s{}_(FrameEvent e) {{", state_node.name));
         self.indent();
 
-        self.serialize.push(format!("\tif (_state_ == _s{}_) stateName = \"{}\"",state_node.name,state_node.name));
+        if self.generate_timers {
+            self.newline();
+            self.add_code(&format!("if (e.Msg.EqualsEx(\"{}\")) {{", self.symbol_config.exit_msg_symbol));
+            self.indent();
+            self.newline();
+            self.add_code("_cancelTimer_();");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
+
+        if let Some(deferred_events) = &state_node.deferred_events_opt {
+            self.generate_deferred_event_checks(deferred_events);
+        }
 
-        self.deserialize.push(format!("\t\tcase \"{}\": _state_ = _s{}_; break;",state_node.name,state_node.name));
+        if let Some(timeout_node) = &state_node.timeout_opt {
+            self.newline();
+            self.add_code(&format!("if (e.Msg.EqualsEx(\"{}\")) {{", self.symbol_config.enter_msg_symbol));
+            self.indent();
+            self.generate_timer_start(timeout_node);
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
 
         if let Some(calls) = &state_node.calls_opt {
             for call in calls {
@@ -1223,6 +2311,10 @@ impl AstVisitor for CsVisitorForBob {
 
         self.first_event_handler = true; // context for formatting
 
+        if let Some(timeout_node) = &state_node.timeout_opt {
+            timeout_node.accept(self);
+        }
+
         if state_node.evt_handlers_rcref.len() > 0 {
             for evt_handler_node in &state_node.evt_handlers_rcref {
                 evt_handler_node.as_ref().borrow().accept(self);
@@ -1247,7 +2339,10 @@ impl AstVisitor for CsVisitorForBob {
     //* --------------------------------------------------------------------- *//
 
     fn visit_event_handler_node(&mut self, evt_handler_node: &EventHandlerNode) -> AstVisitorReturnType {
-        self.current_event_ret_type = evt_handler_node.get_event_ret_type();
+        self.current_event_ret_type = match evt_handler_node.get_event_ret_type_node_opt() {
+            Some(ret_type) => self.format_type(&ret_type),
+            None => String::new(),
+        };
         self.newline();
         self.generate_comment(evt_handler_node.line);
 //        let mut generate_final_close_paren = true;
@@ -1478,13 +2573,33 @@ impl AstVisitor for CsVisitorForBob {
 
     fn visit_transition_statement_node(&mut self, transition_statement: &TransitionStatementNode) -> AstVisitorReturnType {
 
+        if self.generate_history {
+            self.newline();
+            self.add_code("_recordHistory_();");
+        }
+
         match &transition_statement.target_state_context_t {
             StateContextType::StateRef {..}
                 => self.generate_state_ref_transition(transition_statement),
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..}
+                => self.generate_state_stack_pop_transition(transition_statement),
+            // a history target is restored like a popped state
+            StateContextType::StateHistory {..}
                 => self.generate_state_stack_pop_transition(transition_statement),
         };
 
+        if self.generate_deferred_events {
+            self.newline();
+            self.add_code("_replayDeferred_();");
+        }
+        if self.generate_final_states && !self.generate_regions {
+            self.generate_finished_check();
+        }
+        if self.generate_completions {
+            self.newline();
+            self.add_code("_complete_();");
+        }
+
         AstVisitorReturnType::CallStatementNode {}
     }
 
@@ -1501,13 +2616,30 @@ impl AstVisitor for CsVisitorForBob {
 
     fn visit_change_state_statement_node(&mut self, change_state_stmt_node:&ChangeStateStatementNode) -> AstVisitorReturnType {
 
+        if self.generate_history {
+            self.newline();
+            self.add_code("_recordHistory_();");
+        }
+
         match &change_state_stmt_node.state_context_t {
             StateContextType::StateRef {..}
                 => self.generate_state_ref_change_state(change_state_stmt_node),
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..} | StateContextType::StateHistory {..}
                 => self.errors.push(format!("Fatal error - change state stack pop not implemented."),)
         };
 
+        if self.generate_deferred_events {
+            self.newline();
+            self.add_code("_replayDeferred_();");
+        }
+        if self.generate_final_states && !self.generate_regions {
+            self.generate_finished_check();
+        }
+        if self.generate_completions {
+            self.newline();
+            self.add_code("_complete_();");
+        }
+
         AstVisitorReturnType::ChangeStateStmtNode {}
     }
 
@@ -1534,6 +2666,33 @@ impl AstVisitor for CsVisitorForBob {
 
     //* --------------------------------------------------------------------- *//
 
+    fn visit_timeout_node(&mut self, timeout_node: &TimeoutNode) -> AstVisitorReturnType {
+        self.newline();
+        self.generate_comment(timeout_node.line);
+        if self.first_event_handler {
+            self.add_code(&format!("if (e.Msg.EqualsEx(\"{}\")) {{", self.symbol_config.timeout_msg_symbol));
+        } else {
+            self.add_code(&format!("else if (e.Msg.EqualsEx(\"{}\")) {{", self.symbol_config.timeout_msg_symbol));
+        }
+        self.indent();
+        if self.generate_state_context {
+            self.newline();
+            self.add_code(&format!("StateContext stateContext = null;"));
+        }
+        timeout_node.transition_statement_node.accept(self);
+        self.newline();
+        self.add_code("return;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+
+        self.first_event_handler = false;
+
+        AstVisitorReturnType::TimeoutNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_test_statement_node(&mut self, test_stmt_node: &TestStatementNode) -> AstVisitorReturnType {
 
         match &test_stmt_node.test_t {
@@ -2055,6 +3214,74 @@ impl AstVisitor for CsVisitorForBob {
 
     //* --------------------------------------------------------------------- *//
 
+    fn visit_list_literal_node(&mut self, list_literal_node: &ListLiteralNode) -> AstVisitorReturnType {
+
+        let mut output = String::new();
+        self.visit_list_literal_node_to_string(list_literal_node, &mut output);
+        self.add_code(&output);
+
+        AstVisitorReturnType::ListLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_list_literal_node_to_string(&mut self, list_literal_node: &ListLiteralNode, output:&mut String) -> AstVisitorReturnType {
+
+        let list_type = match &list_literal_node.type_opt {
+            Some(type_node) => self.format_type(type_node),
+            None => format!("List<{}>", self.format_element_type(&list_literal_node.element_type_opt)),
+        };
+        output.push_str(&format!("new {} {{", list_type));
+        let mut separator = "";
+        for expr_t in &list_literal_node.exprs_t {
+            output.push_str(separator);
+            expr_t.accept_to_string(self, output);
+            separator = ",";
+        }
+        output.push_str("}");
+
+        AstVisitorReturnType::ListLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_map_literal_node(&mut self, map_literal_node: &MapLiteralNode) -> AstVisitorReturnType {
+
+        let mut output = String::new();
+        self.visit_map_literal_node_to_string(map_literal_node, &mut output);
+        self.add_code(&output);
+
+        AstVisitorReturnType::MapLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_map_literal_node_to_string(&mut self, map_literal_node: &MapLiteralNode, output:&mut String) -> AstVisitorReturnType {
+
+        let map_type = match &map_literal_node.type_opt {
+            Some(type_node) => self.format_type(type_node),
+            None => format!("Dictionary<{}, {}>"
+                            , self.format_element_type(&map_literal_node.key_type_opt)
+                            , self.format_element_type(&map_literal_node.value_type_opt)),
+        };
+        output.push_str(&format!("new {} {{", map_type));
+        let mut separator = "";
+        for (key_expr_t, value_expr_t) in &map_literal_node.entries {
+            output.push_str(separator);
+            output.push_str("{");
+            key_expr_t.accept_to_string(self, output);
+            output.push_str(",");
+            value_expr_t.accept_to_string(self, output);
+            output.push_str("}");
+            separator = ",";
+        }
+        output.push_str("}");
+
+        AstVisitorReturnType::MapLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_expression_list_node(&mut self, expr_list: &ExprListNode) -> AstVisitorReturnType {
 
         let mut separator = "";
@@ -2192,9 +3419,9 @@ impl AstVisitor for CsVisitorForBob {
             StateStackOperationType::Push => {
                 self.newline();
                 if self.generate_state_context {
-                    self.add_code(&format!("_stateStack_push_(_state_context_);"));
+                    self.generate_state_stack_push("_stateStack_push_(_state_context_);");
                 } else {
-                    self.add_code(&format!("_stateStack_push_(_state_);"));
+                    self.generate_state_stack_push("_stateStack_push_(_state_);");
                 }
             },
             StateStackOperationType::Pop => {
@@ -2209,6 +3436,26 @@ impl AstVisitor for CsVisitorForBob {
     }
     //* --------------------------------------------------------------------- *//
 
+    fn visit_raise_statement_node(&mut self, raise_statement_node:&RaiseStatementNode) -> AstVisitorReturnType {
+        self.newline();
+        self.add_code(&format!("{}", raise_statement_node.interface_method_name));
+        raise_statement_node.call_expr_list_node.accept(self);
+        self.add_code(";");
+
+        AstVisitorReturnType::RaiseStatementNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_forward_statement_node(&mut self, forward_statement_node:&ForwardStatementNode) -> AstVisitorReturnType {
+        self.newline();
+        self.add_code(&format!("_s{}_(e);", forward_statement_node.target_state_ref.name));
+
+        AstVisitorReturnType::ForwardStatementNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_state_context_node(&mut self, _state_context_node:&StateContextNode) -> AstVisitorReturnType {
 
         // TODO
@@ -2254,11 +3501,26 @@ impl AstVisitor for CsVisitorForBob {
 
         self.newline();
         let action_ret_type:String = match &action_decl_node.type_opt {
-            Some(ret_type) => ret_type.get_type_str(),
+            Some(ret_type) => self.format_type(ret_type),
             None => String::from("void"),
         };
 
         let action_name = self.format_action_name(&action_decl_node.name);
+        if action_decl_node.has_frame_body() {
+            self.add_code(&format!("protected virtual {} {}(",action_ret_type, action_name));
+            if let Some(params) = &action_decl_node.params {
+                self.format_parameter_list(params);
+            }
+            self.add_code(") {");
+            self.indent();
+            self.generate_frame_body(&action_decl_node.statements, action_decl_node.get_return_expr_opt());
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+            self.newline();
+            return AstVisitorReturnType::ActionDeclNode {};
+        }
+
         self.add_code(&format!("protected virtual {} {}(",action_ret_type, action_name));
         let mut action_code = String::new();
         action_code.push_str(&format!("\tprotected virtual {} {}(",action_ret_type, action_name));
@@ -2289,6 +3551,55 @@ impl AstVisitor for CsVisitorForBob {
 
     //* --------------------------------------------------------------------- *//
 
+    fn visit_operations_block_node(&mut self, operations_block_node: &OperationsBlockNode) -> AstVisitorReturnType {
+        self.newline();
+        self.add_code("//===================== Operations Block ===================//");
+        self.newline();
+
+        for operation_node_rcref in &operations_block_node.operations {
+            operation_node_rcref.borrow().accept(self);
+        }
+
+        AstVisitorReturnType::OperationsBlockNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_operation_node(&mut self, operation_node: &OperationNode) -> AstVisitorReturnType {
+
+        self.newline();
+        let return_type = match &operation_node.type_opt {
+            Some(ret) => self.format_type(ret),
+            None => "void".to_string(),
+        };
+
+        self.add_code(&format!("public {} {}(",return_type, operation_node.name));
+
+        match &operation_node.params {
+            Some (params)
+            =>  self.format_parameter_list(params),
+            None => {},
+        }
+
+        self.add_code(") {");
+        self.indent();
+        match &operation_node.code_opt {
+            Some(code) => {
+                self.newline();
+                self.add_code(code);
+            },
+            None => self.generate_frame_body(&operation_node.statements, operation_node.get_return_expr_opt()),
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+
+        AstVisitorReturnType::OperationNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_domain_variable_decl_node(&mut self, variable_decl_node: &VariableDeclNode) -> AstVisitorReturnType {
 
         self.visit_variable_decl_node(variable_decl_node);
@@ -2301,19 +3612,21 @@ impl AstVisitor for CsVisitorForBob {
     fn visit_variable_decl_node(&mut self, variable_decl_node: &VariableDeclNode) -> AstVisitorReturnType {
 
         let var_type = match &variable_decl_node.type_opt {
-            Some(x) => x.get_type_str(),
+            Some(x) => self.format_type(x),
             None => String::from("<?>"),
         };
         let var_name =  &variable_decl_node.name;
         let var_init_expr = &variable_decl_node.initializer_expr_t_opt.as_ref().unwrap();
         self.newline();
+        // Domain parameters are assigned in the constructor.
+        if matches!(variable_decl_node.identifier_decl_scope, IdentifierDeclScope::DomainBlock) && self.domain_param_names.contains(var_name) {
+            self.add_code( &format!("{} {};",var_type,var_name));
+            return AstVisitorReturnType::VariableDeclNode {};
+        }
         let mut code = String::new();
         var_init_expr.accept_to_string(self, &mut code);
         self.add_code( &format!("{} {} = {};",var_type,var_name, code));
 
-        self.serialize.push(format!("\tbag.domain[\"{}\"] = {};",var_name,var_name));
-        self.deserialize.push(format!("\t{} = bag.domain[\"{}\"];",var_name,var_name));
-
         AstVisitorReturnType::VariableDeclNode {}
     }
 
@@ -2390,10 +3703,9 @@ impl AstVisitor for CsVisitorForBob {
 
     fn visit_unary_expr_node(&mut self, unary_expr_node: &UnaryExprNode) -> AstVisitorReturnType {
 
-        // TODO
-        //       self.generate_comment(assignment_expr_node.line);
-        unary_expr_node.operator.accept(self);
-        unary_expr_node.right_rcref.borrow().accept(self);
+        let mut output = String::new();
+        self.visit_unary_expr_node_to_string(unary_expr_node, &mut output);
+        self.add_code(&output);
 
         AstVisitorReturnType::UnaryExprNode {}
     }
@@ -2405,7 +3717,7 @@ impl AstVisitor for CsVisitorForBob {
         // TODO
         //       self.generate_comment(assignment_expr_node.line);
         unary_expr_node.operator.accept_to_string(self, output);
-        unary_expr_node.right_rcref.borrow().accept_to_string(self,output);
+        self.visit_operand_to_string(&unary_expr_node.operator, &unary_expr_node.right_rcref.borrow(), false, output);
 
         AstVisitorReturnType::UnaryExprNode {}
     }
@@ -2414,23 +3726,9 @@ impl AstVisitor for CsVisitorForBob {
 
     fn visit_binary_expr_node(&mut self, binary_expr_node: &BinaryExprNode) -> AstVisitorReturnType {
 
-        // TODO
-        //       self.generate_comment(assignment_expr_node.line);
-        if binary_expr_node.operator == OperatorType::LogicalXor {
-            self.add_code("((");
-            binary_expr_node.left_rcref.borrow().accept(self);
-            self.add_code(") && !(");
-            binary_expr_node.right_rcref.borrow().accept(self);
-            self.add_code(")) || (!(");
-            binary_expr_node.left_rcref.borrow().accept(self);
-            self.add_code(") && (");
-            binary_expr_node.right_rcref.borrow().accept(self);
-            self.add_code("))");
-        } else {
-            binary_expr_node.left_rcref.borrow().accept(self);
-            binary_expr_node.operator.accept(self);
-            binary_expr_node.right_rcref.borrow().accept(self);
-        }
+        let mut output = String::new();
+        self.visit_binary_expr_node_to_string(binary_expr_node, &mut output);
+        self.add_code(&output);
 
         AstVisitorReturnType::BinaryExprNode {}
     }
@@ -2439,26 +3737,41 @@ impl AstVisitor for CsVisitorForBob {
 
     fn visit_binary_expr_node_to_string(&mut self, binary_expr_node: &BinaryExprNode, output:&mut String) -> AstVisitorReturnType {
 
-        if binary_expr_node.operator == OperatorType::LogicalXor {
-            output.push_str("((");
-            binary_expr_node.left_rcref.borrow().accept_to_string(self,output);
-            output.push_str(") && !(");
-            binary_expr_node.right_rcref.borrow().accept_to_string(self,output);
-            output.push_str(")) || (!(");
-            binary_expr_node.left_rcref.borrow().accept_to_string(self,output);
-            output.push_str(") && (");
-            binary_expr_node.right_rcref.borrow().accept_to_string(self,output);
-            output.push_str("))");
+        let operator_type = &binary_expr_node.operator;
+        self.visit_operand_to_string(operator_type, &binary_expr_node.left_rcref.borrow(), true, output);
+        operator_type.accept_to_string(self, output);
+        self.visit_operand_to_string(operator_type, &binary_expr_node.right_rcref.borrow(), false, output);
 
-        } else {
-            binary_expr_node.left_rcref.borrow().accept_to_string(self,output);
-            binary_expr_node.operator.accept_to_string(self, output);
-            binary_expr_node.right_rcref.borrow().accept_to_string(self,output);
-        }
         AstVisitorReturnType::BinaryExprNode {}
     }
 
 
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_conditional_expr_node(&mut self, conditional_expr_node: &ConditionalExprNode) -> AstVisitorReturnType {
+
+        conditional_expr_node.condition_rcref.borrow().accept(self);
+        self.add_code(" ? ");
+        conditional_expr_node.true_expr_rcref.borrow().accept(self);
+        self.add_code(" : ");
+        conditional_expr_node.false_expr_rcref.borrow().accept(self);
+
+        AstVisitorReturnType::ConditionalExprNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_conditional_expr_node_to_string(&mut self, conditional_expr_node: &ConditionalExprNode, output:&mut String) -> AstVisitorReturnType {
+
+        conditional_expr_node.condition_rcref.borrow().accept_to_string(self, output);
+        output.push_str(" ? ");
+        conditional_expr_node.true_expr_rcref.borrow().accept_to_string(self, output);
+        output.push_str(" : ");
+        conditional_expr_node.false_expr_rcref.borrow().accept_to_string(self, output);
+
+        AstVisitorReturnType::ConditionalExprNode {}
+    }
+
     //* --------------------------------------------------------------------- *//
 
     fn visit_operator_type(&mut self, operator_type: &OperatorType) -> AstVisitorReturnType {
@@ -2478,7 +3791,7 @@ impl AstVisitor for CsVisitorForBob {
             OperatorType::NotEqual => self.add_code(" != "),
             OperatorType::LogicalAnd => self.add_code(" && "),
             OperatorType::LogicalOr => self.add_code(" || "),
-            OperatorType::LogicalXor => self.add_code(""),
+            OperatorType::LogicalXor => self.add_code(" ^ "),
         }
 
         AstVisitorReturnType::BinaryExprNode {}
@@ -2503,7 +3816,7 @@ impl AstVisitor for CsVisitorForBob {
             OperatorType::NotEqual => output.push_str(" != "),
             OperatorType::LogicalAnd => output.push_str(" && "),
             OperatorType::LogicalOr => output.push_str(" || "),
-            OperatorType::LogicalXor => output.push_str(""),
+            OperatorType::LogicalXor => output.push_str(" ^ "),
         }
 
         AstVisitorReturnType::BinaryExprNode {}
@@ -19,8 +19,6 @@ pub struct GdScript32Visitor {
     first_event_handler:bool,
     system_name:String,
     first_state_name:String,
-    serialize:Vec<String>,
-    deserialize:Vec<String>,
     subclass_code:Vec<String>,
     warnings:Vec<String>,
     has_states:bool,
@@ -32,6 +30,18 @@ pub struct GdScript32Visitor {
     generate_change_state:bool,
     generate_transition_state:bool,
     event_handler_has_code:bool,
+    generate_timers:bool,
+    generate_event_queue:bool,
+    generate_deferred_events:bool,
+    generate_history:bool,
+    generate_regions:bool,
+    generate_final_states:bool,
+    generate_completions:bool,
+    pushed_state_names:Vec<String>,
+    domain_param_names:Vec<String>,
+    generate_start_enter:bool,
+    state_stack_max_opt:Option<usize>,
+    state_stack_underflow_opt:Option<StateStackUnderflowPolicy>,
 }
 
 impl GdScript32Visitor {
@@ -60,8 +70,6 @@ impl GdScript32Visitor {
             first_event_handler:true,
             system_name:String::new(),
             first_state_name:String::new(),
-            serialize:Vec::new(),
-            deserialize:Vec::new(),
             has_states:false,
             errors:Vec::new(),
             subclass_code:Vec::new(),
@@ -73,6 +81,18 @@ impl GdScript32Visitor {
             generate_change_state,
             generate_transition_state,
             event_handler_has_code:false,
+            generate_timers:false,
+            generate_event_queue:false,
+            generate_deferred_events:false,
+            generate_history:false,
+            generate_regions:false,
+            generate_final_states:false,
+            generate_completions:false,
+            pushed_state_names:Vec::new(),
+            domain_param_names:Vec::new(),
+            generate_start_enter:false,
+            state_stack_max_opt:None,
+            state_stack_underflow_opt:None,
         }
     }
 
@@ -250,6 +270,12 @@ impl GdScript32Visitor {
                         StatementType::StateStackStmt {state_stack_operation_statement_node} => {
                             state_stack_operation_statement_node.accept(self);
                         },
+                        StatementType::RaiseStmt {raise_statement_node} => {
+                            raise_statement_node.accept(self);
+                        },
+                        StatementType::ForwardStmt {forward_statement_node} => {
+                            forward_statement_node.accept(self);
+                        },
                         StatementType::ChangeStateStmt {change_state_stmt} => {
                             change_state_stmt.accept(self);
                         },
@@ -264,6 +290,26 @@ impl GdScript32Visitor {
     }
 
 
+    // Generates a Frame body for an operation or action.
+
+    fn generate_frame_body(&mut self, statements:&Vec<DeclOrStmtType>, return_expr_t_opt:Option<&ExprType>) {
+        self.visit_decl_stmts(statements);
+        match return_expr_t_opt {
+            Some(expr_t) => {
+                self.newline();
+                self.add_code("return ");
+                expr_t.accept(self);
+            },
+            None if statements.is_empty() => {
+                self.newline();
+                self.add_code("pass");
+            },
+            None => {},
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     //* --------------------------------------------------------------------- *//
 
     fn generate_machinery(&mut self, system_node: &SystemNode) {
@@ -306,6 +352,14 @@ impl GdScript32Visitor {
                 self.newline();
                 self.add_code(&format!("self._state_.call_func(exitEvent)"));
                 self.newline();
+                if self.generate_history {
+                    self.add_code(&format!("self._recordHistory_()"));
+                    self.newline();
+                }
+                if self.generate_timers {
+                    self.add_code(&format!("self._cancelTimer_()"));
+                    self.newline();
+                }
                 self.add_code(&format!("self._state_ = newState"));
                 self.newline();
                 if self.generate_state_context {
@@ -318,6 +372,17 @@ impl GdScript32Visitor {
                     self.newline();
                 }
                 self.add_code(&format!("self._state_.call_func(enterEvent)"));
+                if self.generate_deferred_events {
+                    self.newline();
+                    self.add_code(&format!("self._replayDeferred_()"));
+                }
+                if self.generate_final_states && !self.generate_regions {
+                    self.generate_finished_check();
+                }
+                if self.generate_completions {
+                    self.newline();
+                    self.add_code("self._complete_()");
+                }
                 self.outdent();
                 self.newline();
                 // self.add_code(&format!("}}"));
@@ -367,28 +432,672 @@ impl GdScript32Visitor {
             if self.generate_change_state {
                 self.newline();
                 self.newline();
-                self.add_code(&format!("func _changeState_(newState):"));
+                if self.generate_state_context {
+                    self.add_code(&format!("func _changeState_(newState, stateContext):"));
+                } else {
+                    self.add_code(&format!("func _changeState_(newState):"));
+                }
                 self.indent();
                 self.newline();
+                if self.generate_history {
+                    self.add_code(&format!("self._recordHistory_()"));
+                    self.newline();
+                }
+                if self.generate_timers {
+                    self.add_code(&format!("self._cancelTimer_()"));
+                    self.newline();
+                }
                 self.add_code(&format!("self._state_ = newState"));
+                if self.generate_state_context {
+                    self.newline();
+                    self.add_code(&format!("self._stateContext_ = stateContext"));
+                }
+                if self.generate_deferred_events {
+                    self.newline();
+                    self.add_code(&format!("self._replayDeferred_()"));
+                }
+                if self.generate_final_states && !self.generate_regions {
+                    self.generate_finished_check();
+                }
+                if self.generate_completions {
+                    self.newline();
+                    self.add_code("self._complete_()");
+                }
                 self.outdent();
                 self.newline();
 //                self.add_code(&format!("}}"));
             }
+            if self.generate_timers {
+                self.generate_timers();
+            }
+            if self.generate_event_queue {
+                self.generate_event_queue();
+            }
+            if self.generate_deferred_events {
+                self.generate_deferred_events();
+            }
+            if self.generate_history {
+                self.generate_history(system_node);
+            }
+            if self.generate_regions {
+                self.generate_regions();
+            }
+            if self.generate_final_states {
+                self.generate_final_states(system_node);
+            }
+            if self.generate_completions {
+                self.generate_completions(system_node);
+            }
+            if self.generate_start_enter {
+                self.generate_start();
+            }
             self.newline();
 
             if self.arcanium.is_serializable() {
-                for line in self.serialize.iter() {
-                    self.code.push_str(&*format!("{}",line));
-                    self.code.push_str(&*format!("\n{}",self.dent()));
+                self.generate_serialization(system_node);
+            }
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Only one timer is active at a time. It is cancelled when a state is
+    // exited so a timeout always belongs to the current state. The scheduler
+    // passed to _init() needs schedule(delayMs, callback) and cancel() methods.
+
+    fn generate_timers(&mut self) {
+        self.newline();
+        self.newline();
+        self.add_code("var _scheduler_ = null");
+        self.newline();
+        self.add_code("var _timerActive_ = false");
+        self.newline();
+        self.newline();
+        self.add_code("func _startTimer_(delayMs):");
+        self.indent();
+        self.newline();
+        self.add_code("if self._timerActive_:");
+        self.indent();
+        self.newline();
+        self.add_code("return");
+        self.outdent();
+        self.newline();
+        self.add_code("self._timerActive_ = true");
+        self.newline();
+        self.add_code("self._scheduler_.schedule(delayMs, funcref(self,\"_timeout_\"))");
+        self.newline();
+        self.outdent();
+        self.newline();
+        self.add_code("func _cancelTimer_():");
+        self.indent();
+        self.newline();
+        self.add_code("if self._timerActive_:");
+        self.indent();
+        self.newline();
+        self.add_code("self._timerActive_ = false");
+        self.newline();
+        self.add_code("self._scheduler_.cancel()");
+        self.newline();
+        self.outdent();
+        self.outdent();
+        self.newline();
+        self.add_code("func _timeout_():");
+        self.indent();
+        self.newline();
+        self.add_code("if self._timerActive_:");
+        self.indent();
+        self.newline();
+        self.add_code("self._timerActive_ = false");
+        self.newline();
+        if self.generate_event_queue {
+            self.add_code(&format!("self._dispatch_(FrameEvent.new(\"{}\",null))", self.symbol_config.timeout_msg_symbol));
+        } else {
+            self.add_code(&format!("self._state_.call_func(FrameEvent.new(\"{}\",null))", self.symbol_config.timeout_msg_symbol));
+        }
+        self.outdent();
+        self.outdent();
+        self.newline();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Events sent while the machine is handling an event are queued and
+    // dispatched in order once the current event has been fully handled.
+
+    fn generate_event_queue(&mut self) {
+        let state_dispatch = if self.generate_regions {"self._dispatchRegions_"} else {"self._state_.call_func"};
+        self.newline();
+        self.newline();
+        self.add_code("var _eventQueue_ = []");
+        self.newline();
+        self.add_code("var _processing_ = false");
+        self.newline();
+        self.newline();
+        self.add_code("func _dispatch_(e):");
+        self.indent();
+        self.newline();
+        self.add_code("if self._processing_:");
+        self.indent();
+        self.newline();
+        self.add_code("self._eventQueue_.append(e)");
+        self.newline();
+        self.add_code("return");
+        self.outdent();
+        self.newline();
+        self.add_code("self._processing_ = true");
+        self.newline();
+        self.add_code(&format!("{}(e)", state_dispatch));
+        self.newline();
+        self.add_code("while self._eventQueue_.size() > 0:");
+        self.indent();
+        self.newline();
+        self.add_code(&format!("{}(self._eventQueue_.pop_front())", state_dispatch));
+        self.outdent();
+        self.newline();
+        self.add_code("self._processing_ = false");
+        self.outdent();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // The machine is finished once it is in a #[final] state, or once
+    // every region is.
+
+    fn generate_final_states(&mut self, system_node: &SystemNode) {
+        let final_states:Vec<String> = system_node.get_final_state_names().iter()
+            .map(|state_name| format!("state.function == \"_s{}_\"", state_name)).collect();
+        self.newline();
+        self.newline();
+        self.add_code("func _isFinalState_(state):");
+        self.indent();
+        self.newline();
+        self.add_code(&format!("return {}", final_states.join(" or ")));
+        self.outdent();
+        self.newline();
+        if self.generate_regions {
+            self.newline();
+            self.add_code("func is_finished():");
+            self.indent();
+            self.newline();
+            self.add_code("for state in self._regions_:");
+            self.indent();
+            self.newline();
+            self.add_code("if not self._isFinalState_(state):");
+            self.indent();
+            self.newline();
+            self.add_code("return false");
+            self.outdent();
+            self.outdent();
+            self.newline();
+            self.add_code("return true");
+            self.outdent();
+        } else {
+            self.newline();
+            self.add_code("func is_finished():");
+            self.indent();
+            self.newline();
+            self.add_code("return self._isFinalState_(self._state_)");
+            self.outdent();
+        }
+        self.newline();
+        self.newline();
+        self.add_code("func on_finished():");
+        self.indent();
+        self.newline();
+        self.add_code("pass");
+        self.outdent();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Entering a final state completes the machine.
+
+    fn generate_finished_check(&mut self) {
+        self.newline();
+        self.add_code("if self.is_finished():");
+        self.indent();
+        self.newline();
+        self.add_code("self.on_finished()");
+        self.outdent();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // The start state is entered on the first interface call rather than
+    // in _init() so a subclass is fully set up before its actions run.
+
+    fn generate_start(&mut self) {
+        self.newline();
+        self.newline();
+        self.add_code("var _started_ = false");
+        self.newline();
+        self.newline();
+        self.add_code("func _start_():");
+        self.indent();
+        self.newline();
+        self.add_code("self._started_ = true");
+        self.newline();
+        self.add_code("self._state_.call_func(FrameEvent.new(\">\",self._stateContext_.getEnterArgs()))");
+        self.outdent();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Completion transitions are taken one after another in a loop rather
+    // than from inside the transition that entered the state, so long
+    // chains don't grow the stack.
+
+    fn generate_completions(&mut self, system_node: &SystemNode) {
+        self.newline();
+        self.newline();
+        self.add_code("var _completing_ = false");
+        self.newline();
+        self.newline();
+        self.add_code("func _complete_():");
+        self.indent();
+        self.newline();
+        self.add_code("if self._completing_:");
+        self.indent();
+        self.newline();
+        self.add_code("return");
+        self.outdent();
+        self.newline();
+        self.add_code("self._completing_ = true");
+        self.newline();
+        self.add_code("while self._completion_():");
+        self.indent();
+        self.newline();
+        self.add_code("pass");
+        self.outdent();
+        self.newline();
+        self.add_code("self._completing_ = false");
+        self.outdent();
+        self.newline();
+        self.newline();
+        self.add_code("func _completion_():");
+        self.indent();
+        if let Some(machine_block_node) = &system_node.machine_block_node_opt {
+            for state_node_rcref in &machine_block_node.states {
+                let state_node = state_node_rcref.borrow();
+                if state_node.completions.len() == 0 {
+                    continue;
+                }
+                self.current_state_name_opt = Some(state_node.name.clone());
+                self.newline();
+                self.add_code(&format!("if self._state_.function == \"_s{}_\":", state_node.name));
+                self.indent();
+                for completion_node in &state_node.completions {
+                    if let Some(guard_expr_t) = &completion_node.guard_expr_t_opt {
+                        let mut guard = String::new();
+                        guard_expr_t.accept_to_string(self, &mut guard);
+                        self.newline();
+                        self.add_code(&format!("if {}:", guard));
+                        self.indent();
+                    }
+                    completion_node.transition_statement_node.accept(self);
+                    self.newline();
+                    self.add_code("return true");
+                    if completion_node.guard_expr_t_opt.is_some() {
+                        self.outdent();
+                    }
                 }
+                self.outdent();
+                self.current_state_name_opt = None;
+            }
+        }
+        self.newline();
+        self.add_code("return false");
+        self.outdent();
+    }
+
+    //* --------------------------------------------------------------------- *//
 
-                for line in self.deserialize.iter() {
-                    self.code.push_str(&*format!("{}",line));
-                    self.code.push_str(&*format!("\n{}",self.dent()));
+    // The snapshot is a JSON document written with to_json(). Godot parses
+    // every JSON number as a float so ints are converted back on restore.
+
+    fn generate_serialization(&mut self, system_node: &SystemNode) {
+        let domain_vars = system_node.get_domain_vars();
+        let snapshot_version = system_node.get_snapshot_version();
+        let snapshot_hash = system_node.get_snapshot_hash();
+        self.newline();
+        self.add_code("func _serialize__do():");
+        self.indent();
+        self.newline();
+        self.add_code("var bag = {}");
+        self.newline();
+        self.add_code(&format!("bag[\"version\"] = {}", snapshot_version));
+        self.newline();
+        self.add_code(&format!("bag[\"hash\"] = \"{}\"", snapshot_hash));
+        self.newline();
+        if self.generate_state_context {
+            self.add_code("bag[\"state\"] = self._serializeState_(self._stateContext_)");
+        } else {
+            self.add_code("bag[\"state\"] = self._serializeState_(self._state_)");
+        }
+        if self.generate_state_stack {
+            self.newline();
+            self.add_code("var stateStack = []");
+            self.newline();
+            self.add_code("for state in self._stateStack_:");
+            self.indent();
+            self.newline();
+            self.add_code("stateStack.append(self._serializeState_(state))");
+            self.outdent();
+            self.newline();
+            self.add_code("bag[\"stateStack\"] = stateStack");
+        }
+        self.newline();
+        self.add_code("var domain = {}");
+        for var_rcref in &domain_vars {
+            let var_name = var_rcref.borrow().name.clone();
+            self.newline();
+            self.add_code(&format!("domain[\"{}\"] = self.{}", var_name, var_name));
+        }
+        self.newline();
+        self.add_code("bag[\"domain\"] = domain");
+        self.newline();
+        self.add_code("return to_json(bag)");
+        self.outdent();
+        self.newline();
+        self.newline();
+        self.add_code("func _deserialize__do(data):");
+        self.indent();
+        self.newline();
+        self.add_code("var bag = parse_json(data)");
+        self.newline();
+        self.add_code("var version = int(bag[\"version\"])");
+        self.newline();
+        self.add_code(&format!("if version > {}:", snapshot_version));
+        self.indent();
+        self.newline();
+        self.add_code(&format!("push_error(\"Snapshot version \" + str(version) + \" is newer than version {} of {}\")", snapshot_version, system_node.name));
+        self.newline();
+        self.add_code("return");
+        self.outdent();
+        self.newline();
+        self.add_code(&format!("if version == {} and bag[\"hash\"] != \"{}\":", snapshot_version, snapshot_hash));
+        self.indent();
+        self.newline();
+        self.add_code(&format!("push_error(\"Snapshot does not match version {} of {}\")", snapshot_version, system_node.name));
+        self.newline();
+        self.add_code("return");
+        self.outdent();
+        self.newline();
+        if self.generate_state_context {
+            self.add_code("self._stateContext_ = self._deserializeState_(bag[\"state\"])");
+            self.newline();
+            self.add_code("self._state_ = self._stateContext_.state");
+        } else {
+            self.add_code("self._state_ = self._deserializeState_(bag[\"state\"])");
+        }
+        if self.generate_state_stack {
+            self.newline();
+            self.add_code("self._stateStack_ = []");
+            self.newline();
+            self.add_code("for state in bag[\"stateStack\"]:");
+            self.indent();
+            self.newline();
+            self.add_code("self._stateStack_.append(self._deserializeState_(state))");
+            self.outdent();
+        }
+        if self.generate_start_enter {
+            self.newline();
+            self.add_code("self._started_ = true");
+        }
+        self.newline();
+        self.add_code("var domain = bag[\"domain\"]");
+        for var_rcref in &domain_vars {
+            let var = var_rcref.borrow();
+            let value = self.format_serialized_value(&format!("domain[\"{}\"]", var.name), &var.type_opt);
+            self.newline();
+            if var.has_snapshot_default() {
+                self.add_code(&format!("if domain.has(\"{}\"):", var.name));
+                self.indent();
+                self.newline();
+                self.add_code(&format!("self.{} = {}", var.name, value));
+                self.outdent();
+            } else {
+                self.add_code(&format!("self.{} = {}", var.name, value));
+            }
+        }
+        self.outdent();
+        self.newline();
+        self.newline();
+        if self.generate_state_context {
+            self.add_code("func _serializeState_(stateContext):");
+        } else {
+            self.add_code("func _serializeState_(state):");
+        }
+        self.indent();
+        self.newline();
+        self.add_code("var data = {}");
+        if self.generate_state_context {
+            self.newline();
+            self.add_code("var stateArgs = {}");
+            self.newline();
+            self.add_code("var stateVars = {}");
+        }
+        for state_node_rcref in system_node.get_states() {
+            let state_node = state_node_rcref.borrow();
+            let state_ref = self.format_target_state_name(&state_node.name);
+            self.newline();
+            if self.generate_state_context {
+                self.add_code(&format!("if stateContext.state.function == \"{}\":", state_ref));
+            } else {
+                self.add_code(&format!("if state.function == \"{}\":", state_ref));
+            }
+            self.indent();
+            self.newline();
+            self.add_code(&format!("data[\"state\"] = \"{}\"", state_node.name));
+            for param_name in state_node.get_param_names() {
+                self.newline();
+                self.add_code(&format!("stateArgs[\"{}\"] = stateContext.getStateArg(\"{}\")", param_name, param_name));
+            }
+            for var_name in state_node.get_var_names() {
+                self.newline();
+                self.add_code(&format!("stateVars[\"{}\"] = stateContext.getStateVar(\"{}\")", var_name, var_name));
+            }
+            self.outdent();
+        }
+        if self.generate_state_context {
+            self.newline();
+            self.add_code("data[\"stateArgs\"] = stateArgs");
+            self.newline();
+            self.add_code("data[\"stateVars\"] = stateVars");
+        }
+        self.newline();
+        self.add_code("return data");
+        self.outdent();
+        self.newline();
+        self.newline();
+        self.add_code("func _deserializeState_(data):");
+        self.indent();
+        for state_node_rcref in system_node.get_states() {
+            let state_node = state_node_rcref.borrow();
+            let state_ref = self.format_target_state_name(&state_node.name);
+            let conditions:Vec<String> = std::iter::once(state_node.name.clone()).chain(state_node.get_renamed_from())
+                .map(|name| format!("data[\"state\"] == \"{}\"", name)).collect();
+            self.newline();
+            self.add_code(&format!("if {}:", conditions.join(" or ")));
+            self.indent();
+            self.newline();
+            if self.generate_state_context {
+                self.add_code(&format!("var stateContext = StateContext.new(funcref(self,\"{}\"))", state_ref));
+                if let Some(params) = &state_node.params_opt {
+                    for param in params {
+                        let value = self.format_serialized_value(&format!("data[\"stateArgs\"][\"{}\"]", param.param_name), &param.param_type_opt);
+                        self.newline();
+                        self.add_code(&format!("stateContext.addStateArg(\"{}\",{})", param.param_name, value));
+                    }
+                }
+                if let Some(vars) = &state_node.vars_opt {
+                    for var_rcref in vars {
+                        let var = var_rcref.borrow();
+                        let value = self.format_serialized_value(&format!("data[\"stateVars\"][\"{}\"]", var.name), &var.type_opt);
+                        self.newline();
+                        self.add_code(&format!("stateContext.addStateVar(\"{}\",{})", var.name, value));
+                    }
+                }
+                self.newline();
+                self.add_code("return stateContext");
+            } else {
+                self.add_code(&format!("return funcref(self,\"{}\")", state_ref));
+            }
+            self.outdent();
+        }
+        self.newline();
+        self.add_code("push_error(\"Unknown state \" + data[\"state\"])");
+        self.newline();
+        self.add_code("return null");
+        self.outdent();
+        self.newline();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Restored ints are converted back from the floats JSON parses them as.
+
+    fn format_serialized_value(&self, value:&str, type_opt:&Option<TypeNode>) -> String {
+        match type_opt.as_ref().and_then(|type_node| type_node.get_frame_type_opt()) {
+            Some(FrameType::Int) => format!("int({})", value),
+            _ => value.to_string(),
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Each region has its own active state. An event is dispatched to
+    // every region in turn with the region's state made current.
+
+    fn generate_regions(&mut self) {
+        self.newline();
+        self.newline();
+        self.add_code("var _regions_ = []");
+        self.newline();
+        self.newline();
+        self.add_code("func _dispatchRegions_(e):");
+        self.indent();
+        if self.generate_final_states {
+            self.newline();
+            self.add_code("var wasFinished = self.is_finished()");
+        }
+        self.newline();
+        self.add_code("for i in range(self._regions_.size()):");
+        self.indent();
+        self.newline();
+        self.add_code("self._state_ = self._regions_[i]");
+        self.newline();
+        self.add_code("self._state_.call_func(e)");
+        self.newline();
+        self.add_code("self._regions_[i] = self._state_");
+        self.outdent();
+        if self.generate_final_states {
+            self.newline();
+            self.add_code("if not wasFinished and self.is_finished():");
+            self.indent();
+            self.newline();
+            self.add_code("self.on_finished()");
+            self.outdent();
+        }
+        self.outdent();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // History is recorded on the way out of a state. Every ancestor that
+    // is a history target remembers the child it was left through and
+    // the leaf state that was active.
+
+    fn generate_history(&mut self, system_node: &SystemNode) {
+        self.newline();
+        self.newline();
+        self.add_code("var _shallowHistory_ = {}");
+        self.newline();
+        self.add_code("var _deepHistory_ = {}");
+        self.newline();
+        self.newline();
+        self.add_code("func _recordHistory_():");
+        self.indent();
+        let mut is_first = true;
+        if let Some(machine_block_node) = &system_node.machine_block_node_opt {
+            for state_node_rcref in &machine_block_node.states {
+                let state_node = state_node_rcref.borrow();
+                if state_node.history_records.len() == 0 {
+                    continue;
+                }
+                let state_ref = self.format_target_state_name(&state_node.name);
+                self.newline();
+                if is_first {
+                    self.add_code(&format!("if self._state_.function == \"{}\":", state_ref));
+                } else {
+                    self.add_code(&format!("elif self._state_.function == \"{}\":", state_ref));
                 }
+                is_first = false;
+                self.indent();
+                for history_record in &state_node.history_records {
+                    let child_ref = self.format_target_state_name(&history_record.child_state_name);
+                    self.newline();
+                    self.add_code(&format!("self._shallowHistory_[\"{}\"] = funcref(self,\"{}\")", history_record.parent_state_name, child_ref));
+                    self.newline();
+                    self.add_code(&format!("self._deepHistory_[\"{}\"] = funcref(self,\"{}\")", history_record.parent_state_name, state_ref));
+                }
+                self.outdent();
             }
         }
+        self.outdent();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Deferred events are replayed in the order they arrived. Any the
+    // new state defers again go back on the queue.
+
+    fn generate_deferred_events(&mut self) {
+        self.newline();
+        self.newline();
+        self.add_code("var _deferredQueue_ = []");
+        self.newline();
+        self.newline();
+        self.add_code("func _replayDeferred_():");
+        self.indent();
+        self.newline();
+        self.add_code("var deferred = self._deferredQueue_");
+        self.newline();
+        self.add_code("self._deferredQueue_ = []");
+        self.newline();
+        self.add_code("for e in deferred:");
+        self.indent();
+        self.newline();
+        self.add_code("self._state_.call_func(e)");
+        self.outdent();
+        self.outdent();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // A deferred event is put aside rather than handled and is replayed
+    // after the next transition.
+
+    fn generate_deferred_event_checks(&mut self, deferred_events:&Vec<String>) {
+        for msg in deferred_events {
+            self.newline();
+            self.add_code(&format!("if e._message == \"{}\":", msg));
+            self.indent();
+            self.newline();
+            self.add_code("self._deferredQueue_.append(e)");
+            self.newline();
+            self.add_code("return");
+            self.outdent();
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn generate_timer_start(&mut self, timeout_node:&TimeoutNode) {
+        let mut duration = String::new();
+        timeout_node.duration_expr_t.accept_to_string(self, &mut duration);
+        self.newline();
+        self.add_code(&format!("self._startTimer_({})", duration));
     }
 
     //* --------------------------------------------------------------------- *//
@@ -441,8 +1150,21 @@ impl GdScript32Visitor {
             },
         };
 
+        let target_state_args_opt = match &change_state_stmt_node.state_context_t {
+            StateContextType::StateRef { state_context_node}
+                => &state_context_node.state_ref_args_opt,
+            _ => &Option::None,
+        };
+
         self.newline();
-        self.add_code(&format!("self._changeState_(funcref(self,\"{}\"))",self.format_target_state_name(target_state_name)));
+        if self.generate_state_context {
+            self.add_code(&format!("var stateContext = StateContext.new(funcref(self,\"{}\"))", self.generate_state_ref_code(target_state_name)));
+            self.newline();
+            self.generate_state_args_and_vars(target_state_name, target_state_args_opt);
+            self.add_code(&format!("self._changeState_(funcref(self,\"{}\"),stateContext)",self.format_target_state_name(target_state_name)));
+        } else {
+            self.add_code(&format!("self._changeState_(funcref(self,\"{}\"))",self.format_target_state_name(target_state_name)));
+        }
 
     }
 
@@ -533,7 +1255,7 @@ impl GdScript32Visitor {
         let enter_args_opt = match &transition_statement.target_state_context_t {
             StateContextType::StateRef { state_context_node}
             => &state_context_node.enter_args_opt,
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..} | StateContextType::StateHistory {..}
             => &None,
         };
 
@@ -577,10 +1299,38 @@ impl GdScript32Visitor {
         let target_state_args_opt = match &transition_statement.target_state_context_t {
             StateContextType::StateRef { state_context_node}
             => &state_context_node.state_ref_args_opt,
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..} | StateContextType::StateHistory {..}
             => &Option::None,
         };
 //
+        self.generate_state_args_and_vars(&target_state_name, target_state_args_opt);
+        let exit_args = if has_exit_args {
+            "exitArgs"
+        } else {
+            "None"
+        };
+        if self.generate_state_context {
+            if self.generate_exit_args {
+                self.add_code(&format!("self._transition_(funcref(self,\"{}\"),{},stateContext)",self.format_target_state_name(target_state_name),exit_args));
+            } else {
+                self.add_code(&format!("self._transition_(funcref(self,\"{}\"),stateContext)",self.format_target_state_name(target_state_name)));
+            }
+        } else {
+            if self.generate_exit_args {
+                self.add_code(&format!("self._transition_(funcref(self,\"{}\"),{})",self.format_target_state_name(target_state_name),exit_args));
+            } else {
+                self.add_code(&format!("self._transition_(funcref(self,\"{}\"))",self.format_target_state_name(target_state_name)));
+            }
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Adds the target state's arguments and initialized
+    // state variables to the new state context.
+
+    fn generate_state_args_and_vars(&mut self, target_state_name:&str, target_state_args_opt:&Option<ExprListNode>) {
+
         if let Some(state_args) = target_state_args_opt {
 //            let mut params_copy = Vec::new();
             if let Some(state_sym) = self.arcanium.get_state(&target_state_name) {
@@ -637,26 +1387,8 @@ impl GdScript32Visitor {
                 }
             },
             None => {
-//                code = target_state_vars.clone();
-            },
-        }
-        let exit_args = if has_exit_args {
-            "exitArgs"
-        } else {
-            "None"
-        };
-        if self.generate_state_context {
-            if self.generate_exit_args {
-                self.add_code(&format!("self._transition_(funcref(self,\"{}\"),{},stateContext)",self.format_target_state_name(target_state_name),exit_args));
-            } else {
-                self.add_code(&format!("self._transition_(funcref(self,\"{}\"),stateContext)",self.format_target_state_name(target_state_name)));
-            }
-        } else {
-            if self.generate_exit_args {
-                self.add_code(&format!("self._transition_(funcref(self,\"{}\"),{})",self.format_target_state_name(target_state_name),exit_args));
-            } else {
-                self.add_code(&format!("self._transition_(funcref(self,\"{}\"))",self.format_target_state_name(target_state_name)));
-            }
+//                code = target_state_vars.clone();
+            },
         }
     }
 
@@ -731,12 +1463,29 @@ impl GdScript32Visitor {
             }
         }
 
-        if self.generate_state_context {
+        if let StateContextType::StateStackPop {..} = &transition_statement.target_state_context_t {
+            let exit_args = match &transition_statement.exit_args_opt {
+                Some(exit_args) if exit_args.exprs_t.len() > 0 => "exitArgs",
+                _ => "null",
+            };
+            self.generate_state_stack_underflow_check(exit_args);
+        }
+        if let StateContextType::StateHistory {state_ref_node, is_deep} = &transition_statement.target_state_context_t {
+            let history_map = if *is_deep {"_deepHistory_"} else {"_shallowHistory_"};
+            self.add_code(&format!("var state = self.{}.get(\"{}\", funcref(self,\"{}\"))", history_map, state_ref_node.name, self.format_target_state_name(&state_ref_node.name)));
+            if self.generate_state_context {
+                self.newline();
+                self.add_code("var stateContext = StateContext.new(state)");
+            }
+        } else if self.generate_state_context {
             self.add_code(&format!("var stateContext = self._stateStack_pop_()"));
         } else {
             self.add_code(&format!("var state = self._stateStack_pop_()"));
         }
         self.newline();
+        if let StateContextType::StateStackPop { enter_args_opt:Some(enter_args) } = &transition_statement.target_state_context_t {
+            self.generate_state_stack_pop_enter_args(enter_args);
+        }
         if self.generate_exit_args {
             if self.generate_state_context {
                 self.add_code(&format!("self._transition_(stateContext.state,exitArgs,stateContext)"));
@@ -751,6 +1500,175 @@ impl GdScript32Visitor {
             }
         }
     }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Refuses to push past #![state_stack_max], tells the current state
+    // with a stateStackOverflow event and ends the handler so the
+    // transition after the push does not happen.
+
+    fn generate_state_stack_overflow_check(&mut self) {
+        let max = match self.state_stack_max_opt {
+            Some(max) => max,
+            None => return,
+        };
+        self.add_code(&format!("if self._stateStack_.size() >= {}:", max));
+        self.indent();
+        self.newline();
+        let message = self.symbol_config.state_stack_overflow_msg_symbol.clone();
+        self.generate_state_stack_error_event(&message);
+        self.newline();
+        self.add_code("return");
+        self.outdent();
+        self.newline();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Applies the #![state_stack_underflow] policy when $$[-] finds the
+    // state stack empty.
+
+    fn generate_state_stack_underflow_check(&mut self, exit_args:&str) {
+        let underflow = match &self.state_stack_underflow_opt {
+            Some(underflow) => underflow.clone(),
+            None => return,
+        };
+        self.add_code("if self._stateStack_.size() == 0:");
+        self.indent();
+        self.newline();
+        match underflow {
+            StateStackUnderflowPolicy::Error => {
+                let message = self.symbol_config.state_stack_underflow_msg_symbol.clone();
+                self.generate_state_stack_error_event(&message);
+                self.newline();
+            },
+            StateStackUnderflowPolicy::Stay => {},
+            StateStackUnderflowPolicy::State {state_name} => {
+                if self.generate_state_context {
+                    self.add_code(&format!("var stateContext = StateContext.new(funcref(self,\"{}\"))", self.generate_state_ref_code(&state_name)));
+                    self.newline();
+                    self.generate_state_args_and_vars(&state_name, &None);
+                    if self.generate_exit_args {
+                        self.add_code(&format!("self._transition_(funcref(self,\"{}\"),{},stateContext)", self.format_target_state_name(&state_name), exit_args));
+                    } else {
+                        self.add_code(&format!("self._transition_(funcref(self,\"{}\"),stateContext)", self.format_target_state_name(&state_name)));
+                    }
+                } else if self.generate_exit_args {
+                    self.add_code(&format!("self._transition_(funcref(self,\"{}\"),{})", self.format_target_state_name(&state_name), exit_args));
+                } else {
+                    self.add_code(&format!("self._transition_(funcref(self,\"{}\"))", self.format_target_state_name(&state_name)));
+                }
+                self.newline();
+            },
+        }
+        self.add_code("return");
+        self.outdent();
+        self.newline();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Sends a state stack error event to the current state.
+
+    fn generate_state_stack_error_event(&mut self, message:&str) {
+        if self.generate_event_queue {
+            self.add_code(&format!("self._dispatch_(FrameEvent.new(\"{}\",null))", message));
+        } else {
+            self.add_code(&format!("self._state_.call_func(FrameEvent.new(\"{}\",null))", message));
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Adds the enter arguments of a state stack pop to the popped state
+    // context. Every pushed state takes the same enter parameters so the
+    // first one names them.
+
+    fn generate_state_stack_pop_enter_args(&mut self, enter_args:&ExprListNode) {
+        let mut msg = match self.pushed_state_names.first() {
+            Some(pushed_state_name) => pushed_state_name.clone(),
+            None => return,
+        };
+        msg.push_str(":");
+        msg.push_str(&self.symbol_config.enter_msg_symbol);
+
+        if let Some(event_sym) = self.arcanium.get_event(&msg,&self.current_state_name_opt) {
+            if let Some(event_params) = &event_sym.borrow().params_opt {
+                for (p, expr_t) in event_params.iter().zip(enter_args.exprs_t.iter()) {
+                    let mut expr = String::new();
+                    expr_t.accept_to_string(self,&mut expr);
+                    self.add_code(&format!("stateContext.addEnterArg(\"{}\",{})", p.name, expr));
+                    self.newline();
+                }
+            }
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Maps portable Frame types to GDScript. Other types are emitted as written.
+    // GDScript types have no reference marker so Frame's '&' is dropped.
+
+    fn format_type(&self, type_node:&TypeNode) -> String {
+        match type_node.get_frame_type_opt() {
+            Some(frame_type) => self.format_frame_type(frame_type),
+            None => type_node.get_type_str(),
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn format_frame_type(&self, frame_type:&FrameType) -> String {
+        match frame_type {
+            FrameType::Int => String::from("int"),
+            FrameType::Float => String::from("float"),
+            FrameType::Bool => String::from("bool"),
+            FrameType::String => String::from("String"),
+            FrameType::List {..} => String::from("Array"),
+            FrameType::Map {..} => String::from("Dictionary"),
+            // Type parameters are erased and their values left untyped.
+            FrameType::TypeParam {..} => String::new(),
+        }
+    }
+}
+
+//* --------------------------------------------------------------------- *//
+
+impl OperatorPrecedence for GdScript32Visitor {
+
+    //* --------------------------------------------------------------------- *//
+
+    // Binding strength of each operator in GDScript, higher binds tighter.
+    // Xor is lowered to '!=' on bools so it sits with the comparisons.
+
+    fn operator_precedence(&self, operator_type:&OperatorType) -> usize {
+        match operator_type {
+            OperatorType::LogicalOr => 1,
+            OperatorType::LogicalAnd => 2,
+            OperatorType::Not => 3,
+            OperatorType::EqualEqual
+            | OperatorType::NotEqual
+            | OperatorType::Greater
+            | OperatorType::GreaterEqual
+            | OperatorType::Less
+            | OperatorType::LessEqual
+            | OperatorType::LogicalXor => 4,
+            OperatorType::Plus
+            | OperatorType::Minus => 5,
+            OperatorType::Multiply
+            | OperatorType::Divide => 6,
+            OperatorType::Negated => 7,
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Comparisons are kept non-associative so that GDScript output reads
+    // the same as the Python output.
+
+    fn is_non_associative(&self, operator_type:&OperatorType) -> bool {
+        self.operator_precedence(operator_type) == 4
+    }
 }
 
 //* --------------------------------------------------------------------- *//
@@ -770,6 +1688,19 @@ impl AstVisitor for GdScript32Visitor {
 
         self.newline();
 
+        self.generate_timers = system_node.has_timeouts();
+        self.generate_event_queue = system_node.is_run_to_completion();
+        self.generate_deferred_events = system_node.has_deferred_events();
+        self.generate_history = system_node.has_history();
+        self.generate_regions = system_node.has_regions();
+        self.generate_final_states = system_node.get_final_state_names().len() > 0;
+        self.generate_completions = system_node.has_completions();
+        self.pushed_state_names = system_node.get_pushed_state_names();
+        self.domain_param_names = system_node.get_domain_param_names();
+        self.generate_start_enter = system_node.start_state_enter_params_opt.is_some();
+        self.state_stack_max_opt = system_node.get_state_stack_max();
+        self.state_stack_underflow_opt = system_node.get_state_stack_underflow();
+
         // First state name needed for machinery.
         // Don't generate if there isn't at least one state.
         match (&system_node).get_first_state() {
@@ -784,10 +1715,26 @@ impl AstVisitor for GdScript32Visitor {
 
         if self.has_states {
             self.newline();
-            self.add_code(&format!("func _init():"));
+            let mut constructor_params:Vec<String> = system_node.get_system_params().iter()
+                .map(|param| param.param_name.clone())
+                .collect();
+            if self.generate_timers {
+                constructor_params.push(String::from("scheduler"));
+            }
+            self.add_code(&format!("func _init({}):", constructor_params.join(",")));
             self.indent();
             self.newline();
             self.add_code(&format!("self._state_ = funcref(self,\"_s{}_\")", self.first_state_name));
+            if self.generate_regions {
+                let region_states:Vec<String> = system_node.get_region_initial_state_names().iter()
+                    .map(|state_name| format!("funcref(self,\"_s{}_\")", state_name)).collect();
+                self.newline();
+                self.add_code(&format!("self._regions_ = [{}]", region_states.join(", ")));
+            }
+            if self.generate_timers {
+                self.newline();
+                self.add_code(&format!("self._scheduler_ = scheduler"));
+            }
 
             if self.generate_state_context {
                 self.newline();
@@ -809,6 +1756,12 @@ impl AstVisitor for GdScript32Visitor {
                     }
 
                 }
+                if let Some(params) = &system_node.start_state_state_params_opt {
+                    for param in params {
+                        self.newline();
+                        self.add_code(&format!("self._stateContext_.addStateArg(\"{}\",{})", param.param_name, param.param_name));
+                    }
+                }
             }
 
             if self.generate_state_stack {
@@ -816,19 +1769,34 @@ impl AstVisitor for GdScript32Visitor {
                 self.add_code(&format!("self._stateStack_ = []"));
             }
 
+            // The start state is never entered so start its timer here.
+            if let Some(first_state_node_rcref) = system_node.get_first_state() {
+                let first_state_node = first_state_node_rcref.borrow();
+                if let Some(timeout_node) = &first_state_node.timeout_opt {
+                    self.current_state_name_opt = Some(first_state_node.name.clone());
+                    self.generate_timer_start(timeout_node);
+                    self.current_state_name_opt = None;
+                }
+            }
+
+            for var_name in &self.domain_param_names.clone() {
+                self.newline();
+                self.add_code(&format!("self.{} = {}", var_name, var_name));
+            }
+
+            // The start state is entered with these on the first event.
+            if let Some(params) = &system_node.start_state_enter_params_opt {
+                for param in params {
+                    self.newline();
+                    self.add_code(&format!("self._stateContext_.addEnterArg(\"{}\",{})", param.param_name, param.param_name));
+                }
+            }
+
             self.outdent();
             self.newline();
             // end of generate constructor
         }
 
-        self.serialize.push("".to_string());
-        self.serialize.push("Bag _serialize__do() {".to_string());
-
-        self.deserialize.push("".to_string());
-
-        // @TODO: _do needs to be configurable.
-        self.deserialize.push("void _deserialize__do(Bag data) {".to_string());
-
 
         self.subclass_code.push("".to_string());
         self.subclass_code.push("/********************".to_string());
@@ -838,6 +1806,10 @@ impl AstVisitor for GdScript32Visitor {
             interface_block_node.accept(self);
         }
 
+        if let Some(operations_block_node) = &system_node.operations_block_node_opt {
+            operations_block_node.accept(self);
+        }
+
         if let Some(machine_block_node) = &system_node.machine_block_node_opt {
             machine_block_node.accept(self);
         }
@@ -848,14 +1820,6 @@ impl AstVisitor for GdScript32Visitor {
 
         self.subclass_code.push("********************/".to_string());
 
-        self.serialize.push("".to_string());
-        self.serialize.push("\treturn JSON.stringify(bag)".to_string());
-        self.serialize.push("}".to_string());
-        self.serialize.push("".to_string());
-
-        self.deserialize.push("".to_string());
-        self.deserialize.push("}".to_string());
-
         if self.has_states {
             self.generate_machinery(system_node);
         }
@@ -959,12 +1923,42 @@ impl AstVisitor for GdScript32Visitor {
             params_param_code = String::from("null");
         }
 
+        if *method_name_or_alias == self.symbol_config.restore_msg_symbol {
+            if let Some(params) = &interface_method_node.params {
+                self.newline();
+                self.add_code(&format!("self._deserialize__do({})", params[0].param_name));
+            }
+        }
+        if self.generate_start_enter {
+            self.newline();
+            self.add_code("if not self._started_:");
+            self.indent();
+            self.newline();
+            self.add_code("self._start_()");
+            self.outdent();
+        }
         self.newline();
         self.add_code(&format!("var e = FrameEvent.new(\"{}\",{})", method_name_or_alias,params_param_code));
+        if let Some(default_return_expr_t) = &interface_method_node.default_return_expr_opt {
+            let mut default_return = String::new();
+            default_return_expr_t.accept_to_string(self, &mut default_return);
+            self.newline();
+            self.add_code(&format!("e._return = {}", default_return));
+        }
         self.newline();
-        self.add_code(&format!("self._state_.call_func(e)"));
+        if self.generate_event_queue {
+            self.add_code(&format!("self._dispatch_(e)"));
+        } else if self.generate_regions {
+            self.add_code(&format!("self._dispatchRegions_(e)"));
+        } else {
+            self.add_code(&format!("self._state_.call_func(e)"));
+        }
 
         match &interface_method_node.return_type_opt {
+            Some(_) if *method_name_or_alias == self.symbol_config.save_msg_symbol => {
+                self.newline();
+                self.add_code("return self._serialize__do()");
+            },
             Some(_) => {
                 self.newline();
                 self.add_code(&format!("return e._return"));
@@ -987,29 +1981,11 @@ impl AstVisitor for GdScript32Visitor {
         self.newline();
         self.newline();
 
-        self.serialize.push("".to_string());
-        self.serialize.push("\tvar stateName = null;".to_string());
-
-        self.deserialize.push("".to_string());
-        self.deserialize.push("\tbag = JSON.parse(data)".to_string());
-        self.deserialize.push("".to_string());
-        self.deserialize.push("\tswitch (bag.state) {".to_string());
-
 
         for state_node_rcref in &machine_block_node.states {
             state_node_rcref.borrow().accept(self);
         }
 
-        self.serialize.push("".to_string());
-        self.serialize.push("\tbag = {".to_string());
-        self.serialize.push("\t\tstate : stateName,".to_string());
-        self.serialize.push("\t\tdomain : {}".to_string());
-        self.serialize.push("\t}".to_string());
-        self.serialize.push("".to_string());
-
-        self.deserialize.push("\t}".to_string());
-        self.deserialize.push("".to_string());
-
 
         AstVisitorReturnType::MachineBlockNode {}
     }
@@ -1073,11 +2049,21 @@ impl AstVisitor for GdScript32Visitor {
         self.add_code(&format!("func _s{}_(e):", state_node.name));
         self.indent();
 
-        self.serialize.push(format!("\tif (self._state_ == _s{}_) stateName = \"{}\"",state_node.name,state_node.name));
+        let mut generate_pass = true;
 
-        self.deserialize.push(format!("\t\tcase \"{}\": _state_ = _s{}_; break;",state_node.name,state_node.name));
+        if let Some(deferred_events) = &state_node.deferred_events_opt {
+            generate_pass = false;
+            self.generate_deferred_event_checks(deferred_events);
+        }
 
-        let mut generate_pass = true;
+        if let Some(timeout_node) = &state_node.timeout_opt {
+            generate_pass = false;
+            self.newline();
+            self.add_code(&format!("if e._message == \"{}\":", self.symbol_config.enter_msg_symbol));
+            self.indent();
+            self.generate_timer_start(timeout_node);
+            self.outdent();
+        }
 
         if let Some(calls) = &state_node.calls_opt {
             generate_pass = false;
@@ -1089,6 +2075,10 @@ impl AstVisitor for GdScript32Visitor {
 
         self.first_event_handler = true; // context for formatting
 
+        if let Some(timeout_node) = &state_node.timeout_opt {
+            timeout_node.accept(self);
+        }
+
         if state_node.evt_handlers_rcref.len() > 0 {
             generate_pass = false;
             for evt_handler_node in &state_node.evt_handlers_rcref {
@@ -1336,8 +2326,11 @@ impl AstVisitor for GdScript32Visitor {
         match &transition_statement.target_state_context_t {
             StateContextType::StateRef {..}
             => self.generate_state_ref_transition(transition_statement),
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..}
             => self.generate_state_stack_pop_transition(transition_statement),
+            // a history target is restored like a popped state
+            StateContextType::StateHistory {..}
+                => self.generate_state_stack_pop_transition(transition_statement),
         };
 
         AstVisitorReturnType::CallStatementNode {}
@@ -1359,7 +2352,7 @@ impl AstVisitor for GdScript32Visitor {
         match &change_state_stmt_node.state_context_t {
             StateContextType::StateRef {..}
             => self.generate_state_ref_change_state(change_state_stmt_node),
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..} | StateContextType::StateHistory {..}
             => self.errors.push(format!("Fatal error - change state stack pop not implemented."),)
         };
 
@@ -1389,6 +2382,28 @@ impl AstVisitor for GdScript32Visitor {
 
     //* --------------------------------------------------------------------- *//
 
+    fn visit_timeout_node(&mut self, timeout_node: &TimeoutNode) -> AstVisitorReturnType {
+        self.newline();
+        self.generate_comment(timeout_node.line);
+        if self.first_event_handler {
+            self.add_code(&format!("if e._message == \"{}\":", self.symbol_config.timeout_msg_symbol));
+        } else {
+            self.add_code(&format!("elif e._message == \"{}\":", self.symbol_config.timeout_msg_symbol));
+        }
+        self.indent();
+        timeout_node.transition_statement_node.accept(self);
+        self.newline();
+        self.add_code("return");
+        self.outdent();
+        self.newline();
+
+        self.first_event_handler = false;
+
+        AstVisitorReturnType::TimeoutNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_test_statement_node(&mut self, test_stmt_node: &TestStatementNode) -> AstVisitorReturnType {
 
         match &test_stmt_node.test_t {
@@ -1897,6 +2912,62 @@ impl AstVisitor for GdScript32Visitor {
 
     //* --------------------------------------------------------------------- *//
 
+    fn visit_list_literal_node(&mut self, list_literal_node: &ListLiteralNode) -> AstVisitorReturnType {
+
+        let mut output = String::new();
+        self.visit_list_literal_node_to_string(list_literal_node, &mut output);
+        self.add_code(&output);
+
+        AstVisitorReturnType::ListLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_list_literal_node_to_string(&mut self, list_literal_node: &ListLiteralNode, output:&mut String) -> AstVisitorReturnType {
+
+        output.push_str("[");
+        let mut separator = "";
+        for expr_t in &list_literal_node.exprs_t {
+            output.push_str(separator);
+            expr_t.accept_to_string(self, output);
+            separator = ",";
+        }
+        output.push_str("]");
+
+        AstVisitorReturnType::ListLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_map_literal_node(&mut self, map_literal_node: &MapLiteralNode) -> AstVisitorReturnType {
+
+        let mut output = String::new();
+        self.visit_map_literal_node_to_string(map_literal_node, &mut output);
+        self.add_code(&output);
+
+        AstVisitorReturnType::MapLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_map_literal_node_to_string(&mut self, map_literal_node: &MapLiteralNode, output:&mut String) -> AstVisitorReturnType {
+
+        output.push_str("{");
+        let mut separator = "";
+        for (key_expr_t, value_expr_t) in &map_literal_node.entries {
+            output.push_str(separator);
+            key_expr_t.accept_to_string(self, output);
+            output.push_str(": ");
+            value_expr_t.accept_to_string(self, output);
+            separator = ",";
+        }
+        output.push_str("}");
+
+        AstVisitorReturnType::MapLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_expression_list_node(&mut self, expr_list: &ExprListNode) -> AstVisitorReturnType {
 
         let mut separator = "";
@@ -2029,6 +3100,7 @@ impl AstVisitor for GdScript32Visitor {
         match state_stack_op_statement_node.state_stack_operation_node.operation_t {
             StateStackOperationType::Push => {
                 self.newline();
+                self.generate_state_stack_overflow_check();
                 if self.generate_state_context {
                     self.add_code(&format!("self._stateStack_push_(self._stateContext_)"));
                 } else {
@@ -2047,6 +3119,25 @@ impl AstVisitor for GdScript32Visitor {
     }
     //* --------------------------------------------------------------------- *//
 
+    fn visit_raise_statement_node(&mut self, raise_statement_node:&RaiseStatementNode) -> AstVisitorReturnType {
+        self.newline();
+        self.add_code(&format!("self.{}", raise_statement_node.interface_method_name));
+        raise_statement_node.call_expr_list_node.accept(self);
+
+        AstVisitorReturnType::RaiseStatementNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_forward_statement_node(&mut self, forward_statement_node:&ForwardStatementNode) -> AstVisitorReturnType {
+        self.newline();
+        self.add_code(&format!("self._s{}_(e)", forward_statement_node.target_state_ref.name));
+
+        AstVisitorReturnType::ForwardStatementNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_state_context_node(&mut self, _state_context_node:&StateContextNode) -> AstVisitorReturnType {
 
         // TODO
@@ -2105,6 +3196,12 @@ impl AstVisitor for GdScript32Visitor {
 
         self.add_code(&format!("):"));
         self.indent();
+        if action_decl_node.has_frame_body() {
+            self.generate_frame_body(&action_decl_node.statements, action_decl_node.get_return_expr_opt());
+            self.outdent();
+            self.newline();
+            return AstVisitorReturnType::ActionDeclNode {};
+        }
         self.newline();
         self.add_code(&format!("assert(true,\"Error - unimplemented action\")"));
         self.outdent();
@@ -2122,6 +3219,49 @@ impl AstVisitor for GdScript32Visitor {
 
     //* --------------------------------------------------------------------- *//
 
+    fn visit_operations_block_node(&mut self, operations_block_node: &OperationsBlockNode) -> AstVisitorReturnType {
+        self.newline();
+        self.newline();
+        self.add_code("# ===================== Operations Block =================== #");
+        self.newline();
+
+        for operation_node_rcref in &operations_block_node.operations {
+            operation_node_rcref.borrow().accept(self);
+        }
+
+        AstVisitorReturnType::OperationsBlockNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_operation_node(&mut self, operation_node: &OperationNode) -> AstVisitorReturnType {
+
+        self.newline();
+        self.add_code(&format!("func {}(", operation_node.name));
+
+        match &operation_node.params {
+            Some (params)
+                =>  self.format_parameter_list(params),
+            None => {},
+        }
+
+        self.add_code("):");
+        self.indent();
+        match &operation_node.code_opt {
+            Some(code) => {
+                self.newline();
+                self.add_code(code);
+            },
+            None => self.generate_frame_body(&operation_node.statements, operation_node.get_return_expr_opt()),
+        }
+        self.outdent();
+        self.newline();
+
+        AstVisitorReturnType::OperationNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_domain_variable_decl_node(&mut self, variable_decl_node: &VariableDeclNode) -> AstVisitorReturnType {
 
         self.visit_variable_decl_node(variable_decl_node);
@@ -2135,15 +3275,24 @@ impl AstVisitor for GdScript32Visitor {
 
         let mut has_type = false;
         let var_type = match &variable_decl_node.type_opt {
-            Some(x) => {
+            Some(x) if !x.is_type_param() => {
                 has_type = true;
-                x.get_type_str()
+                self.format_type(x)
             },
-            None => String::from(""),
+            _ => String::from(""),
         };
         let var_name =  &variable_decl_node.name;
         let var_init_expr = &variable_decl_node.initializer_expr_t_opt.as_ref().unwrap();
         self.newline();
+        // Domain parameters are assigned in _init().
+        if matches!(variable_decl_node.identifier_decl_scope, IdentifierDeclScope::DomainBlock) && self.domain_param_names.contains(var_name) {
+            if has_type {
+                self.add_code( &format!("var {}:{}",var_name,var_type));
+            } else {
+                self.add_code( &format!("var {}",var_name));
+            }
+            return AstVisitorReturnType::VariableDeclNode {};
+        }
         let mut code = String::new();
         var_init_expr.accept_to_string(self, &mut code);
         if has_type {
@@ -2152,9 +3301,6 @@ impl AstVisitor for GdScript32Visitor {
             self.add_code( &format!("var {} = {}",var_name,code));
         }
 
-        self.serialize.push(format!("\tbag.domain[\"{}\"] = {};",var_name,var_name));
-        self.deserialize.push(format!("\t{} = bag.domain[\"{}\"];",var_name,var_name));
-
         AstVisitorReturnType::VariableDeclNode {}
     }
 
@@ -2229,10 +3375,9 @@ impl AstVisitor for GdScript32Visitor {
 
     fn visit_unary_expr_node(&mut self, unary_expr_node: &UnaryExprNode) -> AstVisitorReturnType {
 
-        // TODO
-        //       self.generate_comment(assignment_expr_node.line);
-        unary_expr_node.operator.accept(self);
-        unary_expr_node.right_rcref.borrow().accept(self);
+        let mut output = String::new();
+        self.visit_unary_expr_node_to_string(unary_expr_node, &mut output);
+        self.add_code(&output);
 
         AstVisitorReturnType::UnaryExprNode {}
     }
@@ -2244,7 +3389,7 @@ impl AstVisitor for GdScript32Visitor {
         // TODO
         //       self.generate_comment(assignment_expr_node.line);
         unary_expr_node.operator.accept_to_string(self, output);
-        unary_expr_node.right_rcref.borrow().accept_to_string(self,output);
+        self.visit_operand_to_string(&unary_expr_node.operator, &unary_expr_node.right_rcref.borrow(), false, output);
 
         AstVisitorReturnType::UnaryExprNode {}
     }
@@ -2253,24 +3398,9 @@ impl AstVisitor for GdScript32Visitor {
 
     fn visit_binary_expr_node(&mut self, binary_expr_node: &BinaryExprNode) -> AstVisitorReturnType {
 
-        // TODO
-        //       self.generate_comment(assignment_expr_node.line);
-        if binary_expr_node.operator == OperatorType::LogicalXor {
-            self.add_code("((");
-            binary_expr_node.left_rcref.borrow().accept(self);
-            self.add_code(") and not (");
-            binary_expr_node.right_rcref.borrow().accept(self);
-            self.add_code(")) or (not (");
-            binary_expr_node.left_rcref.borrow().accept(self);
-            self.add_code(") and (");
-            binary_expr_node.right_rcref.borrow().accept(self);
-            self.add_code("))");
-
-        } else {
-            binary_expr_node.left_rcref.borrow().accept(self);
-            binary_expr_node.operator.accept(self);
-            binary_expr_node.right_rcref.borrow().accept(self);
-        }
+        let mut output = String::new();
+        self.visit_binary_expr_node_to_string(binary_expr_node, &mut output);
+        self.add_code(&output);
 
         AstVisitorReturnType::BinaryExprNode {}
     }
@@ -2279,26 +3409,58 @@ impl AstVisitor for GdScript32Visitor {
 
     fn visit_binary_expr_node_to_string(&mut self, binary_expr_node: &BinaryExprNode, output:&mut String) -> AstVisitorReturnType {
 
-        if binary_expr_node.operator == OperatorType::LogicalXor {
-            output.push_str("((");
-            binary_expr_node.left_rcref.borrow().accept_to_string(self,output);
-            output.push_str(") and not (");
-            binary_expr_node.right_rcref.borrow().accept_to_string(self,output);
-            output.push_str(")) or (not (");
-            binary_expr_node.left_rcref.borrow().accept_to_string(self,output);
-            output.push_str(") and (");
-            binary_expr_node.right_rcref.borrow().accept_to_string(self,output);
-            output.push_str("))");
+        let operator_type = &binary_expr_node.operator;
+        self.visit_operand_to_string(operator_type, &binary_expr_node.left_rcref.borrow(), true, output);
+        operator_type.accept_to_string(self, output);
+        self.visit_operand_to_string(operator_type, &binary_expr_node.right_rcref.borrow(), false, output);
+
+        AstVisitorReturnType::BinaryExprNode {}
+    }
+
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_conditional_expr_node(&mut self, conditional_expr_node: &ConditionalExprNode) -> AstVisitorReturnType {
 
+        // Python binds the true branch tighter than a conditional so a
+        // nested conditional there needs parentheses.
+        let true_expr_t = conditional_expr_node.true_expr_rcref.borrow();
+        if let ExprType::ConditionalExprT {..} = *true_expr_t {
+            self.add_code("(");
+            true_expr_t.accept(self);
+            self.add_code(")");
         } else {
-            binary_expr_node.left_rcref.borrow().accept_to_string(self,output);
-            binary_expr_node.operator.accept_to_string(self, output);
-            binary_expr_node.right_rcref.borrow().accept_to_string(self,output);
+            true_expr_t.accept(self);
         }
+        self.add_code(" if ");
+        conditional_expr_node.condition_rcref.borrow().accept(self);
+        self.add_code(" else ");
+        conditional_expr_node.false_expr_rcref.borrow().accept(self);
 
-        AstVisitorReturnType::BinaryExprNode {}
+        AstVisitorReturnType::ConditionalExprNode {}
     }
 
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_conditional_expr_node_to_string(&mut self, conditional_expr_node: &ConditionalExprNode, output:&mut String) -> AstVisitorReturnType {
+
+        // Python binds the true branch tighter than a conditional so a
+        // nested conditional there needs parentheses.
+        let true_expr_t = conditional_expr_node.true_expr_rcref.borrow();
+        if let ExprType::ConditionalExprT {..} = *true_expr_t {
+            output.push_str("(");
+            true_expr_t.accept_to_string(self, output);
+            output.push_str(")");
+        } else {
+            true_expr_t.accept_to_string(self, output);
+        }
+        output.push_str(" if ");
+        conditional_expr_node.condition_rcref.borrow().accept_to_string(self, output);
+        output.push_str(" else ");
+        conditional_expr_node.false_expr_rcref.borrow().accept_to_string(self, output);
+
+        AstVisitorReturnType::ConditionalExprNode {}
+    }
 
     //* --------------------------------------------------------------------- *//
 
@@ -2314,12 +3476,12 @@ impl AstVisitor for GdScript32Visitor {
             OperatorType::GreaterEqual => self.add_code(" >= "),
             OperatorType::Less => self.add_code(" < "),
             OperatorType::LessEqual => self.add_code(" <= "),
-            OperatorType::Not => self.add_code(" not "),
+            OperatorType::Not => self.add_code("not "),
             OperatorType::EqualEqual => self.add_code(" == "),
             OperatorType::NotEqual => self.add_code(" != "),
             OperatorType::LogicalAnd => self.add_code(" and "),
             OperatorType::LogicalOr => self.add_code(" or "),
-            OperatorType::LogicalXor => self.add_code(""),
+            OperatorType::LogicalXor => self.add_code(" != "),
         }
 
         AstVisitorReturnType::BinaryExprNode {}
@@ -2339,12 +3501,12 @@ impl AstVisitor for GdScript32Visitor {
             OperatorType::GreaterEqual => output.push_str(" >= "),
             OperatorType::Less => output.push_str(" < "),
             OperatorType::LessEqual => output.push_str(" <= "),
-            OperatorType::Not => output.push_str(" not "),
+            OperatorType::Not => output.push_str("not "),
             OperatorType::EqualEqual => output.push_str(" == "),
             OperatorType::NotEqual => output.push_str(" != "),
             OperatorType::LogicalAnd => output.push_str(" and "),
             OperatorType::LogicalOr => output.push_str(" or "),
-            OperatorType::LogicalXor => output.push_str(""),
+            OperatorType::LogicalXor => output.push_str(" != "),
         }
 
         AstVisitorReturnType::BinaryExprNode {}
@@ -356,7 +356,7 @@ impl GraphVizVisitor {
         // let state_args_opt = match &change_state_stmt_node.state_context_t {
         //     StateContextType::StateRef { state_context_node}
         //     => &state_context_node.state_ref_args_opt,
-        //     StateContextType::StateStackPop {}
+        //     StateContextType::StateStackPop {..}
         //     => &Option::None,
         // };
         //
@@ -499,7 +499,7 @@ impl GraphVizVisitor {
         let enter_args_opt = match &transition_statement.target_state_context_t {
             StateContextType::StateRef { state_context_node}
             => &state_context_node.enter_args_opt,
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..}
             => &None,
         };
 
@@ -547,7 +547,7 @@ impl GraphVizVisitor {
         let target_state_args_opt = match &transition_statement.target_state_context_t {
             StateContextType::StateRef { state_context_node}
             => &state_context_node.state_ref_args_opt,
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..}
             => &Option::None,
         };
 //
@@ -974,7 +974,7 @@ impl AstVisitor for GraphVizVisitor {
         match &transition_statement.target_state_context_t {
             StateContextType::StateRef { state_context_node}
             => self.generate_state_ref_transition(transition_statement),
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..}
             => self.generate_state_stack_pop_transition(transition_statement),
         };
 
@@ -997,7 +997,7 @@ impl AstVisitor for GraphVizVisitor {
         match &change_state_stmt_node.state_context_t {
             StateContextType::StateRef { state_context_node}
             => self.generate_state_ref_change_state(change_state_stmt_node),
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..}
             => panic!("TODO - not implemented"),
         };
 
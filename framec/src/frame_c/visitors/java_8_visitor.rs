@@ -20,9 +20,8 @@ pub struct Java8Visitor {
     first_event_handler:bool,
     system_name:String,
     first_state_name:String,
-    serialize:Vec<String>,
-    deserialize:Vec<String>,
     subclass_code:Vec<String>,
+    generate_abstract_actions:bool,
     warnings:Vec<String>,
     has_states:bool,
     errors:Vec<String>,
@@ -32,6 +31,18 @@ pub struct Java8Visitor {
     generate_state_stack:bool,
     generate_change_state:bool,
     generate_transition_state:bool,
+    generate_timers:bool,
+    generate_event_queue:bool,
+    generate_deferred_events:bool,
+    generate_history:bool,
+    generate_regions:bool,
+    generate_final_states:bool,
+    generate_completions:bool,
+    pushed_state_names:Vec<String>,
+    domain_param_names:Vec<String>,
+    generate_start_enter:bool,
+    state_stack_max_opt:Option<usize>,
+    state_stack_underflow_opt:Option<StateStackUnderflowPolicy>,
 }
 
 impl Java8Visitor {
@@ -60,11 +71,10 @@ impl Java8Visitor {
             first_event_handler:true,
             system_name:String::new(),
             first_state_name:String::new(),
-            serialize:Vec::new(),
-            deserialize:Vec::new(),
             has_states:false,
             errors:Vec::new(),
             subclass_code:Vec::new(),
+            generate_abstract_actions:false,
             warnings:Vec::new(),
             visiting_call_chain_literal_variable:false,
             generate_exit_args,
@@ -72,10 +82,39 @@ impl Java8Visitor {
             generate_state_stack,
             generate_change_state,
             generate_transition_state,
+            generate_timers:false,
+            generate_event_queue:false,
+            generate_deferred_events:false,
+            generate_history:false,
+            generate_regions:false,
+            generate_final_states:false,
+            generate_completions:false,
+            pushed_state_names:Vec::new(),
+            domain_param_names:Vec::new(),
+            generate_start_enter:false,
+            state_stack_max_opt:None,
+            state_stack_underflow_opt:None,
         }
     }
 
 
+    //* --------------------------------------------------------------------- *//
+
+    // The subclass implementing the system's external actions, written
+    // once to its own file so regenerating the system keeps the user's code.
+
+    pub fn get_actions_stub(&self) -> Option<String> {
+        if !self.generate_abstract_actions {
+            return None;
+        }
+        let mut stub = String::new();
+        for line in self.subclass_code.iter() {
+            stub.push_str(line);
+            stub.push('\n');
+        }
+        Some(stub)
+    }
+
     //* --------------------------------------------------------------------- *//
 
     pub fn get_code(&self) -> String {
@@ -96,30 +135,30 @@ impl Java8Visitor {
         let var_type = match &*symbol_type {
             DomainVariableSymbolT { domain_variable_symbol_rcref } => {
                 match &domain_variable_symbol_rcref.borrow().var_type {
-                    Some(x) => x.get_type_str(),
+                    Some(x) => self.format_type(x),
                     None => String::from("<?>"),
                 }
             },
             StateParamSymbolT { state_param_symbol_rcref } => {
                 match &state_param_symbol_rcref.borrow().param_type_opt {
-                    Some(x) => x.get_type_str(),
+                    Some(x) => self.format_type(x),
                     None => String::from("<?>"),
                 }
             },
             StateVariableSymbolT { state_variable_symbol_rcref } => {
                 match &state_variable_symbol_rcref.borrow().var_type {
-                    Some(x) => x.get_type_str(),
+                    Some(x) => self.format_type(x),
                     None => String::from("<?>"),
                 }                    },
             EventHandlerParamSymbolT { event_handler_param_symbol_rcref } => {
                 match &event_handler_param_symbol_rcref.borrow().param_type_opt {
-                    Some(x) => x.get_type_str(),
+                    Some(x) => self.format_type(x),
                     None => String::from("<?>"),
                 }
             },
             EventHandlerVariableSymbolT { event_handler_variable_symbol_rcref } => {
                 match &event_handler_variable_symbol_rcref.borrow().var_type {
-                    Some(x) => x.get_type_str(),
+                    Some(x) => self.format_type(x),
                     None => String::from("<?>"),
                 }
             },
@@ -214,7 +253,7 @@ impl Java8Visitor {
         for param in params {
             self.add_code(&format!("{}", separator));
             let param_type: String = match &param.param_type_opt {
-                Some(ret_type) => ret_type.get_type_str(),
+                Some(ret_type) => self.format_type(ret_type),
                 None => String::from("<?>"),
             };
             self.add_code(&format!("{} {}", param_type, param.param_name));
@@ -230,7 +269,7 @@ impl Java8Visitor {
             self.add_code(&format!("{}", separator));
             subclass_actions.push_str(&format!("{}", separator));
             let param_type: String = match &param.param_type_opt {
-                Some(ret_type) => ret_type.get_type_str(),
+                Some(ret_type) => self.format_type(ret_type),
                 None => String::from("<?>"),
             };
             self.add_code(&format!("{} {}", param_type, param.param_name));
@@ -337,6 +376,12 @@ impl Java8Visitor {
                         StatementType::StateStackStmt {state_stack_operation_statement_node} => {
                             state_stack_operation_statement_node.accept(self);
                         },
+                        StatementType::RaiseStmt {raise_statement_node} => {
+                            raise_statement_node.accept(self);
+                        },
+                        StatementType::ForwardStmt {forward_statement_node} => {
+                            forward_statement_node.accept(self);
+                        },
                         StatementType::ChangeStateStmt {change_state_stmt} => {
                             change_state_stmt.accept(self);
                         },
@@ -351,6 +396,20 @@ impl Java8Visitor {
     }
 
 
+    // Generates a Frame body for an operation or action.
+
+    fn generate_frame_body(&mut self, statements:&Vec<DeclOrStmtType>, return_expr_t_opt:Option<&ExprType>) {
+        self.visit_decl_stmts(statements);
+        if let Some(expr_t) = return_expr_t_opt {
+            self.newline();
+            self.add_code("return ");
+            expr_t.accept(self);
+            self.add_code(";");
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     //* --------------------------------------------------------------------- *//
 
     fn generate_machinery(&mut self, system_node: &SystemNode) {
@@ -391,6 +450,14 @@ impl Java8Visitor {
                 self.newline();
                 self.add_code(&format!("_state_.call(exitEvent);"));
                 self.newline();
+                if self.generate_history {
+                    self.add_code(&format!("_recordHistory_();"));
+                    self.newline();
+                }
+                if self.generate_timers {
+                    self.add_code(&format!("_cancelTimer_();"));
+                    self.newline();
+                }
                 self.add_code(&format!("_state_ = newState;"));
                 self.newline();
                 if self.generate_state_context {
@@ -403,6 +470,17 @@ impl Java8Visitor {
                     self.newline();
                 }
                 self.add_code(&format!("_state_.call(enterEvent);"));
+                if self.generate_deferred_events {
+                    self.newline();
+                    self.add_code(&format!("_replayDeferred_();"));
+                }
+                if self.generate_final_states && !self.generate_regions {
+                    self.generate_finished_check();
+                }
+                if self.generate_completions {
+                    self.newline();
+                    self.add_code("_complete_();");
+                }
                 self.outdent();
                 self.newline();
                 self.add_code(&format!("}}"));
@@ -453,154 +531,959 @@ impl Java8Visitor {
             if self.generate_change_state {
                 self.newline();
                 self.newline();
-                self.add_code(&format!("private void _changeState_(newState) {{"));
+                if self.generate_state_context {
+                    self.add_code(&format!("private void _changeState_(FrameState newState, StateContext stateContext) {{"));
+                } else {
+                    self.add_code(&format!("private void _changeState_(FrameState newState) {{"));
+                }
                 self.indent();
                 self.newline();
+                if self.generate_history {
+                    self.add_code(&format!("_recordHistory_();"));
+                    self.newline();
+                }
+                if self.generate_timers {
+                    self.add_code(&format!("_cancelTimer_();"));
+                    self.newline();
+                }
                 self.add_code(&format!("_state_ = newState;"));
+                if self.generate_state_context {
+                    self.newline();
+                    self.add_code(&format!("_stateContext_ = stateContext;"));
+                }
+                if self.generate_deferred_events {
+                    self.newline();
+                    self.add_code(&format!("_replayDeferred_();"));
+                }
+                if self.generate_final_states && !self.generate_regions {
+                    self.generate_finished_check();
+                }
+                if self.generate_completions {
+                    self.newline();
+                    self.add_code("_complete_();");
+                }
                 self.outdent();
                 self.newline();
                 self.add_code(&format!("}}"));
             }
+            if self.generate_timers {
+                self.generate_timers();
+            }
+            if self.generate_event_queue {
+                self.generate_event_queue();
+            }
+            if self.generate_deferred_events {
+                self.generate_deferred_events();
+            }
+            if self.generate_history {
+                self.generate_history(system_node);
+            }
+            if self.generate_regions {
+                self.generate_regions();
+            }
+            if self.generate_final_states {
+                self.generate_final_states(system_node);
+            }
+            if self.generate_completions {
+                self.generate_completions(system_node);
+            }
+            if self.generate_start_enter {
+                self.generate_start();
+            }
             self.newline();
 
             if self.arcanium.is_serializable() {
-                for line in self.serialize.iter() {
-                    self.code.push_str(&*format!("{}",line));
-                    self.code.push_str(&*format!("\n{}",self.dent()));
-                }
-
-                for line in self.deserialize.iter() {
-                    self.code.push_str(&*format!("{}",line));
-                    self.code.push_str(&*format!("\n{}",self.dent()));
-                }
+                self.generate_serialization(system_node);
             }
         }
     }
 
     //* --------------------------------------------------------------------- *//
 
-    fn generate_subclass(&mut self) {
-        for line in self.subclass_code.iter() {
-            self.code.push_str(&*format!("{}",line));
-            self.code.push_str(&*format!("\n{}",self.dent()));
-        }
+    // The system is given a FrameScheduler to run its timers so that
+    // the clock can be replaced, e.g. by a virtual clock in tests.
+
+    fn generate_scheduler_interface(&mut self) {
+        self.add_code("public interface FrameScheduler {");
+        self.indent();
+        self.newline();
+        self.add_code("void schedule(long delayMs, Runnable callback);");
+        self.newline();
+        self.add_code("void cancel();");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
     }
 
     //* --------------------------------------------------------------------- *//
 
-    fn generate_comment(&mut self,line:usize) {
-
-        // can't use self.newline() or self.add_code() due to double borrow.
-        let mut generated_comment = false;
-        while self.current_comment_idx < self.comments.len() &&
-            line >= self.comments[self.current_comment_idx].line {
-            let comment = &self.comments[self.current_comment_idx];
-            if comment.token_type == TokenType::SingleLineCommentTok {
-                self.code.push_str(&*format!("  // {}",&comment.lexeme[3..]));
-                self.code.push_str(&*format!("\n{}",(0..self.dent).map(|_| "    ").collect::<String>()));
-
-            } else {
-                let len = &comment.lexeme.len() - 3;
-                self.code.push_str(&*format!("/* {}",&comment.lexeme[3..len]));
-                self.code.push_str(&*format!("*/"));
-
-            }
+    // Only one timer is active at a time. It is cancelled when a state is
+    // exited so a timeout always belongs to the current state.
 
-            self.current_comment_idx += 1;
-            generated_comment = true;
-        }
-        if generated_comment {
-//            self.code.push_str(&*format!("\n{}",(0..self.dent).map(|_| "\t").collect::<String>()));
+    fn generate_timers(&mut self) {
+        self.newline();
+        self.newline();
+        self.add_code("private FrameScheduler _scheduler_;");
+        self.newline();
+        self.add_code("private boolean _timerActive_ = false;");
+        self.newline();
+        self.newline();
+        self.add_code("private void _startTimer_(long delayMs) {");
+        self.indent();
+        self.newline();
+        self.add_code("if (_timerActive_) return;");
+        self.newline();
+        self.add_code("_timerActive_ = true;");
+        self.newline();
+        self.add_code("_scheduler_.schedule(delayMs, this::_timeout_);");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        self.add_code("private void _cancelTimer_() {");
+        self.indent();
+        self.newline();
+        self.add_code("if (!_timerActive_) return;");
+        self.newline();
+        self.add_code("_timerActive_ = false;");
+        self.newline();
+        self.add_code("_scheduler_.cancel();");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        self.add_code("private void _timeout_() {");
+        self.indent();
+        self.newline();
+        self.add_code("if (!_timerActive_) return;");
+        self.newline();
+        self.add_code("_timerActive_ = false;");
+        self.newline();
+        if self.generate_event_queue {
+            self.add_code(&format!("_dispatch_(new FrameEvent(\"{}\",null));", self.symbol_config.timeout_msg_symbol));
+        } else {
+            self.add_code(&format!("_state_.call(new FrameEvent(\"{}\",null));", self.symbol_config.timeout_msg_symbol));
         }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
     }
 
     //* --------------------------------------------------------------------- *//
 
-    // TODO
-    fn generate_state_ref_change_state(&mut self, change_state_stmt_node: &ChangeStateStatementNode) {
-
-        let target_state_name = match &change_state_stmt_node.state_context_t {
-            StateContextType::StateRef {state_context_node}
-            => &state_context_node.state_ref_node.name,
-            _ => {
-                self.errors.push("Change state target not found.".to_string());
-                "error"
-            },
-        };
+    // Events sent while the machine is handling an event are queued and
+    // dispatched in order once the current event has been fully handled.
 
+    fn generate_event_queue(&mut self) {
+        let state_dispatch = if self.generate_regions {"_dispatchRegions_"} else {"_state_.call"};
         self.newline();
-        self.add_code(&format!("_changeState_({});", self.format_target_state_name(target_state_name)));
-
+        self.newline();
+        self.add_code("private java.util.ArrayDeque<FrameEvent> _eventQueue_ = new java.util.ArrayDeque<FrameEvent>();");
+        self.newline();
+        self.add_code("private boolean _processing_ = false;");
+        self.newline();
+        self.newline();
+        self.add_code("private void _dispatch_(FrameEvent e) {");
+        self.indent();
+        self.newline();
+        self.add_code("if (_processing_) {");
+        self.indent();
+        self.newline();
+        self.add_code("_eventQueue_.add(e);");
+        self.newline();
+        self.add_code("return;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.add_code("_processing_ = true;");
+        self.newline();
+        // Clear the flag even if a handler throws, otherwise every later
+        // event would be queued and never dispatched.
+        self.add_code("try {");
+        self.indent();
+        self.newline();
+        self.add_code(&format!("{}(e);", state_dispatch));
+        self.newline();
+        self.add_code("while (!_eventQueue_.isEmpty()) {");
+        self.indent();
+        self.newline();
+        self.add_code(&format!("{}(_eventQueue_.poll());", state_dispatch));
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.outdent();
+        self.newline();
+        self.add_code("} finally {");
+        self.indent();
+        self.newline();
+        self.add_code("_processing_ = false;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
     }
 
-    // //* --------------------------------------------------------------------- *//
-    //
-    // fn generate_state_ref_code(&self, target_state_name:&str) -> String {
-    //     format!("{}",self.format_target_state_name(target_state_name))
-    // }
-
     //* --------------------------------------------------------------------- *//
 
-    fn generate_state_ref_transition(&mut self, transition_statement: &TransitionStatementNode) {
+    // The machine is finished once it is in a #[final] state, or once
+    // every region is.
 
+    fn generate_final_states(&mut self, system_node: &SystemNode) {
+        let final_states:Vec<String> = system_node.get_final_state_names().iter()
+            .map(|state_name| format!("state == {}", self.format_target_state_reference(state_name))).collect();
         self.newline();
-        let target_state_name = match &transition_statement.target_state_context_t {
-            StateContextType::StateRef {state_context_node} => {
-                &state_context_node.state_ref_node.name
-            },
-            _ => {
-                self.errors.push("Unknown error.".to_string());
-                ""
-            },
-        };
-        match &transition_statement.label_opt {
-            Some(label) => {
-                self.add_code(&format!("// {}", label));
-                self.newline();
-            },
-            None => {},
+        if !self.generate_history {
+            self.newline();
+            self.generate_state_refs(system_node);
         }
-
-        if self.generate_state_context {
-            self.add_code(&format!("stateContext = new StateContext({});", self.format_target_state_reference(&target_state_name)));
+        self.newline();
+        self.add_code("private boolean _isFinalState_(FrameState state) {");
+        self.indent();
+        self.newline();
+        self.add_code(&format!("return {};", final_states.join(" || ")));
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        if self.generate_regions {
+            self.newline();
+            self.add_code("public boolean isFinished() {");
+            self.indent();
+            self.newline();
+            self.add_code("for (FrameState state : _regions_) {");
+            self.indent();
+            self.newline();
+            self.add_code("if (!_isFinalState_(state)) {");
+            self.indent();
+            self.newline();
+            self.add_code("return false;");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+            self.newline();
+            self.add_code("return true;");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        } else {
+            self.newline();
+            self.add_code("public boolean isFinished() {");
+            self.indent();
+            self.newline();
+            self.add_code("return _isFinalState_(_state_);");
+            self.outdent();
             self.newline();
+            self.add_code("}");
         }
+        self.newline();
+        self.newline();
+        self.add_code("protected void onFinished() {}");
+    }
 
-        // -- Exit Arguments --
+    //* --------------------------------------------------------------------- *//
 
-        let mut has_exit_args = false;
-        if let Some(exit_args) = &transition_statement.exit_args_opt {
-            if exit_args.exprs_t.len() > 0 {
-                has_exit_args = true;
+    // Entering a final state completes the machine.
 
-                // Note - searching for event keyed with "State:<"
-                // e.g. "S1:<"
+    fn generate_finished_check(&mut self) {
+        self.newline();
+        self.add_code("if (isFinished()) {");
+        self.indent();
+        self.newline();
+        self.add_code("onFinished();");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
 
-                let mut msg: String = String::new();
-                if let Some(state_name) = &self.current_state_name_opt {
-                    msg = state_name.clone();
-                }
-                msg.push_str(":");
-                msg.push_str(&self.symbol_config.exit_msg_symbol);
+    //* --------------------------------------------------------------------- *//
 
-                if let Some(event_sym) = self.arcanium.get_event(&msg, &self.current_state_name_opt) {
-                    match &event_sym.borrow().params_opt {
-                        Some(event_params) => {
-                            if exit_args.exprs_t.len() != event_params.len() {
-                                self.errors.push("Fatal error: misaligned parameters to arguments.".to_string());
-                            }
-                            let mut param_symbols_it = event_params.iter();
-                            self.add_code("HashMap<String,Object> exitArgs = new HashMap<String,Object>();");
-                            self.newline();
-                            // Loop through the ARGUMENTS...
-                            for expr_t in &exit_args.exprs_t {
-                                // ...and validate w/ the PARAMETERS
-                                match param_symbols_it.next() {
-                                    Some(p) => {
-                                        let mut expr = String::new();
-                                        expr_t.accept_to_string(self, &mut expr);
-                                        self.add_code(&format!("exitArgs.put(\"{}\",{});", p.name, expr));
-                                        self.newline();
+    // Actions called from the constructor would run before a subclass is
+    // initialized so the start state is not entered there. The first
+    // interface call enters it with the system enter parameters.
+
+    fn generate_start(&mut self) {
+        self.newline();
+        self.newline();
+        self.add_code("private boolean _started_ = false;");
+        self.newline();
+        self.newline();
+        self.add_code("private void _start_() {");
+        self.indent();
+        self.newline();
+        self.add_code("_started_ = true;");
+        self.newline();
+        self.add_code("_state_.call(new FrameEvent(\">\",_stateContext_.getEnterArgs()));");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Completion transitions are taken one after another in a loop rather
+    // than from inside the transition that entered the state, so long
+    // chains don't grow the stack.
+
+    fn generate_completions(&mut self, system_node: &SystemNode) {
+        self.newline();
+        if !self.generate_history && !self.generate_final_states {
+            self.newline();
+            self.generate_state_refs(system_node);
+        }
+        self.newline();
+        self.add_code("private boolean _completing_ = false;");
+        self.newline();
+        self.newline();
+        self.add_code("private void _complete_() {");
+        self.indent();
+        self.newline();
+        self.add_code("if (_completing_) {");
+        self.indent();
+        self.newline();
+        self.add_code("return;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.add_code("_completing_ = true;");
+        self.newline();
+        self.add_code("while (_completion_()) {}");
+        self.newline();
+        self.add_code("_completing_ = false;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        self.add_code("private boolean _completion_() {");
+        self.indent();
+        if let Some(machine_block_node) = &system_node.machine_block_node_opt {
+            for state_node_rcref in &machine_block_node.states {
+                let state_node = state_node_rcref.borrow();
+                if state_node.completions.len() == 0 {
+                    continue;
+                }
+                self.current_state_name_opt = Some(state_node.name.clone());
+                self.newline();
+                self.add_code(&format!("if (_state_ == {}) {{", self.format_target_state_reference(&state_node.name)));
+                self.indent();
+                for completion_node in &state_node.completions {
+                    if let Some(guard_expr_t) = &completion_node.guard_expr_t_opt {
+                        let mut guard = String::new();
+                        guard_expr_t.accept_to_string(self, &mut guard);
+                        self.newline();
+                        self.add_code(&format!("if ({}) {{", guard));
+                        self.indent();
+                    }
+                    completion_node.transition_statement_node.accept(self);
+                    self.newline();
+                    self.add_code("return true;");
+                    if completion_node.guard_expr_t_opt.is_some() {
+                        self.outdent();
+                        self.newline();
+                        self.add_code("}");
+                    }
+                }
+                self.outdent();
+                self.newline();
+                self.add_code("}");
+                self.current_state_name_opt = None;
+            }
+        }
+        self.newline();
+        self.add_code("return false;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Java 8 has no JSON library so the snapshot is the current state with
+    // its state arguments and variables, the state stack and the domain
+    // written with Java serialization and encoded as Base64.
+
+    fn generate_serialization(&mut self, system_node: &SystemNode) {
+        let domain_vars = system_node.get_domain_vars();
+        let snapshot_version = system_node.get_snapshot_version();
+        let snapshot_hash = system_node.get_snapshot_hash();
+        self.newline();
+        if !self.generate_history && !self.generate_final_states && !self.generate_completions {
+            self.newline();
+            self.generate_state_refs(system_node);
+        }
+        self.newline();
+        self.add_code("public String _serialize__do() {");
+        self.indent();
+        self.newline();
+        self.add_code("HashMap<String,Object> bag = new HashMap<String,Object>();");
+        self.newline();
+        self.add_code(&format!("bag.put(\"version\", {});", snapshot_version));
+        self.newline();
+        self.add_code(&format!("bag.put(\"hash\", \"{}\");", snapshot_hash));
+        self.newline();
+        if self.generate_state_context {
+            self.add_code("bag.put(\"state\", _serializeState_(_stateContext_));");
+        } else {
+            self.add_code("bag.put(\"state\", _serializeState_(_state_));");
+        }
+        if self.generate_state_stack {
+            let stack_type = if self.generate_state_context {"StateContext"} else {"FrameState"};
+            self.newline();
+            self.add_code("java.util.ArrayList<HashMap<String,Object>> stateStack = new java.util.ArrayList<HashMap<String,Object>>();");
+            self.newline();
+            self.add_code(&format!("for ({} state : _stateStack_) {{", stack_type));
+            self.indent();
+            self.newline();
+            self.add_code("stateStack.add(_serializeState_(state));");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+            self.newline();
+            self.add_code("bag.put(\"stateStack\", stateStack);");
+        }
+        self.newline();
+        self.add_code("HashMap<String,Object> domain = new HashMap<String,Object>();");
+        for var_rcref in &domain_vars {
+            let var_name = var_rcref.borrow().name.clone();
+            self.newline();
+            self.add_code(&format!("domain.put(\"{}\", this.{});", var_name, var_name));
+        }
+        self.newline();
+        self.add_code("bag.put(\"domain\", domain);");
+        self.newline();
+        self.add_code("try {");
+        self.indent();
+        self.newline();
+        self.add_code("java.io.ByteArrayOutputStream bytes = new java.io.ByteArrayOutputStream();");
+        self.newline();
+        self.add_code("java.io.ObjectOutputStream out = new java.io.ObjectOutputStream(bytes);");
+        self.newline();
+        self.add_code("out.writeObject(bag);");
+        self.newline();
+        self.add_code("out.close();");
+        self.newline();
+        self.add_code("return java.util.Base64.getEncoder().encodeToString(bytes.toByteArray());");
+        self.outdent();
+        self.newline();
+        self.add_code("} catch (java.io.IOException ex) {");
+        self.indent();
+        self.newline();
+        self.add_code("throw new RuntimeException(ex);");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        self.add_code("@SuppressWarnings(\"unchecked\")");
+        self.newline();
+        self.add_code("public void _deserialize__do(String data) {");
+        self.indent();
+        self.newline();
+        self.add_code("HashMap<String,Object> bag;");
+        self.newline();
+        self.add_code("try {");
+        self.indent();
+        self.newline();
+        self.add_code("java.io.ObjectInputStream in = new java.io.ObjectInputStream(new java.io.ByteArrayInputStream(java.util.Base64.getDecoder().decode(data)));");
+        self.newline();
+        self.add_code("bag = (HashMap<String,Object>) in.readObject();");
+        self.outdent();
+        self.newline();
+        self.add_code("} catch (java.io.IOException | ClassNotFoundException ex) {");
+        self.indent();
+        self.newline();
+        self.add_code("throw new RuntimeException(ex);");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.add_code("int version = (Integer) bag.get(\"version\");");
+        self.newline();
+        self.add_code(&format!("if (version > {}) {{", snapshot_version));
+        self.indent();
+        self.newline();
+        self.add_code(&format!("throw new IllegalArgumentException(\"Snapshot version \" + version + \" is newer than version {} of {}\");", snapshot_version, system_node.name));
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.add_code(&format!("if (version == {} && !\"{}\".equals(bag.get(\"hash\"))) {{", snapshot_version, snapshot_hash));
+        self.indent();
+        self.newline();
+        self.add_code(&format!("throw new IllegalArgumentException(\"Snapshot does not match version {} of {}\");", snapshot_version, system_node.name));
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        if self.generate_state_context {
+            self.add_code("_stateContext_ = _deserializeState_((HashMap<String,Object>) bag.get(\"state\"));");
+            self.newline();
+            self.add_code("_state_ = _stateContext_.state;");
+        } else {
+            self.add_code("_state_ = _deserializeState_((HashMap<String,Object>) bag.get(\"state\"));");
+        }
+        if self.generate_state_stack {
+            self.newline();
+            self.add_code("_stateStack_.clear();");
+            self.newline();
+            self.add_code("for (HashMap<String,Object> state : (java.util.ArrayList<HashMap<String,Object>>) bag.get(\"stateStack\")) {");
+            self.indent();
+            self.newline();
+            self.add_code("_stateStack_.push(_deserializeState_(state));");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
+        if self.generate_start_enter {
+            self.newline();
+            self.add_code("_started_ = true;");
+        }
+        self.newline();
+        self.add_code("HashMap<String,Object> domain = (HashMap<String,Object>) bag.get(\"domain\");");
+        for var_rcref in &domain_vars {
+            let var = var_rcref.borrow();
+            self.newline();
+            if var.has_snapshot_default() {
+                self.add_code(&format!("if (domain.containsKey(\"{}\")) {{", var.name));
+                self.indent();
+                self.newline();
+            }
+            match &var.type_opt {
+                Some(type_node) => self.add_code(&format!("this.{} = ({}) domain.get(\"{}\");", var.name, self.format_type(type_node), var.name)),
+                None => self.add_code(&format!("this.{} = domain.get(\"{}\");", var.name, var.name)),
+            }
+            if var.has_snapshot_default() {
+                self.outdent();
+                self.newline();
+                self.add_code("}");
+            }
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        if self.generate_state_context {
+            self.add_code("private HashMap<String,Object> _serializeState_(StateContext stateContext) {");
+        } else {
+            self.add_code("private HashMap<String,Object> _serializeState_(FrameState state) {");
+        }
+        self.indent();
+        self.newline();
+        self.add_code("HashMap<String,Object> data = new HashMap<String,Object>();");
+        if self.generate_state_context {
+            self.newline();
+            self.add_code("HashMap<String,Object> stateArgs = new HashMap<String,Object>();");
+            self.newline();
+            self.add_code("HashMap<String,Object> stateVars = new HashMap<String,Object>();");
+        }
+        for state_node_rcref in system_node.get_states() {
+            let state_node = state_node_rcref.borrow();
+            let state_ref = self.format_target_state_reference(&state_node.name);
+            self.newline();
+            if self.generate_state_context {
+                self.add_code(&format!("if (stateContext.state == {}) {{", state_ref));
+            } else {
+                self.add_code(&format!("if (state == {}) {{", state_ref));
+            }
+            self.indent();
+            self.newline();
+            self.add_code(&format!("data.put(\"state\", \"{}\");", state_node.name));
+            for param_name in state_node.get_param_names() {
+                self.newline();
+                self.add_code(&format!("stateArgs.put(\"{}\", stateContext.getStateArg(\"{}\"));", param_name, param_name));
+            }
+            for var_name in state_node.get_var_names() {
+                self.newline();
+                self.add_code(&format!("stateVars.put(\"{}\", stateContext.getStateVar(\"{}\"));", var_name, var_name));
+            }
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
+        if self.generate_state_context {
+            self.newline();
+            self.add_code("data.put(\"stateArgs\", stateArgs);");
+            self.newline();
+            self.add_code("data.put(\"stateVars\", stateVars);");
+        }
+        self.newline();
+        self.add_code("return data;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        self.add_code("@SuppressWarnings(\"unchecked\")");
+        self.newline();
+        if self.generate_state_context {
+            self.add_code("private StateContext _deserializeState_(HashMap<String,Object> data) {");
+        } else {
+            self.add_code("private FrameState _deserializeState_(HashMap<String,Object> data) {");
+        }
+        self.indent();
+        if self.generate_state_context {
+            self.newline();
+            self.add_code("HashMap<String,Object> stateArgs = (HashMap<String,Object>) data.get(\"stateArgs\");");
+            self.newline();
+            self.add_code("HashMap<String,Object> stateVars = (HashMap<String,Object>) data.get(\"stateVars\");");
+        }
+        self.newline();
+        self.add_code("switch ((String) data.get(\"state\")) {");
+        self.indent();
+        for state_node_rcref in system_node.get_states() {
+            let state_node = state_node_rcref.borrow();
+            let state_ref = self.format_target_state_reference(&state_node.name);
+            for renamed_from in state_node.get_renamed_from() {
+                self.newline();
+                self.add_code(&format!("case \"{}\":", renamed_from));
+            }
+            self.newline();
+            self.add_code(&format!("case \"{}\": {{", state_node.name));
+            self.indent();
+            self.newline();
+            if self.generate_state_context {
+                self.add_code(&format!("StateContext stateContext = new StateContext({});", state_ref));
+                for param_name in state_node.get_param_names() {
+                    self.newline();
+                    self.add_code(&format!("stateContext.addStateArg(\"{}\",stateArgs.get(\"{}\"));", param_name, param_name));
+                }
+                for var_name in state_node.get_var_names() {
+                    self.newline();
+                    self.add_code(&format!("stateContext.addStateVar(\"{}\",stateVars.get(\"{}\"));", var_name, var_name));
+                }
+                self.newline();
+                self.add_code("return stateContext;");
+            } else {
+                self.add_code(&format!("return {};", state_ref));
+            }
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.add_code("throw new IllegalArgumentException(\"Unknown state \" + data.get(\"state\"));");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Each region has its own active state. An event is dispatched to
+    // every region in turn with the region's state made current.
+
+    fn generate_regions(&mut self) {
+        self.newline();
+        self.newline();
+        self.add_code("private FrameState[] _regions_;");
+        self.newline();
+        self.newline();
+        self.add_code("private void _dispatchRegions_(FrameEvent e) {");
+        self.indent();
+        if self.generate_final_states {
+            self.newline();
+            self.add_code("boolean wasFinished = isFinished();");
+        }
+        self.newline();
+        self.add_code("for (int i = 0; i < _regions_.length; i++) {");
+        self.indent();
+        self.newline();
+        self.add_code("_state_ = _regions_[i];");
+        self.newline();
+        self.add_code("_state_.call(e);");
+        self.newline();
+        self.add_code("_regions_[i] = _state_;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        if self.generate_final_states {
+            self.newline();
+            self.add_code("if (!wasFinished && isFinished()) {");
+            self.indent();
+            self.newline();
+            self.add_code("onFinished();");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Method references are not stable in Java so states that are compared
+    // by identity get one reference each.
+
+    fn generate_state_refs(&mut self, system_node: &SystemNode) {
+        if let Some(machine_block_node) = &system_node.machine_block_node_opt {
+            for state_node_rcref in &machine_block_node.states {
+                let state_name = state_node_rcref.borrow().name.clone();
+                self.add_code(&format!("private final FrameState {} = this::{};", self.format_target_state_reference(&state_name), self.format_target_state_name(&state_name)));
+                self.newline();
+            }
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // History is recorded on the way out of a state. Every ancestor that
+    // is a history target remembers the child it was left through and
+    // the leaf state that was active.
+
+    fn generate_history(&mut self, system_node: &SystemNode) {
+        self.newline();
+        self.newline();
+        self.add_code("private java.util.HashMap<String,FrameState> _shallowHistory_ = new java.util.HashMap<String,FrameState>();");
+        self.newline();
+        self.add_code("private java.util.HashMap<String,FrameState> _deepHistory_ = new java.util.HashMap<String,FrameState>();");
+        self.newline();
+        self.newline();
+        self.generate_state_refs(system_node);
+        self.newline();
+        self.add_code("private void _recordHistory_() {");
+        self.indent();
+        let mut is_first = true;
+        if let Some(machine_block_node) = &system_node.machine_block_node_opt {
+            for state_node_rcref in &machine_block_node.states {
+                let state_node = state_node_rcref.borrow();
+                if state_node.history_records.len() == 0 {
+                    continue;
+                }
+                let state_ref = self.format_target_state_reference(&state_node.name);
+                self.newline();
+                if is_first {
+                    self.add_code(&format!("if (_state_ == {}) {{", state_ref));
+                } else {
+                    self.add_code(&format!("}} else if (_state_ == {}) {{", state_ref));
+                }
+                is_first = false;
+                self.indent();
+                for history_record in &state_node.history_records {
+                    let child_ref = self.format_target_state_reference(&history_record.child_state_name);
+                    self.newline();
+                    self.add_code(&format!("_shallowHistory_.put(\"{}\", {});", history_record.parent_state_name, child_ref));
+                    self.newline();
+                    self.add_code(&format!("_deepHistory_.put(\"{}\", {});", history_record.parent_state_name, state_ref));
+                }
+                self.outdent();
+            }
+        }
+        if !is_first {
+            self.newline();
+            self.add_code("}");
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Deferred events are replayed in the order they arrived. Any the
+    // new state defers again go back on the queue.
+
+    fn generate_deferred_events(&mut self) {
+        self.newline();
+        self.newline();
+        self.add_code("private java.util.ArrayList<FrameEvent> _deferredQueue_ = new java.util.ArrayList<FrameEvent>();");
+        self.newline();
+        self.newline();
+        self.add_code("private void _replayDeferred_() {");
+        self.indent();
+        self.newline();
+        self.add_code("java.util.ArrayList<FrameEvent> deferred = _deferredQueue_;");
+        self.newline();
+        self.add_code("_deferredQueue_ = new java.util.ArrayList<FrameEvent>();");
+        self.newline();
+        self.add_code("for (FrameEvent e : deferred) {");
+        self.indent();
+        self.newline();
+        self.add_code("_state_.call(e);");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // A deferred event is put aside rather than handled and is replayed
+    // after the next transition.
+
+    fn generate_deferred_event_checks(&mut self, deferred_events:&Vec<String>) {
+        for msg in deferred_events {
+            self.newline();
+            self.add_code(&format!("if (e._message == \"{}\") {{", msg));
+            self.indent();
+            self.newline();
+            self.add_code("_deferredQueue_.add(e);");
+            self.newline();
+            self.add_code("return;");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn generate_timer_start(&mut self, timeout_node:&TimeoutNode) {
+        let mut duration = String::new();
+        timeout_node.duration_expr_t.accept_to_string(self, &mut duration);
+        self.newline();
+        self.add_code(&format!("_startTimer_({});", duration));
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn generate_comment(&mut self,line:usize) {
+
+        // can't use self.newline() or self.add_code() due to double borrow.
+        let mut generated_comment = false;
+        while self.current_comment_idx < self.comments.len() &&
+            line >= self.comments[self.current_comment_idx].line {
+            let comment = &self.comments[self.current_comment_idx];
+            if comment.token_type == TokenType::SingleLineCommentTok {
+                self.code.push_str(&*format!("  // {}",&comment.lexeme[3..]));
+                self.code.push_str(&*format!("\n{}",(0..self.dent).map(|_| "    ").collect::<String>()));
+
+            } else {
+                let len = &comment.lexeme.len() - 3;
+                self.code.push_str(&*format!("/* {}",&comment.lexeme[3..len]));
+                self.code.push_str(&*format!("*/"));
+
+            }
+
+            self.current_comment_idx += 1;
+            generated_comment = true;
+        }
+        if generated_comment {
+//            self.code.push_str(&*format!("\n{}",(0..self.dent).map(|_| "\t").collect::<String>()));
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // TODO
+    fn generate_state_ref_change_state(&mut self, change_state_stmt_node: &ChangeStateStatementNode) {
+
+        let target_state_name = match &change_state_stmt_node.state_context_t {
+            StateContextType::StateRef {state_context_node}
+            => &state_context_node.state_ref_node.name,
+            _ => {
+                self.errors.push("Change state target not found.".to_string());
+                "error"
+            },
+        };
+
+        let target_state_args_opt = match &change_state_stmt_node.state_context_t {
+            StateContextType::StateRef { state_context_node}
+                => &state_context_node.state_ref_args_opt,
+            _ => &Option::None,
+        };
+
+        self.newline();
+        if self.generate_state_context {
+            self.add_code(&format!("stateContext = new StateContext({});", self.format_target_state_reference(target_state_name)));
+            self.newline();
+            self.generate_state_args_and_vars(target_state_name, target_state_args_opt);
+            self.add_code(&format!("_changeState_({},stateContext);", self.format_target_state_reference(target_state_name)));
+        } else {
+            self.add_code(&format!("_changeState_({});", self.format_target_state_reference(target_state_name)));
+        }
+
+    }
+
+    // //* --------------------------------------------------------------------- *//
+    //
+    // fn generate_state_ref_code(&self, target_state_name:&str) -> String {
+    //     format!("{}",self.format_target_state_name(target_state_name))
+    // }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn generate_state_ref_transition(&mut self, transition_statement: &TransitionStatementNode) {
+
+        self.newline();
+        let target_state_name = match &transition_statement.target_state_context_t {
+            StateContextType::StateRef {state_context_node} => {
+                &state_context_node.state_ref_node.name
+            },
+            _ => {
+                self.errors.push("Unknown error.".to_string());
+                ""
+            },
+        };
+        match &transition_statement.label_opt {
+            Some(label) => {
+                self.add_code(&format!("// {}", label));
+                self.newline();
+            },
+            None => {},
+        }
+
+        if self.generate_state_context {
+            self.add_code(&format!("stateContext = new StateContext({});", self.format_target_state_reference(&target_state_name)));
+            self.newline();
+        }
+
+        // -- Exit Arguments --
+
+        let mut has_exit_args = false;
+        if let Some(exit_args) = &transition_statement.exit_args_opt {
+            if exit_args.exprs_t.len() > 0 {
+                has_exit_args = true;
+
+                // Note - searching for event keyed with "State:<"
+                // e.g. "S1:<"
+
+                let mut msg: String = String::new();
+                if let Some(state_name) = &self.current_state_name_opt {
+                    msg = state_name.clone();
+                }
+                msg.push_str(":");
+                msg.push_str(&self.symbol_config.exit_msg_symbol);
+
+                if let Some(event_sym) = self.arcanium.get_event(&msg, &self.current_state_name_opt) {
+                    match &event_sym.borrow().params_opt {
+                        Some(event_params) => {
+                            if exit_args.exprs_t.len() != event_params.len() {
+                                self.errors.push("Fatal error: misaligned parameters to arguments.".to_string());
+                            }
+                            let mut param_symbols_it = event_params.iter();
+                            self.add_code("HashMap<String,Object> exitArgs = new HashMap<String,Object>();");
+                            self.newline();
+                            // Loop through the ARGUMENTS...
+                            for expr_t in &exit_args.exprs_t {
+                                // ...and validate w/ the PARAMETERS
+                                match param_symbols_it.next() {
+                                    Some(p) => {
+                                        let mut expr = String::new();
+                                        expr_t.accept_to_string(self, &mut expr);
+                                        self.add_code(&format!("exitArgs.put(\"{}\",{});", p.name, expr));
+                                        self.newline();
                                     },
                                     None => self.errors.push(format!("Invalid number of arguments for \"{}\" event handler.", msg)),
 
@@ -620,7 +1503,7 @@ impl Java8Visitor {
         let enter_args_opt = match &transition_statement.target_state_context_t {
             StateContextType::StateRef { state_context_node}
                 => &state_context_node.enter_args_opt,
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..} | StateContextType::StateHistory {..}
                 => &None,
         };
 
@@ -664,10 +1547,39 @@ impl Java8Visitor {
         let target_state_args_opt = match &transition_statement.target_state_context_t {
             StateContextType::StateRef { state_context_node}
                 => &state_context_node.state_ref_args_opt,
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..} | StateContextType::StateHistory {..}
                 => &Option::None,
         };
 //
+        self.generate_state_args_and_vars(&target_state_name, target_state_args_opt);
+        let exit_args = if has_exit_args {
+            "exitArgs"
+        } else {
+            "null"
+        };
+        // TODO: create formatters for _transition_ calls/refs.
+        if self.generate_state_context {
+            if self.generate_exit_args {
+                self.add_code(&format!("_transition_({},{},stateContext);",self.format_target_state_reference(target_state_name),exit_args));
+            } else {
+                self.add_code(&format!("_transition_({},stateContext);",self.format_target_state_reference(target_state_name)));
+            }
+        } else {
+            if self.generate_exit_args {
+                self.add_code(&format!("_transition_({},{});",self.format_target_state_reference(target_state_name),exit_args));
+            } else {
+                self.add_code(&format!("_transition_({});",self.format_target_state_reference(target_state_name)));
+            }
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Adds the target state's arguments and initialized
+    // state variables to the new state context.
+
+    fn generate_state_args_and_vars(&mut self, target_state_name:&str, target_state_args_opt:&Option<ExprListNode>) {
+
         if let Some(state_args) = target_state_args_opt {
 //            let mut params_copy = Vec::new();
             if let Some(state_sym) = self.arcanium.get_state(&target_state_name) {
@@ -727,25 +1639,6 @@ impl Java8Visitor {
 //                code = target_state_vars.clone();
             },
         }
-        let exit_args = if has_exit_args {
-            "exitArgs"
-        } else {
-            "null"
-        };
-        // TODO: create formatters for _transition_ calls/refs.
-        if self.generate_state_context {
-            if self.generate_exit_args {
-                self.add_code(&format!("_transition_({},{},stateContext);",self.format_target_state_reference(target_state_name),exit_args));
-            } else {
-                self.add_code(&format!("_transition_({},stateContext);",self.format_target_state_reference(target_state_name)));
-            }
-        } else {
-            if self.generate_exit_args {
-                self.add_code(&format!("_transition_({},{});",self.format_target_state_reference(target_state_name),exit_args));
-            } else {
-                self.add_code(&format!("_transition_({});",self.format_target_state_reference(target_state_name)));
-            }
-        }
     }
 
     //* --------------------------------------------------------------------- *//
@@ -756,7 +1649,13 @@ impl Java8Visitor {
 
     //* --------------------------------------------------------------------- *//
 
+    // Method references are new objects each time they are evaluated so
+    // history, which compares states, needs one stable reference per state.
+
     fn format_target_state_reference(&self,state_name:&str) -> String {
+        if self.generate_history || self.generate_final_states || self.generate_completions || self.arcanium.is_serializable() {
+            return format!("_s{}Ref_",state_name);
+        }
         format!("this::{}",self.format_target_state_name(state_name))
     }
 
@@ -820,29 +1719,234 @@ impl Java8Visitor {
                             self.errors.push(format!("Fatal error: misaligned parameters to arguments.")),
                     }
                 } else {
-                    self.errors.push(format!("TODO"));
+                    self.errors.push(format!("TODO"));
+                }
+            }
+        }
+
+        if let StateContextType::StateStackPop {..} = &transition_statement.target_state_context_t {
+            let exit_args = match &transition_statement.exit_args_opt {
+                Some(exit_args) if exit_args.exprs_t.len() > 0 => "exitArgs",
+                _ => "null",
+            };
+            self.generate_state_stack_underflow_check(exit_args);
+        }
+        if let StateContextType::StateHistory {state_ref_node, is_deep} = &transition_statement.target_state_context_t {
+            let history_map = if *is_deep {"_deepHistory_"} else {"_shallowHistory_"};
+            self.add_code(&format!("FrameState state = {}.getOrDefault(\"{}\", {});", history_map, state_ref_node.name, self.format_target_state_reference(&state_ref_node.name)));
+            if self.generate_state_context {
+                self.newline();
+                self.add_code("stateContext = new StateContext(state);");
+            }
+        } else if self.generate_state_context {
+            self.add_code(&format!("stateContext = _stateStack_pop_();"));
+        } else {
+            self.add_code(&format!("FrameState state = _stateStack_pop_();"));
+        }
+        self.newline();
+        if let StateContextType::StateStackPop { enter_args_opt:Some(enter_args) } = &transition_statement.target_state_context_t {
+            self.generate_state_stack_pop_enter_args(enter_args);
+        }
+        if self.generate_exit_args {
+            if self.generate_state_context {
+                self.add_code(&format!("_transition_(stateContext.state,exitArgs,stateContext);"));
+            } else {
+                self.add_code(&format!("_transition_(state,exitArgs);"));
+            }
+        } else {
+            if self.generate_state_context {
+                self.add_code(&format!("_transition_(stateContext.state,stateContext);"));
+            } else {
+                self.add_code(&format!("_transition_(state);"));
+            }
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Refuses to push past #![state_stack_max], tells the current state
+    // with a stateStackOverflow event and ends the handler so the
+    // transition after the push does not happen.
+
+    fn generate_state_stack_overflow_check(&mut self) {
+        let max = match self.state_stack_max_opt {
+            Some(max) => max,
+            None => return,
+        };
+        self.add_code(&format!("if (_stateStack_.size() >= {}) {{", max));
+        self.indent();
+        self.newline();
+        let message = self.symbol_config.state_stack_overflow_msg_symbol.clone();
+        self.generate_state_stack_error_event(&message);
+        self.newline();
+        self.add_code("return;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Applies the #![state_stack_underflow] policy when $$[-] finds the
+    // state stack empty.
+
+    fn generate_state_stack_underflow_check(&mut self, exit_args:&str) {
+        let underflow = match &self.state_stack_underflow_opt {
+            Some(underflow) => underflow.clone(),
+            None => return,
+        };
+        self.add_code("if (_stateStack_.size() == 0) {");
+        self.indent();
+        self.newline();
+        match underflow {
+            StateStackUnderflowPolicy::Error => {
+                let message = self.symbol_config.state_stack_underflow_msg_symbol.clone();
+                self.generate_state_stack_error_event(&message);
+                self.newline();
+            },
+            StateStackUnderflowPolicy::Stay => {},
+            StateStackUnderflowPolicy::State {state_name} => {
+                if self.generate_state_context {
+                    self.add_code(&format!("stateContext = new StateContext({});", self.format_target_state_reference(&state_name)));
+                    self.newline();
+                    self.generate_state_args_and_vars(&state_name, &None);
+                    if self.generate_exit_args {
+                        self.add_code(&format!("_transition_({},{},stateContext);", self.format_target_state_reference(&state_name), exit_args));
+                    } else {
+                        self.add_code(&format!("_transition_({},stateContext);", self.format_target_state_reference(&state_name)));
+                    }
+                } else if self.generate_exit_args {
+                    self.add_code(&format!("_transition_({},{});", self.format_target_state_reference(&state_name), exit_args));
+                } else {
+                    self.add_code(&format!("_transition_({});", self.format_target_state_reference(&state_name)));
                 }
-            }
+                self.newline();
+            },
         }
+        self.add_code("return;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+    }
 
-        if self.generate_state_context {
-            self.add_code(&format!("stateContext = _stateStack_pop_();"));
+    //* --------------------------------------------------------------------- *//
+
+    // Sends a state stack error event to the current state.
+
+    fn generate_state_stack_error_event(&mut self, message:&str) {
+        if self.generate_event_queue {
+            self.add_code(&format!("_dispatch_(new FrameEvent(\"{}\",null));", message));
         } else {
-            self.add_code(&format!("FrameState state = _stateStack_pop_();"));
+            self.add_code(&format!("_state_.call(new FrameEvent(\"{}\",null));", message));
         }
-        self.newline();
-        if self.generate_exit_args {
-            if self.generate_state_context {
-                self.add_code(&format!("_transition_(stateContext.state,exitArgs,stateContext);"));
-            } else {
-                self.add_code(&format!("_transition_(state,exitArgs);"));
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Adds the enter arguments of a state stack pop to the popped state
+    // context. Every pushed state takes the same enter parameters so the
+    // first one names them.
+
+    fn generate_state_stack_pop_enter_args(&mut self, enter_args:&ExprListNode) {
+        let mut msg = match self.pushed_state_names.first() {
+            Some(pushed_state_name) => pushed_state_name.clone(),
+            None => return,
+        };
+        msg.push_str(":");
+        msg.push_str(&self.symbol_config.enter_msg_symbol);
+
+        if let Some(event_sym) = self.arcanium.get_event(&msg,&self.current_state_name_opt) {
+            if let Some(event_params) = &event_sym.borrow().params_opt {
+                for (p, expr_t) in event_params.iter().zip(enter_args.exprs_t.iter()) {
+                    let mut expr = String::new();
+                    expr_t.accept_to_string(self,&mut expr);
+                    self.add_code(&format!("stateContext.addEnterArg(\"{}\",{});", p.name, expr));
+                    self.newline();
+                }
             }
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Maps portable Frame types to Java. Other types are emitted as written.
+    // Frame's '&' is dropped as Java already passes objects by reference.
+
+    fn format_type(&self, type_node:&TypeNode) -> String {
+        match type_node.get_frame_type_opt() {
+            Some(frame_type) => self.format_frame_type(frame_type),
+            None => type_node.get_type_str(),
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn format_frame_type(&self, frame_type:&FrameType) -> String {
+        match frame_type {
+            FrameType::Int => String::from("int"),
+            FrameType::Float => String::from("double"),
+            FrameType::Bool => String::from("boolean"),
+            FrameType::String => String::from("String"),
+            FrameType::List {element_type} => format!("List<{}>", self.format_type_argument(element_type)),
+            FrameType::Map {key_type, value_type} => format!("Map<{}, {}>", self.format_type_argument(key_type), self.format_type_argument(value_type)),
+            FrameType::TypeParam {name} => name.clone(),
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // A generic system is a generic class over the system's type parameters.
+
+    fn format_type_params(system_node:&SystemNode) -> String {
+        if system_node.type_params.is_empty() {
+            String::new()
         } else {
-            if self.generate_state_context {
-                self.add_code(&format!("_transition_(stateContext.state,stateContext);"));
-            } else {
-                self.add_code(&format!("_transition_(state);"));
-            }
+            format!("<{}>", system_node.type_params.join(", "))
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Generic type arguments must be reference types in Java.
+
+    fn format_type_argument(&self, frame_type:&FrameType) -> String {
+        match frame_type {
+            FrameType::Int => String::from("Integer"),
+            FrameType::Float => String::from("Double"),
+            FrameType::Bool => String::from("Boolean"),
+            _ => self.format_frame_type(frame_type),
+        }
+    }
+}
+
+//* --------------------------------------------------------------------- *//
+
+impl OperatorPrecedence for Java8Visitor {
+
+    //* --------------------------------------------------------------------- *//
+
+    // Binding strength of each operator in Java, higher binds tighter.
+    // Xor is lowered to '!=' on booleans so it sits with the equality operators.
+
+    fn operator_precedence(&self, operator_type:&OperatorType) -> usize {
+        match operator_type {
+            OperatorType::LogicalOr => 1,
+            OperatorType::LogicalAnd => 2,
+            OperatorType::EqualEqual
+            | OperatorType::NotEqual
+            | OperatorType::LogicalXor => 3,
+            OperatorType::Greater
+            | OperatorType::GreaterEqual
+            | OperatorType::Less
+            | OperatorType::LessEqual => 4,
+            OperatorType::Plus
+            | OperatorType::Minus => 5,
+            OperatorType::Multiply
+            | OperatorType::Divide => 6,
+            OperatorType::Not
+            | OperatorType::Negated => 7,
         }
     }
 }
@@ -860,10 +1964,32 @@ impl AstVisitor for Java8Visitor {
         self.add_code("// get include files at https://github.com/frame-lang/frame-ancillary-files");
         self.newline();
         self.newline();
-        self.add_code(&format!("class {} {{", system_node.name));
+        let type_params = Self::format_type_params(system_node);
+        self.generate_abstract_actions = system_node.has_external_actions();
+        if self.generate_abstract_actions {
+            self.add_code(&format!("abstract class {}{} {{", system_node.name, type_params));
+        } else {
+            self.add_code(&format!("class {}{} {{", system_node.name, type_params));
+        }
         self.indent();
         self.newline();
 
+        self.generate_timers = system_node.has_timeouts();
+        self.generate_event_queue = system_node.is_run_to_completion();
+        self.generate_deferred_events = system_node.has_deferred_events();
+        self.generate_history = system_node.has_history();
+        self.generate_regions = system_node.has_regions();
+        self.generate_final_states = system_node.get_final_state_names().len() > 0;
+        self.generate_completions = system_node.has_completions();
+        self.pushed_state_names = system_node.get_pushed_state_names();
+        self.domain_param_names = system_node.get_domain_param_names();
+        self.generate_start_enter = system_node.start_state_enter_params_opt.is_some();
+        self.state_stack_max_opt = system_node.get_state_stack_max();
+        self.state_stack_underflow_opt = system_node.get_state_stack_underflow();
+        if self.generate_timers {
+            self.generate_scheduler_interface();
+        }
+
         // First state name needed for machinery.
         // Don't generate if there isn't at least one state.
         match (&system_node).get_first_state() {
@@ -876,15 +2002,47 @@ impl AstVisitor for Java8Visitor {
 
         // generate constructor
 
+        let system_params = system_node.get_system_params();
+        let mut constructor_params:Vec<String> = system_params.iter()
+            .map(|param| {
+                let param_type = match &param.param_type_opt {
+                    Some(param_type) => self.format_type(param_type),
+                    None => String::from("<?>"),
+                };
+                format!("{} {}", param_type, param.param_name)
+            })
+            .collect();
+        let mut super_args:Vec<String> = system_params.iter().map(|param| param.param_name.clone()).collect();
+        if self.generate_timers {
+            constructor_params.push(String::from("FrameScheduler scheduler"));
+            super_args.push(String::from("scheduler"));
+        }
+
         if self.has_states {
-            self.add_code(&format!("{}() {{",system_node.name));
+            self.add_code(&format!("{}({}) {{",system_node.name, constructor_params.join(", ")));
             self.indent();
             self.newline();
             self.newline();
-            self.add_code(&format!("_state_ = this::_s{}_;", self.first_state_name));
+            self.add_code(&format!("_state_ = {};", self.format_target_state_reference(&self.first_state_name)));
+            if self.generate_regions {
+                let region_states:Vec<String> = system_node.get_region_initial_state_names().iter()
+                    .map(|state_name| self.format_target_state_reference(state_name)).collect();
+                self.newline();
+                self.add_code(&format!("_regions_ = new FrameState[] {{{}}};", region_states.join(", ")));
+            }
+            if self.generate_timers {
+                self.newline();
+                self.add_code(&format!("_scheduler_ = scheduler;"));
+            }
             if self.generate_state_context {
                 self.newline();
                 self.add_code(&format!("_stateContext_ = new StateContext({});", self.format_target_state_reference(&self.first_state_name)));
+                if let Some(params) = &system_node.start_state_state_params_opt {
+                    for param in params {
+                        self.newline();
+                        self.add_code(&format!("_stateContext_.addStateArg(\"{}\",{});", param.param_name, param.param_name));
+                    }
+                }
                 if let Some(state_symbol_rcref) = self.arcanium.get_state(&self.first_state_name) {
                     //   self.newline();
                     let state_symbol = state_symbol_rcref.borrow();
@@ -904,6 +2062,29 @@ impl AstVisitor for Java8Visitor {
                 }
             }
 
+            // The start state is never entered so start its timer here.
+            if let Some(first_state_node_rcref) = system_node.get_first_state() {
+                let first_state_node = first_state_node_rcref.borrow();
+                if let Some(timeout_node) = &first_state_node.timeout_opt {
+                    self.current_state_name_opt = Some(first_state_node.name.clone());
+                    self.generate_timer_start(timeout_node);
+                    self.current_state_name_opt = None;
+                }
+            }
+
+            for var_name in &self.domain_param_names.clone() {
+                self.newline();
+                self.add_code(&format!("this.{} = {};", var_name, var_name));
+            }
+
+            // The start state is entered with these on the first event.
+            if let Some(params) = &system_node.start_state_enter_params_opt {
+                for param in params {
+                    self.newline();
+                    self.add_code(&format!("_stateContext_.addEnterArg(\"{}\",{});", param.param_name, param.param_name));
+                }
+            }
+
             self.outdent();
             self.newline();
             self.add_code(&format!("}}"));
@@ -912,23 +2093,20 @@ impl AstVisitor for Java8Visitor {
 
         // end of generate constructor
 
-        self.serialize.push("".to_string());
-        self.serialize.push("Bag _serialize__do() {".to_string());
 
-        self.deserialize.push("".to_string());
-
-        // @TODO: _do needs to be configurable.
-        self.deserialize.push("void _deserialize__do(Bag data) {".to_string());
-
-
-        self.subclass_code.push("".to_string());
-        self.subclass_code.push("/********************\n".to_string());
-        self.subclass_code.push(format!("public class {}Controller extends {} {{",system_node.name,system_node.name));
+        self.subclass_code.push(format!("public class {}Controller{} extends {}{} {{",system_node.name,type_params,system_node.name,type_params));
+        if !constructor_params.is_empty() {
+            self.subclass_code.push(format!("\tpublic {}Controller({}) {{ super({}); }}",system_node.name, constructor_params.join(", "), super_args.join(", ")));
+        }
 
         if let Some(interface_block_node) = &system_node.interface_block_node_opt {
             interface_block_node.accept(self);
         }
 
+        if let Some(operations_block_node) = &system_node.operations_block_node_opt {
+            operations_block_node.accept(self);
+        }
+
         if let Some(machine_block_node) = &system_node.machine_block_node_opt {
             machine_block_node.accept(self);
         }
@@ -942,15 +2120,6 @@ impl AstVisitor for Java8Visitor {
         }
 
         self.subclass_code.push(format!("}}"));
-        self.subclass_code.push("\n********************/".to_string());
-
-        self.serialize.push("".to_string());
-        self.serialize.push("\treturn JSON.stringify(bag);".to_string());
-        self.serialize.push("}".to_string());
-        self.serialize.push("".to_string());
-
-        self.deserialize.push("".to_string());
-        self.deserialize.push("}".to_string());
 
         if self.has_states {
             self.generate_machinery(system_node);
@@ -965,8 +2134,6 @@ impl AstVisitor for Java8Visitor {
         self.add_code("}");
         self.newline();
 
-        self.generate_subclass();
-
         AstVisitorReturnType::SystemNode {}
     }
 
@@ -1006,7 +2173,7 @@ impl AstVisitor for Java8Visitor {
 
         self.newline();
         let return_type = match &interface_method_node.return_type_opt {
-            Some(ret) => ret.get_type_str(),
+            Some(ret) => self.format_type(ret),
             None => "void".to_string(),
         };
 
@@ -1051,15 +2218,41 @@ impl AstVisitor for Java8Visitor {
             params_param_code = String::from("null");
         }
 
+        if *method_name_or_alias == self.symbol_config.restore_msg_symbol {
+            if let Some(params) = &interface_method_node.params {
+                self.newline();
+                self.add_code(&format!("_deserialize__do({});", params[0].param_name));
+            }
+        }
+        if self.generate_start_enter {
+            self.newline();
+            self.add_code("if (!_started_) _start_();");
+        }
         self.newline();
         self.add_code(&format!("FrameEvent e = new FrameEvent(\"{}\",{});", method_name_or_alias,params_param_code));
+        if let Some(default_return_expr_t) = &interface_method_node.default_return_expr_opt {
+            let mut default_return = String::new();
+            default_return_expr_t.accept_to_string(self, &mut default_return);
+            self.newline();
+            self.add_code(&format!("e._return = {};", default_return));
+        }
         self.newline();
-        self.add_code(&format!("_state_.call(e);"));
+        if self.generate_event_queue {
+            self.add_code(&format!("_dispatch_(e);"));
+        } else if self.generate_regions {
+            self.add_code(&format!("_dispatchRegions_(e);"));
+        } else {
+            self.add_code(&format!("_state_.call(e);"));
+        }
 
         match &interface_method_node.return_type_opt {
+            Some(_) if *method_name_or_alias == self.symbol_config.save_msg_symbol => {
+                self.newline();
+                self.add_code("return _serialize__do();");
+            },
             Some(return_type) => {
                 self.newline();
-                self.add_code(&format!("return ({}) e._return;",return_type.get_type_str()));
+                self.add_code(&format!("return ({}) e._return;",self.format_type(return_type)));
             },
             None => {}
         }
@@ -1079,29 +2272,11 @@ impl AstVisitor for Java8Visitor {
         self.newline();
         self.add_code("//===================== Machine Block ===================//");
 
-        self.serialize.push("".to_string());
-        self.serialize.push("\tvar stateName = null;".to_string());
-
-        self.deserialize.push("".to_string());
-        self.deserialize.push("\tconst bag = JSON.parse(data);".to_string());
-        self.deserialize.push("".to_string());
-        self.deserialize.push("\tswitch (bag.state) {".to_string());
-
 
         for state_node_rcref in &machine_block_node.states {
             state_node_rcref.borrow().accept(self);
         }
 
-        self.serialize.push("".to_string());
-        self.serialize.push("\tvar bag = {".to_string());
-        self.serialize.push("\t\tstate : stateName,".to_string());
-        self.serialize.push("\t\tdomain : {}".to_string());
-        self.serialize.push("\t};".to_string());
-        self.serialize.push("".to_string());
-
-        self.deserialize.push("\t}".to_string());
-        self.deserialize.push("".to_string());
-
 
         AstVisitorReturnType::MachineBlockNode {}
     }
@@ -1167,9 +2342,19 @@ impl AstVisitor for Java8Visitor {
         self.add_code(&format!("private void _s{}_(FrameEvent e) {{", state_node.name));
         self.indent();
 
-        self.serialize.push(format!("\tif (_state_ == _s{}_) stateName = \"{}\"",state_node.name,state_node.name));
+        if let Some(deferred_events) = &state_node.deferred_events_opt {
+            self.generate_deferred_event_checks(deferred_events);
+        }
 
-        self.deserialize.push(format!("\t\tcase \"{}\": _state_ = _s{}_; break;",state_node.name,state_node.name));
+        if let Some(timeout_node) = &state_node.timeout_opt {
+            self.newline();
+            self.add_code(&format!("if (e._message == \"{}\") {{", self.symbol_config.enter_msg_symbol));
+            self.indent();
+            self.generate_timer_start(timeout_node);
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
 
         if let Some(calls) = &state_node.calls_opt {
             for call in calls {
@@ -1181,6 +2366,10 @@ impl AstVisitor for Java8Visitor {
 
         self.first_event_handler = true; // context for formatting
 
+        if let Some(timeout_node) = &state_node.timeout_opt {
+            timeout_node.accept(self);
+        }
+
         if state_node.evt_handlers_rcref.len() > 0 {
             for evt_handler_node in &state_node.evt_handlers_rcref {
                 evt_handler_node.as_ref().borrow().accept(self);
@@ -1205,7 +2394,10 @@ impl AstVisitor for Java8Visitor {
     //* --------------------------------------------------------------------- *//
 
     fn visit_event_handler_node(&mut self, evt_handler_node: &EventHandlerNode) -> AstVisitorReturnType {
-        self.current_event_ret_type = evt_handler_node.get_event_ret_type();
+        self.current_event_ret_type = match evt_handler_node.get_event_ret_type_node_opt() {
+            Some(ret_type) => self.format_type(&ret_type),
+            None => String::new(),
+        };
         self.newline();
         self.generate_comment(evt_handler_node.line);
 
@@ -1428,7 +2620,10 @@ impl AstVisitor for Java8Visitor {
         match &transition_statement.target_state_context_t {
             StateContextType::StateRef {..}
                 => self.generate_state_ref_transition(transition_statement),
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..}
+                => self.generate_state_stack_pop_transition(transition_statement),
+            // a history target is restored like a popped state
+            StateContextType::StateHistory {..}
                 => self.generate_state_stack_pop_transition(transition_statement),
         };
 
@@ -1451,7 +2646,7 @@ impl AstVisitor for Java8Visitor {
         match &change_state_stmt_node.state_context_t {
             StateContextType::StateRef {..}
                 => self.generate_state_ref_change_state(change_state_stmt_node),
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..} | StateContextType::StateHistory {..}
                 => self.errors.push(format!("Fatal error - change state stack pop not implemented."),)
         };
 
@@ -1481,6 +2676,33 @@ impl AstVisitor for Java8Visitor {
 
     //* --------------------------------------------------------------------- *//
 
+    fn visit_timeout_node(&mut self, timeout_node: &TimeoutNode) -> AstVisitorReturnType {
+        self.newline();
+        self.generate_comment(timeout_node.line);
+        if self.first_event_handler {
+            self.add_code(&format!("if (e._message == \"{}\") {{", self.symbol_config.timeout_msg_symbol));
+        } else {
+            self.add_code(&format!("else if (e._message == \"{}\") {{", self.symbol_config.timeout_msg_symbol));
+        }
+        self.indent();
+        if self.generate_state_context {
+            self.newline();
+            self.add_code(&format!("StateContext stateContext = null;"));
+        }
+        timeout_node.transition_statement_node.accept(self);
+        self.newline();
+        self.add_code("return;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+
+        self.first_event_handler = false;
+
+        AstVisitorReturnType::TimeoutNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_test_statement_node(&mut self, test_stmt_node: &TestStatementNode) -> AstVisitorReturnType {
 
         match &test_stmt_node.test_t {
@@ -2000,6 +3222,78 @@ impl AstVisitor for Java8Visitor {
 
     //* --------------------------------------------------------------------- *//
 
+    fn visit_list_literal_node(&mut self, list_literal_node: &ListLiteralNode) -> AstVisitorReturnType {
+
+        let mut output = String::new();
+        self.visit_list_literal_node_to_string(list_literal_node, &mut output);
+        self.add_code(&output);
+
+        AstVisitorReturnType::ListLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_list_literal_node_to_string(&mut self, list_literal_node: &ListLiteralNode, output:&mut String) -> AstVisitorReturnType {
+
+        output.push_str("List.of(");
+        let mut separator = "";
+        for expr_t in &list_literal_node.exprs_t {
+            output.push_str(separator);
+            expr_t.accept_to_string(self, output);
+            separator = ",";
+        }
+        output.push_str(")");
+
+        AstVisitorReturnType::ListLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_map_literal_node(&mut self, map_literal_node: &MapLiteralNode) -> AstVisitorReturnType {
+
+        let mut output = String::new();
+        self.visit_map_literal_node_to_string(map_literal_node, &mut output);
+        self.add_code(&output);
+
+        AstVisitorReturnType::MapLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_map_literal_node_to_string(&mut self, map_literal_node: &MapLiteralNode, output:&mut String) -> AstVisitorReturnType {
+
+        // Map.of() only has overloads for up to 10 entries.
+        if map_literal_node.entries.len() <= 10 {
+            output.push_str("Map.of(");
+            let mut separator = "";
+            for (key_expr_t, value_expr_t) in &map_literal_node.entries {
+                output.push_str(separator);
+                key_expr_t.accept_to_string(self, output);
+                output.push_str(",");
+                value_expr_t.accept_to_string(self, output);
+                separator = ",";
+            }
+            output.push_str(")");
+        } else {
+            output.push_str("Map.ofEntries(");
+            let mut separator = "";
+            for (key_expr_t, value_expr_t) in &map_literal_node.entries {
+                output.push_str(separator);
+                output.push_str("Map.entry(");
+                key_expr_t.accept_to_string(self, output);
+                output.push_str(",");
+                value_expr_t.accept_to_string(self, output);
+                output.push_str(")");
+                separator = ",";
+            }
+            output.push_str(")");
+        }
+
+        AstVisitorReturnType::MapLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_expression_list_node(&mut self, expr_list: &ExprListNode) -> AstVisitorReturnType {
 
         let mut separator = "";
@@ -2132,6 +3426,7 @@ impl AstVisitor for Java8Visitor {
         match state_stack_op_statement_node.state_stack_operation_node.operation_t {
             StateStackOperationType::Push => {
                 self.newline();
+                self.generate_state_stack_overflow_check();
                 if self.generate_state_context {
                     self.add_code(&format!("_stateStack_push_(_stateContext_);"));
                 } else {
@@ -2150,6 +3445,26 @@ impl AstVisitor for Java8Visitor {
     }
     //* --------------------------------------------------------------------- *//
 
+    fn visit_raise_statement_node(&mut self, raise_statement_node:&RaiseStatementNode) -> AstVisitorReturnType {
+        self.newline();
+        self.add_code(&format!("{}", raise_statement_node.interface_method_name));
+        raise_statement_node.call_expr_list_node.accept(self);
+        self.add_code(";");
+
+        AstVisitorReturnType::RaiseStatementNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_forward_statement_node(&mut self, forward_statement_node:&ForwardStatementNode) -> AstVisitorReturnType {
+        self.newline();
+        self.add_code(&format!("_s{}_(e);", forward_statement_node.target_state_ref.name));
+
+        AstVisitorReturnType::ForwardStatementNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_state_context_node(&mut self, _state_context_node:&StateContextNode) -> AstVisitorReturnType {
 
         // TODO
@@ -2199,12 +3514,27 @@ impl AstVisitor for Java8Visitor {
         self.newline_to_string(&mut subclass_code);
 
         let action_ret_type:String = match &action_decl_node.type_opt {
-            Some(ret_type) => ret_type.get_type_str(),
+            Some(ret_type) => self.format_type(ret_type),
             None => String::from("void"),
         };
 
         let action_name = self.format_action_name(&action_decl_node.name);
-        self.add_code(&format!("protected {} {}(",action_ret_type, action_name));
+        if action_decl_node.has_frame_body() {
+            self.add_code(&format!("protected {} {}(",action_ret_type, action_name));
+            if let Some(params) = &action_decl_node.params {
+                self.format_parameter_list(params);
+            }
+            self.add_code(") {");
+            self.indent();
+            self.generate_frame_body(&action_decl_node.statements, action_decl_node.get_return_expr_opt());
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+            self.newline();
+            return AstVisitorReturnType::ActionDeclNode {};
+        }
+
+        self.add_code(&format!("protected abstract {} {}(",action_ret_type, action_name));
         subclass_code.push_str(&format!("protected {} {}(",action_ret_type, action_name));
 
         match &action_decl_node.params {
@@ -2215,7 +3545,7 @@ impl AstVisitor for Java8Visitor {
         subclass_code.push_str(&format!(") {{}}"));
         self.subclass_code.push(subclass_code);
 
-        self.add_code(&format!(") {{ throw new UnsupportedOperationException(); }}"));
+        self.add_code(");");
 
         AstVisitorReturnType::ActionDeclNode {}
     }
@@ -2234,19 +3564,21 @@ impl AstVisitor for Java8Visitor {
     fn visit_variable_decl_node(&mut self, variable_decl_node: &VariableDeclNode) -> AstVisitorReturnType {
 
         let var_type = match &variable_decl_node.type_opt {
-            Some(x) => x.get_type_str(),
+            Some(x) => self.format_type(x),
             None => String::from("<?>"),
         };
         let var_name =  &variable_decl_node.name;
         let var_init_expr = &variable_decl_node.initializer_expr_t_opt.as_ref().unwrap();
         self.newline();
+        // Domain parameters are assigned in the constructor.
+        if matches!(variable_decl_node.identifier_decl_scope, IdentifierDeclScope::DomainBlock) && self.domain_param_names.contains(var_name) {
+            self.add_code( &format!("{} {};",var_type,var_name));
+            return AstVisitorReturnType::VariableDeclNode {};
+        }
         let mut code = String::new();
         var_init_expr.accept_to_string(self, &mut code);
         self.add_code( &format!("{} {} = {};",var_type,var_name, code));
 
-        self.serialize.push(format!("\tbag.domain[\"{}\"] = {};",var_name,var_name));
-        self.deserialize.push(format!("\t{} = bag.domain[\"{}\"];",var_name,var_name));
-
         AstVisitorReturnType::VariableDeclNode {}
     }
 
@@ -2258,6 +3590,55 @@ impl AstVisitor for Java8Visitor {
 
     //* --------------------------------------------------------------------- *//
 
+    fn visit_operations_block_node(&mut self, operations_block_node: &OperationsBlockNode) -> AstVisitorReturnType {
+        self.newline();
+        self.add_code("//===================== Operations Block ===================//");
+        self.newline();
+
+        for operation_node_rcref in &operations_block_node.operations {
+            operation_node_rcref.borrow().accept(self);
+        }
+
+        AstVisitorReturnType::OperationsBlockNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_operation_node(&mut self, operation_node: &OperationNode) -> AstVisitorReturnType {
+
+        self.newline();
+        let return_type = match &operation_node.type_opt {
+            Some(ret) => self.format_type(ret),
+            None => "void".to_string(),
+        };
+
+        self.add_code(&format!("public {} {}(",return_type, operation_node.name));
+
+        match &operation_node.params {
+            Some (params)
+            =>  self.format_parameter_list(params),
+            None => {},
+        }
+
+        self.add_code(") {");
+        self.indent();
+        match &operation_node.code_opt {
+            Some(code) => {
+                self.newline();
+                self.add_code(code);
+            },
+            None => self.generate_frame_body(&operation_node.statements, operation_node.get_return_expr_opt()),
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+
+        AstVisitorReturnType::OperationNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_variable_expr_node(&mut self, variable_node: &VariableNode) -> AstVisitorReturnType {
         let code = self.format_variable_expr(variable_node);
         self.add_code(&code);
@@ -2330,10 +3711,9 @@ impl AstVisitor for Java8Visitor {
 
     fn visit_unary_expr_node(&mut self, unary_expr_node: &UnaryExprNode) -> AstVisitorReturnType {
 
-        // TODO
-        //       self.generate_comment(assignment_expr_node.line);
-        unary_expr_node.operator.accept(self);
-        unary_expr_node.right_rcref.borrow().accept(self);
+        let mut output = String::new();
+        self.visit_unary_expr_node_to_string(unary_expr_node, &mut output);
+        self.add_code(&output);
 
         AstVisitorReturnType::UnaryExprNode {}
     }
@@ -2345,7 +3725,7 @@ impl AstVisitor for Java8Visitor {
         // TODO
         //       self.generate_comment(assignment_expr_node.line);
         unary_expr_node.operator.accept_to_string(self, output);
-        unary_expr_node.right_rcref.borrow().accept_to_string(self,output);
+        self.visit_operand_to_string(&unary_expr_node.operator, &unary_expr_node.right_rcref.borrow(), false, output);
 
         AstVisitorReturnType::UnaryExprNode {}
     }
@@ -2354,23 +3734,9 @@ impl AstVisitor for Java8Visitor {
 
     fn visit_binary_expr_node(&mut self, binary_expr_node: &BinaryExprNode) -> AstVisitorReturnType {
 
-        // TODO
-        //       self.generate_comment(assignment_expr_node.line);
-        if binary_expr_node.operator == OperatorType::LogicalXor {
-            self.add_code("((");
-            binary_expr_node.left_rcref.borrow().accept(self);
-            self.add_code(") && !(");
-            binary_expr_node.right_rcref.borrow().accept(self);
-            self.add_code(")) || (!(");
-            binary_expr_node.left_rcref.borrow().accept(self);
-            self.add_code(") && (");
-            binary_expr_node.right_rcref.borrow().accept(self);
-            self.add_code("))");
-        } else {
-            binary_expr_node.left_rcref.borrow().accept(self);
-            binary_expr_node.operator.accept(self);
-            binary_expr_node.right_rcref.borrow().accept(self);
-        }
+        let mut output = String::new();
+        self.visit_binary_expr_node_to_string(binary_expr_node, &mut output);
+        self.add_code(&output);
 
         AstVisitorReturnType::BinaryExprNode {}
     }
@@ -2379,27 +3745,41 @@ impl AstVisitor for Java8Visitor {
 
     fn visit_binary_expr_node_to_string(&mut self, binary_expr_node: &BinaryExprNode, output:&mut String) -> AstVisitorReturnType {
 
-        if binary_expr_node.operator == OperatorType::LogicalXor {
-            output.push_str("((");
-            binary_expr_node.left_rcref.borrow().accept_to_string(self,output);
-            output.push_str(") && !(");
-            binary_expr_node.right_rcref.borrow().accept_to_string(self,output);
-            output.push_str(")) || (!(");
-            binary_expr_node.left_rcref.borrow().accept_to_string(self,output);
-            output.push_str(") && (");
-            binary_expr_node.right_rcref.borrow().accept_to_string(self,output);
-            output.push_str("))");
-
-        } else {
-            binary_expr_node.left_rcref.borrow().accept_to_string(self,output);
-            binary_expr_node.operator.accept_to_string(self, output);
-            binary_expr_node.right_rcref.borrow().accept_to_string(self,output);
-        }
+        let operator_type = &binary_expr_node.operator;
+        self.visit_operand_to_string(operator_type, &binary_expr_node.left_rcref.borrow(), true, output);
+        operator_type.accept_to_string(self, output);
+        self.visit_operand_to_string(operator_type, &binary_expr_node.right_rcref.borrow(), false, output);
 
         AstVisitorReturnType::BinaryExprNode {}
     }
 
 
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_conditional_expr_node(&mut self, conditional_expr_node: &ConditionalExprNode) -> AstVisitorReturnType {
+
+        conditional_expr_node.condition_rcref.borrow().accept(self);
+        self.add_code(" ? ");
+        conditional_expr_node.true_expr_rcref.borrow().accept(self);
+        self.add_code(" : ");
+        conditional_expr_node.false_expr_rcref.borrow().accept(self);
+
+        AstVisitorReturnType::ConditionalExprNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_conditional_expr_node_to_string(&mut self, conditional_expr_node: &ConditionalExprNode, output:&mut String) -> AstVisitorReturnType {
+
+        conditional_expr_node.condition_rcref.borrow().accept_to_string(self, output);
+        output.push_str(" ? ");
+        conditional_expr_node.true_expr_rcref.borrow().accept_to_string(self, output);
+        output.push_str(" : ");
+        conditional_expr_node.false_expr_rcref.borrow().accept_to_string(self, output);
+
+        AstVisitorReturnType::ConditionalExprNode {}
+    }
+
     //* --------------------------------------------------------------------- *//
 
     fn visit_operator_type(&mut self, operator_type: &OperatorType) -> AstVisitorReturnType {
@@ -2419,7 +3799,7 @@ impl AstVisitor for Java8Visitor {
             OperatorType::NotEqual => self.add_code(" != "),
             OperatorType::LogicalAnd => self.add_code(" && "),
             OperatorType::LogicalOr => self.add_code(" || "),
-            OperatorType::LogicalXor => self.add_code(""),
+            OperatorType::LogicalXor => self.add_code(" != "),
         }
 
         AstVisitorReturnType::BinaryExprNode {}
@@ -2444,7 +3824,7 @@ impl AstVisitor for Java8Visitor {
             OperatorType::NotEqual => output.push_str(" != "),
             OperatorType::LogicalAnd => output.push_str(" && "),
             OperatorType::LogicalOr => output.push_str(" || "),
-            OperatorType::LogicalXor => output.push_str(""),
+            OperatorType::LogicalXor => output.push_str(" != "),
         }
 
         AstVisitorReturnType::BinaryExprNode {}
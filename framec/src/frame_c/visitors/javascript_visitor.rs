@@ -18,9 +18,8 @@ pub struct JavaScriptVisitor {
     first_event_handler:bool,
     system_name:String,
     first_state_name:String,
-    serialize:Vec<String>,
-    deserialize:Vec<String>,
     subclass_code:Vec<String>,
+    generate_abstract_actions:bool,
     warnings:Vec<String>,
     has_states:bool,
     errors:Vec<String>,
@@ -30,6 +29,18 @@ pub struct JavaScriptVisitor {
     generate_state_stack:bool,
     generate_change_state:bool,
     generate_transition_state:bool,
+    generate_timers:bool,
+    generate_event_queue:bool,
+    generate_deferred_events:bool,
+    generate_history:bool,
+    generate_regions:bool,
+    generate_final_states:bool,
+    generate_completions:bool,
+    pushed_state_names:Vec<String>,
+    domain_param_names:Vec<String>,
+    generate_start_enter:bool,
+    state_stack_max_opt:Option<usize>,
+    state_stack_underflow_opt:Option<StateStackUnderflowPolicy>,
 }
 
 impl JavaScriptVisitor {
@@ -64,22 +75,50 @@ impl JavaScriptVisitor {
             first_event_handler:true,
             system_name:String::new(),
             first_state_name:String::new(),
-            serialize:Vec::new(),
-            deserialize:Vec::new(),
             has_states:false,
             warnings:Vec::new(),
             visiting_call_chain_literal_variable:false,
             errors:Vec::new(),
             subclass_code:Vec::new(),
+            generate_abstract_actions:false,
             generate_exit_args,
             generate_state_context,
             generate_state_stack,
             generate_change_state,
             generate_transition_state,
+            generate_timers:false,
+            generate_event_queue:false,
+            generate_deferred_events:false,
+            generate_history:false,
+            generate_regions:false,
+            generate_final_states:false,
+            generate_completions:false,
+            pushed_state_names:Vec::new(),
+            domain_param_names:Vec::new(),
+            generate_start_enter:false,
+            state_stack_max_opt:None,
+            state_stack_underflow_opt:None,
         }
     }
 
 
+    //* --------------------------------------------------------------------- *//
+
+    // The controller implementing the system's external actions, written
+    // once to its own file so regenerating the system keeps the user's code.
+
+    pub fn get_actions_stub(&self) -> Option<String> {
+        if !self.generate_abstract_actions {
+            return None;
+        }
+        let mut stub = String::new();
+        for line in self.subclass_code.iter() {
+            stub.push_str(line);
+            stub.push('\n');
+        }
+        Some(stub)
+    }
+
     //* --------------------------------------------------------------------- *//
 
     pub fn get_code(&self) -> String {
@@ -299,6 +338,12 @@ impl JavaScriptVisitor {
                         StatementType::StateStackStmt {state_stack_operation_statement_node} => {
                             state_stack_operation_statement_node.accept(self);
                         },
+                        StatementType::RaiseStmt {raise_statement_node} => {
+                            raise_statement_node.accept(self);
+                        },
+                        StatementType::ForwardStmt {forward_statement_node} => {
+                            forward_statement_node.accept(self);
+                        },
                         StatementType::ChangeStateStmt {change_state_stmt} => {
                             change_state_stmt.accept(self);
                         },
@@ -313,6 +358,20 @@ impl JavaScriptVisitor {
     }
 
 
+    // Generates a Frame body for an operation or action.
+
+    fn generate_frame_body(&mut self, statements:&Vec<DeclOrStmtType>, return_expr_t_opt:Option<&ExprType>) {
+        self.visit_decl_stmts(statements);
+        if let Some(expr_t) = return_expr_t_opt {
+            self.newline();
+            self.add_code("return ");
+            expr_t.accept(self);
+            self.add_code(";");
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     //* --------------------------------------------------------------------- *//
 
     fn generate_machinery(&mut self, system_node: &SystemNode) {
@@ -323,9 +382,21 @@ impl JavaScriptVisitor {
         if let Some(_) = system_node.get_first_state() {
             self.newline();
             self.add_code(&format!("let _state_ = _s{}_;",self.first_state_name));
+            if self.generate_regions {
+                let region_states:Vec<String> = system_node.get_region_initial_state_names().iter()
+                    .map(|state_name| format!("_s{}_", state_name)).collect();
+                self.newline();
+                self.add_code(&format!("let _regions_ = [{}];", region_states.join(", ")));
+            }
             if self.generate_state_context {
                 self.newline();
                 self.add_code(&format!("let _stateContext_ = StateContext(_state_);"));
+                if let Some(params) = &system_node.start_state_state_params_opt {
+                    for param in params {
+                        self.newline();
+                        self.add_code(&format!("_stateContext_.addStateArg(\"{}\",{});", param.param_name, param.param_name));
+                    }
+                }
                 self.newline();
                 if self.has_states {
                     if let Some(state_symbol_rcref) = self.arcanium.get_state(&self.first_state_name) {
@@ -377,6 +448,14 @@ impl JavaScriptVisitor {
                 self.newline();
                 self.add_code(&format!("_state_(exitEvent);"));
                 self.newline();
+                if self.generate_history {
+                    self.add_code(&format!("_recordHistory_();"));
+                    self.newline();
+                }
+                if self.generate_timers {
+                    self.add_code(&format!("_cancelTimer_();"));
+                    self.newline();
+                }
                 self.add_code(&format!("_state_ = newState;"));
                 self.newline();
                 if self.generate_state_context {
@@ -389,6 +468,17 @@ impl JavaScriptVisitor {
                     self.newline();
                 }
                 self.add_code(&format!("_state_(enterEvent);"));
+                if self.generate_deferred_events {
+                    self.newline();
+                    self.add_code(&format!("_replayDeferred_();"));
+                }
+                if self.generate_final_states && !self.generate_regions {
+                    self.generate_finished_check();
+                }
+                if self.generate_completions {
+                    self.newline();
+                    self.add_code("_complete_();");
+                }
                 self.outdent();
                 self.newline();
                 self.add_code(&format!("}}"));
@@ -419,41 +509,712 @@ impl JavaScriptVisitor {
             if self.generate_change_state {
                 self.newline();
                 self.newline();
-                self.add_code(&format!("let _changeState_ = function(newState) {{"));
+                if self.generate_state_context {
+                    self.add_code(&format!("let _changeState_ = function(newState, stateContext) {{"));
+                } else {
+                    self.add_code(&format!("let _changeState_ = function(newState) {{"));
+                }
                 self.indent();
                 self.newline();
+                if self.generate_history {
+                    self.add_code(&format!("_recordHistory_();"));
+                    self.newline();
+                }
+                if self.generate_timers {
+                    self.add_code(&format!("_cancelTimer_();"));
+                    self.newline();
+                }
                 self.add_code(&format!("_state_ = newState;"));
+                if self.generate_state_context {
+                    self.newline();
+                    self.add_code(&format!("_stateContext_ = stateContext;"));
+                }
+                if self.generate_deferred_events {
+                    self.newline();
+                    self.add_code(&format!("_replayDeferred_();"));
+                }
+                if self.generate_final_states && !self.generate_regions {
+                    self.generate_finished_check();
+                }
+                if self.generate_completions {
+                    self.newline();
+                    self.add_code("_complete_();");
+                }
                 self.outdent();
                 self.newline();
                 self.add_code(&format!("}}"));
             }
+            if self.generate_timers {
+                self.generate_timers(system_node);
+            }
+            if self.generate_event_queue {
+                self.generate_event_queue();
+            }
+            if self.generate_deferred_events {
+                self.generate_deferred_events();
+            }
+            if self.generate_history {
+                self.generate_history(system_node);
+            }
+            if self.generate_regions {
+                self.generate_regions();
+            }
+            if self.generate_final_states {
+                self.generate_final_states(system_node);
+            }
+            if self.generate_completions {
+                self.generate_completions(system_node);
+            }
+            if self.generate_start_enter {
+                self.generate_start(system_node);
+            }
 
             if self.arcanium.is_serializable() {
+                self.generate_serialization(system_node);
+            }
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Only one timer is active at a time. It is cancelled when a state is
+    // exited so a timeout always belongs to the current state. The default
+    // scheduler uses setTimeout(). Pass an object with the same
+    // schedule()/cancel() functions to the system to use another clock,
+    // e.g. a virtual clock for tests.
+
+    fn generate_timers(&mut self, system_node: &SystemNode) {
+        self.newline();
+        self.newline();
+        self.add_code("let _scheduler_ = scheduler || {");
+        self.indent();
+        self.newline();
+        self.add_code("_timer: null,");
+        self.newline();
+        self.add_code("schedule: function(delayMs, callback) {");
+        self.indent();
+        self.newline();
+        self.add_code("this._timer = setTimeout(callback, delayMs);");
+        self.outdent();
+        self.newline();
+        self.add_code("},");
+        self.newline();
+        self.add_code("cancel: function() {");
+        self.indent();
+        self.newline();
+        self.add_code("clearTimeout(this._timer);");
+        self.newline();
+        self.add_code("this._timer = null;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.outdent();
+        self.newline();
+        self.add_code("};");
+        self.newline();
+        self.add_code("let _timerActive_ = false;");
+        self.newline();
+        self.newline();
+        self.add_code("let _startTimer_ = function(delayMs) {");
+        self.indent();
+        self.newline();
+        self.add_code("if (_timerActive_) return;");
+        self.newline();
+        self.add_code("_timerActive_ = true;");
+        self.newline();
+        self.add_code("_scheduler_.schedule(delayMs, _timeout_);");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        self.add_code("let _cancelTimer_ = function() {");
+        self.indent();
+        self.newline();
+        self.add_code("if (!_timerActive_) return;");
+        self.newline();
+        self.add_code("_timerActive_ = false;");
+        self.newline();
+        self.add_code("_scheduler_.cancel();");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        self.add_code("let _timeout_ = function() {");
+        self.indent();
+        self.newline();
+        self.add_code("if (!_timerActive_) return;");
+        self.newline();
+        self.add_code("_timerActive_ = false;");
+        self.newline();
+        if self.generate_event_queue {
+            self.add_code(&format!("_dispatch_(FrameEvent(\"{}\",null));", self.symbol_config.timeout_msg_symbol));
+        } else {
+            self.add_code(&format!("_state_(FrameEvent(\"{}\",null));", self.symbol_config.timeout_msg_symbol));
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+
+        // The start state is never entered so start its timer here.
+        if let Some(first_state_node_rcref) = system_node.get_first_state() {
+            let first_state_node = first_state_node_rcref.borrow();
+            if let Some(timeout_node) = &first_state_node.timeout_opt {
+                self.current_state_name_opt = Some(first_state_node.name.clone());
+                self.newline();
+                self.generate_timer_start(timeout_node);
+                self.current_state_name_opt = None;
+            }
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Events sent while the machine is handling an event are queued and
+    // dispatched in order once the current event has been fully handled.
+
+    fn generate_event_queue(&mut self) {
+        let state_dispatch = if self.generate_regions {"_dispatchRegions_"} else {"_state_"};
+        self.newline();
+        self.newline();
+        self.add_code("let _eventQueue_ = [];");
+        self.newline();
+        self.add_code("let _processing_ = false;");
+        self.newline();
+        self.newline();
+        self.add_code("let _dispatch_ = function(e) {");
+        self.indent();
+        self.newline();
+        self.add_code("if (_processing_) {");
+        self.indent();
+        self.newline();
+        self.add_code("_eventQueue_.push(e);");
+        self.newline();
+        self.add_code("return;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.add_code("_processing_ = true;");
+        self.newline();
+        // Clear the flag even if a handler throws, otherwise every later
+        // event would be queued and never dispatched.
+        self.add_code("try {");
+        self.indent();
+        self.newline();
+        self.add_code(&format!("{}(e);", state_dispatch));
+        self.newline();
+        self.add_code("while (_eventQueue_.length > 0) {");
+        self.indent();
+        self.newline();
+        self.add_code(&format!("{}(_eventQueue_.shift());", state_dispatch));
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.outdent();
+        self.newline();
+        self.add_code("} finally {");
+        self.indent();
+        self.newline();
+        self.add_code("_processing_ = false;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // The machine is finished once it is in a #[final] state, or once
+    // every region is.
+
+    fn generate_final_states(&mut self, system_node: &SystemNode) {
+        let final_states:Vec<String> = system_node.get_final_state_names().iter()
+            .map(|state_name| format!("state == _s{}_", state_name)).collect();
+        self.newline();
+        self.newline();
+        self.add_code("let _isFinalState_ = function(state) {");
+        self.indent();
+        self.newline();
+        self.add_code(&format!("return {};", final_states.join(" || ")));
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        if self.generate_regions {
+            self.newline();
+            self.add_code("that.isFinished = function() {");
+            self.indent();
+            self.newline();
+            self.add_code("return _regions_.every(_isFinalState_);");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        } else {
+            self.newline();
+            self.add_code("that.isFinished = function() {");
+            self.indent();
+            self.newline();
+            self.add_code("return _isFinalState_(_state_);");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
+        self.newline();
+        self.newline();
+        self.add_code("that.onFinished = function () {}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Entering a final state completes the machine.
+
+    fn generate_finished_check(&mut self) {
+        self.newline();
+        self.add_code("if (that.isFinished()) {");
+        self.indent();
+        self.newline();
+        self.add_code("that.onFinished();");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Actions are overridden after the system is constructed so the start
+    // state can not be entered from the constructor. The first interface
+    // call enters it with the system enter parameters.
+
+    fn generate_start(&mut self, system_node: &SystemNode) {
+        self.newline();
+        self.newline();
+        self.add_code("let _started_ = false;");
+        if let Some(params) = &system_node.start_state_enter_params_opt {
+            for param in params {
+                self.newline();
+                self.add_code(&format!("_stateContext_.addEnterArg(\"{}\",{});", param.param_name, param.param_name));
+            }
+        }
+        self.newline();
+        self.newline();
+        self.add_code("let _start_ = function() {");
+        self.indent();
+        self.newline();
+        self.add_code("_started_ = true;");
+        self.newline();
+        self.add_code("_state_(FrameEvent(\">\",_stateContext_.getEnterArgs()));");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Completion transitions are taken one after another in a loop rather
+    // than from inside the transition that entered the state, so long
+    // chains don't grow the stack.
+
+    fn generate_completions(&mut self, system_node: &SystemNode) {
+        self.newline();
+        self.newline();
+        self.add_code("let _completing_ = false;");
+        self.newline();
+        self.newline();
+        self.add_code("let _complete_ = function() {");
+        self.indent();
+        self.newline();
+        self.add_code("if (_completing_) {");
+        self.indent();
+        self.newline();
+        self.add_code("return;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.add_code("_completing_ = true;");
+        self.newline();
+        self.add_code("while (_completion_()) {}");
+        self.newline();
+        self.add_code("_completing_ = false;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        self.add_code("let _completion_ = function() {");
+        self.indent();
+        if let Some(machine_block_node) = &system_node.machine_block_node_opt {
+            for state_node_rcref in &machine_block_node.states {
+                let state_node = state_node_rcref.borrow();
+                if state_node.completions.len() == 0 {
+                    continue;
+                }
+                self.current_state_name_opt = Some(state_node.name.clone());
+                self.newline();
+                self.add_code(&format!("if (_state_ == _s{}_) {{", state_node.name));
+                self.indent();
+                for completion_node in &state_node.completions {
+                    if let Some(guard_expr_t) = &completion_node.guard_expr_t_opt {
+                        let mut guard = String::new();
+                        guard_expr_t.accept_to_string(self, &mut guard);
+                        self.newline();
+                        self.add_code(&format!("if ({}) {{", guard));
+                        self.indent();
+                    }
+                    completion_node.transition_statement_node.accept(self);
+                    self.newline();
+                    self.add_code("return true;");
+                    if completion_node.guard_expr_t_opt.is_some() {
+                        self.outdent();
+                        self.newline();
+                        self.add_code("}");
+                    }
+                }
+                self.outdent();
+                self.newline();
+                self.add_code("}");
+                self.current_state_name_opt = None;
+            }
+        }
+        self.newline();
+        self.add_code("return false;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Snapshots the machine as JSON: the current state with its state
+    // arguments and variables, the state stack and the domain.
+
+    fn generate_serialization(&mut self, system_node: &SystemNode) {
+        let state_param = if self.generate_state_context {"stateContext"} else {"state"};
+        let domain_vars:Vec<(String,bool)> = system_node.get_domain_vars().iter()
+            .map(|var_rcref| (var_rcref.borrow().name.clone(), var_rcref.borrow().has_snapshot_default())).collect();
+        let snapshot_version = system_node.get_snapshot_version();
+        let snapshot_hash = system_node.get_snapshot_hash();
+        self.newline();
+        self.newline();
+        self.add_code("that._serialize__do = function() {");
+        self.indent();
+        self.newline();
+        self.add_code("let bag = {};");
+        self.newline();
+        self.add_code(&format!("bag.version = {};", snapshot_version));
+        self.newline();
+        self.add_code(&format!("bag.hash = \"{}\";", snapshot_hash));
+        self.newline();
+        if self.generate_state_context {
+            self.add_code("bag.state = _serializeState_(_stateContext_);");
+        } else {
+            self.add_code("bag.state = _serializeState_(_state_);");
+        }
+        if self.generate_state_stack {
+            self.newline();
+            self.add_code("bag.stateStack = _stateStack_.map(_serializeState_);");
+        }
+        self.newline();
+        self.add_code("bag.domain = {};");
+        for (var_name, _) in &domain_vars {
+            self.newline();
+            self.add_code(&format!("bag.domain[\"{}\"] = that.{};", var_name, var_name));
+        }
+        self.newline();
+        self.add_code("return JSON.stringify(bag);");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        self.add_code("that._deserialize__do = function(data) {");
+        self.indent();
+        self.newline();
+        self.add_code("const bag = JSON.parse(data);");
+        self.newline();
+        self.add_code(&format!("if (bag.version > {}) {{", snapshot_version));
+        self.indent();
+        self.newline();
+        self.add_code(&format!("throw new Error(\"Snapshot version \" + bag.version + \" is newer than version {} of {}\");", snapshot_version, system_node.name));
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.add_code(&format!("if (bag.version == {} && bag.hash != \"{}\") {{", snapshot_version, snapshot_hash));
+        self.indent();
+        self.newline();
+        self.add_code(&format!("throw new Error(\"Snapshot does not match version {} of {}\");", snapshot_version, system_node.name));
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        if self.generate_state_context {
+            self.add_code("_stateContext_ = _deserializeState_(bag.state);");
+            self.newline();
+            self.add_code("_state_ = _stateContext_.state;");
+        } else {
+            self.add_code("_state_ = _deserializeState_(bag.state);");
+        }
+        if self.generate_state_stack {
+            self.newline();
+            self.add_code("_stateStack_ = bag.stateStack.map(_deserializeState_);");
+        }
+        if self.generate_start_enter {
+            self.newline();
+            self.add_code("_started_ = true;");
+        }
+        for (var_name, has_snapshot_default) in &domain_vars {
+            self.newline();
+            if *has_snapshot_default {
+                self.add_code(&format!("if (\"{}\" in bag.domain) {{", var_name));
+                self.indent();
+                self.newline();
+                self.add_code(&format!("that.{} = bag.domain[\"{}\"];", var_name, var_name));
+                self.outdent();
+                self.newline();
+                self.add_code("}");
+            } else {
+                self.add_code(&format!("that.{} = bag.domain[\"{}\"];", var_name, var_name));
+            }
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        self.add_code(&format!("let _serializeState_ = function({}) {{", state_param));
+        self.indent();
+        for state_node_rcref in system_node.get_states() {
+            let state_node = state_node_rcref.borrow();
+            self.newline();
+            if self.generate_state_context {
+                self.add_code(&format!("if (stateContext.state == _s{}_) {{", state_node.name));
+                self.indent();
                 self.newline();
-                for line in self.serialize.iter() {
-                    self.code.push_str(&*format!("{}",line));
-                    self.code.push_str(&*format!("\n{}",self.dent()));
+                let state_args:Vec<String> = state_node.get_param_names().iter()
+                    .map(|name| format!("\"{}\":stateContext.getStateArg(\"{}\")", name, name)).collect();
+                let state_vars:Vec<String> = state_node.get_var_names().iter()
+                    .map(|name| format!("\"{}\":stateContext.getStateVar(\"{}\")", name, name)).collect();
+                self.add_code(&format!("return {{state:\"{}\", stateArgs:{{{}}}, stateVars:{{{}}}}};"
+                                       , state_node.name, state_args.join(","), state_vars.join(",")));
+            } else {
+                self.add_code(&format!("if (state == _s{}_) {{", state_node.name));
+                self.indent();
+                self.newline();
+                self.add_code(&format!("return {{state:\"{}\"}};", state_node.name));
+            }
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
+        self.newline();
+        self.add_code("throw new Error(\"Unknown state\");");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        self.add_code("let _deserializeState_ = function(data) {");
+        self.indent();
+        self.newline();
+        self.add_code("switch (data.state) {");
+        self.indent();
+        for state_node_rcref in system_node.get_states() {
+            let state_node = state_node_rcref.borrow();
+            for renamed_from in state_node.get_renamed_from() {
+                self.newline();
+                self.add_code(&format!("case \"{}\":", renamed_from));
+            }
+            self.newline();
+            self.add_code(&format!("case \"{}\": {{", state_node.name));
+            self.indent();
+            self.newline();
+            if self.generate_state_context {
+                self.add_code(&format!("let stateContext = StateContext(_s{}_);", state_node.name));
+                for param_name in state_node.get_param_names() {
+                    self.newline();
+                    self.add_code(&format!("stateContext.addStateArg(\"{}\",data.stateArgs[\"{}\"]);", param_name, param_name));
                 }
+                for var_name in state_node.get_var_names() {
+                    self.newline();
+                    self.add_code(&format!("stateContext.addStateVar(\"{}\",data.stateVars[\"{}\"]);", var_name, var_name));
+                }
+                self.newline();
+                self.add_code("return stateContext;");
+            } else {
+                self.add_code(&format!("return _s{}_;", state_node.name));
+            }
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.add_code("throw new Error(\"Unknown state \" + data.state);");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
 
-                for line in self.deserialize.iter() {
-                    self.code.push_str(&*format!("{}",line));
-                    self.code.push_str(&*format!("\n{}",self.dent()));
+    //* --------------------------------------------------------------------- *//
+
+    // Each region has its own active state. An event is dispatched to
+    // every region in turn with the region's state made current.
+
+    fn generate_regions(&mut self) {
+        self.newline();
+        self.newline();
+        self.add_code("let _dispatchRegions_ = function(e) {");
+        self.indent();
+        if self.generate_final_states {
+            self.newline();
+            self.add_code("let wasFinished = that.isFinished();");
+        }
+        self.newline();
+        self.add_code("for (let i = 0; i < _regions_.length; i++) {");
+        self.indent();
+        self.newline();
+        self.add_code("_state_ = _regions_[i];");
+        self.newline();
+        self.add_code("_state_(e);");
+        self.newline();
+        self.add_code("_regions_[i] = _state_;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        if self.generate_final_states {
+            self.newline();
+            self.add_code("if (!wasFinished && that.isFinished()) {");
+            self.indent();
+            self.newline();
+            self.add_code("that.onFinished();");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // History is recorded on the way out of a state. Every ancestor that
+    // is a history target remembers the child it was left through and
+    // the leaf state that was active.
+
+    fn generate_history(&mut self, system_node: &SystemNode) {
+        self.newline();
+        self.newline();
+        self.add_code("let _shallowHistory_ = {};");
+        self.newline();
+        self.add_code("let _deepHistory_ = {};");
+        self.newline();
+        self.newline();
+        self.add_code("let _recordHistory_ = function() {");
+        self.indent();
+        let mut is_first = true;
+        if let Some(machine_block_node) = &system_node.machine_block_node_opt {
+            for state_node_rcref in &machine_block_node.states {
+                let state_node = state_node_rcref.borrow();
+                if state_node.history_records.len() == 0 {
+                    continue;
                 }
+                let state_ref = self.format_target_state_name(&state_node.name);
+                self.newline();
+                if is_first {
+                    self.add_code(&format!("if (_state_ == {}) {{", state_ref));
+                } else {
+                    self.add_code(&format!("}} else if (_state_ == {}) {{", state_ref));
+                }
+                is_first = false;
+                self.indent();
+                for history_record in &state_node.history_records {
+                    let child_ref = self.format_target_state_name(&history_record.child_state_name);
+                    self.newline();
+                    self.add_code(&format!("_shallowHistory_[\"{}\"] = {};", history_record.parent_state_name, child_ref));
+                    self.newline();
+                    self.add_code(&format!("_deepHistory_[\"{}\"] = {};", history_record.parent_state_name, state_ref));
+                }
+                self.outdent();
             }
         }
+        if !is_first {
+            self.newline();
+            self.add_code("}");
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
     }
 
     //* --------------------------------------------------------------------- *//
 
-    fn generate_subclass(&mut self) {
-        for line in self.subclass_code.iter() {
-            self.code.push_str(&*format!("{}",line));
-            self.code.push_str(&*format!("\n{}",self.dent()));
+    // Deferred events are replayed in the order they arrived. Any the
+    // new state defers again go back on the queue.
+
+    fn generate_deferred_events(&mut self) {
+        self.newline();
+        self.newline();
+        self.add_code("let _deferredQueue_ = [];");
+        self.newline();
+        self.newline();
+        self.add_code("let _replayDeferred_ = function() {");
+        self.indent();
+        self.newline();
+        self.add_code("let deferred = _deferredQueue_;");
+        self.newline();
+        self.add_code("_deferredQueue_ = [];");
+        self.newline();
+        self.add_code("for (let e of deferred) {");
+        self.indent();
+        self.newline();
+        self.add_code("_state_(e);");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // A deferred event is put aside rather than handled and is replayed
+    // after the next transition.
+
+    fn generate_deferred_event_checks(&mut self, deferred_events:&Vec<String>) {
+        for msg in deferred_events {
+            self.newline();
+            self.add_code(&format!("if (e._message == \"{}\") {{", msg));
+            self.indent();
+            self.newline();
+            self.add_code("_deferredQueue_.push(e);");
+            self.newline();
+            self.add_code("return;");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
         }
     }
 
     //* --------------------------------------------------------------------- *//
 
+    fn generate_timer_start(&mut self, timeout_node:&TimeoutNode) {
+        let mut duration = String::new();
+        timeout_node.duration_expr_t.accept_to_string(self, &mut duration);
+        self.newline();
+        self.add_code(&format!("_startTimer_({});", duration));
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn generate_comment(&mut self,line:usize) -> bool {
 
         // can't use self.newline() or self.add_code() due to double borrow.
@@ -493,8 +1254,21 @@ impl JavaScriptVisitor {
             _ => panic!("TODO"),
         };
 
+        let target_state_args_opt = match &change_state_stmt_node.state_context_t {
+            StateContextType::StateRef { state_context_node}
+                => &state_context_node.state_ref_args_opt,
+            _ => &Option::None,
+        };
+
         self.newline();
-        self.add_code(&format!("_changeState_({});", self.format_target_state_name(target_state_name)));
+        if self.generate_state_context {
+            self.add_code(&format!("stateContext = StateContext({});", self.format_target_state_name(target_state_name)));
+            self.newline();
+            self.generate_state_args_and_vars(target_state_name, target_state_args_opt);
+            self.add_code(&format!("_changeState_({},stateContext);", self.format_target_state_name(target_state_name)));
+        } else {
+            self.add_code(&format!("_changeState_({});", self.format_target_state_name(target_state_name)));
+        }
 
     }
 
@@ -585,7 +1359,7 @@ impl JavaScriptVisitor {
         let enter_args_opt = match &transition_statement.target_state_context_t {
             StateContextType::StateRef { state_context_node}
                 => &state_context_node.enter_args_opt,
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..} | StateContextType::StateHistory {..}
                 => &None,
         };
 
@@ -628,15 +1402,43 @@ impl JavaScriptVisitor {
             }
         }
 
-        // -- State Arguments --
+        // -- State Arguments --
+
+        let target_state_args_opt = match &transition_statement.target_state_context_t {
+            StateContextType::StateRef { state_context_node}
+                => &state_context_node.state_ref_args_opt,
+            StateContextType::StateStackPop {..} | StateContextType::StateHistory {..}
+                => &Option::None,
+        };
+//
+        self.generate_state_args_and_vars(&target_state_name, target_state_args_opt);
+        let exit_args = if has_exit_args {
+            "exitArgs"
+        } else {
+            "null"
+        };
+        if self.generate_state_context {
+            if self.generate_exit_args {
+                self.add_code(&format!("_transition_({},{},stateContext);",self.format_target_state_name(target_state_name),exit_args));
+            } else {
+                self.add_code(&format!("_transition_({},stateContext);",self.format_target_state_name(target_state_name)));
+            }
+        } else {
+            if self.generate_exit_args {
+                self.add_code(&format!("_transition_({},{});",self.format_target_state_name(target_state_name),exit_args));
+            } else {
+                self.add_code(&format!("_transition_({});",self.format_target_state_name(target_state_name)));
+            }
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Adds the target state's arguments and initialized
+    // state variables to the new state context.
+
+    fn generate_state_args_and_vars(&mut self, target_state_name:&str, target_state_args_opt:&Option<ExprListNode>) {
 
-        let target_state_args_opt = match &transition_statement.target_state_context_t {
-            StateContextType::StateRef { state_context_node}
-                => &state_context_node.state_ref_args_opt,
-            StateContextType::StateStackPop {}
-                => &Option::None,
-        };
-//
         if let Some(state_args) = target_state_args_opt {
 //            let mut params_copy = Vec::new();
             if let Some(state_sym) = self.arcanium.get_state(&target_state_name) {
@@ -703,24 +1505,6 @@ impl JavaScriptVisitor {
 //                code = target_state_vars.clone();
             },
         }
-        let exit_args = if has_exit_args {
-            "exitArgs"
-        } else {
-            "null"
-        };
-        if self.generate_state_context {
-            if self.generate_exit_args {
-                self.add_code(&format!("_transition_({},{},stateContext);",self.format_target_state_name(target_state_name),exit_args));
-            } else {
-                self.add_code(&format!("_transition_({},stateContext);",self.format_target_state_name(target_state_name)));
-            }
-        } else {
-            if self.generate_exit_args {
-                self.add_code(&format!("_transition_({},{});",self.format_target_state_name(target_state_name),exit_args));
-            } else {
-                self.add_code(&format!("_transition_({});",self.format_target_state_name(target_state_name)));
-            }
-        }
     }
 
     //* --------------------------------------------------------------------- *//
@@ -794,12 +1578,29 @@ impl JavaScriptVisitor {
                 }
             }
         }
-        if self.generate_state_context {
-            self.add_code(&format!("let stateContext = _stateStack_pop();"));
+        if let StateContextType::StateStackPop {..} = &transition_statement.target_state_context_t {
+            let exit_args = match &transition_statement.exit_args_opt {
+                Some(exit_args) if exit_args.exprs_t.len() > 0 => "exitArgs",
+                _ => "null",
+            };
+            self.generate_state_stack_underflow_check(exit_args);
+        }
+        if let StateContextType::StateHistory {state_ref_node, is_deep} = &transition_statement.target_state_context_t {
+            let history_map = if *is_deep {"_deepHistory_"} else {"_shallowHistory_"};
+            self.add_code(&format!("let state = {}[\"{}\"] || {};", history_map, state_ref_node.name, self.format_target_state_name(&state_ref_node.name)));
+            if self.generate_state_context {
+                self.newline();
+                self.add_code("stateContext = StateContext(state);");
+            }
+        } else if self.generate_state_context {
+            self.add_code(&format!("stateContext = _stateStack_pop_();"));
         } else {
             self.add_code(&format!("let state = _stateStack_pop_();"));
         }
         self.newline();
+        if let StateContextType::StateStackPop { enter_args_opt:Some(enter_args) } = &transition_statement.target_state_context_t {
+            self.generate_state_stack_pop_enter_args(enter_args);
+        }
         if self.generate_exit_args {
             if self.generate_state_context {
                 self.add_code(&format!("_transition_(stateContext.state,exitArgs,stateContext);"));
@@ -814,6 +1615,143 @@ impl JavaScriptVisitor {
             }
         }
     }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Refuses to push past #![state_stack_max], tells the current state
+    // with a stateStackOverflow event and ends the handler so the
+    // transition after the push does not happen.
+
+    fn generate_state_stack_overflow_check(&mut self) {
+        let max = match self.state_stack_max_opt {
+            Some(max) => max,
+            None => return,
+        };
+        self.add_code(&format!("if (_stateStack_.length >= {}) {{", max));
+        self.indent();
+        self.newline();
+        let message = self.symbol_config.state_stack_overflow_msg_symbol.clone();
+        self.generate_state_stack_error_event(&message);
+        self.newline();
+        self.add_code("return;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Applies the #![state_stack_underflow] policy when $$[-] finds the
+    // state stack empty.
+
+    fn generate_state_stack_underflow_check(&mut self, exit_args:&str) {
+        let underflow = match &self.state_stack_underflow_opt {
+            Some(underflow) => underflow.clone(),
+            None => return,
+        };
+        self.add_code("if (_stateStack_.length == 0) {");
+        self.indent();
+        self.newline();
+        match underflow {
+            StateStackUnderflowPolicy::Error => {
+                let message = self.symbol_config.state_stack_underflow_msg_symbol.clone();
+                self.generate_state_stack_error_event(&message);
+                self.newline();
+            },
+            StateStackUnderflowPolicy::Stay => {},
+            StateStackUnderflowPolicy::State {state_name} => {
+                if self.generate_state_context {
+                    self.add_code(&format!("stateContext = StateContext({});", self.format_target_state_name(&state_name)));
+                    self.newline();
+                    self.generate_state_args_and_vars(&state_name, &None);
+                    if self.generate_exit_args {
+                        self.add_code(&format!("_transition_({},{},stateContext);", self.format_target_state_name(&state_name), exit_args));
+                    } else {
+                        self.add_code(&format!("_transition_({},stateContext);", self.format_target_state_name(&state_name)));
+                    }
+                } else if self.generate_exit_args {
+                    self.add_code(&format!("_transition_({},{});", self.format_target_state_name(&state_name), exit_args));
+                } else {
+                    self.add_code(&format!("_transition_({});", self.format_target_state_name(&state_name)));
+                }
+                self.newline();
+            },
+        }
+        self.add_code("return;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Sends a state stack error event to the current state.
+
+    fn generate_state_stack_error_event(&mut self, message:&str) {
+        if self.generate_event_queue {
+            self.add_code(&format!("_dispatch_(FrameEvent(\"{}\",null));", message));
+        } else {
+            self.add_code(&format!("_state_(FrameEvent(\"{}\",null));", message));
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Adds the enter arguments of a state stack pop to the popped state
+    // context. Every pushed state takes the same enter parameters so the
+    // first one names them.
+
+    fn generate_state_stack_pop_enter_args(&mut self, enter_args:&ExprListNode) {
+        let mut msg = match self.pushed_state_names.first() {
+            Some(pushed_state_name) => pushed_state_name.clone(),
+            None => return,
+        };
+        msg.push_str(":");
+        msg.push_str(&self.symbol_config.enter_msg_symbol);
+
+        if let Some(event_sym) = self.arcanium.get_event(&msg,&self.current_state_name_opt) {
+            if let Some(event_params) = &event_sym.borrow().params_opt {
+                for (p, expr_t) in event_params.iter().zip(enter_args.exprs_t.iter()) {
+                    let mut expr = String::new();
+                    expr_t.accept_to_string(self,&mut expr);
+                    self.add_code(&format!("stateContext.addEnterArg(\"{}\",{});", p.name, expr));
+                    self.newline();
+                }
+            }
+        }
+    }
+}
+
+//* --------------------------------------------------------------------- *//
+
+impl OperatorPrecedence for JavaScriptVisitor {
+
+    //* --------------------------------------------------------------------- *//
+
+    // Binding strength of each operator in JavaScript, higher binds tighter.
+    // Xor is lowered to '!=' on bools so it sits with the equality operators.
+
+    fn operator_precedence(&self, operator_type:&OperatorType) -> usize {
+        match operator_type {
+            OperatorType::LogicalOr => 1,
+            OperatorType::LogicalAnd => 2,
+            OperatorType::EqualEqual
+            | OperatorType::NotEqual
+            | OperatorType::LogicalXor => 3,
+            OperatorType::Greater
+            | OperatorType::GreaterEqual
+            | OperatorType::Less
+            | OperatorType::LessEqual => 4,
+            OperatorType::Plus
+            | OperatorType::Minus => 5,
+            OperatorType::Multiply
+            | OperatorType::Divide => 6,
+            OperatorType::Not
+            | OperatorType::Negated => 7,
+        }
+    }
 }
 
 //* --------------------------------------------------------------------- *//
@@ -829,7 +1767,27 @@ impl AstVisitor for JavaScriptVisitor {
         self.add_code("// get include files at https://github.com/frame-lang/frame-ancillary-files");
         self.newline();
         self.newline();
-        self.add_code(&format!("let {} = function () {{", system_node.name));
+        self.generate_timers = system_node.has_timeouts();
+        self.generate_event_queue = system_node.is_run_to_completion();
+        self.generate_deferred_events = system_node.has_deferred_events();
+        self.generate_history = system_node.has_history();
+        self.generate_regions = system_node.has_regions();
+        self.generate_final_states = system_node.get_final_state_names().len() > 0;
+        self.generate_completions = system_node.has_completions();
+        self.generate_abstract_actions = system_node.has_external_actions();
+        self.pushed_state_names = system_node.get_pushed_state_names();
+        self.domain_param_names = system_node.get_domain_param_names();
+        self.generate_start_enter = system_node.start_state_enter_params_opt.is_some();
+        self.state_stack_max_opt = system_node.get_state_stack_max();
+        self.state_stack_underflow_opt = system_node.get_state_stack_underflow();
+        let system_params:Vec<String> = system_node.get_system_params().iter().map(|param| param.param_name.clone()).collect();
+        if self.generate_timers {
+            let mut constructor_params = system_params.clone();
+            constructor_params.push(String::from("scheduler"));
+            self.add_code(&format!("let {} = function ({}) {{", system_node.name, constructor_params.join(", ")));
+        } else {
+            self.add_code(&format!("let {} = function ({}) {{", system_node.name, system_params.join(", ")));
+        }
         self.indent();
         self.newline();
         self.newline();
@@ -850,24 +1808,22 @@ impl AstVisitor for JavaScriptVisitor {
         }
 
 
-        self.serialize.push("".to_string());
-        self.serialize.push("that._serialize__do = function() {".to_string());
-
-        self.deserialize.push("".to_string());
-
-        // @TODO: _do needs to be configurable.
-        self.deserialize.push("that._deserialize__do = function(data) {".to_string());
-
 
-        self.subclass_code.push("".to_string());
-        self.subclass_code.push("/********************".to_string());
-        self.subclass_code.push(format!("let {}Controller = function () {{",system_node.name));
-        self.subclass_code.push(format!("\tlet that = {}.call(this);", system_node.name));
+        self.subclass_code.push(format!("let {}Controller = function ({}) {{",system_node.name, system_params.join(", ")));
+        if system_params.is_empty() {
+            self.subclass_code.push(format!("\tlet that = {}.call(this);", system_node.name));
+        } else {
+            self.subclass_code.push(format!("\tlet that = {}.call(this, {});", system_node.name, system_params.join(", ")));
+        }
 
         if let Some(interface_block_node) = &system_node.interface_block_node_opt {
             interface_block_node.accept(self);
         }
 
+        if let Some(operations_block_node) = &system_node.operations_block_node_opt {
+            operations_block_node.accept(self);
+        }
+
         if let Some(machine_block_node) = &system_node.machine_block_node_opt {
             machine_block_node.accept(self);
         }
@@ -882,15 +1838,6 @@ impl AstVisitor for JavaScriptVisitor {
 
         self.subclass_code.push(format!("\treturn that;"));
         self.subclass_code.push(format!("}};"));
-        self.subclass_code.push("********************/".to_string());
-
-        self.serialize.push("".to_string());
-        self.serialize.push("\treturn JSON.stringify(bag);".to_string());
-        self.serialize.push("}".to_string());
-        self.serialize.push("".to_string());
-
-        self.deserialize.push("".to_string());
-        self.deserialize.push("}".to_string());
 
         if self.has_states {
             self.generate_machinery(system_node);
@@ -910,8 +1857,6 @@ impl AstVisitor for JavaScriptVisitor {
         self.add_code("};");
         self.newline();
 
-        self.generate_subclass();
-
         AstVisitorReturnType::SystemNode {}
     }
 
@@ -983,15 +1928,41 @@ impl AstVisitor for JavaScriptVisitor {
         self.add_code(&format!("{}) {{", call_params));
         self.indent();
         self.newline();
+        if *method_name_or_alias == self.symbol_config.restore_msg_symbol {
+            if let Some(params) = &interface_method_node.params {
+                self.add_code(&format!("that._deserialize__do({});", params[0].param_name));
+                self.newline();
+            }
+        }
+        if self.generate_start_enter {
+            self.add_code("if (!_started_) _start_();");
+            self.newline();
+        }
         if has_params {
             self.add_code(&format!("let e = FrameEvent(\"{}\",{{{}}});", method_name_or_alias, send_params));
         } else {
             self.add_code(&format!("let e = FrameEvent(\"{}\",null);", method_name_or_alias));
         }
+        if let Some(default_return_expr_t) = &interface_method_node.default_return_expr_opt {
+            let mut default_return = String::new();
+            default_return_expr_t.accept_to_string(self, &mut default_return);
+            self.newline();
+            self.add_code(&format!("e._return = {};", default_return));
+        }
         self.newline();
-        self.add_code(&format!("_state_(e);"));
+        if self.generate_event_queue {
+            self.add_code(&format!("_dispatch_(e);"));
+        } else if self.generate_regions {
+            self.add_code(&format!("_dispatchRegions_(e);"));
+        } else {
+            self.add_code(&format!("_state_(e);"));
+        }
 
         match &interface_method_node.return_type_opt {
+            Some(_return_type) if *method_name_or_alias == self.symbol_config.save_msg_symbol => {
+                self.newline();
+                self.add_code(&format!("return that._serialize__do();"));
+            },
             Some(_return_type) => {
                 self.newline();
                 self.add_code(&format!("return e._return;"));
@@ -1012,28 +1983,10 @@ impl AstVisitor for JavaScriptVisitor {
         self.newline();
         self.add_code("//===================== Machine Block ===================//");
 
-        self.serialize.push("".to_string());
-        self.serialize.push("\tlet stateName = null".to_string());
-
-        self.deserialize.push("".to_string());
-        self.deserialize.push("\tconst bag = JSON.parse(data);".to_string());
-        self.deserialize.push("".to_string());
-        self.deserialize.push("\tswitch (bag.state) {".to_string());
-
         for state_node_rcref in &machine_block_node.states {
             state_node_rcref.borrow().accept(self);
         }
 
-        self.serialize.push("".to_string());
-        self.serialize.push("\tvar bag = {".to_string());
-        self.serialize.push("\t\tstate : stateName,".to_string());
-        self.serialize.push("\t\tdomain : {}".to_string());
-        self.serialize.push("\t};".to_string());
-        self.serialize.push("".to_string());
-
-        self.deserialize.push("\t}".to_string());
-        self.deserialize.push("".to_string());
-
 
         AstVisitorReturnType::MachineBlockNode {}
     }
@@ -1097,16 +2050,26 @@ impl AstVisitor for JavaScriptVisitor {
         self.add_code(&format!("let _s{}_ = function (e) {{", state_node.name));
         self.indent();
 
-        self.serialize.push(format!("\tif (_state_ == _s{}_) stateName = \"{}\"",state_node.name,state_node.name));
-
-        self.deserialize.push(format!("\t\tcase \"{}\": _state_ = _s{}_; break;",state_node.name,state_node.name));
-
         // @TODO: remove if not needed
         // let state_symbol = match self.arcanium.get_state(&state_node.name) {
         //     Some(state_symbol) => state_symbol,
         //     None => panic!("TODO"),
         // };
 
+        if let Some(deferred_events) = &state_node.deferred_events_opt {
+            self.generate_deferred_event_checks(deferred_events);
+        }
+
+        if let Some(timeout_node) = &state_node.timeout_opt {
+            self.newline();
+            self.add_code(&format!("if (e._message == \"{}\") {{", self.symbol_config.enter_msg_symbol));
+            self.indent();
+            self.generate_timer_start(timeout_node);
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
+
         if let Some(calls) = &state_node.calls_opt {
             for call in calls {
                 self.newline();
@@ -1117,6 +2080,10 @@ impl AstVisitor for JavaScriptVisitor {
 
         self.first_event_handler = true; // context for formatting
 
+        if let Some(timeout_node) = &state_node.timeout_opt {
+            timeout_node.accept(self);
+        }
+
         if state_node.evt_handlers_rcref.len() > 0 {
             for evt_handler_node in &state_node.evt_handlers_rcref {
                 evt_handler_node.as_ref().borrow().accept(self);
@@ -1374,7 +2341,10 @@ impl AstVisitor for JavaScriptVisitor {
         match &transition_statement.target_state_context_t {
             StateContextType::StateRef {..}
                 => self.generate_state_ref_transition(transition_statement),
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..}
+                => self.generate_state_stack_pop_transition(transition_statement),
+            // a history target is restored like a popped state
+            StateContextType::StateHistory {..}
                 => self.generate_state_stack_pop_transition(transition_statement),
         };
 
@@ -1397,7 +2367,7 @@ impl AstVisitor for JavaScriptVisitor {
         match &change_state_stmt_node.state_context_t {
             StateContextType::StateRef {..}
                 => self.generate_state_ref_change_state(change_state_stmt_node),
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..} | StateContextType::StateHistory {..}
                 => panic!("TODO - not implemented"),
         };
 
@@ -1427,6 +2397,33 @@ impl AstVisitor for JavaScriptVisitor {
 
     //* --------------------------------------------------------------------- *//
 
+    fn visit_timeout_node(&mut self, timeout_node: &TimeoutNode) -> AstVisitorReturnType {
+        self.newline();
+        self.generate_comment(timeout_node.line);
+        if self.first_event_handler {
+            self.add_code(&format!("if (e._message == \"{}\") {{", self.symbol_config.timeout_msg_symbol));
+        } else {
+            self.add_code(&format!("else if (e._message == \"{}\") {{", self.symbol_config.timeout_msg_symbol));
+        }
+        self.indent();
+        if self.generate_state_context {
+            self.newline();
+            self.add_code(&format!("let stateContext = null;"));
+        }
+        timeout_node.transition_statement_node.accept(self);
+        self.newline();
+        self.add_code("return;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+
+        self.first_event_handler = false;
+
+        AstVisitorReturnType::TimeoutNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_test_statement_node(&mut self, test_stmt_node: &TestStatementNode) -> AstVisitorReturnType {
 
         match &test_stmt_node.test_t {
@@ -1933,6 +2930,69 @@ impl AstVisitor for JavaScriptVisitor {
 
     //* --------------------------------------------------------------------- *//
 
+    fn visit_list_literal_node(&mut self, list_literal_node: &ListLiteralNode) -> AstVisitorReturnType {
+
+        let mut output = String::new();
+        self.visit_list_literal_node_to_string(list_literal_node, &mut output);
+        self.add_code(&output);
+
+        AstVisitorReturnType::ListLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_list_literal_node_to_string(&mut self, list_literal_node: &ListLiteralNode, output:&mut String) -> AstVisitorReturnType {
+
+        output.push_str("[");
+        let mut separator = "";
+        for expr_t in &list_literal_node.exprs_t {
+            output.push_str(separator);
+            expr_t.accept_to_string(self, output);
+            separator = ",";
+        }
+        output.push_str("]");
+
+        AstVisitorReturnType::ListLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_map_literal_node(&mut self, map_literal_node: &MapLiteralNode) -> AstVisitorReturnType {
+
+        let mut output = String::new();
+        self.visit_map_literal_node_to_string(map_literal_node, &mut output);
+        self.add_code(&output);
+
+        AstVisitorReturnType::MapLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_map_literal_node_to_string(&mut self, map_literal_node: &MapLiteralNode, output:&mut String) -> AstVisitorReturnType {
+
+        output.push_str("{");
+        let mut separator = "";
+        for (key_expr_t, value_expr_t) in &map_literal_node.entries {
+            output.push_str(separator);
+            // object literal keys that are not literals must be computed
+            if let ExprType::LiteralExprT {..} = key_expr_t {
+                key_expr_t.accept_to_string(self, output);
+            } else {
+                output.push_str("[");
+                key_expr_t.accept_to_string(self, output);
+                output.push_str("]");
+            }
+            output.push_str(": ");
+            value_expr_t.accept_to_string(self, output);
+            separator = ",";
+        }
+        output.push_str("}");
+
+        AstVisitorReturnType::MapLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_expression_list_node(&mut self, expr_list: &ExprListNode) -> AstVisitorReturnType {
 
         let mut separator = "";
@@ -2069,6 +3129,7 @@ impl AstVisitor for JavaScriptVisitor {
         match state_stack_op_statement_node.state_stack_operation_node.operation_t {
             StateStackOperationType::Push => {
                 self.newline();
+                self.generate_state_stack_overflow_check();
                 if self.generate_state_context {
                     self.add_code(&format!("_stateStack_push_(_stateContext_);"));
                 } else {
@@ -2087,6 +3148,26 @@ impl AstVisitor for JavaScriptVisitor {
     }
     //* --------------------------------------------------------------------- *//
 
+    fn visit_raise_statement_node(&mut self, raise_statement_node:&RaiseStatementNode) -> AstVisitorReturnType {
+        self.newline();
+        self.add_code(&format!("that.{}", raise_statement_node.interface_method_name));
+        raise_statement_node.call_expr_list_node.accept(self);
+        self.add_code(";");
+
+        AstVisitorReturnType::RaiseStatementNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_forward_statement_node(&mut self, forward_statement_node:&ForwardStatementNode) -> AstVisitorReturnType {
+        self.newline();
+        self.add_code(&format!("_s{}_(e);", forward_statement_node.target_state_ref.name));
+
+        AstVisitorReturnType::ForwardStatementNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_state_context_node(&mut self, _state_context_node:&StateContextNode) -> AstVisitorReturnType {
 
         // TODO
@@ -2133,6 +3214,22 @@ impl AstVisitor for JavaScriptVisitor {
 
         self.newline();
         let action_name = self.format_action_name(&action_decl_node.name);
+
+        if action_decl_node.has_frame_body() {
+            let param_names:Vec<String> = match &action_decl_node.params {
+                Some(params) => params.iter().map(|param| param.param_name.clone()).collect(),
+                None => Vec::new(),
+            };
+            self.add_code(&format!("{} = function ({}) {{", action_name, param_names.join(",")));
+            self.indent();
+            self.generate_frame_body(&action_decl_node.statements, action_decl_node.get_return_expr_opt());
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+            self.newline();
+            return AstVisitorReturnType::ActionDeclNode {};
+        }
+
         // self.add_code(&format!("let {} = function (",action_name));
         self.add_code(&format!("{} = function (",action_name));
         subclass_code.push_str(&format!("\t{} = function (",action_name));
@@ -2164,6 +3261,46 @@ impl AstVisitor for JavaScriptVisitor {
 
     //* --------------------------------------------------------------------- *//
 
+    fn visit_operations_block_node(&mut self, operations_block_node: &OperationsBlockNode) -> AstVisitorReturnType {
+        self.newline();
+        self.add_code("//===================== Operations Block ===================//");
+        self.newline();
+
+        for operation_node_rcref in &operations_block_node.operations {
+            operation_node_rcref.borrow().accept(self);
+        }
+
+        AstVisitorReturnType::OperationsBlockNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_operation_node(&mut self, operation_node: &OperationNode) -> AstVisitorReturnType {
+        let param_names:Vec<String> = match &operation_node.params {
+            Some(params) => params.iter().map(|param| param.param_name.clone()).collect(),
+            None => Vec::new(),
+        };
+
+        self.newline();
+        self.add_code(&format!("that.{} = function ({}) {{", operation_node.name, param_names.join(",")));
+        self.indent();
+        match &operation_node.code_opt {
+            Some(code) => {
+                self.newline();
+                self.add_code(code);
+            },
+            None => self.generate_frame_body(&operation_node.statements, operation_node.get_return_expr_opt()),
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+
+        AstVisitorReturnType::OperationNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_domain_variable_decl_node(&mut self, variable_decl_node: &VariableDeclNode) -> AstVisitorReturnType {
 
         self.visit_variable_decl_node(variable_decl_node);
@@ -2182,6 +3319,9 @@ impl AstVisitor for JavaScriptVisitor {
         var_init_expr.accept_to_string(self, &mut code);
         match &variable_decl_node.identifier_decl_scope {
             IdentifierDeclScope::DomainBlock => {
+                if self.domain_param_names.contains(var_name) {
+                    code = var_name.clone();
+                }
                 self.add_code( &format!("that.{} = {};",var_name, code));
             },
             IdentifierDeclScope::EventHandlerVar => {
@@ -2190,9 +3330,6 @@ impl AstVisitor for JavaScriptVisitor {
             _ => panic!("Error - unexpected scope for variable declaration"),
         }
 
-        self.serialize.push(format!("\tbag.domain[\"{}\"] = {};",var_name,var_name));
-        self.deserialize.push(format!("\t{} = bag.domain[\"{}\"];",var_name,var_name));
-
         AstVisitorReturnType::VariableDeclNode {}
     }
 
@@ -2269,10 +3406,9 @@ impl AstVisitor for JavaScriptVisitor {
 
     fn visit_unary_expr_node(&mut self, unary_expr_node: &UnaryExprNode) -> AstVisitorReturnType {
 
-        // TODO
-        //       self.generate_comment(assignment_expr_node.line);
-        unary_expr_node.operator.accept(self);
-        unary_expr_node.right_rcref.borrow().accept(self);
+        let mut output = String::new();
+        self.visit_unary_expr_node_to_string(unary_expr_node, &mut output);
+        self.add_code(&output);
 
         AstVisitorReturnType::UnaryExprNode {}
     }
@@ -2284,7 +3420,7 @@ impl AstVisitor for JavaScriptVisitor {
         // TODO
         //       self.generate_comment(assignment_expr_node.line);
         unary_expr_node.operator.accept_to_string(self, output);
-        unary_expr_node.right_rcref.borrow().accept_to_string(self,output);
+        self.visit_operand_to_string(&unary_expr_node.operator, &unary_expr_node.right_rcref.borrow(), false, output);
 
         AstVisitorReturnType::UnaryExprNode {}
     }
@@ -2293,23 +3429,9 @@ impl AstVisitor for JavaScriptVisitor {
 
     fn visit_binary_expr_node(&mut self, binary_expr_node: &BinaryExprNode) -> AstVisitorReturnType {
 
-        // TODO
-        //       self.generate_comment(assignment_expr_node.line);
-        if binary_expr_node.operator == OperatorType::LogicalXor {
-            self.add_code("((");
-            binary_expr_node.left_rcref.borrow().accept(self);
-            self.add_code(") && !(");
-            binary_expr_node.right_rcref.borrow().accept(self);
-            self.add_code(")) || (!(");
-            binary_expr_node.left_rcref.borrow().accept(self);
-            self.add_code(") && (");
-            binary_expr_node.right_rcref.borrow().accept(self);
-            self.add_code("))");
-        } else {
-            binary_expr_node.left_rcref.borrow().accept(self);
-            binary_expr_node.operator.accept(self);
-            binary_expr_node.right_rcref.borrow().accept(self);
-        }
+        let mut output = String::new();
+        self.visit_binary_expr_node_to_string(binary_expr_node, &mut output);
+        self.add_code(&output);
 
         AstVisitorReturnType::BinaryExprNode {}
     }
@@ -2318,27 +3440,41 @@ impl AstVisitor for JavaScriptVisitor {
 
     fn visit_binary_expr_node_to_string(&mut self, binary_expr_node: &BinaryExprNode, output:&mut String) -> AstVisitorReturnType {
 
-        if binary_expr_node.operator == OperatorType::LogicalXor {
-            output.push_str("((");
-            binary_expr_node.left_rcref.borrow().accept_to_string(self,output);
-            output.push_str(") && !(");
-            binary_expr_node.right_rcref.borrow().accept_to_string(self,output);
-            output.push_str(")) || (!(");
-            binary_expr_node.left_rcref.borrow().accept_to_string(self,output);
-            output.push_str(") && (");
-            binary_expr_node.right_rcref.borrow().accept_to_string(self,output);
-            output.push_str("))");
-
-        } else {
-            binary_expr_node.left_rcref.borrow().accept_to_string(self,output);
-            binary_expr_node.operator.accept_to_string(self, output);
-            binary_expr_node.right_rcref.borrow().accept_to_string(self,output);
-        }
+        let operator_type = &binary_expr_node.operator;
+        self.visit_operand_to_string(operator_type, &binary_expr_node.left_rcref.borrow(), true, output);
+        operator_type.accept_to_string(self, output);
+        self.visit_operand_to_string(operator_type, &binary_expr_node.right_rcref.borrow(), false, output);
 
         AstVisitorReturnType::BinaryExprNode {}
     }
 
 
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_conditional_expr_node(&mut self, conditional_expr_node: &ConditionalExprNode) -> AstVisitorReturnType {
+
+        conditional_expr_node.condition_rcref.borrow().accept(self);
+        self.add_code(" ? ");
+        conditional_expr_node.true_expr_rcref.borrow().accept(self);
+        self.add_code(" : ");
+        conditional_expr_node.false_expr_rcref.borrow().accept(self);
+
+        AstVisitorReturnType::ConditionalExprNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_conditional_expr_node_to_string(&mut self, conditional_expr_node: &ConditionalExprNode, output:&mut String) -> AstVisitorReturnType {
+
+        conditional_expr_node.condition_rcref.borrow().accept_to_string(self, output);
+        output.push_str(" ? ");
+        conditional_expr_node.true_expr_rcref.borrow().accept_to_string(self, output);
+        output.push_str(" : ");
+        conditional_expr_node.false_expr_rcref.borrow().accept_to_string(self, output);
+
+        AstVisitorReturnType::ConditionalExprNode {}
+    }
+
     //* --------------------------------------------------------------------- *//
 
     fn visit_operator_type(&mut self, operator_type: &OperatorType) -> AstVisitorReturnType {
@@ -2358,7 +3494,7 @@ impl AstVisitor for JavaScriptVisitor {
             OperatorType::NotEqual => self.add_code(" != "),
             OperatorType::LogicalAnd => self.add_code(" && "),
             OperatorType::LogicalOr => self.add_code(" || "),
-            OperatorType::LogicalXor => self.add_code(""),
+            OperatorType::LogicalXor => self.add_code(" != "),
         }
 
         AstVisitorReturnType::BinaryExprNode {}
@@ -2383,7 +3519,7 @@ impl AstVisitor for JavaScriptVisitor {
             OperatorType::NotEqual => output.push_str(" != "),
             OperatorType::LogicalAnd => output.push_str(" && "),
             OperatorType::LogicalOr => output.push_str(" || "),
-            OperatorType::LogicalXor => output.push_str(""),
+            OperatorType::LogicalXor => output.push_str(" != "),
         }
 
         AstVisitorReturnType::BinaryExprNode {}
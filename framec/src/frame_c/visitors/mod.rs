@@ -17,6 +17,8 @@ pub enum AstVisitorReturnType {
     InterfaceMethodNode,
     MachineBlockNode,
     ActionBlockNode,
+    OperationsBlockNode,
+    OperationNode,
     DomainBlockNode,
     StateNode,
     EventHandlerNode,
@@ -31,6 +33,7 @@ pub enum AstVisitorReturnType {
     StateRefNode,
     ParameterNode,
     DispatchNode,
+    TimeoutNode,
     ParentheticalExpressionNode,
     IdentifierNode,
     BoolTestNode,
@@ -46,6 +49,8 @@ pub enum AstVisitorReturnType {
     NumberMatchTestPatternNode,
     StateStackOperationNode,
     StateStackOperationStatementNode,
+    RaiseStatementNode,
+    ForwardStatementNode,
     StateContextNode,
     ChangeStateStmtNode,
     FrameEventExprType,
@@ -54,6 +59,9 @@ pub enum AstVisitorReturnType {
     VariableDeclNode,
     UnaryExprNode,
     BinaryExprNode,
+    ConditionalExprNode,
+    ListLiteralNode,
+    MapLiteralNode,
     OperatorType,
     CallExprListNode,
 }
@@ -86,6 +94,7 @@ pub trait AstVisitor {
 //    fn visit_argument_node(&mut self, argument:&Argument) -> AstVisitorReturnType;
     fn visit_parameter_node(&mut self, parameter_node:&ParameterNode) -> AstVisitorReturnType;
     fn visit_dispatch_node(&mut self, dispatch_node:&DispatchNode) -> AstVisitorReturnType;
+    fn visit_timeout_node(&mut self, timeout_node:&TimeoutNode) -> AstVisitorReturnType;
     fn visit_test_statement_node(&mut self, test_statement_node:&TestStatementNode) -> AstVisitorReturnType;
     fn visit_bool_test_node(&mut self, bool_test_body_node:&BoolTestNode) -> AstVisitorReturnType;
     fn visit_bool_test_conditional_branch_node(&mut self, bool_test_true_branch_node:&BoolTestConditionalBranchNode) -> AstVisitorReturnType;
@@ -101,6 +110,10 @@ pub trait AstVisitor {
 //    fn visit_expression_node(&mut self, expression_node:&ExpressionNode) -> AstVisitorReturnType;
     fn visit_expression_list_node(&mut self, expr_list:&ExprListNode) -> AstVisitorReturnType;
     fn visit_expression_list_node_to_string(&mut self, expr_list:&ExprListNode, output:&mut String) -> AstVisitorReturnType;
+    fn visit_list_literal_node(&mut self, list_literal_node:&ListLiteralNode) -> AstVisitorReturnType;
+    fn visit_list_literal_node_to_string(&mut self, list_literal_node:&ListLiteralNode, output:&mut String) -> AstVisitorReturnType;
+    fn visit_map_literal_node(&mut self, map_literal_node:&MapLiteralNode) -> AstVisitorReturnType;
+    fn visit_map_literal_node_to_string(&mut self, map_literal_node:&MapLiteralNode, output:&mut String) -> AstVisitorReturnType;
     fn visit_literal_expression_node(&mut self, literal_expression_node:&LiteralExprNode) -> AstVisitorReturnType;
     fn visit_literal_expression_node_to_string(&mut self, literal_expression_node:&LiteralExprNode, output:&mut String) -> AstVisitorReturnType;
     fn visit_identifier_node(&mut self, identifier_node:&IdentifierNode) -> AstVisitorReturnType;
@@ -108,6 +121,8 @@ pub trait AstVisitor {
     fn visit_state_stack_operation_node(&mut self, state_stack_op_node:&StateStackOperationNode) -> AstVisitorReturnType;
     fn visit_state_stack_operation_node_to_string(&mut self, state_stack_op_node:&StateStackOperationNode, output:&mut String) -> AstVisitorReturnType;
     fn visit_state_stack_operation_statement_node(&mut self, state_stack_op_statement_node:&StateStackOperationStatementNode) -> AstVisitorReturnType;
+    fn visit_raise_statement_node(&mut self, raise_statement_node:&RaiseStatementNode) -> AstVisitorReturnType;
+    fn visit_forward_statement_node(&mut self, forward_statement_node:&ForwardStatementNode) -> AstVisitorReturnType;
     fn visit_state_context_node(&mut self, state_context_node:&StateContextNode) -> AstVisitorReturnType;
     fn visit_change_state_statement_node(&mut self, change_state_stmt_node:&ChangeStateStatementNode) -> AstVisitorReturnType;
     fn visit_frame_event_part(&mut self, frame_event_part:&FrameEventPart) -> AstVisitorReturnType;
@@ -117,6 +132,8 @@ pub trait AstVisitor {
     fn visit_actions_node_rust_impl(&mut self, actions_block_node: &ActionsBlockNode) -> AstVisitorReturnType;
     fn visit_action_decl_node(&mut self, action_decl_node: &ActionNode) -> AstVisitorReturnType;
     fn visit_action_impl_node(&mut self, action_decl_node: &ActionNode) -> AstVisitorReturnType;
+    fn visit_operations_block_node(&mut self, operations_block_node: &OperationsBlockNode) -> AstVisitorReturnType;
+    fn visit_operation_node(&mut self, operation_node: &OperationNode) -> AstVisitorReturnType;
 
     fn visit_action_call_expression_node(&mut self, action_call_expr_node: &ActionCallExprNode) -> AstVisitorReturnType;
     fn visit_action_call_expression_node_to_string(&mut self, action_call_expr_node: &ActionCallExprNode, output:&mut String) -> AstVisitorReturnType;
@@ -134,7 +151,56 @@ pub trait AstVisitor {
     fn visit_unary_expr_node_to_string(&mut self, unary_expr_node: &UnaryExprNode, output:&mut String) -> AstVisitorReturnType;
     fn visit_binary_expr_node(&mut self, binary_expr_node: &BinaryExprNode) -> AstVisitorReturnType;
     fn visit_binary_expr_node_to_string(&mut self, binary_expr_node: &BinaryExprNode, output:&mut String) -> AstVisitorReturnType;
+    fn visit_conditional_expr_node(&mut self, conditional_expr_node: &ConditionalExprNode) -> AstVisitorReturnType;
+    fn visit_conditional_expr_node_to_string(&mut self, conditional_expr_node: &ConditionalExprNode, output:&mut String) -> AstVisitorReturnType;
     fn visit_operator_type(&mut self, operator_type: &OperatorType) -> AstVisitorReturnType;
     fn visit_operator_type_to_string(&mut self, operator_type: &OperatorType, output:&mut String) -> AstVisitorReturnType;
 
 }
+
+//* --------------------------------------------------------------------- *//
+
+// Operator binding strengths of a target language. Operands are only
+// parenthesized when they bind more loosely than their operator.
+
+pub trait OperatorPrecedence {
+
+    // Binding strength of each operator, higher binds tighter.
+
+    fn operator_precedence(&self, operator_type:&OperatorType) -> usize;
+
+    // Operators that can't be chained without parentheses.
+
+    fn is_non_associative(&self, _operator_type:&OperatorType) -> bool {
+        false
+    }
+
+    // At equal strength only the right operand is wrapped as all binary
+    // operators associate to the left.
+
+    fn operand_needs_parens(&self, operator_type:&OperatorType, operand_t:&ExprType, is_left:bool) -> bool {
+        match operand_t.get_operator_opt() {
+            Some(operand_operator_type) => {
+                let precedence = self.operator_precedence(operator_type);
+                let operand_precedence = self.operator_precedence(&operand_operator_type);
+                if operand_precedence == precedence {
+                    !is_left || self.is_non_associative(operator_type)
+                } else {
+                    operand_precedence < precedence
+                }
+            },
+            None => false,
+        }
+    }
+
+    fn visit_operand_to_string(&mut self, operator_type:&OperatorType, operand_t:&ExprType, is_left:bool, output:&mut String)
+        where Self: AstVisitor + Sized {
+        if self.operand_needs_parens(operator_type, operand_t, is_left) {
+            output.push_str("(");
+            operand_t.accept_to_string(self, output);
+            output.push_str(")");
+        } else {
+            operand_t.accept_to_string(self, output);
+        }
+    }
+}
@@ -20,6 +20,7 @@ pub struct PlantUmlVisitor {
     first_event_handler:bool,
     system_name:String,
     first_state_name:String,
+    final_state_names:Vec<String>,
     generate_exit_args:bool,
     // generate_state_context:bool,
     // generate_state_stack:bool,
@@ -64,6 +65,7 @@ impl PlantUmlVisitor {
             first_event_handler:true,
             system_name:String::new(),
             first_state_name:String::new(),
+            final_state_names:Vec::new(),
             generate_exit_args,
             // generate_state_context,
             // generate_state_stack,
@@ -129,12 +131,17 @@ impl PlantUmlVisitor {
     fn generate_states(&self, node_name:&String, is_system_node:bool, indent:usize, output:&mut String) {
  //       let state_name = &node.name;
         let mut actual_indent = indent;
+        let node = self.system_hierarchy.get_node(node_name).unwrap();
+        // final states are drawn as end states
+        if !is_system_node && node.children.is_empty() && self.final_state_names.contains(node_name) {
+            output.push_str(&format!("{}state {} <<end>>\n",self.specifiy_dent(indent),node_name));
+            return;
+        }
         if !is_system_node{
   //          output.push_str(&*format!("\n{}",indent));
             actual_indent += 1;
             output.push_str(&format!("{}state {} {{\n",self.specifiy_dent(indent),node_name));
         }
-        let node = self.system_hierarchy.get_node(node_name).unwrap();
         for child_node_name in &node.children {
             let child_node = self.system_hierarchy.get_node(&child_node_name).unwrap();
             self.generate_states(&child_node.name, false,actual_indent, output);
@@ -144,6 +151,28 @@ impl PlantUmlVisitor {
         }
     }
 
+    //* --------------------------------------------------------------------- *//
+
+    // Regions are drawn as the concurrent regions of a composite state
+    // named after the system, each with its own start state.
+
+    fn generate_regions(&self, machine_block_node:&MachineBlockNode, output:&mut String) {
+        let system_node = self.system_hierarchy.get_node(&self.system_name).unwrap();
+        output.push_str(&format!("state {} {{\n", self.system_name));
+        for (i, region_node) in machine_block_node.regions.iter().enumerate() {
+            if i > 0 {
+                output.push_str(&format!("{}--\n", self.specifiy_dent(1)));
+            }
+            output.push_str(&format!("{}[*] --> {}\n", self.specifiy_dent(1), region_node.state_names[0]));
+            for state_name in &region_node.state_names {
+                if system_node.children.contains(state_name) {
+                    self.generate_states(state_name, false, 1, output);
+                }
+            }
+        }
+        output.push_str("}\n");
+    }
+
     // fn debug_print_states(&mut self, node_rc_ref:&Rc<RefCell<Node>>) {
     //     let state_name = node_rc_ref.borrow().name.clone();
     //     //println!(&format!("state {} \n", state_name));
@@ -317,6 +346,12 @@ impl PlantUmlVisitor {
                         StatementType::StateStackStmt {state_stack_operation_statement_node} => {
                             state_stack_operation_statement_node.accept(self);
                         },
+                        StatementType::RaiseStmt {raise_statement_node} => {
+                            raise_statement_node.accept(self);
+                        },
+                        StatementType::ForwardStmt {forward_statement_node} => {
+                            forward_statement_node.accept(self);
+                        },
                         StatementType::ChangeStateStmt {change_state_stmt} => {
                             change_state_stmt.accept(self);
                         },
@@ -576,7 +611,7 @@ impl PlantUmlVisitor {
 //         let enter_args_opt = match &transition_statement.target_state_context_t {
 //             StateContextType::StateRef { state_context_node}
 //             => &state_context_node.enter_args_opt,
-//             StateContextType::StateStackPop {}
+//             StateContextType::StateStackPop {..}
 //             => &None,
 //         };
 //
@@ -624,7 +659,7 @@ impl PlantUmlVisitor {
 //         let target_state_args_opt = match &transition_statement.target_state_context_t {
 //             StateContextType::StateRef { state_context_node}
 //             => &state_context_node.state_ref_args_opt,
-//             StateContextType::StateStackPop {}
+//             StateContextType::StateStackPop {..}
 //             => &Option::None,
 //         };
 // //
@@ -820,7 +855,12 @@ impl PlantUmlVisitor {
                 format!(" : {}",self.event_handler_msg.clone())
             },
         };
-        self.transitions.push_str(&format!("{} --> [H*]{}\n",&self.current_state_name_opt.as_ref().unwrap(),label));
+        let target = match &transition_statement.target_state_context_t {
+            StateContextType::StateHistory {state_ref_node, is_deep: true} => format!("{}[H*]", state_ref_node.name),
+            StateContextType::StateHistory {state_ref_node, is_deep: false} => format!("{}[H]", state_ref_node.name),
+            _ => String::from("[H*]"),
+        };
+        self.transitions.push_str(&format!("{} --> {}{}\n",&self.current_state_name_opt.as_ref().unwrap(),target,label));
 
     }
 }
@@ -850,7 +890,10 @@ impl AstVisitor for PlantUmlVisitor {
         match (&system_node).get_first_state() {
             Some(x) => {
                 self.first_state_name = x.borrow().name.clone();
-                self.transitions.push_str(&format!("[*] --> {}\n",self.first_state_name));
+                // regions draw their own start states
+                if !system_node.has_regions() {
+                    self.transitions.push_str(&format!("[*] --> {}\n",self.first_state_name));
+                }
                 has_states = true;
             },
             None => {},
@@ -1045,7 +1088,15 @@ impl AstVisitor for PlantUmlVisitor {
         let mut output = String::new();
         let sys_name = self.system_name.clone();
         let _system_node = self.system_hierarchy.get_system_node().unwrap();
-        self.generate_states(&sys_name, true,0, &mut output);
+        self.final_state_names = machine_block_node.states.iter()
+            .filter(|state_node_rcref| state_node_rcref.borrow().is_final())
+            .map(|state_node_rcref| state_node_rcref.borrow().name.clone())
+            .collect();
+        if machine_block_node.regions.len() > 0 {
+            self.generate_regions(machine_block_node, &mut output);
+        } else {
+            self.generate_states(&sys_name, true,0, &mut output);
+        }
         self.states = output;
 
         for state_node_rcref in &machine_block_node.states {
@@ -1134,6 +1185,25 @@ impl AstVisitor for PlantUmlVisitor {
 
         self.first_event_handler = true; // context for formatting
 
+        if let Some(timeout_node) = &state_node.timeout_opt {
+            timeout_node.accept(self);
+        }
+
+        // Guards aren't rendered as expressions aren't rendered here.
+        for completion_node in &state_node.completions {
+            self.event_handler_msg = match &completion_node.guard_expr_t_opt {
+                Some(_) => "done [guard]".to_string(),
+                None => "done".to_string(),
+            };
+            completion_node.transition_statement_node.accept(self);
+        }
+
+        if let Some(deferred_events) = &state_node.deferred_events_opt {
+            for msg in deferred_events {
+                self.transitions.push_str(&format!("{} : defer &#124;{}&#124;\n", state_node.name, msg));
+            }
+        }
+
         if state_node.evt_handlers_rcref.len() > 0 {
             for evt_handler_node in &state_node.evt_handlers_rcref {
                 evt_handler_node.as_ref().borrow().accept(self);
@@ -1378,8 +1448,11 @@ impl AstVisitor for PlantUmlVisitor {
         match &transition_statement.target_state_context_t {
             StateContextType::StateRef {..}
             => self.generate_state_ref_transition(transition_statement),
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..}
             => self.generate_state_stack_pop_transition(transition_statement),
+            // a history target is restored like a popped state
+            StateContextType::StateHistory {..}
+                => self.generate_state_stack_pop_transition(transition_statement),
         };
 
         AstVisitorReturnType::CallStatementNode {}
@@ -1401,7 +1474,7 @@ impl AstVisitor for PlantUmlVisitor {
         match &change_state_stmt_node.state_context_t {
             StateContextType::StateRef { ..}
             => self.generate_state_ref_change_state(change_state_stmt_node),
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..} | StateContextType::StateHistory {..}
             => panic!("TODO - not implemented"),
         };
 
@@ -1431,6 +1504,21 @@ impl AstVisitor for PlantUmlVisitor {
 
     //* --------------------------------------------------------------------- *//
 
+    fn visit_timeout_node(&mut self, timeout_node: &TimeoutNode) -> AstVisitorReturnType {
+        // Only literal durations are shown as expressions aren't rendered here.
+        self.event_handler_msg = match &timeout_node.duration_expr_t {
+            ExprType::LiteralExprT {literal_expr_node} => format!("after({})", literal_expr_node.value),
+            _ => "after".to_string(),
+        };
+        timeout_node.transition_statement_node.accept(self);
+
+        self.first_event_handler = false;
+
+        AstVisitorReturnType::TimeoutNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_test_statement_node(&mut self, test_stmt_node: &TestStatementNode) -> AstVisitorReturnType {
 
         match &test_stmt_node.test_t {
@@ -1916,6 +2004,34 @@ impl AstVisitor for PlantUmlVisitor {
 
     //* --------------------------------------------------------------------- *//
 
+    fn visit_list_literal_node(&mut self, _list_literal_node: &ListLiteralNode) -> AstVisitorReturnType {
+
+        AstVisitorReturnType::ListLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_list_literal_node_to_string(&mut self, _list_literal_node: &ListLiteralNode, _output:&mut String) -> AstVisitorReturnType {
+
+        AstVisitorReturnType::ListLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_map_literal_node(&mut self, _map_literal_node: &MapLiteralNode) -> AstVisitorReturnType {
+
+        AstVisitorReturnType::MapLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_map_literal_node_to_string(&mut self, _map_literal_node: &MapLiteralNode, _output:&mut String) -> AstVisitorReturnType {
+
+        AstVisitorReturnType::MapLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_expression_list_node(&mut self, expr_list: &ExprListNode) -> AstVisitorReturnType {
 
         for expr in &expr_list.exprs_t {
@@ -2056,6 +2172,18 @@ impl AstVisitor for PlantUmlVisitor {
     }
     //* --------------------------------------------------------------------- *//
 
+    fn visit_raise_statement_node(&mut self, _raise_statement_node:&RaiseStatementNode) -> AstVisitorReturnType {
+        AstVisitorReturnType::RaiseStatementNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_forward_statement_node(&mut self, _forward_statement_node:&ForwardStatementNode) -> AstVisitorReturnType {
+        AstVisitorReturnType::ForwardStatementNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_state_context_node(&mut self, _state_context_node:&StateContextNode) -> AstVisitorReturnType {
 
         // TODO
@@ -2127,6 +2255,20 @@ impl AstVisitor for PlantUmlVisitor {
 
     //* --------------------------------------------------------------------- *//
 
+    // Operations don't go through the state machine so they are not drawn.
+
+    fn visit_operations_block_node(&mut self, _operations_block_node: &OperationsBlockNode) -> AstVisitorReturnType {
+        AstVisitorReturnType::OperationsBlockNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_operation_node(&mut self, _operation_node: &OperationNode) -> AstVisitorReturnType {
+        AstVisitorReturnType::OperationNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_domain_variable_decl_node(&mut self, variable_decl_node: &VariableDeclNode) -> AstVisitorReturnType {
 
         self.visit_variable_decl_node(variable_decl_node);
@@ -2254,6 +2396,20 @@ impl AstVisitor for PlantUmlVisitor {
     }
 
 
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_conditional_expr_node(&mut self, _conditional_expr_node: &ConditionalExprNode) -> AstVisitorReturnType {
+
+        AstVisitorReturnType::ConditionalExprNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_conditional_expr_node_to_string(&mut self, _conditional_expr_node: &ConditionalExprNode, _output:&mut String) -> AstVisitorReturnType {
+
+        AstVisitorReturnType::ConditionalExprNode {}
+    }
+
     //* --------------------------------------------------------------------- *//
 
     fn visit_operator_type(&mut self, _operator_type: &OperatorType) -> AstVisitorReturnType {
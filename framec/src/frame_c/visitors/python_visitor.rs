@@ -19,9 +19,8 @@ pub struct PythonVisitor {
     first_event_handler:bool,
     system_name:String,
     first_state_name:String,
-    serialize:Vec<String>,
-    deserialize:Vec<String>,
     subclass_code:Vec<String>,
+    generate_abstract_actions:bool,
     warnings:Vec<String>,
     has_states:bool,
     errors:Vec<String>,
@@ -31,6 +30,18 @@ pub struct PythonVisitor {
     generate_state_stack:bool,
     generate_change_state:bool,
     generate_transition_state:bool,
+    generate_timers:bool,
+    generate_event_queue:bool,
+    generate_deferred_events:bool,
+    generate_history:bool,
+    generate_regions:bool,
+    generate_final_states:bool,
+    generate_completions:bool,
+    pushed_state_names:Vec<String>,
+    domain_param_names:Vec<String>,
+    generate_start_enter:bool,
+    state_stack_max_opt:Option<usize>,
+    state_stack_underflow_opt:Option<StateStackUnderflowPolicy>,
     event_handler_has_code:bool,
 }
 
@@ -60,11 +71,10 @@ impl PythonVisitor {
             first_event_handler:true,
             system_name:String::new(),
             first_state_name:String::new(),
-            serialize:Vec::new(),
-            deserialize:Vec::new(),
             has_states:false,
             errors:Vec::new(),
             subclass_code:Vec::new(),
+            generate_abstract_actions:false,
             warnings:Vec::new(),
             visiting_call_chain_literal_variable:false,
             generate_exit_args,
@@ -72,6 +82,18 @@ impl PythonVisitor {
             generate_state_stack,
             generate_change_state,
             generate_transition_state,
+            generate_timers:false,
+            generate_event_queue:false,
+            generate_deferred_events:false,
+            generate_history:false,
+            generate_regions:false,
+            generate_final_states:false,
+            generate_completions:false,
+            pushed_state_names:Vec::new(),
+            domain_param_names:Vec::new(),
+            generate_start_enter:false,
+            state_stack_max_opt:None,
+            state_stack_underflow_opt:None,
             event_handler_has_code:false,
         }
     }
@@ -93,6 +115,25 @@ impl PythonVisitor {
 
     //* --------------------------------------------------------------------- *//
 
+    // The subclass implementing the system's external actions. It is
+    // meant to be generated once into its own file so regenerating the
+    // system never overwrites the user's implementation. It imports the
+    // system from the module the generated code is saved as.
+
+    pub fn get_actions_stub(&self, module_name:&str) -> Option<String> {
+        if !self.generate_abstract_actions {
+            return None;
+        }
+        let mut stub = format!("from {} import {}\n\n\n", module_name, self.system_name);
+        for line in self.subclass_code.iter() {
+            stub.push_str(line);
+            stub.push('\n');
+        }
+        Some(stub)
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn format_variable_expr(&mut self, variable_node:&VariableNode) -> String {
         let mut code = String::new();
 
@@ -125,7 +166,7 @@ impl PythonVisitor {
                 // if self.visiting_call_chain_literal_variable {
                 //     code.push_str("(");
                 // }
-                code.push_str(&format!("e.params[\"{}\"]"
+                code.push_str(&format!("e._parameters[\"{}\"]"
                                        ,variable_node.id_node.name.lexeme));
                 // if self.visiting_call_chain_literal_variable {
                 //     code.push_str(")");
@@ -262,6 +303,12 @@ impl PythonVisitor {
                         StatementType::StateStackStmt {state_stack_operation_statement_node} => {
                             state_stack_operation_statement_node.accept(self);
                         },
+                        StatementType::RaiseStmt {raise_statement_node} => {
+                            raise_statement_node.accept(self);
+                        },
+                        StatementType::ForwardStmt {forward_statement_node} => {
+                            forward_statement_node.accept(self);
+                        },
                         StatementType::ChangeStateStmt {change_state_stmt} => {
                             change_state_stmt.accept(self);
                         },
@@ -276,6 +323,26 @@ impl PythonVisitor {
     }
 
 
+    // Generates a Frame body for an operation or action.
+
+    fn generate_frame_body(&mut self, statements:&Vec<DeclOrStmtType>, return_expr_t_opt:Option<&ExprType>) {
+        self.visit_decl_stmts(statements);
+        match return_expr_t_opt {
+            Some(expr_t) => {
+                self.newline();
+                self.add_code("return ");
+                expr_t.accept(self);
+            },
+            None if statements.is_empty() => {
+                self.newline();
+                self.add_code("pass");
+            },
+            None => {},
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     //* --------------------------------------------------------------------- *//
 
     fn generate_machinery(&mut self, system_node: &SystemNode) {
@@ -318,6 +385,14 @@ impl PythonVisitor {
                 self.newline();
                 self.add_code(&format!("self._state_(exitEvent)"));
                 self.newline();
+                if self.generate_history {
+                    self.add_code(&format!("self._recordHistory_()"));
+                    self.newline();
+                }
+                if self.generate_timers {
+                    self.add_code(&format!("self._cancelTimer_()"));
+                    self.newline();
+                }
                 self.add_code(&format!("self._state_ = newState"));
                 self.newline();
                 if self.generate_state_context {
@@ -330,6 +405,17 @@ impl PythonVisitor {
                     self.newline();
                 }
                 self.add_code(&format!("self._state_(enterEvent)"));
+                if self.generate_deferred_events {
+                    self.newline();
+                    self.add_code(&format!("self._replayDeferred_()"));
+                }
+                if self.generate_final_states && !self.generate_regions {
+                    self.generate_finished_check();
+                }
+                if self.generate_completions {
+                    self.newline();
+                    self.add_code("self._complete_()");
+                }
                 self.outdent();
                 self.newline();
                 // self.add_code(&format!("}}"));
@@ -378,41 +464,633 @@ impl PythonVisitor {
             if self.generate_change_state {
                 self.newline();
                 self.newline();
-                self.add_code(&format!("def _changeState_(newState):"));
+                if self.generate_state_context {
+                    self.add_code(&format!("def _changeState_(self, newState, stateContext):"));
+                } else {
+                    self.add_code(&format!("def _changeState_(self, newState):"));
+                }
                 self.indent();
                 self.newline();
+                if self.generate_history {
+                    self.add_code(&format!("self._recordHistory_()"));
+                    self.newline();
+                }
+                if self.generate_timers {
+                    self.add_code(&format!("self._cancelTimer_()"));
+                    self.newline();
+                }
                 self.add_code(&format!("self._state_ = newState"));
+                if self.generate_state_context {
+                    self.newline();
+                    self.add_code(&format!("self._stateContext_ = stateContext"));
+                }
+                if self.generate_deferred_events {
+                    self.newline();
+                    self.add_code(&format!("self._replayDeferred_()"));
+                }
+                if self.generate_final_states && !self.generate_regions {
+                    self.generate_finished_check();
+                }
+                if self.generate_completions {
+                    self.newline();
+                    self.add_code("self._complete_()");
+                }
                 self.outdent();
                 self.newline();
 //                self.add_code(&format!("}}"));
             }
+            if self.generate_timers {
+                self.generate_timers();
+            }
+            if self.generate_event_queue {
+                self.generate_event_queue();
+            }
+            if self.generate_deferred_events {
+                self.generate_deferred_events();
+            }
+            if self.generate_history {
+                self.generate_history(system_node);
+            }
+            if self.generate_regions {
+                self.generate_regions();
+            }
+            if self.generate_final_states {
+                self.generate_final_states(system_node);
+            }
+            if self.generate_completions {
+                self.generate_completions(system_node);
+            }
+            if self.generate_start_enter {
+                self.generate_start();
+            }
             self.newline();
 
             if self.arcanium.is_serializable() {
-                for line in self.serialize.iter() {
-                    self.code.push_str(&*format!("{}",line));
-                    self.code.push_str(&*format!("\n{}",self.dent()));
+                self.generate_serialization(system_node);
+            }
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // The first interface call enters the start state with the system
+    // enter parameters. As in the other languages this is not done in the
+    // constructor so a subclass is fully set up before its actions run.
+
+    fn generate_start(&mut self) {
+        self.newline();
+        self.newline();
+        self.add_code("def _start_(self):");
+        self.indent();
+        self.newline();
+        self.add_code("self._started_ = True");
+        self.newline();
+        self.add_code("self._state_(FrameEvent(\">\",self._stateContext_.getEnterArgs()))");
+        self.outdent();
+        self.newline();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Only one timer is active at a time. It is cancelled when a state is
+    // exited so a timeout always belongs to the current state.
+
+    fn generate_timers(&mut self) {
+        self.newline();
+        self.newline();
+        self.add_code("def _startTimer_(self, delayMs):");
+        self.indent();
+        self.newline();
+        self.add_code("if self._timerActive_:");
+        self.indent();
+        self.newline();
+        self.add_code("return");
+        self.outdent();
+        self.newline();
+        self.add_code("self._timerActive_ = True");
+        self.newline();
+        self.add_code("self._scheduler_.schedule(delayMs, self._timeout_)");
+        self.outdent();
+        self.newline();
+        self.newline();
+        self.add_code("def _cancelTimer_(self):");
+        self.indent();
+        self.newline();
+        self.add_code("if self._timerActive_:");
+        self.indent();
+        self.newline();
+        self.add_code("self._timerActive_ = False");
+        self.newline();
+        self.add_code("self._scheduler_.cancel()");
+        self.outdent();
+        self.outdent();
+        self.newline();
+        self.newline();
+        self.add_code("def _timeout_(self):");
+        self.indent();
+        self.newline();
+        self.add_code("if self._timerActive_:");
+        self.indent();
+        self.newline();
+        self.add_code("self._timerActive_ = False");
+        self.newline();
+        if self.generate_event_queue {
+            self.add_code(&format!("self._dispatch_(FrameEvent(\"{}\",None))", self.symbol_config.timeout_msg_symbol));
+        } else {
+            self.add_code(&format!("self._state_(FrameEvent(\"{}\",None))", self.symbol_config.timeout_msg_symbol));
+        }
+        self.outdent();
+        self.outdent();
+        self.newline();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Snapshots the machine as JSON: the current state with its state
+    // arguments and variables, the state stack and the domain.
+
+    fn generate_serialization(&mut self, system_node: &SystemNode) {
+        let state_param = if self.generate_state_context {"stateContext"} else {"state"};
+        let domain_vars:Vec<(String,bool)> = system_node.get_domain_vars().iter()
+            .map(|var_rcref| (var_rcref.borrow().name.clone(), var_rcref.borrow().has_snapshot_default())).collect();
+        let snapshot_version = system_node.get_snapshot_version();
+        let snapshot_hash = system_node.get_snapshot_hash();
+        self.newline();
+        self.add_code("def _serialize__do(self):");
+        self.indent();
+        self.newline();
+        self.add_code("bag = {}");
+        self.newline();
+        self.add_code(&format!("bag[\"version\"] = {}", snapshot_version));
+        self.newline();
+        self.add_code(&format!("bag[\"hash\"] = \"{}\"", snapshot_hash));
+        self.newline();
+        if self.generate_state_context {
+            self.add_code("bag[\"state\"] = self._serializeState_(self._stateContext_)");
+        } else {
+            self.add_code("bag[\"state\"] = self._serializeState_(self._state_)");
+        }
+        if self.generate_state_stack {
+            self.newline();
+            self.add_code("bag[\"stateStack\"] = [self._serializeState_(state) for state in self._stateStack_]");
+        }
+        self.newline();
+        self.add_code("bag[\"domain\"] = {}");
+        for (var_name, _) in &domain_vars {
+            self.newline();
+            self.add_code(&format!("bag[\"domain\"][\"{}\"] = self.{}", var_name, var_name));
+        }
+        self.newline();
+        self.add_code("return json.dumps(bag)");
+        self.outdent();
+        self.newline();
+        self.newline();
+        self.add_code("def _deserialize__do(self, data):");
+        self.indent();
+        self.newline();
+        self.add_code("bag = json.loads(data)");
+        self.newline();
+        self.add_code(&format!("if bag[\"version\"] > {}:", snapshot_version));
+        self.indent();
+        self.newline();
+        self.add_code(&format!("raise ValueError(\"Snapshot version \" + str(bag[\"version\"]) + \" is newer than version {} of {}\")", snapshot_version, system_node.name));
+        self.outdent();
+        self.newline();
+        self.add_code(&format!("if bag[\"version\"] == {} and bag[\"hash\"] != \"{}\":", snapshot_version, snapshot_hash));
+        self.indent();
+        self.newline();
+        self.add_code(&format!("raise ValueError(\"Snapshot does not match version {} of {}\")", snapshot_version, system_node.name));
+        self.outdent();
+        self.newline();
+        if self.generate_state_context {
+            self.add_code("self._stateContext_ = self._deserializeState_(bag[\"state\"])");
+            self.newline();
+            self.add_code("self._state_ = self._stateContext_.state");
+        } else {
+            self.add_code("self._state_ = self._deserializeState_(bag[\"state\"])");
+        }
+        if self.generate_state_stack {
+            self.newline();
+            self.add_code("self._stateStack_ = [self._deserializeState_(state) for state in bag[\"stateStack\"]]");
+        }
+        if self.generate_start_enter {
+            self.newline();
+            self.add_code("self._started_ = True");
+        }
+        for (var_name, has_snapshot_default) in &domain_vars {
+            self.newline();
+            if *has_snapshot_default {
+                self.add_code(&format!("if \"{}\" in bag[\"domain\"]:", var_name));
+                self.indent();
+                self.newline();
+                self.add_code(&format!("self.{} = bag[\"domain\"][\"{}\"]", var_name, var_name));
+                self.outdent();
+            } else {
+                self.add_code(&format!("self.{} = bag[\"domain\"][\"{}\"]", var_name, var_name));
+            }
+        }
+        self.outdent();
+        self.newline();
+        self.newline();
+        self.add_code(&format!("def _serializeState_(self, {}):", state_param));
+        self.indent();
+        for state_node_rcref in system_node.get_states() {
+            let state_node = state_node_rcref.borrow();
+            self.newline();
+            if self.generate_state_context {
+                self.add_code(&format!("if stateContext.state == self._s{}_:", state_node.name));
+                self.indent();
+                self.newline();
+                let state_args:Vec<String> = state_node.get_param_names().iter()
+                    .map(|name| format!("\"{}\": stateContext.getStateArg(\"{}\")", name, name)).collect();
+                let state_vars:Vec<String> = state_node.get_var_names().iter()
+                    .map(|name| format!("\"{}\": stateContext.getStateVar(\"{}\")", name, name)).collect();
+                self.add_code(&format!("return {{\"state\": \"{}\", \"stateArgs\": {{{}}}, \"stateVars\": {{{}}}}}"
+                                       , state_node.name, state_args.join(", "), state_vars.join(", ")));
+            } else {
+                self.add_code(&format!("if state == self._s{}_:", state_node.name));
+                self.indent();
+                self.newline();
+                self.add_code(&format!("return {{\"state\": \"{}\"}}", state_node.name));
+            }
+            self.outdent();
+        }
+        self.newline();
+        self.add_code("raise ValueError(\"Unknown state\")");
+        self.outdent();
+        self.newline();
+        self.newline();
+        self.add_code("def _deserializeState_(self, data):");
+        self.indent();
+        for state_node_rcref in system_node.get_states() {
+            let state_node = state_node_rcref.borrow();
+            self.newline();
+            let conditions:Vec<String> = std::iter::once(state_node.name.clone()).chain(state_node.get_renamed_from())
+                .map(|name| format!("data[\"state\"] == \"{}\"", name)).collect();
+            self.add_code(&format!("if {}:", conditions.join(" or ")));
+            self.indent();
+            self.newline();
+            if self.generate_state_context {
+                self.add_code(&format!("stateContext = StateContext(self._s{}_)", state_node.name));
+                for param_name in state_node.get_param_names() {
+                    self.newline();
+                    self.add_code(&format!("stateContext.addStateArg(\"{}\",data[\"stateArgs\"][\"{}\"])", param_name, param_name));
+                }
+                for var_name in state_node.get_var_names() {
+                    self.newline();
+                    self.add_code(&format!("stateContext.addStateVar(\"{}\",data[\"stateVars\"][\"{}\"])", var_name, var_name));
+                }
+                self.newline();
+                self.add_code("return stateContext");
+            } else {
+                self.add_code(&format!("return self._s{}_", state_node.name));
+            }
+            self.outdent();
+        }
+        self.newline();
+        self.add_code("raise ValueError(\"Unknown state \" + data[\"state\"])");
+        self.outdent();
+        self.newline();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // The default scheduler uses threading.Timer. Pass a scheduler with
+    // the same schedule()/cancel() methods to the system constructor to
+    // use another clock, e.g. a virtual clock for tests.
+
+    fn generate_default_scheduler(&mut self) {
+        self.add_code("import threading");
+        self.newline();
+        self.newline();
+        self.newline();
+        self.add_code("class FrameThreadingScheduler:");
+        self.indent();
+        self.newline();
+        self.newline();
+        self.add_code("def __init__(self):");
+        self.indent();
+        self.newline();
+        self.add_code("self._timer = None");
+        self.outdent();
+        self.newline();
+        self.newline();
+        self.add_code("def schedule(self, delayMs, callback):");
+        self.indent();
+        self.newline();
+        self.add_code("self._timer = threading.Timer(delayMs / 1000.0, callback)");
+        self.newline();
+        self.add_code("self._timer.start()");
+        self.outdent();
+        self.newline();
+        self.newline();
+        self.add_code("def cancel(self):");
+        self.indent();
+        self.newline();
+        self.add_code("if self._timer is not None:");
+        self.indent();
+        self.newline();
+        self.add_code("self._timer.cancel()");
+        self.newline();
+        self.add_code("self._timer = None");
+        self.outdent();
+        self.outdent();
+        self.outdent();
+        self.newline();
+        self.newline();
+        self.newline();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Events sent while the machine is handling an event are queued and
+    // dispatched in order once the current event has been fully handled.
+
+    fn generate_event_queue(&mut self) {
+        let state_dispatch = if self.generate_regions {"self._dispatchRegions_"} else {"self._state_"};
+        self.newline();
+        self.newline();
+        self.add_code("def _dispatch_(self, e):");
+        self.indent();
+        self.newline();
+        self.add_code("if self._processing_:");
+        self.indent();
+        self.newline();
+        self.add_code("self._eventQueue_.append(e)");
+        self.newline();
+        self.add_code("return");
+        self.outdent();
+        self.newline();
+        self.add_code("self._processing_ = True");
+        self.newline();
+        // Clear the flag even if a handler raises, otherwise every later
+        // event would be queued and never dispatched.
+        self.add_code("try:");
+        self.indent();
+        self.newline();
+        self.add_code(&format!("{}(e)", state_dispatch));
+        self.newline();
+        self.add_code("while len(self._eventQueue_) > 0:");
+        self.indent();
+        self.newline();
+        self.add_code(&format!("{}(self._eventQueue_.pop(0))", state_dispatch));
+        self.outdent();
+        self.outdent();
+        self.newline();
+        self.add_code("finally:");
+        self.indent();
+        self.newline();
+        self.add_code("self._processing_ = False");
+        self.outdent();
+        self.outdent();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // The machine is finished once it is in a #[final] state, or once
+    // every region is.
+
+    fn generate_final_states(&mut self, system_node: &SystemNode) {
+        let final_states:Vec<String> = system_node.get_final_state_names().iter()
+            .map(|state_name| format!("state == self._s{}_", state_name)).collect();
+        self.newline();
+        self.newline();
+        self.add_code("def _isFinalState_(self, state):");
+        self.indent();
+        self.newline();
+        self.add_code(&format!("return {}", final_states.join(" or ")));
+        self.outdent();
+        self.newline();
+        if self.generate_regions {
+            self.newline();
+            self.add_code("def is_finished(self):");
+            self.indent();
+            self.newline();
+            self.add_code("return all(self._isFinalState_(state) for state in self._regions_)");
+            self.outdent();
+        } else {
+            self.newline();
+            self.add_code("def is_finished(self):");
+            self.indent();
+            self.newline();
+            self.add_code("return self._isFinalState_(self._state_)");
+            self.outdent();
+        }
+        self.newline();
+        self.newline();
+        self.add_code("def on_finished(self):");
+        self.indent();
+        self.newline();
+        self.add_code("pass");
+        self.outdent();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Entering a final state completes the machine.
+
+    fn generate_finished_check(&mut self) {
+        self.newline();
+        self.add_code("if self.is_finished():");
+        self.indent();
+        self.newline();
+        self.add_code("self.on_finished()");
+        self.outdent();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Completion transitions are taken one after another in a loop rather
+    // than from inside the transition that entered the state, so long
+    // chains don't grow the stack.
+
+    fn generate_completions(&mut self, system_node: &SystemNode) {
+        self.newline();
+        self.newline();
+        self.add_code("def _complete_(self):");
+        self.indent();
+        self.newline();
+        self.add_code("if self._completing_:");
+        self.indent();
+        self.newline();
+        self.add_code("return");
+        self.outdent();
+        self.newline();
+        self.add_code("self._completing_ = True");
+        self.newline();
+        self.add_code("while self._completion_():");
+        self.indent();
+        self.newline();
+        self.add_code("pass");
+        self.outdent();
+        self.newline();
+        self.add_code("self._completing_ = False");
+        self.outdent();
+        self.newline();
+        self.newline();
+        self.add_code("def _completion_(self):");
+        self.indent();
+        if let Some(machine_block_node) = &system_node.machine_block_node_opt {
+            for state_node_rcref in &machine_block_node.states {
+                let state_node = state_node_rcref.borrow();
+                if state_node.completions.len() == 0 {
+                    continue;
                 }
+                self.current_state_name_opt = Some(state_node.name.clone());
+                self.newline();
+                self.add_code(&format!("if self._state_ == self._s{}_:", state_node.name));
+                self.indent();
+                for completion_node in &state_node.completions {
+                    if let Some(guard_expr_t) = &completion_node.guard_expr_t_opt {
+                        let mut guard = String::new();
+                        guard_expr_t.accept_to_string(self, &mut guard);
+                        self.newline();
+                        self.add_code(&format!("if {}:", guard));
+                        self.indent();
+                    }
+                    completion_node.transition_statement_node.accept(self);
+                    self.newline();
+                    self.add_code("return True");
+                    if completion_node.guard_expr_t_opt.is_some() {
+                        self.outdent();
+                    }
+                }
+                self.outdent();
+                self.current_state_name_opt = None;
+            }
+        }
+        self.newline();
+        self.add_code("return False");
+        self.outdent();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Each region has its own active state. An event is dispatched to
+    // every region in turn with the region's state made current.
+
+    fn generate_regions(&mut self) {
+        self.newline();
+        self.newline();
+        self.add_code("def _dispatchRegions_(self, e):");
+        self.indent();
+        if self.generate_final_states {
+            self.newline();
+            self.add_code("wasFinished = self.is_finished()");
+        }
+        self.newline();
+        self.add_code("for i in range(len(self._regions_)):");
+        self.indent();
+        self.newline();
+        self.add_code("self._state_ = self._regions_[i]");
+        self.newline();
+        self.add_code("self._state_(e)");
+        self.newline();
+        self.add_code("self._regions_[i] = self._state_");
+        self.outdent();
+        if self.generate_final_states {
+            self.newline();
+            self.add_code("if not wasFinished and self.is_finished():");
+            self.indent();
+            self.newline();
+            self.add_code("self.on_finished()");
+            self.outdent();
+        }
+        self.outdent();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // History is recorded on the way out of a state. Every ancestor that
+    // is a history target remembers the child it was left through and
+    // the leaf state that was active.
 
-                for line in self.deserialize.iter() {
-                    self.code.push_str(&*format!("{}",line));
-                    self.code.push_str(&*format!("\n{}",self.dent()));
+    fn generate_history(&mut self, system_node: &SystemNode) {
+        self.newline();
+        self.newline();
+        self.add_code("def _recordHistory_(self):");
+        self.indent();
+        let mut is_first = true;
+        if let Some(machine_block_node) = &system_node.machine_block_node_opt {
+            for state_node_rcref in &machine_block_node.states {
+                let state_node = state_node_rcref.borrow();
+                if state_node.history_records.len() == 0 {
+                    continue;
+                }
+                let state_ref = self.format_target_state_name(&state_node.name);
+                self.newline();
+                if is_first {
+                    self.add_code(&format!("if self._state_ == self.{}:", state_ref));
+                } else {
+                    self.add_code(&format!("elif self._state_ == self.{}:", state_ref));
+                }
+                is_first = false;
+                self.indent();
+                for history_record in &state_node.history_records {
+                    let child_ref = self.format_target_state_name(&history_record.child_state_name);
+                    self.newline();
+                    self.add_code(&format!("self._shallowHistory_[\"{}\"] = self.{}", history_record.parent_state_name, child_ref));
+                    self.newline();
+                    self.add_code(&format!("self._deepHistory_[\"{}\"] = self.{}", history_record.parent_state_name, state_ref));
                 }
+                self.outdent();
             }
         }
+        self.outdent();
     }
 
     //* --------------------------------------------------------------------- *//
 
-    fn generate_subclass(&mut self) {
-        for line in self.subclass_code.iter() {
-            self.code.push_str(&*format!("{}",line));
-            self.code.push_str(&*format!("\n{}",self.dent()));
+    // Deferred events are replayed in the order they arrived. Any the
+    // new state defers again go back on the queue.
+
+    fn generate_deferred_events(&mut self) {
+        self.newline();
+        self.newline();
+        self.add_code("def _replayDeferred_(self):");
+        self.indent();
+        self.newline();
+        self.add_code("deferred = self._deferredQueue_");
+        self.newline();
+        self.add_code("self._deferredQueue_ = []");
+        self.newline();
+        self.add_code("for e in deferred:");
+        self.indent();
+        self.newline();
+        self.add_code("self._state_(e)");
+        self.outdent();
+        self.outdent();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // A deferred event is put aside rather than handled and is replayed
+    // after the next transition.
+
+    fn generate_deferred_event_checks(&mut self, deferred_events:&Vec<String>) {
+        for msg in deferred_events {
+            self.newline();
+            self.add_code(&format!("if e._message == \"{}\":", msg));
+            self.indent();
+            self.newline();
+            self.add_code("self._deferredQueue_.append(e)");
+            self.newline();
+            self.add_code("return");
+            self.outdent();
         }
     }
 
     //* --------------------------------------------------------------------- *//
 
+    fn generate_timer_start(&mut self, timeout_node:&TimeoutNode) {
+        let mut duration = String::new();
+        timeout_node.duration_expr_t.accept_to_string(self, &mut duration);
+        self.newline();
+        self.add_code(&format!("self._startTimer_({})", duration));
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn generate_comment(&mut self,line:usize) -> bool {
 
         // can't use self.newline() or self.add_code() due to double borrow.
@@ -452,8 +1130,21 @@ impl PythonVisitor {
             },
         };
 
+        let target_state_args_opt = match &change_state_stmt_node.state_context_t {
+            StateContextType::StateRef { state_context_node}
+                => &state_context_node.state_ref_args_opt,
+            _ => &Option::None,
+        };
+
         self.newline();
-        self.add_code(&format!("self._changeState_({});", self.format_target_state_name(target_state_name)));
+        if self.generate_state_context {
+            self.add_code(&format!("stateContext = StateContext(self.{})", self.generate_state_ref_code(target_state_name)));
+            self.newline();
+            self.generate_state_args_and_vars(target_state_name, target_state_args_opt);
+            self.add_code(&format!("self._changeState_(self.{},stateContext)", self.format_target_state_name(target_state_name)));
+        } else {
+            self.add_code(&format!("self._changeState_(self.{})", self.format_target_state_name(target_state_name)));
+        }
 
     }
 
@@ -544,7 +1235,7 @@ impl PythonVisitor {
         let enter_args_opt = match &transition_statement.target_state_context_t {
             StateContextType::StateRef { state_context_node}
                 => &state_context_node.enter_args_opt,
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..} | StateContextType::StateHistory {..}
                 => &None,
         };
 
@@ -588,10 +1279,38 @@ impl PythonVisitor {
         let target_state_args_opt = match &transition_statement.target_state_context_t {
             StateContextType::StateRef { state_context_node}
                 => &state_context_node.state_ref_args_opt,
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..} | StateContextType::StateHistory {..}
                 => &Option::None,
         };
 //
+        self.generate_state_args_and_vars(&target_state_name, target_state_args_opt);
+        let exit_args = if has_exit_args {
+            "exitArgs"
+        } else {
+            "None"
+        };
+        if self.generate_state_context {
+            if self.generate_exit_args {
+                self.add_code(&format!("self._transition_(self.{},{},stateContext)",self.format_target_state_name(target_state_name),exit_args));
+            } else {
+                self.add_code(&format!("self._transition_(self.{},stateContext)",self.format_target_state_name(target_state_name)));
+            }
+        } else {
+            if self.generate_exit_args {
+                self.add_code(&format!("self._transition_(self.{},{})",self.format_target_state_name(target_state_name),exit_args));
+            } else {
+                self.add_code(&format!("self._transition_(self.{})",self.format_target_state_name(target_state_name)));
+            }
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Adds the target state's arguments and initialized
+    // state variables to the new state context.
+
+    fn generate_state_args_and_vars(&mut self, target_state_name:&str, target_state_args_opt:&Option<ExprListNode>) {
+
         if let Some(state_args) = target_state_args_opt {
 //            let mut params_copy = Vec::new();
             if let Some(state_sym) = self.arcanium.get_state(&target_state_name) {
@@ -651,24 +1370,6 @@ impl PythonVisitor {
 //                code = target_state_vars.clone();
             },
         }
-        let exit_args = if has_exit_args {
-            "exitArgs"
-        } else {
-            "None"
-        };
-        if self.generate_state_context {
-            if self.generate_exit_args {
-                self.add_code(&format!("self._transition_(self.{},{},stateContext)",self.format_target_state_name(target_state_name),exit_args));
-            } else {
-                self.add_code(&format!("self._transition_(self.{},stateContext)",self.format_target_state_name(target_state_name)));
-            }
-        } else {
-            if self.generate_exit_args {
-                self.add_code(&format!("self._transition_(self.{},{})",self.format_target_state_name(target_state_name),exit_args));
-            } else {
-                self.add_code(&format!("self._transition_(self.{})",self.format_target_state_name(target_state_name)));
-            }
-        }
     }
 
     //* --------------------------------------------------------------------- *//
@@ -737,28 +1438,148 @@ impl PythonVisitor {
                             self.errors.push(format!("Fatal error: misaligned parameters to arguments.")),
                     }
                 } else {
-                    self.errors.push(format!("TODO"));
+                    self.errors.push(format!("TODO"));
+                }
+            }
+        }
+
+        if let StateContextType::StateStackPop {..} = &transition_statement.target_state_context_t {
+            let exit_args = match &transition_statement.exit_args_opt {
+                Some(exit_args) if exit_args.exprs_t.len() > 0 => "exitArgs",
+                _ => "None",
+            };
+            self.generate_state_stack_underflow_check(exit_args);
+        }
+        if let StateContextType::StateHistory {state_ref_node, is_deep} = &transition_statement.target_state_context_t {
+            let history_map = if *is_deep {"_deepHistory_"} else {"_shallowHistory_"};
+            self.add_code(&format!("state = self.{}.get(\"{}\", self.{})", history_map, state_ref_node.name, self.format_target_state_name(&state_ref_node.name)));
+            if self.generate_state_context {
+                self.newline();
+                self.add_code("stateContext = StateContext(state)");
+            }
+        } else if self.generate_state_context {
+            self.add_code(&format!("stateContext = self._stateStack_pop_()"));
+        } else {
+            self.add_code(&format!("state = self._stateStack_pop_()"));
+        }
+        self.newline();
+        if let StateContextType::StateStackPop { enter_args_opt:Some(enter_args) } = &transition_statement.target_state_context_t {
+            self.generate_state_stack_pop_enter_args(enter_args);
+        }
+        if self.generate_exit_args {
+            if self.generate_state_context {
+                self.add_code(&format!("self._transition_(stateContext.state,exitArgs,stateContext)"));
+            } else {
+                self.add_code(&format!("self._transition_(state,exitArgs)"));
+            }
+        } else {
+            if self.generate_state_context {
+                self.add_code(&format!("self._transition_(stateContext.state,stateContext)"));
+            } else {
+                self.add_code(&format!("self._transition_(state)"));
+            }
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Refuses to push past #![state_stack_max], tells the current state
+    // with a stateStackOverflow event and ends the handler so the
+    // transition after the push does not happen.
+
+    fn generate_state_stack_overflow_check(&mut self) {
+        let max = match self.state_stack_max_opt {
+            Some(max) => max,
+            None => return,
+        };
+        self.add_code(&format!("if len(self._stateStack_) >= {}:", max));
+        self.indent();
+        self.newline();
+        let message = self.symbol_config.state_stack_overflow_msg_symbol.clone();
+        self.generate_state_stack_error_event(&message);
+        self.newline();
+        self.add_code("return");
+        self.outdent();
+        self.newline();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Applies the #![state_stack_underflow] policy when $$[-] finds the
+    // state stack empty.
+
+    fn generate_state_stack_underflow_check(&mut self, exit_args:&str) {
+        let underflow = match &self.state_stack_underflow_opt {
+            Some(underflow) => underflow.clone(),
+            None => return,
+        };
+        self.add_code("if len(self._stateStack_) == 0:");
+        self.indent();
+        self.newline();
+        match underflow {
+            StateStackUnderflowPolicy::Error => {
+                let message = self.symbol_config.state_stack_underflow_msg_symbol.clone();
+                self.generate_state_stack_error_event(&message);
+                self.newline();
+            },
+            StateStackUnderflowPolicy::Stay => {},
+            StateStackUnderflowPolicy::State {state_name} => {
+                if self.generate_state_context {
+                    self.add_code(&format!("stateContext = StateContext(self.{})", self.generate_state_ref_code(&state_name)));
+                    self.newline();
+                    self.generate_state_args_and_vars(&state_name, &None);
+                    if self.generate_exit_args {
+                        self.add_code(&format!("self._transition_(self.{},{},stateContext)", self.format_target_state_name(&state_name), exit_args));
+                    } else {
+                        self.add_code(&format!("self._transition_(self.{},stateContext)", self.format_target_state_name(&state_name)));
+                    }
+                } else if self.generate_exit_args {
+                    self.add_code(&format!("self._transition_(self.{},{})", self.format_target_state_name(&state_name), exit_args));
+                } else {
+                    self.add_code(&format!("self._transition_(self.{})", self.format_target_state_name(&state_name)));
                 }
-            }
+                self.newline();
+            },
         }
+        self.add_code("return");
+        self.outdent();
+        self.newline();
+    }
 
-        if self.generate_state_context {
-            self.add_code(&format!("stateContext = self._stateStack_pop_()"));
+    //* --------------------------------------------------------------------- *//
+
+    // Sends a state stack error event to the current state.
+
+    fn generate_state_stack_error_event(&mut self, message:&str) {
+        if self.generate_event_queue {
+            self.add_code(&format!("self._dispatch_(FrameEvent(\"{}\",None))", message));
         } else {
-            self.add_code(&format!("state = self._stateStack_pop_()"));
+            self.add_code(&format!("self._state_(FrameEvent(\"{}\",None))", message));
         }
-        self.newline();
-        if self.generate_exit_args {
-            if self.generate_state_context {
-                self.add_code(&format!("self._transition_(stateContext.state,exitArgs,stateContext)"));
-            } else {
-                self.add_code(&format!("self._transition_(state,exitArgs)"));
-            }
-        } else {
-            if self.generate_state_context {
-                self.add_code(&format!("self._transition_(stateContext.state,stateContext)"));
-            } else {
-                self.add_code(&format!("self._transition_(state)"));
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Adds the enter arguments of a state stack pop to the popped state
+    // context. Every pushed state takes the same enter parameters so the
+    // first one names them.
+
+    fn generate_state_stack_pop_enter_args(&mut self, enter_args:&ExprListNode) {
+        let mut msg = match self.pushed_state_names.first() {
+            Some(pushed_state_name) => pushed_state_name.clone(),
+            None => return,
+        };
+        msg.push_str(":");
+        msg.push_str(&self.symbol_config.enter_msg_symbol);
+
+        if let Some(event_sym) = self.arcanium.get_event(&msg,&self.current_state_name_opt) {
+            if let Some(event_params) = &event_sym.borrow().params_opt {
+                for (p, expr_t) in event_params.iter().zip(enter_args.exprs_t.iter()) {
+                    let mut expr = String::new();
+                    expr_t.accept_to_string(self,&mut expr);
+                    self.add_code(&format!("stateContext.addEnterArg(\"{}\",{})", p.name, expr));
+                    self.newline();
+                }
             }
         }
     }
@@ -766,6 +1587,45 @@ impl PythonVisitor {
 
 //* --------------------------------------------------------------------- *//
 
+impl OperatorPrecedence for PythonVisitor {
+
+    //* --------------------------------------------------------------------- *//
+
+    // Binding strength of each operator in Python, higher binds tighter.
+    // Xor is lowered to '!=' on bools so it sits with the comparisons.
+
+    fn operator_precedence(&self, operator_type:&OperatorType) -> usize {
+        match operator_type {
+            OperatorType::LogicalOr => 1,
+            OperatorType::LogicalAnd => 2,
+            OperatorType::Not => 3,
+            OperatorType::EqualEqual
+            | OperatorType::NotEqual
+            | OperatorType::Greater
+            | OperatorType::GreaterEqual
+            | OperatorType::Less
+            | OperatorType::LessEqual
+            | OperatorType::LogicalXor => 4,
+            OperatorType::Plus
+            | OperatorType::Minus => 5,
+            OperatorType::Multiply
+            | OperatorType::Divide => 6,
+            OperatorType::Negated => 7,
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Python chains comparisons ('a < b < c'), so nested comparisons
+    // always need parentheses.
+
+    fn is_non_associative(&self, operator_type:&OperatorType) -> bool {
+        self.operator_precedence(operator_type) == 4
+    }
+}
+
+//* --------------------------------------------------------------------- *//
+
 impl AstVisitor for PythonVisitor {
 
     //* --------------------------------------------------------------------- *//
@@ -777,7 +1637,39 @@ impl AstVisitor for PythonVisitor {
         self.add_code("# get include files at https://github.com/frame-lang/frame-ancillary-files");
         self.newline();
         self.newline();
-        self.add_code(&format!("class {}:", system_node.name));
+        self.generate_timers = system_node.has_timeouts();
+        self.generate_event_queue = system_node.is_run_to_completion();
+        self.generate_deferred_events = system_node.has_deferred_events();
+        self.generate_history = system_node.has_history();
+        self.generate_regions = system_node.has_regions();
+        self.generate_final_states = system_node.get_final_state_names().len() > 0;
+        self.generate_completions = system_node.has_completions();
+        self.pushed_state_names = system_node.get_pushed_state_names();
+        self.domain_param_names = system_node.get_domain_param_names();
+        self.generate_start_enter = system_node.start_state_enter_params_opt.is_some();
+        self.state_stack_max_opt = system_node.get_state_stack_max();
+        self.state_stack_underflow_opt = system_node.get_state_stack_underflow();
+        self.generate_abstract_actions = system_node.has_external_actions();
+        if self.arcanium.is_serializable() {
+            self.add_code("import json");
+            self.newline();
+            self.newline();
+            self.newline();
+        }
+        if self.generate_abstract_actions {
+            self.add_code("from abc import ABC, abstractmethod");
+            self.newline();
+            self.newline();
+            self.newline();
+        }
+        if self.generate_timers {
+            self.generate_default_scheduler();
+        }
+        if self.generate_abstract_actions {
+            self.add_code(&format!("class {}(ABC):", system_node.name));
+        } else {
+            self.add_code(&format!("class {}:", system_node.name));
+        }
         self.indent();
         self.newline();
 
@@ -795,13 +1687,57 @@ impl AstVisitor for PythonVisitor {
 
         if self.has_states {
             self.newline();
-            self.add_code(&format!("def __init__(self):"));
+            let system_params:Vec<String> = system_node.get_system_params().iter()
+                .map(|param| format!(", {}", param.param_name)).collect();
+            if self.generate_timers {
+                self.add_code(&format!("def __init__(self{}, scheduler=None):", system_params.join("")));
+            } else {
+                self.add_code(&format!("def __init__(self{}):", system_params.join("")));
+            }
             self.indent();
             self.newline();
             self.add_code(&format!("self._state_ = self._s{}_", self.first_state_name));
+            if self.generate_regions {
+                let region_states:Vec<String> = system_node.get_region_initial_state_names().iter()
+                    .map(|state_name| format!("self._s{}_", state_name)).collect();
+                self.newline();
+                self.add_code(&format!("self._regions_ = [{}]", region_states.join(", ")));
+            }
+            if self.generate_timers {
+                self.newline();
+                self.add_code(&format!("self._scheduler_ = scheduler if scheduler is not None else FrameThreadingScheduler()"));
+                self.newline();
+                self.add_code(&format!("self._timerActive_ = False"));
+            }
+            if self.generate_event_queue {
+                self.newline();
+                self.add_code(&format!("self._eventQueue_ = []"));
+                self.newline();
+                self.add_code(&format!("self._processing_ = False"));
+            }
+            if self.generate_deferred_events {
+                self.newline();
+                self.add_code(&format!("self._deferredQueue_ = []"));
+            }
+            if self.generate_history {
+                self.newline();
+                self.add_code(&format!("self._shallowHistory_ = {{}}"));
+                self.newline();
+                self.add_code(&format!("self._deepHistory_ = {{}}"));
+            }
+            if self.generate_completions {
+                self.newline();
+                self.add_code(&format!("self._completing_ = False"));
+            }
             if self.generate_state_context {
                 self.newline();
                 self.add_code(&format!("self._stateContext_ = StateContext(self._s{}_)", self.first_state_name));
+                if let Some(params) = &system_node.start_state_state_params_opt {
+                    for param in params {
+                        self.newline();
+                        self.add_code(&format!("self._stateContext_.addStateArg(\"{}\",{})", param.param_name, param.param_name));
+                    }
+                }
                 if let Some(state_symbol_rcref) = self.arcanium.get_state(&self.first_state_name) {
                     //   self.newline();
                     let state_symbol = state_symbol_rcref.borrow();
@@ -830,29 +1766,43 @@ impl AstVisitor for PythonVisitor {
                 domain_block_node.accept(self);
             }
 
+            // The start state is never entered so start its timer here.
+            if let Some(first_state_node_rcref) = system_node.get_first_state() {
+                let first_state_node = first_state_node_rcref.borrow();
+                if let Some(timeout_node) = &first_state_node.timeout_opt {
+                    self.current_state_name_opt = Some(first_state_node.name.clone());
+                    self.generate_timer_start(timeout_node);
+                    self.current_state_name_opt = None;
+                }
+            }
+
+            // The start state is entered with these on the first event.
+            if let Some(params) = &system_node.start_state_enter_params_opt {
+                for param in params {
+                    self.newline();
+                    self.add_code(&format!("self._stateContext_.addEnterArg(\"{}\",{})", param.param_name, param.param_name));
+                }
+                self.newline();
+                self.add_code("self._started_ = False");
+            }
+
             self.outdent();
             self.newline();
         }
 
         // end of generate constructor
 
-        self.serialize.push("".to_string());
-        self.serialize.push("Bag _serialize__do() {".to_string());
 
-        self.deserialize.push("".to_string());
-
-        // @TODO: _do needs to be configurable.
-        self.deserialize.push("void _deserialize__do(Bag data) {".to_string());
-
-
-        self.subclass_code.push("".to_string());
-        self.subclass_code.push("# ********************\n".to_string());
-        self.subclass_code.push(format!("#class {}Controller({}):",system_node.name,system_node.name));
+        self.subclass_code.push(format!("class {}Controller({}):",system_node.name,system_node.name));
 
         if let Some(interface_block_node) = &system_node.interface_block_node_opt {
             interface_block_node.accept(self);
         }
 
+        if let Some(operations_block_node) = &system_node.operations_block_node_opt {
+            operations_block_node.accept(self);
+        }
+
         if let Some(machine_block_node) = &system_node.machine_block_node_opt {
             machine_block_node.accept(self);
         }
@@ -861,16 +1811,6 @@ impl AstVisitor for PythonVisitor {
             actions_block_node.accept(self);
         }
 
-        self.subclass_code.push("\n# ********************\n".to_string());
-
-        self.serialize.push("".to_string());
-        self.serialize.push("\treturn JSON.stringify(bag)".to_string());
-        self.serialize.push("}".to_string());
-        self.serialize.push("".to_string());
-
-        self.deserialize.push("".to_string());
-        self.deserialize.push("}".to_string());
-
         if self.has_states {
             self.generate_machinery(system_node);
         }
@@ -882,8 +1822,6 @@ impl AstVisitor for PythonVisitor {
         self.outdent();
         self.newline();
 
-        self.generate_subclass();
-
         AstVisitorReturnType::SystemNode {}
     }
 
@@ -967,12 +1905,42 @@ impl AstVisitor for PythonVisitor {
             params_param_code = String::from("None");
         }
 
+        if *method_name_or_alias == self.symbol_config.restore_msg_symbol {
+            if let Some(params) = &interface_method_node.params {
+                self.newline();
+                self.add_code(&format!("self._deserialize__do({})", params[0].param_name));
+            }
+        }
+        if self.generate_start_enter {
+            self.newline();
+            self.add_code("if not self._started_:");
+            self.indent();
+            self.newline();
+            self.add_code("self._start_()");
+            self.outdent();
+        }
         self.newline();
         self.add_code(&format!("e = FrameEvent(\"{}\",{})", method_name_or_alias,params_param_code));
+        if let Some(default_return_expr_t) = &interface_method_node.default_return_expr_opt {
+            let mut default_return = String::new();
+            default_return_expr_t.accept_to_string(self, &mut default_return);
+            self.newline();
+            self.add_code(&format!("e._return = {}", default_return));
+        }
         self.newline();
-        self.add_code(&format!("self._state_(e)"));
+        if self.generate_event_queue {
+            self.add_code(&format!("self._dispatch_(e)"));
+        } else if self.generate_regions {
+            self.add_code(&format!("self._dispatchRegions_(e)"));
+        } else {
+            self.add_code(&format!("self._state_(e)"));
+        }
 
         match &interface_method_node.return_type_opt {
+            Some(_) if *method_name_or_alias == self.symbol_config.save_msg_symbol => {
+                self.newline();
+                self.add_code(&format!("return self._serialize__do()"));
+            },
             Some(_) => {
                 self.newline();
                 self.add_code(&format!("return e._return"));
@@ -994,29 +1962,11 @@ impl AstVisitor for PythonVisitor {
         self.newline();
         self.newline();
 
-        self.serialize.push("".to_string());
-        self.serialize.push("\tvar stateName = null;".to_string());
-
-        self.deserialize.push("".to_string());
-        self.deserialize.push("\tbag = JSON.parse(data)".to_string());
-        self.deserialize.push("".to_string());
-        self.deserialize.push("\tswitch (bag.state) {".to_string());
-
 
         for state_node_rcref in &machine_block_node.states {
             state_node_rcref.borrow().accept(self);
         }
 
-        self.serialize.push("".to_string());
-        self.serialize.push("\tbag = {".to_string());
-        self.serialize.push("\t\tstate : stateName,".to_string());
-        self.serialize.push("\t\tdomain : {}".to_string());
-        self.serialize.push("\t}".to_string());
-        self.serialize.push("".to_string());
-
-        self.deserialize.push("\t}".to_string());
-        self.deserialize.push("".to_string());
-
 
         AstVisitorReturnType::MachineBlockNode {}
     }
@@ -1080,11 +2030,21 @@ impl AstVisitor for PythonVisitor {
         self.add_code(&format!("def _s{}_(self, e):", state_node.name));
         self.indent();
 
-        self.serialize.push(format!("\tif (self._state_ == _s{}_) stateName = \"{}\"",state_node.name,state_node.name));
+        let mut generate_pass = true;
 
-        self.deserialize.push(format!("\t\tcase \"{}\": _state_ = _s{}_; break;",state_node.name,state_node.name));
+        if let Some(deferred_events) = &state_node.deferred_events_opt {
+            generate_pass = false;
+            self.generate_deferred_event_checks(deferred_events);
+        }
 
-        let mut generate_pass = true;
+        if let Some(timeout_node) = &state_node.timeout_opt {
+            generate_pass = false;
+            self.newline();
+            self.add_code(&format!("if e._message == \"{}\":", self.symbol_config.enter_msg_symbol));
+            self.indent();
+            self.generate_timer_start(timeout_node);
+            self.outdent();
+        }
 
         if let Some(calls) = &state_node.calls_opt {
             generate_pass = false;
@@ -1096,6 +2056,10 @@ impl AstVisitor for PythonVisitor {
 
         self.first_event_handler = true; // context for formatting
 
+        if let Some(timeout_node) = &state_node.timeout_opt {
+            timeout_node.accept(self);
+        }
+
         if state_node.evt_handlers_rcref.len() > 0 {
             generate_pass = false;
             for evt_handler_node in &state_node.evt_handlers_rcref {
@@ -1341,7 +2305,10 @@ impl AstVisitor for PythonVisitor {
         match &transition_statement.target_state_context_t {
             StateContextType::StateRef {..}
                 => self.generate_state_ref_transition(transition_statement),
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..}
+                => self.generate_state_stack_pop_transition(transition_statement),
+            // a history target is restored like a popped state
+            StateContextType::StateHistory {..}
                 => self.generate_state_stack_pop_transition(transition_statement),
         };
 
@@ -1364,7 +2331,7 @@ impl AstVisitor for PythonVisitor {
         match &change_state_stmt_node.state_context_t {
             StateContextType::StateRef {..}
                 => self.generate_state_ref_change_state(change_state_stmt_node),
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..} | StateContextType::StateHistory {..}
                 => self.errors.push(format!("Fatal error - change state stack pop not implemented."),)
         };
 
@@ -1394,6 +2361,28 @@ impl AstVisitor for PythonVisitor {
 
     //* --------------------------------------------------------------------- *//
 
+    fn visit_timeout_node(&mut self, timeout_node: &TimeoutNode) -> AstVisitorReturnType {
+        self.newline();
+        self.generate_comment(timeout_node.line);
+        if self.first_event_handler {
+            self.add_code(&format!("if e._message == \"{}\":", self.symbol_config.timeout_msg_symbol));
+        } else {
+            self.add_code(&format!("elif e._message == \"{}\":", self.symbol_config.timeout_msg_symbol));
+        }
+        self.indent();
+        timeout_node.transition_statement_node.accept(self);
+        self.newline();
+        self.add_code("return");
+        self.outdent();
+        self.newline();
+
+        self.first_event_handler = false;
+
+        AstVisitorReturnType::TimeoutNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_test_statement_node(&mut self, test_stmt_node: &TestStatementNode) -> AstVisitorReturnType {
 
         match &test_stmt_node.test_t {
@@ -1902,6 +2891,62 @@ impl AstVisitor for PythonVisitor {
 
     //* --------------------------------------------------------------------- *//
 
+    fn visit_list_literal_node(&mut self, list_literal_node: &ListLiteralNode) -> AstVisitorReturnType {
+
+        let mut output = String::new();
+        self.visit_list_literal_node_to_string(list_literal_node, &mut output);
+        self.add_code(&output);
+
+        AstVisitorReturnType::ListLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_list_literal_node_to_string(&mut self, list_literal_node: &ListLiteralNode, output:&mut String) -> AstVisitorReturnType {
+
+        output.push_str("[");
+        let mut separator = "";
+        for expr_t in &list_literal_node.exprs_t {
+            output.push_str(separator);
+            expr_t.accept_to_string(self, output);
+            separator = ",";
+        }
+        output.push_str("]");
+
+        AstVisitorReturnType::ListLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_map_literal_node(&mut self, map_literal_node: &MapLiteralNode) -> AstVisitorReturnType {
+
+        let mut output = String::new();
+        self.visit_map_literal_node_to_string(map_literal_node, &mut output);
+        self.add_code(&output);
+
+        AstVisitorReturnType::MapLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_map_literal_node_to_string(&mut self, map_literal_node: &MapLiteralNode, output:&mut String) -> AstVisitorReturnType {
+
+        output.push_str("{");
+        let mut separator = "";
+        for (key_expr_t, value_expr_t) in &map_literal_node.entries {
+            output.push_str(separator);
+            key_expr_t.accept_to_string(self, output);
+            output.push_str(": ");
+            value_expr_t.accept_to_string(self, output);
+            separator = ",";
+        }
+        output.push_str("}");
+
+        AstVisitorReturnType::MapLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_expression_list_node(&mut self, expr_list: &ExprListNode) -> AstVisitorReturnType {
 
         let mut separator = "";
@@ -1974,10 +3019,10 @@ impl AstVisitor for PythonVisitor {
                 output.push_str(&format!("\"{}\"", literal_expression_node.value));
             },
             TokenType::TrueTok => {
-                output.push_str("true");
+                output.push_str("True");
             },
             TokenType::FalseTok => {
-                output.push_str("false");
+                output.push_str("False");
             },
             TokenType::NilTok => {
                 output.push_str("None");
@@ -2034,6 +3079,7 @@ impl AstVisitor for PythonVisitor {
         match state_stack_op_statement_node.state_stack_operation_node.operation_t {
             StateStackOperationType::Push => {
                 self.newline();
+                self.generate_state_stack_overflow_check();
                 if self.generate_state_context {
                     self.add_code(&format!("self._stateStack_push_(self._stateContext_)"));
                 } else {
@@ -2052,6 +3098,25 @@ impl AstVisitor for PythonVisitor {
     }
     //* --------------------------------------------------------------------- *//
 
+    fn visit_raise_statement_node(&mut self, raise_statement_node:&RaiseStatementNode) -> AstVisitorReturnType {
+        self.newline();
+        self.add_code(&format!("self.{}", raise_statement_node.interface_method_name));
+        raise_statement_node.call_expr_list_node.accept(self);
+
+        AstVisitorReturnType::RaiseStatementNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_forward_statement_node(&mut self, forward_statement_node:&ForwardStatementNode) -> AstVisitorReturnType {
+        self.newline();
+        self.add_code(&format!("self._s{}_(e)", forward_statement_node.target_state_ref.name));
+
+        AstVisitorReturnType::ForwardStatementNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_state_context_node(&mut self, _state_context_node:&StateContextNode) -> AstVisitorReturnType {
 
         // TODO
@@ -2100,9 +3165,26 @@ impl AstVisitor for PythonVisitor {
         self.newline();
 
         let action_name = self.format_action_name(&action_decl_node.name);
+
+        if action_decl_node.has_frame_body() {
+            self.add_code(&format!("def {}(self", action_name));
+            if let Some(params) = &action_decl_node.params {
+                self.add_code(",");
+                self.format_parameter_list(params);
+            }
+            self.add_code("):");
+            self.indent();
+            self.generate_frame_body(&action_decl_node.statements, action_decl_node.get_return_expr_opt());
+            self.outdent();
+            self.newline();
+            return AstVisitorReturnType::ActionDeclNode {};
+        }
+
+        self.add_code("@abstractmethod");
+        self.newline();
         self.add_code(&format!("def {}(self", action_name));
         self.newline_to_string(&mut subclass_code);
-        subclass_code.push_str(&format!("#def {}(self", action_name));
+        subclass_code.push_str(&format!("def {}(self", action_name));
 
         match &action_decl_node.params {
             Some (params)
@@ -2118,9 +3200,9 @@ impl AstVisitor for PythonVisitor {
         subclass_code.push_str(&format!("):"));
         self.indent();
         self.newline();
-        self.add_code(&format!("raise NotImplementedError"));
+        self.add_code("pass");
         self.newline_to_string(&mut subclass_code);
-        subclass_code.push_str(&format!("#pass"));
+        subclass_code.push_str("pass");
         self.outdent();
         self.newline();
         self.subclass_code.push(subclass_code);
@@ -2136,6 +3218,51 @@ impl AstVisitor for PythonVisitor {
 
     //* --------------------------------------------------------------------- *//
 
+    fn visit_operations_block_node(&mut self, operations_block_node: &OperationsBlockNode) -> AstVisitorReturnType {
+        self.newline();
+        self.add_code("# ===================== Operations Block =================== #");
+        self.newline();
+
+        for operation_node_rcref in &operations_block_node.operations {
+            operation_node_rcref.borrow().accept(self);
+        }
+
+        AstVisitorReturnType::OperationsBlockNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_operation_node(&mut self, operation_node: &OperationNode) -> AstVisitorReturnType {
+
+        self.newline();
+        self.add_code(&format!("def {}(self", operation_node.name));
+
+        match &operation_node.params {
+            Some (params)
+                =>  {
+                    self.add_code(",");
+                    self.format_parameter_list(params);
+            },
+            None => {},
+        }
+
+        self.add_code("):");
+        self.indent();
+        match &operation_node.code_opt {
+            Some(code) => {
+                self.newline();
+                self.add_code(code);
+            },
+            None => self.generate_frame_body(&operation_node.statements, operation_node.get_return_expr_opt()),
+        }
+        self.outdent();
+        self.newline();
+
+        AstVisitorReturnType::OperationNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_domain_variable_decl_node(&mut self, variable_decl_node: &VariableDeclNode) -> AstVisitorReturnType {
 
         self.visit_variable_decl_node(variable_decl_node);
@@ -2154,6 +3281,9 @@ impl AstVisitor for PythonVisitor {
         var_init_expr.accept_to_string(self, &mut code);
         match &variable_decl_node.identifier_decl_scope {
             IdentifierDeclScope::DomainBlock => {
+                if self.domain_param_names.contains(var_name) {
+                    code = var_name.clone();
+                }
                 self.add_code( &format!("self.{} = {};",var_name, code));
             },
             IdentifierDeclScope::EventHandlerVar => {
@@ -2162,9 +3292,6 @@ impl AstVisitor for PythonVisitor {
             _ => panic!("Error - unexpected scope for variable declaration"),
         }
 
-        self.serialize.push(format!("\tbag.domain[\"{}\"] = {};",var_name,var_name));
-        self.deserialize.push(format!("\t{} = bag.domain[\"{}\"];",var_name,var_name));
-
         AstVisitorReturnType::VariableDeclNode {}
     }
 
@@ -2239,10 +3366,9 @@ impl AstVisitor for PythonVisitor {
 
     fn visit_unary_expr_node(&mut self, unary_expr_node: &UnaryExprNode) -> AstVisitorReturnType {
 
-        // TODO
-        //       self.generate_comment(assignment_expr_node.line);
-        unary_expr_node.operator.accept(self);
-        unary_expr_node.right_rcref.borrow().accept(self);
+        let mut output = String::new();
+        self.visit_unary_expr_node_to_string(unary_expr_node, &mut output);
+        self.add_code(&output);
 
         AstVisitorReturnType::UnaryExprNode {}
     }
@@ -2254,7 +3380,7 @@ impl AstVisitor for PythonVisitor {
         // TODO
         //       self.generate_comment(assignment_expr_node.line);
         unary_expr_node.operator.accept_to_string(self, output);
-        unary_expr_node.right_rcref.borrow().accept_to_string(self,output);
+        self.visit_operand_to_string(&unary_expr_node.operator, &unary_expr_node.right_rcref.borrow(), false, output);
 
         AstVisitorReturnType::UnaryExprNode {}
     }
@@ -2263,25 +3389,9 @@ impl AstVisitor for PythonVisitor {
 
     fn visit_binary_expr_node(&mut self, binary_expr_node: &BinaryExprNode) -> AstVisitorReturnType {
 
-        // TODO
-        //       self.generate_comment(assignment_expr_node.line);
-        if binary_expr_node.operator == OperatorType::LogicalXor {
-            self.add_code("((");
-            binary_expr_node.left_rcref.borrow().accept(self);
-            self.add_code(") and not (");
-            binary_expr_node.right_rcref.borrow().accept(self);
-            self.add_code(")) or (not (");
-            binary_expr_node.left_rcref.borrow().accept(self);
-            self.add_code(") and (");
-            binary_expr_node.right_rcref.borrow().accept(self);
-            self.add_code("))");
-
-        } else {
-            binary_expr_node.left_rcref.borrow().accept(self);
-            binary_expr_node.operator.accept(self);
-            binary_expr_node.right_rcref.borrow().accept(self);
-        }
-
+        let mut output = String::new();
+        self.visit_binary_expr_node_to_string(binary_expr_node, &mut output);
+        self.add_code(&output);
 
         AstVisitorReturnType::BinaryExprNode {}
     }
@@ -2290,26 +3400,58 @@ impl AstVisitor for PythonVisitor {
 
     fn visit_binary_expr_node_to_string(&mut self, binary_expr_node: &BinaryExprNode, output:&mut String) -> AstVisitorReturnType {
 
-        if binary_expr_node.operator == OperatorType::LogicalXor {
-            output.push_str("((");
-            binary_expr_node.left_rcref.borrow().accept_to_string(self,output);
-            output.push_str(") and not (");
-            binary_expr_node.right_rcref.borrow().accept_to_string(self,output);
-            output.push_str(")) or (not (");
-            binary_expr_node.left_rcref.borrow().accept_to_string(self,output);
-            output.push_str(") and (");
-            binary_expr_node.right_rcref.borrow().accept_to_string(self,output);
-            output.push_str("))");
+        let operator_type = &binary_expr_node.operator;
+        self.visit_operand_to_string(operator_type, &binary_expr_node.left_rcref.borrow(), true, output);
+        operator_type.accept_to_string(self, output);
+        self.visit_operand_to_string(operator_type, &binary_expr_node.right_rcref.borrow(), false, output);
+
+        AstVisitorReturnType::BinaryExprNode {}
+    }
+
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_conditional_expr_node(&mut self, conditional_expr_node: &ConditionalExprNode) -> AstVisitorReturnType {
 
+        // Python binds the true branch tighter than a conditional so a
+        // nested conditional there needs parentheses.
+        let true_expr_t = conditional_expr_node.true_expr_rcref.borrow();
+        if let ExprType::ConditionalExprT {..} = *true_expr_t {
+            self.add_code("(");
+            true_expr_t.accept(self);
+            self.add_code(")");
         } else {
-            binary_expr_node.left_rcref.borrow().accept_to_string(self,output);
-            binary_expr_node.operator.accept_to_string(self, output);
-            binary_expr_node.right_rcref.borrow().accept_to_string(self,output);
+            true_expr_t.accept(self);
         }
+        self.add_code(" if ");
+        conditional_expr_node.condition_rcref.borrow().accept(self);
+        self.add_code(" else ");
+        conditional_expr_node.false_expr_rcref.borrow().accept(self);
 
-        AstVisitorReturnType::BinaryExprNode {}
+        AstVisitorReturnType::ConditionalExprNode {}
     }
 
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_conditional_expr_node_to_string(&mut self, conditional_expr_node: &ConditionalExprNode, output:&mut String) -> AstVisitorReturnType {
+
+        // Python binds the true branch tighter than a conditional so a
+        // nested conditional there needs parentheses.
+        let true_expr_t = conditional_expr_node.true_expr_rcref.borrow();
+        if let ExprType::ConditionalExprT {..} = *true_expr_t {
+            output.push_str("(");
+            true_expr_t.accept_to_string(self, output);
+            output.push_str(")");
+        } else {
+            true_expr_t.accept_to_string(self, output);
+        }
+        output.push_str(" if ");
+        conditional_expr_node.condition_rcref.borrow().accept_to_string(self, output);
+        output.push_str(" else ");
+        conditional_expr_node.false_expr_rcref.borrow().accept_to_string(self, output);
+
+        AstVisitorReturnType::ConditionalExprNode {}
+    }
 
     //* --------------------------------------------------------------------- *//
 
@@ -2325,12 +3467,12 @@ impl AstVisitor for PythonVisitor {
             OperatorType::GreaterEqual => self.add_code(" >= "),
             OperatorType::Less => self.add_code(" < "),
             OperatorType::LessEqual => self.add_code(" <= "),
-            OperatorType::Not => self.add_code(" not "),
+            OperatorType::Not => self.add_code("not "),
             OperatorType::EqualEqual => self.add_code(" == "),
             OperatorType::NotEqual => self.add_code(" != "),
             OperatorType::LogicalAnd => self.add_code(" and "),
             OperatorType::LogicalOr => self.add_code(" or "),
-            OperatorType::LogicalXor => self.add_code(""),
+            OperatorType::LogicalXor => self.add_code(" != "),
         }
 
         AstVisitorReturnType::BinaryExprNode {}
@@ -2350,12 +3492,12 @@ impl AstVisitor for PythonVisitor {
             OperatorType::GreaterEqual => output.push_str(" >= "),
             OperatorType::Less => output.push_str(" < "),
             OperatorType::LessEqual => output.push_str(" <= "),
-            OperatorType::Not => output.push_str(" not "),
+            OperatorType::Not => output.push_str("not "),
             OperatorType::EqualEqual => output.push_str(" == "),
             OperatorType::NotEqual => output.push_str(" != "),
             OperatorType::LogicalAnd => output.push_str(" and "),
             OperatorType::LogicalOr => output.push_str(" or "),
-            OperatorType::LogicalXor => output.push_str(""),
+            OperatorType::LogicalXor => output.push_str(" != "),
         }
 
         AstVisitorReturnType::BinaryExprNode {}
@@ -80,19 +80,34 @@ pub struct RustVisitor {
     first_event_handler:bool,
     system_name:String,
     first_state_name:String,
-    serialize:Vec<String>,
-    deserialize:Vec<String>,
-//    subclass_code:Vec<String>,
+    subclass_code:Vec<String>,
+    type_params:Vec<String>,
+    type_args:String,
+    impl_generics:String,
+    impl_where_clause:String,
     warnings:Vec<String>,
     has_states:bool,
     errors:Vec<String>,
     visiting_call_chain_literal_variable:bool,
+    visiting_lvalue:bool,
     generate_exit_args:bool,
     generate_state_context:bool,
     generate_state_stack:bool,
     generate_change_state:bool,
     generate_transition_state:bool,
     current_message:String,
+    generate_timers:bool,
+    generate_event_queue:bool,
+    generate_deferred_events:bool,
+    generate_history:bool,
+    generate_regions:bool,
+    generate_final_states:bool,
+    generate_completions:bool,
+    pushed_state_names:Vec<String>,
+    domain_param_names:Vec<String>,
+    generate_start_enter:bool,
+    state_stack_max_opt:Option<usize>,
+    state_stack_underflow_opt:Option<StateStackUnderflowPolicy>,
 }
 
 impl RustVisitor {
@@ -122,19 +137,34 @@ impl RustVisitor {
             first_event_handler:true,
             system_name:String::new(),
             first_state_name:String::new(),
-            serialize:Vec::new(),
-            deserialize:Vec::new(),
             has_states:false,
             errors:Vec::new(),
- //           subclass_code:Vec::new(),
+            subclass_code:Vec::new(),
+            type_params:Vec::new(),
+            type_args:String::new(),
+            impl_generics:String::new(),
+            impl_where_clause:String::new(),
             warnings:Vec::new(),
             visiting_call_chain_literal_variable:false,
+            visiting_lvalue:false,
             generate_exit_args,
             generate_state_context,
             generate_state_stack,
             generate_change_state,
             generate_transition_state,
             current_message:String::new(),
+            generate_timers:false,
+            generate_event_queue:false,
+            generate_deferred_events:false,
+            generate_history:false,
+            generate_regions:false,
+            generate_final_states:false,
+            generate_completions:false,
+            pushed_state_names:Vec::new(),
+            domain_param_names:Vec::new(),
+            generate_start_enter:false,
+            state_stack_max_opt:None,
+            state_stack_underflow_opt:None,
         }
     }
 
@@ -295,6 +325,37 @@ impl RustVisitor {
 
     //* --------------------------------------------------------------------- *//
 
+    // Values whose type uses a system type parameter are cloned when
+    // read as the type argument may own its data. Event parameter getters
+    // already return a clone, enter args kept in the state context don't.
+
+    fn is_cloned_variable(&self, var_node:&VariableNode) -> bool {
+        if self.visiting_lvalue || var_node.id_node.is_reference {
+            return false;
+        }
+        let symbol_type_rcref = match &var_node.symbol_type_rcref_opt {
+            Some(symbol_type_rcref) => symbol_type_rcref,
+            None => return false,
+        };
+        let type_node_opt = match &*symbol_type_rcref.borrow() {
+            SymbolType::DomainVariableSymbolT {domain_variable_symbol_rcref} => domain_variable_symbol_rcref.borrow().var_type.clone(),
+            SymbolType::StateVariableSymbolT {state_variable_symbol_rcref} => state_variable_symbol_rcref.borrow().var_type.clone(),
+            SymbolType::EventHandlerVariableSymbolT {event_handler_variable_symbol_rcref} => event_handler_variable_symbol_rcref.borrow().var_type.clone(),
+            SymbolType::StateParamSymbolT {state_param_symbol_rcref} => state_param_symbol_rcref.borrow().param_type_opt.clone(),
+            SymbolType::EventHandlerParamSymbolT {event_handler_param_symbol_rcref}
+                if self.generate_state_context && self.config.enter_token == self.current_message
+                => event_handler_param_symbol_rcref.borrow().param_type_opt.clone(),
+            _ => None,
+        };
+        let mut type_params = Vec::new();
+        if let Some(type_node) = &type_node_opt {
+            type_node.collect_type_params(&mut type_params);
+        }
+        !type_params.is_empty()
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     // fn get_variable_type(&mut self, symbol_type:&SymbolType) -> String {
     //     let var_type = match &*symbol_type {
     //         DomainVariableSymbolT { domain_variable_symbol_rcref } => {
@@ -465,7 +526,7 @@ impl RustVisitor {
         for param in params {
             self.add_code(&format!("{}", separator));
             let param_type: String = match &param.param_type_opt {
-                Some(ret_type) => ret_type.get_type_str(),
+                Some(ret_type) => self.format_type(ret_type),
                 None => String::from("<?>"),
             };
             self.add_code(&format!("{}:{}", param.param_name, param_type));
@@ -481,7 +542,7 @@ impl RustVisitor {
             self.add_code(&format!("{}", separator));
 //            subclass_actions.push_str(&format!("{}", separator));
             let param_type: String = match &param.param_type_opt {
-                Some(ret_type) => ret_type.get_type_str(),
+                Some(ret_type) => self.format_type(ret_type),
                 None => String::from("<?>"),
             };
             self.add_code(&format!("{}:{}", param.param_name, param_type));
@@ -513,6 +574,64 @@ impl RustVisitor {
 
     //* --------------------------------------------------------------------- *//
 
+    // Actions without a Frame body or native code are left to the user and
+    // declared in the actions trait.
+
+    fn is_external_action(action_node:&ActionNode) -> bool {
+        !action_node.has_frame_body() && action_node.code_opt.is_none()
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // The trait impl for the system's external actions, written once to its
+    // own file so regenerating the system keeps the user's code.
+
+    pub fn get_actions_stub(&self) -> Option<String> {
+        if self.subclass_code.is_empty() {
+            return None;
+        }
+        let mut stub = String::new();
+        for line in self.subclass_code.iter() {
+            stub.push_str(line);
+            stub.push('\n');
+        }
+        Some(stub)
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // State argument and variable structs only take the type parameters
+    // their fields use as unused type parameters are an error in Rust.
+
+    fn format_used_type_args(&self, type_nodes:Vec<&TypeNode>) -> String {
+        let mut used_type_params = Vec::new();
+        for type_node in type_nodes {
+            type_node.collect_type_params(&mut used_type_params);
+        }
+        let type_params:Vec<String> = self.type_params.iter()
+            .filter(|type_param| used_type_params.contains(type_param))
+            .cloned().collect();
+        if type_params.is_empty() {
+            String::new()
+        } else {
+            format!("<{}>", type_params.join(", "))
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // The event enums of a generic system may not use all of its type
+    // parameters so they carry a marker variant that does.
+
+    fn generate_type_params_marker(&mut self) {
+        if !self.type_params.is_empty() {
+            self.newline();
+            self.add_code(&format!("_TypeParams(std::marker::PhantomData<({},)>),", self.type_params.join(", ")));
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn uppercase_first_letter(s: &str) -> String {
         // @TODO - not sure if this is a good idea or not
         // let mut c = s.chars();
@@ -606,6 +725,12 @@ impl RustVisitor {
                         StatementType::StateStackStmt {state_stack_operation_statement_node} => {
                             state_stack_operation_statement_node.accept(self);
                         },
+                        StatementType::RaiseStmt {raise_statement_node} => {
+                            raise_statement_node.accept(self);
+                        },
+                        StatementType::ForwardStmt {forward_statement_node} => {
+                            forward_statement_node.accept(self);
+                        },
                         StatementType::ChangeStateStmt {change_state_stmt} => {
                             change_state_stmt.accept(self);
                         },
@@ -620,6 +745,20 @@ impl RustVisitor {
     }
 
 
+    // Generates a Frame body for an operation or action.
+
+    fn generate_frame_body(&mut self, statements:&Vec<DeclOrStmtType>, return_expr_t_opt:Option<&ExprType>) {
+        self.visit_decl_stmts(statements);
+        if let Some(expr_t) = return_expr_t_opt {
+            self.newline();
+            self.add_code("return ");
+            expr_t.accept(self);
+            self.add_code(";");
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     //* --------------------------------------------------------------------- *//
 
     fn generate_machinery(&mut self, system_node: &SystemNode) {
@@ -641,15 +780,15 @@ impl RustVisitor {
                 self.newline();
                 if self.generate_state_context {
                     if self.generate_exit_args {
-                        self.add_code(&format!("fn transition(&mut self, new_state:FrameState,exit_args:Box<FrameParameters>, state_context_rc:Rc<RefCell<StateContext>>) {{"));
+                        self.add_code(&format!("fn transition(&mut self, new_state:FrameState{0},exit_args:Box<FrameParameters{0}>, state_context_rc:Rc<RefCell<StateContext{0}>>) {{", self.type_args));
                     } else {
-                        self.add_code(&format!("fn transition(&mut self, new_state:FrameState, state_context_rc:Rc<RefCell<StateContext>>) {{"));
+                        self.add_code(&format!("fn transition(&mut self, new_state:FrameState{0}, state_context_rc:Rc<RefCell<StateContext{0}>>) {{", self.type_args));
                     }
                 } else {
                     if self.generate_exit_args {
-                        self.add_code(&format!("fn transition(&mut self, new_state:FrameState,exit_args:Box<FrameParameters>) {{"));
+                        self.add_code(&format!("fn transition(&mut self, new_state:FrameState{0},exit_args:Box<FrameParameters{0}>) {{", self.type_args));
                     } else {
-                        self.add_code(&format!("fn transition(&mut self, new_state:FrameState) {{"));
+                        self.add_code(&format!("fn transition(&mut self, new_state:FrameState{}) {{", self.type_args));
                     }
                 }
                 self.indent();
@@ -662,6 +801,14 @@ impl RustVisitor {
                 self.newline();
                 self.add_code(&format!("(self.{})(self,&mut exit_event);",&self.config.state_var_name));
                 self.newline();
+                if self.generate_history {
+                    self.add_code("self.record_history();");
+                    self.newline();
+                }
+                if self.generate_timers {
+                    self.add_code(&format!("self.cancel_timer();"));
+                    self.newline();
+                }
                 self.add_code(&format!("self.{} = new_state;",&self.config.state_var_name));
                 self.newline();
                 if self.generate_state_context {
@@ -674,6 +821,17 @@ impl RustVisitor {
                     self.newline();
                 }
                 self.add_code(&format!("(self.{})(self,&mut enter_event);",&self.config.state_var_name));
+                if self.generate_deferred_events {
+                    self.newline();
+                    self.add_code("self.replay_deferred();");
+                }
+                if self.generate_final_states && !self.generate_regions {
+                    self.generate_finished_check();
+                }
+                if self.generate_completions {
+                    self.newline();
+                    self.add_code("self.complete();");
+                }
                 self.outdent();
                 self.newline();
                 self.add_code(&format!("}}"));
@@ -683,7 +841,7 @@ impl RustVisitor {
                 self.newline();
                 if self.generate_state_context {
                     self.newline();
-                    self.add_code(&format!("fn state_stack_push(&mut self,state_context_rc:Rc<RefCell<StateContext>>) {{"));
+                    self.add_code(&format!("fn state_stack_push(&mut self,state_context_rc:Rc<RefCell<StateContext{}>>) {{", self.type_args));
                     self.indent();
                     self.newline();
                     self.add_code(&format!("self.state_stack.push(state_context_rc);"));
@@ -692,7 +850,7 @@ impl RustVisitor {
                     self.add_code(&format!("}}"));
                     self.newline();
                     self.newline();
-                    self.add_code(&format!("fn state_stack_pop(&mut self) -> Rc<RefCell<StateContext>> {{"));
+                    self.add_code(&format!("fn state_stack_pop(&mut self) -> Rc<RefCell<StateContext{}>> {{", self.type_args));
                     self.indent();
                     self.newline();
                     self.add_code(&format!("let state_context_opt = self.state_stack.pop();"));
@@ -708,7 +866,7 @@ impl RustVisitor {
                     self.add_code("}");
                 } else {
                     self.newline();
-                    self.add_code(&format!("fn state_stack_push(&mut self,state:FrameState) {{"));
+                    self.add_code(&format!("fn state_stack_push(&mut self,state:FrameState{}) {{", self.type_args));
                     self.indent();
                     self.newline();
                     self.add_code(&format!("self.state_stack.push(Rc::new(RefCell::new(state)));"));
@@ -718,7 +876,7 @@ impl RustVisitor {
                     self.newline();
                     self.newline();
 
-                    self.add_code(&format!("fn state_stack_pop(&mut self) -> FrameState {{"));
+                    self.add_code(&format!("fn state_stack_pop(&mut self) -> FrameState{} {{", self.type_args));
                     self.indent();
                     self.newline();
                     self.add_code(&format!("let state_opt = self.state_stack.pop();"));
@@ -741,28 +899,71 @@ impl RustVisitor {
             if self.generate_change_state {
                 self.newline();
                 self.newline();
-                self.add_code(&format!("fn changeState(&mut self, new_state:FrameState) {{"));
+                if self.generate_state_context {
+                    self.add_code(&format!("fn changeState(&mut self, new_state:FrameState{0}, state_context_rc:Rc<RefCell<StateContext{0}>>) {{", self.type_args));
+                } else {
+                    self.add_code(&format!("fn changeState(&mut self, new_state:FrameState{}) {{", self.type_args));
+                }
                 self.indent();
                 self.newline();
+                if self.generate_history {
+                    self.add_code("self.record_history();");
+                    self.newline();
+                }
+                if self.generate_timers {
+                    self.add_code(&format!("self.cancel_timer();"));
+                    self.newline();
+                }
                 self.add_code(&format!("self.{} = new_state;",&self.config.state_var_name));
+                if self.generate_state_context {
+                    self.newline();
+                    self.add_code(&format!("self.{} = {}.clone();",&self.config.state_context_var_name,&self.config.state_context_var_name));
+                }
+                if self.generate_deferred_events {
+                    self.newline();
+                    self.add_code("self.replay_deferred();");
+                }
+                if self.generate_final_states && !self.generate_regions {
+                    self.generate_finished_check();
+                }
+                if self.generate_completions {
+                    self.newline();
+                    self.add_code("self.complete();");
+                }
 
 
                 self.outdent();
                 self.newline();
                 self.add_code(&format!("}}"));
             }
+            if self.generate_timers {
+                self.generate_timers(system_node);
+            }
+            if self.generate_event_queue {
+                self.generate_event_queue();
+            }
+            if self.generate_deferred_events {
+                self.generate_deferred_events();
+            }
+            if self.generate_history {
+                self.generate_history(system_node);
+            }
+            if self.generate_regions {
+                self.generate_regions();
+            }
+            if self.generate_final_states {
+                self.generate_final_states(system_node);
+            }
+            if self.generate_completions {
+                self.generate_completions(system_node);
+            }
+            if self.generate_start_enter {
+                self.generate_start();
+            }
             self.newline();
 
             if self.arcanium.is_serializable() {
-                for line in self.serialize.iter() {
-                    self.code.push_str(&*format!("{}",line));
-                    self.code.push_str(&*format!("\n{}",self.dent()));
-                }
-
-                for line in self.deserialize.iter() {
-                    self.code.push_str(&*format!("{}",line));
-                    self.code.push_str(&*format!("\n{}",self.dent()));
-                }
+                self.generate_serialization(system_node);
             }
         }
     }
@@ -778,98 +979,976 @@ impl RustVisitor {
 
     //* --------------------------------------------------------------------- *//
 
-    fn generate_comment(&mut self,line:usize) {
-
-        // can't use self.newline() or self.add_code() due to double borrow.
-        let mut generated_comment = false;
-        while self.current_comment_idx < self.comments.len() &&
-            line >= self.comments[self.current_comment_idx].line {
-            let comment = &self.comments[self.current_comment_idx];
-            if comment.token_type == TokenType::SingleLineCommentTok {
-                self.code.push_str(&*format!("  // {}",&comment.lexeme[3..]));
-                self.code.push_str(&*format!("\n{}",(0..self.dent).map(|_| "    ").collect::<String>()));
+    // A snapshot mirrors the JSON layout of the other targets. It is only
+    // compiled with the serde feature so systems that never snapshot do not
+    // depend on serde.
 
+    fn generate_snapshot_types(&mut self, system_node: &SystemNode) {
+        let domain_vars = system_node.get_domain_vars();
+        self.add_code("#[cfg(feature = \"serde\")]");
+        self.newline();
+        self.add_code("#[derive(serde::Serialize, serde::Deserialize)]");
+        self.newline();
+        self.add_code("#[serde(tag = \"state\")]");
+        self.newline();
+        self.add_code(&format!("enum {}StateSnapshot {{", self.system_name));
+        self.indent();
+        for state_node_rcref in system_node.get_states() {
+            let state_node = state_node_rcref.borrow();
+            for renamed_from in state_node.get_renamed_from() {
+                self.newline();
+                self.add_code(&format!("#[serde(alias = \"{}\")]", renamed_from));
+            }
+            self.newline();
+            if !self.generate_state_context || (state_node.params_opt.is_none() && state_node.vars_opt.is_none()) {
+                self.add_code(&format!("{} {{}},", state_node.name));
+                continue;
+            }
+            self.add_code(&format!("{} {{", state_node.name));
+            self.indent();
+            if state_node.params_opt.is_some() {
+                self.newline();
+                self.add_code("#[serde(rename = \"stateArgs\")]");
+                self.newline();
+                self.add_code(&format!("{}:{}StateArgs,", self.config.state_args_var, state_node.name));
+            }
+            if state_node.vars_opt.is_some() {
+                self.newline();
+                self.add_code("#[serde(rename = \"stateVars\")]");
+                self.newline();
+                self.add_code(&format!("{}:{}StateVars,", self.config.state_vars_var_name, state_node.name));
+            }
+            self.outdent();
+            self.newline();
+            self.add_code("},");
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        self.add_code("#[cfg(feature = \"serde\")]");
+        self.newline();
+        self.add_code("#[derive(serde::Serialize, serde::Deserialize)]");
+        self.newline();
+        self.add_code(&format!("struct {}DomainSnapshot {{", self.system_name));
+        self.indent();
+        for var_rcref in &domain_vars {
+            let var = var_rcref.borrow();
+            let var_type = match &var.type_opt {
+                Some(var_type) => self.format_type(var_type),
+                None => String::from("<?>"),
+            };
+            self.newline();
+            if var.has_snapshot_default() {
+                self.add_code("#[serde(default)]");
+                self.newline();
+                self.add_code(&format!("{}:Option<{}>,", var.name, var_type));
             } else {
-                let len = &comment.lexeme.len() - 3;
-                self.code.push_str(&*format!("/* {}",&comment.lexeme[3..len]));
-                self.code.push_str(&*format!("*/"));
-
+                self.add_code(&format!("{}:{},", var.name, var_type));
             }
-
-            self.current_comment_idx += 1;
-            generated_comment = true;
         }
-        if generated_comment {
-//            self.code.push_str(&*format!("\n{}",(0..self.dent).map(|_| "\t").collect::<String>()));
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        self.add_code("#[cfg(feature = \"serde\")]");
+        self.newline();
+        self.add_code("#[derive(serde::Serialize, serde::Deserialize)]");
+        self.newline();
+        self.add_code(&format!("struct {}Snapshot {{", self.system_name));
+        self.indent();
+        self.newline();
+        self.add_code("version:u32,");
+        self.newline();
+        self.add_code("hash:String,");
+        self.newline();
+        self.add_code(&format!("state:{}StateSnapshot,", self.system_name));
+        if self.generate_state_stack {
+            self.newline();
+            self.add_code("#[serde(rename = \"stateStack\")]");
+            self.newline();
+            self.add_code(&format!("state_stack:Vec<{}StateSnapshot>,", self.system_name));
         }
+        self.newline();
+        self.add_code(&format!("domain:{}DomainSnapshot,", self.system_name));
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
     }
 
     //* --------------------------------------------------------------------- *//
 
-    // TODO
-    fn generate_state_ref_change_state(&mut self, change_state_stmt_node: &ChangeStateStatementNode) {
-
-        let target_state_name = match &change_state_stmt_node.state_context_t {
-            StateContextType::StateRef {state_context_node}
-            => &state_context_node.state_ref_node.name,
-            _ => {
-                self.errors.push("Change state target not found.".to_string());
-                "error"
-            },
-        };
+    // The host owns the clock. The system asks its FrameScheduler to start
+    // or cancel a timer and the host calls timer_expired() when it fires.
 
+    fn generate_scheduler_trait(&mut self) {
+        self.add_code("pub trait FrameScheduler {");
+        self.indent();
+        self.newline();
+        self.add_code("fn schedule(&mut self, delay_ms:u64);");
+        self.newline();
+        self.add_code("fn cancel(&mut self);");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
         self.newline();
-        self.add_code(&format!("self.changeState({}::{});",
-                               self.system_name
-                               ,self.format_target_state_name(target_state_name)));
     }
 
     //* --------------------------------------------------------------------- *//
 
-    // fn generate_state_ref_code(&self, target_state_name:&str) -> String {
-    //     format!("{}",self.format_target_state_name(target_state_name))
-    // }
+    // Events sent while the machine is handling an event are queued and
+    // dispatched in order once the current event has been fully handled.
+    // dispatch() returns the event only if it was handled immediately.
+
+    fn generate_event_queue(&mut self) {
+        self.newline();
+        self.newline();
+        self.add_code(&format!("fn dispatch(&mut self, mut e:FrameEvent{0}) -> Option<FrameEvent{0}> {{", self.type_args));
+        self.indent();
+        self.newline();
+        self.add_code("if self.processing {");
+        self.indent();
+        self.newline();
+        self.add_code("self.event_queue.push_back(e);");
+        self.newline();
+        self.add_code("return None;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.add_code("self.processing = true;");
+        self.newline();
+        if self.generate_regions {
+            self.add_code("self.dispatch_regions(&mut e);");
+        } else {
+            self.add_code("(self.state)(self, &mut e);");
+        }
+        self.newline();
+        self.add_code("while let Some(mut queued) = self.event_queue.pop_front() {");
+        self.indent();
+        self.newline();
+        if self.generate_regions {
+            self.add_code("self.dispatch_regions(&mut queued);");
+        } else {
+            self.add_code("(self.state)(self, &mut queued);");
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.add_code("self.processing = false;");
+        self.newline();
+        self.add_code("Some(e)");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
 
     //* --------------------------------------------------------------------- *//
 
-    fn generate_state_ref_transition(&mut self, transition_statement: &TransitionStatementNode) {
+    // The machine is finished once it is in a #[final] state, or once
+    // every region is. The host is told through the on_finished callback.
 
+    fn generate_final_states(&mut self, system_node: &SystemNode) {
+        let final_states:Vec<String> = system_node.get_final_state_names().iter()
+            .map(|state_name| format!("state as usize == {}::{} as usize", system_node.name, self.format_state_name(state_name))).collect();
         self.newline();
-        self.add_code("// Start transition ");
         self.newline();
-        let target_state_name = match &transition_statement.target_state_context_t {
-            StateContextType::StateRef {state_context_node} => {
-                &state_context_node.state_ref_node.name
-            },
-            _ => {
-                self.errors.push("Unknown error.".to_string());
-                ""
-            },
-        };
-        match &transition_statement.label_opt {
-            Some(label) => {
-                self.add_code(&format!("// {}", label));
-                self.newline();
-            },
-            None => {},
+        self.add_code(&format!("fn is_final_state(state:FrameState{}) -> bool {{", self.type_args));
+        self.indent();
+        self.newline();
+        self.add_code(&final_states.join(" || "));
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        self.add_code("pub fn is_finished(&self) -> bool {");
+        self.indent();
+        self.newline();
+        if self.generate_regions {
+            self.add_code(&format!("self.regions.iter().all(|state| {}::is_final_state(*state))", system_node.name));
+        } else {
+            self.add_code(&format!("{}::is_final_state(self.state)", system_node.name));
         }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
 
-        if self.generate_state_context {
+    //* --------------------------------------------------------------------- *//
 
-            self.newline();
-        }
+    // Entering a final state completes the machine.
 
-        // -- Exit Arguments --
+    fn generate_finished_check(&mut self) {
+        self.newline();
+        self.add_code("if self.is_finished() {");
+        self.indent();
+        self.newline();
+        self.add_code("if let Some(on_finished) = self.on_finished.as_mut() {");
+        self.indent();
+        self.newline();
+        self.add_code("on_finished();");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
 
-        let mut has_exit_args = false;
-        if let Some(exit_args) = &transition_statement.exit_args_opt {
-            if exit_args.exprs_t.len() > 0 {
-                has_exit_args = true;
+    //* --------------------------------------------------------------------- *//
 
-                // Note - searching for event keyed with "State:<"
-                // e.g. "S1:<"
+    // The start state is entered on the first interface call rather than
+    // in new() so the system is fully set up before its actions run.
 
-                let mut msg: String = String::new();
+    fn generate_start(&mut self) {
+        self.newline();
+        self.newline();
+        self.add_code("fn start(&mut self) {");
+        self.indent();
+        self.newline();
+        self.add_code("self.started = true;");
+        self.newline();
+        self.add_code("let mut enter_event = FrameEvent::new(FrameMessage::Enter,None);");
+        self.newline();
+        self.add_code(&format!("(self.{})(self,&mut enter_event);", &self.config.state_var_name));
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Completion transitions are taken one after another in a loop rather
+    // than from inside the transition that entered the state, so long
+    // chains don't grow the stack.
+
+    fn generate_completions(&mut self, system_node: &SystemNode) {
+        self.newline();
+        self.newline();
+        self.add_code("fn complete(&mut self) {");
+        self.indent();
+        self.newline();
+        self.add_code("if self.completing {");
+        self.indent();
+        self.newline();
+        self.add_code("return;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.add_code("self.completing = true;");
+        self.newline();
+        self.add_code("while self.completion() {}");
+        self.newline();
+        self.add_code("self.completing = false;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        self.add_code("fn completion(&mut self) -> bool {");
+        self.indent();
+        if let Some(machine_block_node) = &system_node.machine_block_node_opt {
+            for state_node_rcref in &machine_block_node.states {
+                let state_node = state_node_rcref.borrow();
+                if state_node.completions.len() == 0 {
+                    continue;
+                }
+                self.current_state_name_opt = Some(state_node.name.clone());
+                self.newline();
+                self.add_code(&format!("if self.state as usize == {}::{} as usize {{", system_node.name, self.format_state_name(&state_node.name)));
+                self.indent();
+                for completion_node in &state_node.completions {
+                    if let Some(guard_expr_t) = &completion_node.guard_expr_t_opt {
+                        let mut guard = String::new();
+                        guard_expr_t.accept_to_string(self, &mut guard);
+                        self.newline();
+                        self.add_code(&format!("if {} {{", guard));
+                        self.indent();
+                    }
+                    completion_node.transition_statement_node.accept(self);
+                    self.newline();
+                    self.add_code("return true;");
+                    if completion_node.guard_expr_t_opt.is_some() {
+                        self.outdent();
+                        self.newline();
+                        self.add_code("}");
+                    }
+                }
+                self.outdent();
+                self.newline();
+                self.add_code("}");
+                self.current_state_name_opt = None;
+            }
+        }
+        self.newline();
+        self.add_code("false");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // serialize_snapshot() and deserialize_snapshot() back the |>>>| and
+    // |<<<| interface methods.
+
+    fn generate_serialization(&mut self, system_node: &SystemNode) {
+        let domain_vars = system_node.get_domain_vars();
+        let snapshot_name = format!("{}Snapshot", self.system_name);
+        let state_snapshot_name = format!("{}StateSnapshot", self.system_name);
+        let snapshot_version = system_node.get_snapshot_version();
+        let snapshot_hash = system_node.get_snapshot_hash();
+        self.newline();
+        self.newline();
+        self.add_code("#[cfg(feature = \"serde\")]");
+        self.newline();
+        self.add_code("fn serialize_snapshot(&self) -> String {");
+        self.indent();
+        self.newline();
+        self.add_code(&format!("let snapshot = {} {{", snapshot_name));
+        self.indent();
+        self.newline();
+        self.add_code(&format!("version:{},", snapshot_version));
+        self.newline();
+        self.add_code(&format!("hash:String::from(\"{}\"),", snapshot_hash));
+        self.newline();
+        if self.generate_state_context {
+            self.add_code(&format!("state:{}::serialize_state(&self.{}),", self.system_name, self.config.state_context_var_name));
+        } else {
+            self.add_code(&format!("state:{}::serialize_state(self.{}),", self.system_name, self.config.state_var_name));
+        }
+        if self.generate_state_stack {
+            self.newline();
+            if self.generate_state_context {
+                self.add_code(&format!("state_stack:self.{}.iter().map(|state_context_rc| {}::serialize_state(state_context_rc)).collect(),", self.config.state_stack_var_name, self.system_name));
+            } else {
+                self.add_code(&format!("state_stack:self.{}.iter().map(|state| {}::serialize_state(*state.borrow())).collect(),", self.config.state_stack_var_name, self.system_name));
+            }
+        }
+        self.newline();
+        self.add_code(&format!("domain:{}DomainSnapshot {{", self.system_name));
+        self.indent();
+        for var_rcref in &domain_vars {
+            let var = var_rcref.borrow();
+            self.newline();
+            if var.has_snapshot_default() {
+                self.add_code(&format!("{}:Some(self.{}.clone()),", var.name, var.name));
+            } else {
+                self.add_code(&format!("{}:self.{}.clone(),", var.name, var.name));
+            }
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("},");
+        self.outdent();
+        self.newline();
+        self.add_code("};");
+        self.newline();
+        self.add_code("serde_json::to_string(&snapshot).expect(\"Unable to serialize the snapshot\")");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        self.add_code("#[cfg(feature = \"serde\")]");
+        self.newline();
+        self.add_code("fn deserialize_snapshot(&mut self, data:&str) {");
+        self.indent();
+        self.newline();
+        self.add_code("let value:serde_json::Value = serde_json::from_str(data).expect(\"Invalid snapshot\");");
+        self.newline();
+        self.add_code("let version = value[\"version\"].as_u64().expect(\"Invalid snapshot\");");
+        self.newline();
+        self.add_code(&format!("if version > {} {{", snapshot_version));
+        self.indent();
+        self.newline();
+        self.add_code(&format!("panic!(\"Snapshot version {{}} is newer than version {} of {}\", version);", snapshot_version, system_node.name));
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.add_code(&format!("if version == {} && value[\"hash\"] != \"{}\" {{", snapshot_version, snapshot_hash));
+        self.indent();
+        self.newline();
+        self.add_code(&format!("panic!(\"Snapshot does not match version {} of {}\");", snapshot_version, system_node.name));
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.add_code(&format!("let snapshot:{} = serde_json::from_value(value).expect(\"Invalid snapshot\");", snapshot_name));
+        self.newline();
+        if self.generate_state_context {
+            self.add_code(&format!("self.{} = {}::deserialize_state(snapshot.state);", self.config.state_context_var_name, self.system_name));
+            self.newline();
+            self.add_code(&format!("self.{} = self.{}.borrow().getState();", self.config.state_var_name, self.config.state_context_var_name));
+        } else {
+            self.add_code(&format!("self.{} = {}::deserialize_state(snapshot.state);", self.config.state_var_name, self.system_name));
+        }
+        if self.generate_state_stack {
+            self.newline();
+            if self.generate_state_context {
+                self.add_code(&format!("self.{} = snapshot.state_stack.into_iter().map({}::deserialize_state).collect();", self.config.state_stack_var_name, self.system_name));
+            } else {
+                self.add_code(&format!("self.{} = snapshot.state_stack.into_iter().map(|state| Rc::new(RefCell::new({}::deserialize_state(state)))).collect();", self.config.state_stack_var_name, self.system_name));
+            }
+        }
+        if self.generate_start_enter {
+            self.newline();
+            self.add_code("self.started = true;");
+        }
+        for var_rcref in &domain_vars {
+            let var = var_rcref.borrow();
+            self.newline();
+            if var.has_snapshot_default() {
+                self.add_code(&format!("if let Some({}) = snapshot.domain.{} {{", var.name, var.name));
+                self.indent();
+                self.newline();
+                self.add_code(&format!("self.{} = {};", var.name, var.name));
+                self.outdent();
+                self.newline();
+                self.add_code("}");
+            } else {
+                self.add_code(&format!("self.{} = snapshot.domain.{};", var.name, var.name));
+            }
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        self.add_code("#[cfg(feature = \"serde\")]");
+        self.newline();
+        if self.generate_state_context {
+            self.add_code(&format!("fn serialize_state(state_context_rc:&Rc<RefCell<{}>>) -> {} {{", self.config.state_context_name, state_snapshot_name));
+            self.indent();
+            self.newline();
+            self.add_code("match &*state_context_rc.borrow() {");
+            self.indent();
+            for state_node_rcref in system_node.get_states() {
+                let state_node = state_node_rcref.borrow();
+                self.newline();
+                if state_node.params_opt.is_none() && state_node.vars_opt.is_none() {
+                    self.add_code(&format!("{}::{} {{..}} => {}::{} {{}},", self.config.state_context_name, state_node.name, state_snapshot_name, state_node.name));
+                    continue;
+                }
+                self.add_code(&format!("{}::{} {{{}}} => {}::{} {{", self.config.state_context_name, state_node.name, state_node.name, state_snapshot_name, state_node.name));
+                self.indent();
+                if state_node.params_opt.is_some() {
+                    self.newline();
+                    self.add_code(&format!("{}:{}.{}.clone(),", self.config.state_args_var, state_node.name, self.config.state_args_var));
+                }
+                if state_node.vars_opt.is_some() {
+                    self.newline();
+                    self.add_code(&format!("{}:{}.{}.clone(),", self.config.state_vars_var_name, state_node.name, self.config.state_vars_var_name));
+                }
+                self.outdent();
+                self.newline();
+                self.add_code("},");
+            }
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        } else {
+            self.add_code(&format!("fn serialize_state(state:{}) -> {} {{", self.config.frame_state_type_name, state_snapshot_name));
+            self.indent();
+            for state_node_rcref in system_node.get_states() {
+                let state_node = state_node_rcref.borrow();
+                self.newline();
+                self.add_code(&format!("if state as usize == {}::{} as usize {{", self.system_name, self.format_state_name(&state_node.name)));
+                self.indent();
+                self.newline();
+                self.add_code(&format!("return {}::{} {{}};", state_snapshot_name, state_node.name));
+                self.outdent();
+                self.newline();
+                self.add_code("}");
+            }
+            self.newline();
+            self.add_code("panic!(\"Unknown state\");");
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        self.add_code("#[cfg(feature = \"serde\")]");
+        self.newline();
+        if self.generate_state_context {
+            self.add_code(&format!("fn deserialize_state(snapshot:{}) -> Rc<RefCell<{}>> {{", state_snapshot_name, self.config.state_context_name));
+            self.indent();
+            self.newline();
+            self.add_code("let state_context = match snapshot {");
+            self.indent();
+            for state_node_rcref in system_node.get_states() {
+                let state_node = state_node_rcref.borrow();
+                let mut fields = Vec::new();
+                if state_node.params_opt.is_some() {
+                    fields.push(self.config.state_args_var.clone());
+                }
+                if state_node.vars_opt.is_some() {
+                    fields.push(self.config.state_vars_var_name.clone());
+                }
+                self.newline();
+                self.add_code(&format!("{}::{} {{{}}} => {}::{} {{", state_snapshot_name, state_node.name, fields.join(","), self.config.state_context_name, state_node.name));
+                self.indent();
+                self.newline();
+                self.add_code(&format!("{}:{} {{", state_node.name, self.format_state_context_struct_name(&state_node.name)));
+                self.indent();
+                self.newline();
+                self.add_code(&format!("state:{}::{},", self.system_name, self.format_state_name(&state_node.name)));
+                for field in &fields {
+                    self.newline();
+                    self.add_code(&format!("{},", field));
+                }
+                if self.has_enter_params(&state_node) {
+                    self.newline();
+                    self.add_code(&format!("{}:Default::default(),", self.config.enter_args_member_name));
+                }
+                self.outdent();
+                self.newline();
+                self.add_code("},");
+                self.outdent();
+                self.newline();
+                self.add_code("},");
+            }
+            self.outdent();
+            self.newline();
+            self.add_code("};");
+            self.newline();
+            self.add_code("Rc::new(RefCell::new(state_context))");
+        } else {
+            self.add_code(&format!("fn deserialize_state(snapshot:{}) -> {} {{", state_snapshot_name, self.config.frame_state_type_name));
+            self.indent();
+            self.newline();
+            self.add_code("match snapshot {");
+            self.indent();
+            for state_node_rcref in system_node.get_states() {
+                let state_node = state_node_rcref.borrow();
+                self.newline();
+                self.add_code(&format!("{}::{} {{}} => {}::{},", state_snapshot_name, state_node.name, self.system_name, self.format_state_name(&state_node.name)));
+            }
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn has_enter_params(&self, state_node:&StateNode) -> bool {
+        match &state_node.enter_event_handler_opt {
+            Some(enter_event_handler) => enter_event_handler.borrow().event_symbol_rcref.borrow().params_opt.is_some(),
+            None => false,
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Each region has its own active state. An event is dispatched to
+    // every region in turn with the region's state made current.
+
+    fn generate_regions(&mut self) {
+        self.newline();
+        self.newline();
+        self.add_code(&format!("fn dispatch_regions(&mut self, e:&mut {}{}) {{", self.config.frame_event_type_name, self.type_args));
+        self.indent();
+        if self.generate_final_states {
+            self.newline();
+            self.add_code("let was_finished = self.is_finished();");
+        }
+        self.newline();
+        self.add_code("for i in 0..self.regions.len() {");
+        self.indent();
+        self.newline();
+        self.add_code("self.state = self.regions[i];");
+        self.newline();
+        self.add_code("(self.state)(self, e);");
+        self.newline();
+        self.add_code("self.regions[i] = self.state;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        if self.generate_final_states {
+            self.newline();
+            self.add_code("if !was_finished && self.is_finished() {");
+            self.indent();
+            self.newline();
+            self.add_code("if let Some(on_finished) = self.on_finished.as_mut() {");
+            self.indent();
+            self.newline();
+            self.add_code("on_finished();");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // History is recorded on the way out of a state. Every ancestor that
+    // is a history target remembers the child it was left through and
+    // the leaf state that was active.
+
+    fn generate_history(&mut self, system_node: &SystemNode) {
+        self.newline();
+        self.newline();
+        self.add_code("fn record_history(&mut self) {");
+        self.indent();
+        let mut is_first = true;
+        if let Some(machine_block_node) = &system_node.machine_block_node_opt {
+            for state_node_rcref in &machine_block_node.states {
+                let state_node = state_node_rcref.borrow();
+                if state_node.history_records.len() == 0 {
+                    continue;
+                }
+                let state_ref = format!("{}::{}", self.system_name, self.format_state_name(&state_node.name));
+                self.newline();
+                if is_first {
+                    self.add_code(&format!("if self.state as usize == {} as usize {{", state_ref));
+                } else {
+                    self.add_code(&format!("}} else if self.state as usize == {} as usize {{", state_ref));
+                }
+                is_first = false;
+                self.indent();
+                for history_record in &state_node.history_records {
+                    let child_ref = format!("{}::{}", self.system_name, self.format_state_name(&history_record.child_state_name));
+                    self.newline();
+                    self.add_code(&format!("self.shallow_history.insert(String::from(\"{}\"), {});", history_record.parent_state_name, child_ref));
+                    self.newline();
+                    self.add_code(&format!("self.deep_history.insert(String::from(\"{}\"), {});", history_record.parent_state_name, state_ref));
+                }
+                self.outdent();
+            }
+        }
+        if !is_first {
+            self.newline();
+            self.add_code("}");
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        if self.generate_state_context {
+            self.generate_history_state_context(system_node);
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Builds the context of a state returned to through history. These
+    // states have no arguments or variables so only the state is set.
+
+    fn generate_history_state_context(&mut self, system_node: &SystemNode) {
+        let mut state_names:Vec<String> = Vec::new();
+        if let Some(machine_block_node) = &system_node.machine_block_node_opt {
+            for state_node_rcref in &machine_block_node.states {
+                let state_node = state_node_rcref.borrow();
+                let is_history_target = machine_block_node.states.iter().any(|other_state_node_rcref| {
+                    other_state_node_rcref.borrow().history_records.iter()
+                        .any(|history_record| history_record.parent_state_name == state_node.name)
+                });
+                if state_node.history_records.len() > 0 || is_history_target {
+                    state_names.push(state_node.name.clone());
+                }
+            }
+        }
+        self.newline();
+        self.newline();
+        self.add_code(&format!("fn history_state_context(&self, state:FrameState{0}) -> StateContext{0} {{", self.type_args));
+        self.indent();
+        for (i, state_name) in state_names.iter().enumerate() {
+            self.newline();
+            if i == state_names.len() - 1 {
+                self.add_code("} else {");
+            } else if i == 0 {
+                self.add_code(&format!("if state as usize == {}::{} as usize {{", self.system_name, self.format_state_name(state_name)));
+            } else {
+                self.add_code(&format!("}} else if state as usize == {}::{} as usize {{", self.system_name, self.format_state_name(state_name)));
+            }
+            self.indent();
+            self.newline();
+            self.add_code(&format!("StateContext::{0} {{{0}:{1} {{state}}}}", state_name, self.format_state_context_struct_name(state_name)));
+            self.outdent();
+        }
+        self.newline();
+        self.add_code("}");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Deferred events are replayed in the order they arrived. Any the
+    // new state defers again go back on the queue.
+
+    fn generate_deferred_events(&mut self) {
+        self.newline();
+        self.newline();
+        self.add_code("fn replay_deferred(&mut self) {");
+        self.indent();
+        self.newline();
+        self.add_code("let deferred = std::mem::take(&mut self.deferred_queue);");
+        self.newline();
+        self.add_code("for mut e in deferred {");
+        self.indent();
+        self.newline();
+        self.add_code("(self.state)(self, &mut e);");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // A deferred event is put aside rather than handled and is replayed
+    // after the next transition. FrameMessage isn't Clone so the event
+    // is rebuilt from its parameters.
+
+    fn generate_deferred_event_arms(&mut self, deferred_events:&Vec<String>) {
+        for msg in deferred_events {
+            let msg_enum = self.get_msg_enum(msg);
+            self.newline();
+            self.add_code(&format!("{}::{} => {{", self.config.frame_message, msg_enum));
+            self.indent();
+            self.newline();
+            self.add_code(&format!("let parameters = {}.parameters.take();", self.config.frame_event_variable_name));
+            self.newline();
+            self.add_code(&format!("self.deferred_queue.push({}::new({}::{},parameters));"
+                                   ,self.config.frame_event_type_name
+                                   ,self.config.frame_message
+                                   ,msg_enum));
+            self.newline();
+            self.add_code("return;");
+            self.outdent();
+            self.newline();
+            self.add_code("},");
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Only one timer is active at a time. It is cancelled when a state is
+    // exited so a timeout always belongs to the current state.
+
+    fn generate_timers(&mut self, system_node: &SystemNode) {
+        self.newline();
+        self.newline();
+        self.add_code("fn start_timer(&mut self, delay_ms:u64) {");
+        self.indent();
+        self.newline();
+        self.add_code("if self.timer_active {");
+        self.indent();
+        self.newline();
+        self.add_code("return;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.add_code("self.timer_active = true;");
+        self.newline();
+        self.add_code("self.scheduler.schedule(delay_ms);");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        self.add_code("fn cancel_timer(&mut self) {");
+        self.indent();
+        self.newline();
+        self.add_code("if self.timer_active {");
+        self.indent();
+        self.newline();
+        self.add_code("self.timer_active = false;");
+        self.newline();
+        self.add_code("self.scheduler.cancel();");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+        self.newline();
+        self.add_code("pub fn timer_expired(&mut self) {");
+        self.indent();
+        self.newline();
+        self.add_code("if self.timer_active {");
+        self.indent();
+        self.newline();
+        self.add_code("self.timer_active = false;");
+        self.newline();
+        if self.generate_event_queue {
+            self.add_code("let e = FrameEvent::new(FrameMessage::StateTimeout,None);");
+            self.newline();
+            self.add_code("self.dispatch(e);");
+        } else {
+            self.add_code("let mut e = FrameEvent::new(FrameMessage::StateTimeout,None);");
+            self.newline();
+            self.add_code("(self.state)(self,&mut e);");
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+
+        // The start state is never entered so new() starts its timer here.
+        if let Some(first_state_node_rcref) = system_node.get_first_state() {
+            let first_state_node = first_state_node_rcref.borrow();
+            if let Some(timeout_node) = &first_state_node.timeout_opt {
+                self.newline();
+                self.newline();
+                self.add_code("fn start_initial_timer(&mut self) {");
+                self.indent();
+                self.current_state_name_opt = Some(first_state_node.name.clone());
+                self.generate_timer_start(timeout_node);
+                self.current_state_name_opt = None;
+                self.outdent();
+                self.newline();
+                self.add_code("}");
+            }
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn generate_timer_start(&mut self, timeout_node:&TimeoutNode) {
+        let mut duration = String::new();
+        timeout_node.duration_expr_t.accept_to_string(self, &mut duration);
+        if timeout_node.duration_expr_t.get_operator_opt().is_some() {
+            duration = format!("({})", duration);
+        }
+        self.newline();
+        self.add_code(&format!("self.start_timer({} as u64);", duration));
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn generate_comment(&mut self,line:usize) {
+
+        // can't use self.newline() or self.add_code() due to double borrow.
+        let mut generated_comment = false;
+        while self.current_comment_idx < self.comments.len() &&
+            line >= self.comments[self.current_comment_idx].line {
+            let comment = &self.comments[self.current_comment_idx];
+            if comment.token_type == TokenType::SingleLineCommentTok {
+                self.code.push_str(&*format!("  // {}",&comment.lexeme[3..]));
+                self.code.push_str(&*format!("\n{}",(0..self.dent).map(|_| "    ").collect::<String>()));
+
+            } else {
+                let len = &comment.lexeme.len() - 3;
+                self.code.push_str(&*format!("/* {}",&comment.lexeme[3..len]));
+                self.code.push_str(&*format!("*/"));
+
+            }
+
+            self.current_comment_idx += 1;
+            generated_comment = true;
+        }
+        if generated_comment {
+//            self.code.push_str(&*format!("\n{}",(0..self.dent).map(|_| "\t").collect::<String>()));
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // TODO
+    fn generate_state_ref_change_state(&mut self, change_state_stmt_node: &ChangeStateStatementNode) {
+
+        let target_state_name = match &change_state_stmt_node.state_context_t {
+            StateContextType::StateRef {state_context_node}
+            => &state_context_node.state_ref_node.name,
+            _ => {
+                self.errors.push("Change state target not found.".to_string());
+                "error"
+            },
+        };
+
+        let target_state_args_opt = match &change_state_stmt_node.state_context_t {
+            StateContextType::StateRef { state_context_node}
+                => &state_context_node.state_ref_args_opt,
+            _ => &Option::None,
+        };
+
+        self.newline();
+        if self.generate_state_context {
+            self.generate_next_state_context(target_state_name, target_state_args_opt, None);
+            self.add_code(&format!("self.changeState({}::{},Rc::new(RefCell::new(next_state_context)));",
+                                   self.system_name
+                                   ,self.format_target_state_name(target_state_name)));
+        } else {
+            self.add_code(&format!("self.changeState({}::{});",
+                                   self.system_name
+                                   ,self.format_target_state_name(target_state_name)));
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // fn generate_state_ref_code(&self, target_state_name:&str) -> String {
+    //     format!("{}",self.format_target_state_name(target_state_name))
+    // }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn generate_state_ref_transition(&mut self, transition_statement: &TransitionStatementNode) {
+
+        self.newline();
+        self.add_code("// Start transition ");
+        self.newline();
+        let target_state_name = match &transition_statement.target_state_context_t {
+            StateContextType::StateRef {state_context_node} => {
+                &state_context_node.state_ref_node.name
+            },
+            _ => {
+                self.errors.push("Unknown error.".to_string());
+                ""
+            },
+        };
+        match &transition_statement.label_opt {
+            Some(label) => {
+                self.add_code(&format!("// {}", label));
+                self.newline();
+            },
+            None => {},
+        }
+
+        if self.generate_state_context {
+
+            self.newline();
+        }
+
+        // -- Exit Arguments --
+
+        let mut has_exit_args = false;
+        if let Some(exit_args) = &transition_statement.exit_args_opt {
+            if exit_args.exprs_t.len() > 0 {
+                has_exit_args = true;
+
+                // Note - searching for event keyed with "State:<"
+                // e.g. "S1:<"
+
+                let mut msg: String = String::new();
                 if let Some(state_name) = &self.current_state_name_opt {
                     msg = state_name.clone();
                 }
@@ -922,7 +2001,7 @@ impl RustVisitor {
         let enter_args_opt = match &transition_statement.target_state_context_t {
             StateContextType::StateRef { state_context_node}
                 => &state_context_node.enter_args_opt,
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..} | StateContextType::StateHistory {..}
                 => &None,
         };
 
@@ -960,22 +2039,70 @@ impl RustVisitor {
                     None => self.errors.push(format!("Invalid number of arguments for \"{}\" event handler.",msg)),
                 }
             } else {
-                self.warnings.push(format!("State {} does not have an enter event handler but is being passed parameters in a transition", target_state_name));
+                self.warnings.push(format!("State {} does not have an enter event handler but is being passed parameters in a transition", target_state_name));
+            }
+        }
+
+        formatted_enter_args.push_str("}");
+
+        // -- State Arguments --
+
+        let target_state_args_opt = match &transition_statement.target_state_context_t {
+            StateContextType::StateRef { state_context_node}
+                => &state_context_node.state_ref_args_opt,
+            StateContextType::StateStackPop {..} | StateContextType::StateHistory {..}
+                => &Option::None,
+        };
+
+        if self.generate_state_context {
+            let formatted_enter_args_opt = if has_enter_event_params {
+                Some(formatted_enter_args)
+            } else {
+                None
+            };
+            self.generate_next_state_context(target_state_name, target_state_args_opt, formatted_enter_args_opt);
+        }
+        let exit_args = if has_exit_args {
+            "exit_args"
+        } else {
+            "null"
+        };
+        if self.generate_state_context {
+            if self.generate_exit_args {
+                self.add_code(&format!("self.transition({}::{},{},Rc::new(RefCell::new(next_state_context)));"
+                                       ,self.system_name
+                                       ,self.format_target_state_name(target_state_name)
+                                       ,exit_args ));
+            } else {
+                self.add_code(&format!("self.transition({}::{},Rc::new(RefCell::new(next_state_context)));",
+                                       self.system_name
+                                       ,self.format_target_state_name(target_state_name)));
+            }
+        } else {
+            if self.generate_exit_args {
+                self.add_code(&format!("self.transition({}::{},{});"
+                                       ,self.system_name
+                                       ,self.format_target_state_name(target_state_name)
+                                       ,exit_args));
+            } else {
+                self.add_code(&format!("self.transition({}::{});"
+                                       ,self.system_name
+                                       ,self.format_target_state_name(target_state_name)));
             }
         }
+    }
 
-        formatted_enter_args.push_str("}");
+    //* --------------------------------------------------------------------- *//
+
+    // Declares next_state_context for the target state from its
+    // arguments, initialized state variables and any enter arguments.
+
+    fn generate_next_state_context(&mut self, target_state_name:&str, target_state_args_opt:&Option<ExprListNode>, formatted_enter_args_opt:Option<String>) {
 
         // -- State Arguments --
 
         let mut formatted_state_args = String::new();
         let mut has_state_args = false;
-        let target_state_args_opt = match &transition_statement.target_state_context_t {
-            StateContextType::StateRef { state_context_node}
-                => &state_context_node.state_ref_args_opt,
-            StateContextType::StateStackPop {}
-                => &Option::None,
-        };
 //
         if let Some(state_args) = target_state_args_opt {
 //            let mut params_copy = Vec::new();
@@ -1052,84 +2179,53 @@ impl RustVisitor {
             },
         }
 
-        if self.generate_state_context {
-
-            // generate state context enum
-            // let s0_stateContext = S0StateContext {
-            //     x:1
-            // };
-            // let state_context:StateContext = StateContext::S0 {
-            //     S0:s0_stateContext
-            // };
-
-            self.add_code(&format!("let {} = {} {{"
-                                   , self.format_state_context_variable_name(target_state_name)
-                                   , self.format_state_context_struct_name(target_state_name)
-            ));
-            self.indent();
-            self.newline();
-            self.add_code(&format!("state:{}::{},", &self.system_name, self.format_state_name(target_state_name)));
-            //        self.output_string_vec(&enter_arguments);
-            self.newline();
+        // generate state context enum
+        // let s0_stateContext = S0StateContext {
+        //     x:1
+        // };
+        // let state_context:StateContext = StateContext::S0 {
+        //     S0:s0_stateContext
+        // };
 
-            if has_state_args {
-                self.add_code(&format!("state_args:{},", formatted_state_args));
-            }
-            if has_state_vars {
-                self.newline();
-                self.add_code(&format!("state_vars:{},", formatted_state_vars));
-            }
-            if has_enter_event_params {
-                self.newline();
-                self.add_code(&format!("enter_args:{},", formatted_enter_args));
-            }
-            self.outdent();
-            self.newline();
-            self.add_code("};");
-            self.newline();
-            self.newline();
-            self.add_code(&format!("let next_state_context:StateContext = StateContext::{} {{"
-                                   , target_state_name));
-            self.indent();
-            self.newline();
-            self.add_code(&format!("{}:{}"
-                                   , &target_state_name
-                                   , self.format_state_context_variable_name(target_state_name)));
-            self.outdent();
-            self.newline();
+        self.add_code(&format!("let {} = {} {{"
+                               , self.format_state_context_variable_name(target_state_name)
+                               , self.format_state_context_struct_name(target_state_name)
+        ));
+        self.indent();
+        self.newline();
+        self.add_code(&format!("state:{}::{},", &self.system_name, self.format_state_name(target_state_name)));
+        //        self.output_string_vec(&enter_arguments);
+        self.newline();
 
-            self.add_code("};");
-            self.newline();
+        if has_state_args {
+            self.add_code(&format!("state_args:{},", formatted_state_args));
+        }
+        if has_state_vars {
             self.newline();
+            self.add_code(&format!("state_vars:{},", formatted_state_vars));
         }
-        let exit_args = if has_exit_args {
-            "exit_args"
-        } else {
-            "null"
-        };
-        if self.generate_state_context {
-            if self.generate_exit_args {
-                self.add_code(&format!("self.transition({}::{},{},Rc::new(RefCell::new(next_state_context)));"
-                                       ,self.system_name
-                                       ,self.format_target_state_name(target_state_name)
-                                       ,exit_args ));
-            } else {
-                self.add_code(&format!("self.transition({}::{},Rc::new(RefCell::new(next_state_context)));",
-                                       self.system_name
-                                       ,self.format_target_state_name(target_state_name)));
-            }
-        } else {
-            if self.generate_exit_args {
-                self.add_code(&format!("self.transition({}::{},{});"
-                                       ,self.system_name
-                                       ,self.format_target_state_name(target_state_name)
-                                       ,exit_args));
-            } else {
-                self.add_code(&format!("self.transition({}::{});"
-                                       ,self.system_name
-                                       ,self.format_target_state_name(target_state_name)));
-            }
+        if let Some(formatted_enter_args) = formatted_enter_args_opt {
+            self.newline();
+            self.add_code(&format!("enter_args:{},", formatted_enter_args));
         }
+        self.outdent();
+        self.newline();
+        self.add_code("};");
+        self.newline();
+        self.newline();
+        self.add_code(&format!("let next_state_context:StateContext{} = StateContext::{} {{"
+                               , self.type_args, target_state_name));
+        self.indent();
+        self.newline();
+        self.add_code(&format!("{}:{}"
+                               , &target_state_name
+                               , self.format_state_context_variable_name(target_state_name)));
+        self.outdent();
+        self.newline();
+
+        self.add_code("};");
+        self.newline();
+        self.newline();
     }
 
     //* --------------------------------------------------------------------- *//
@@ -1203,13 +2299,30 @@ impl RustVisitor {
             }
         }
 
-        if self.generate_state_context {
+        if let StateContextType::StateStackPop {..} = &transition_statement.target_state_context_t {
+            let exit_args = match &transition_statement.exit_args_opt {
+                Some(exit_args) if exit_args.exprs_t.len() > 0 => "exit_args",
+                _ => "null",
+            };
+            self.generate_state_stack_underflow_check(exit_args);
+        }
+        if let StateContextType::StateHistory {state_ref_node, is_deep} = &transition_statement.target_state_context_t {
+            let history_map = if *is_deep {"deep_history"} else {"shallow_history"};
+            self.add_code(&format!("let state = self.{}.get(\"{}\").copied().unwrap_or({}::{});", history_map, state_ref_node.name, self.system_name, self.format_state_name(&state_ref_node.name)));
+            if self.generate_state_context {
+                self.newline();
+                self.add_code("let state_context_rc = Rc::new(RefCell::new(self.history_state_context(state)));");
+            }
+        } else if self.generate_state_context {
             self.add_code(&format!("let state_context_rc = self.state_stack_pop();"));
             self.add_code(&format!("let state = state_context_rc.borrow().getState();"));
         } else {
             self.add_code(&format!("let state = self.state_stack_pop();"));
         }
         self.newline();
+        if let StateContextType::StateStackPop { enter_args_opt:Some(enter_args) } = &transition_statement.target_state_context_t {
+            self.generate_state_stack_pop_enter_args(enter_args);
+        }
         if self.generate_exit_args {
             if self.generate_state_context {
                 self.add_code(&format!("self.transition(state,exit_args,state_context_rc);"));
@@ -1224,6 +2337,233 @@ impl RustVisitor {
             }
         }
     }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Refuses to push past #![state_stack_max], tells the current state
+    // with a stateStackOverflow event and ends the handler so the
+    // transition after the push does not happen.
+
+    fn generate_state_stack_overflow_check(&mut self) {
+        let max = match self.state_stack_max_opt {
+            Some(max) => max,
+            None => return,
+        };
+        self.add_code(&format!("if self.state_stack.len() >= {} {{", max));
+        self.indent();
+        self.newline();
+        let message = self.symbol_config.state_stack_overflow_msg_symbol.clone();
+        self.generate_state_stack_error_event(&message);
+        self.newline();
+        self.add_code("return;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Applies the #![state_stack_underflow] policy when $$[-] finds the
+    // state stack empty.
+
+    fn generate_state_stack_underflow_check(&mut self, exit_args:&str) {
+        let underflow = match &self.state_stack_underflow_opt {
+            Some(underflow) => underflow.clone(),
+            None => return,
+        };
+        self.add_code("if self.state_stack.is_empty() {");
+        self.indent();
+        self.newline();
+        match underflow {
+            StateStackUnderflowPolicy::Error => {
+                let message = self.symbol_config.state_stack_underflow_msg_symbol.clone();
+                self.generate_state_stack_error_event(&message);
+                self.newline();
+            },
+            StateStackUnderflowPolicy::Stay => {},
+            StateStackUnderflowPolicy::State {state_name} => {
+                if self.generate_state_context {
+                    self.generate_next_state_context(&state_name, &None, None);
+                    if self.generate_exit_args {
+                        self.add_code(&format!("self.transition({}::{},{},Rc::new(RefCell::new(next_state_context)));"
+                                               ,self.system_name
+                                               ,self.format_target_state_name(&state_name)
+                                               ,exit_args));
+                    } else {
+                        self.add_code(&format!("self.transition({}::{},Rc::new(RefCell::new(next_state_context)));"
+                                               ,self.system_name
+                                               ,self.format_target_state_name(&state_name)));
+                    }
+                } else if self.generate_exit_args {
+                    self.add_code(&format!("self.transition({}::{},{});"
+                                           ,self.system_name
+                                           ,self.format_target_state_name(&state_name)
+                                           ,exit_args));
+                } else {
+                    self.add_code(&format!("self.transition({}::{});"
+                                           ,self.system_name
+                                           ,self.format_target_state_name(&state_name)));
+                }
+                self.newline();
+            },
+        }
+        self.add_code("return;");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Sends a state stack error event to the current state.
+
+    fn generate_state_stack_error_event(&mut self, message:&str) {
+        if self.generate_event_queue {
+            self.add_code(&format!("let e = FrameEvent::new(FrameMessage::{},None);", message));
+            self.newline();
+            self.add_code("self.dispatch(e);");
+        } else {
+            self.add_code(&format!("let mut e = FrameEvent::new(FrameMessage::{},None);", message));
+            self.newline();
+            self.add_code("(self.state)(self,&mut e);");
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // The state stack error events a bounded state stack can send that
+    // no state handles, so they still need a FrameMessage.
+
+    fn get_unhandled_state_stack_messages(&self, events:&Vec<String>) -> Vec<String> {
+        let mut messages = Vec::new();
+        if self.state_stack_max_opt.is_some() {
+            messages.push(self.symbol_config.state_stack_overflow_msg_symbol.clone());
+        }
+        if let Some(StateStackUnderflowPolicy::Error) = &self.state_stack_underflow_opt {
+            messages.push(self.symbol_config.state_stack_underflow_msg_symbol.clone());
+        }
+        messages.into_iter().filter(|message| !events.contains(message)).collect()
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Sets the enter arguments of a state stack pop on the popped state
+    // context. Every pushed state takes the same enter parameters so the
+    // first one names them.
+
+    fn generate_state_stack_pop_enter_args(&mut self, enter_args:&ExprListNode) {
+        let pushed_state_names = self.pushed_state_names.clone();
+        let mut msg = match pushed_state_names.first() {
+            Some(pushed_state_name) => pushed_state_name.clone(),
+            None => return,
+        };
+        msg.push_str(":");
+        msg.push_str(&self.symbol_config.enter_msg_symbol);
+
+        let mut formatted_enter_args = String::new();
+        if let Some(event_sym) = self.arcanium.get_event(&msg,&self.current_state_name_opt) {
+            if let Some(event_params) = &event_sym.borrow().params_opt {
+                for (p, expr_t) in event_params.iter().zip(enter_args.exprs_t.iter()) {
+                    let mut expr = String::new();
+                    expr_t.accept_to_string(self,&mut expr);
+                    formatted_enter_args.push_str(&format!("{}:{},", p.name, expr));
+                }
+            }
+        }
+
+        self.add_code(&format!("match &mut *{}.borrow_mut() {{", self.config.state_context_var_name));
+        self.indent();
+        for pushed_state_name in &pushed_state_names {
+            self.newline();
+            self.add_code(&format!("{}::{} {{{}}} => {}.{} = {}EnterArgs {{{}}},"
+                                   , self.config.state_context_name
+                                   , pushed_state_name
+                                   , pushed_state_name
+                                   , pushed_state_name
+                                   , self.config.enter_args_member_name
+                                   , pushed_state_name
+                                   , formatted_enter_args));
+        }
+        self.newline();
+        self.add_code("_ => {}");
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Maps portable Frame types to Rust. Other types are emitted as written.
+
+    fn format_type(&self, type_node:&TypeNode) -> String {
+        match type_node.get_frame_type_opt() {
+            Some(frame_type) => {
+                let mut s = String::new();
+                if type_node.is_reference() {
+                    s.push('&');
+                }
+                s.push_str(&self.format_frame_type(frame_type));
+                s
+            },
+            None => type_node.get_type_str(),
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn format_frame_type(&self, frame_type:&FrameType) -> String {
+        match frame_type {
+            FrameType::Int => String::from("i32"),
+            FrameType::Float => String::from("f64"),
+            FrameType::Bool => String::from("bool"),
+            FrameType::String => String::from("String"),
+            FrameType::List {element_type} => format!("Vec<{}>", self.format_frame_type(element_type)),
+            FrameType::Map {key_type, value_type} => format!("HashMap<{}, {}>", self.format_frame_type(key_type), self.format_frame_type(value_type)),
+            FrameType::TypeParam {name} => name.clone(),
+        }
+    }
+}
+
+//* --------------------------------------------------------------------- *//
+
+impl OperatorPrecedence for RustVisitor {
+
+    //* --------------------------------------------------------------------- *//
+
+    // Binding strength of each operator in Rust, higher binds tighter.
+    // Rust has a native xor ('^') on bools that binds tighter than comparisons.
+
+    fn operator_precedence(&self, operator_type:&OperatorType) -> usize {
+        match operator_type {
+            OperatorType::LogicalOr => 1,
+            OperatorType::LogicalAnd => 2,
+            OperatorType::EqualEqual
+            | OperatorType::NotEqual
+            | OperatorType::Greater
+            | OperatorType::GreaterEqual
+            | OperatorType::Less
+            | OperatorType::LessEqual => 3,
+            OperatorType::LogicalXor => 4,
+            OperatorType::Plus
+            | OperatorType::Minus => 5,
+            OperatorType::Multiply
+            | OperatorType::Divide => 6,
+            OperatorType::Not
+            | OperatorType::Negated => 7,
+        }
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    // Rust comparison operators are non-associative ('a == b == c' does
+    // not compile), so nested comparisons always need parentheses.
+
+    fn is_non_associative(&self, operator_type:&OperatorType) -> bool {
+        self.operator_precedence(operator_type) == 3
+    }
 }
 
 //* --------------------------------------------------------------------- *//
@@ -1234,6 +2574,29 @@ impl AstVisitor for RustVisitor {
 
     fn visit_system_node(&mut self, system_node: &SystemNode) -> AstVisitorReturnType {
         self.system_name = system_node.name.clone();
+        // A generic system's event and state types are generic over all of
+        // its type parameters. Values are cloned in and out of the system so
+        // the impls require Clone.
+        self.type_params = system_node.type_params.clone();
+        if !self.type_params.is_empty() {
+            self.type_args = format!("<{}>", self.type_params.join(", "));
+            let bounded_type_params:Vec<String> = self.type_params.iter()
+                .map(|type_param| format!("{}: Clone", type_param)).collect();
+            self.impl_generics = format!("<{}>", bounded_type_params.join(", "));
+            // The machine calls the external actions so it is only available
+            // for the type arguments the actions are implemented for.
+            let has_external_actions = match &system_node.actions_block_node_opt {
+                Some(actions_block_node) => actions_block_node.actions.iter()
+                    .any(|action_node_rcref| Self::is_external_action(&action_node_rcref.borrow())),
+                None => false,
+            };
+            if has_external_actions {
+                self.impl_where_clause = format!(" where Self: {}Actions{}", system_node.name, self.type_args);
+            }
+            if self.arcanium.is_serializable() {
+                self.errors.push(format!("Error - generic system {} can't be serialized in Rust.", system_node.name));
+            }
+        }
         self.add_code(&format!("// {}", self.compiler_version));
         self.newline();
         self.add_code(&system_node.header);
@@ -1245,6 +2608,23 @@ impl AstVisitor for RustVisitor {
         self.add_code("use std::cell::RefCell;");
         self.newline();
 
+        self.generate_timers = system_node.has_timeouts();
+        self.generate_event_queue = system_node.is_run_to_completion();
+        self.generate_deferred_events = system_node.has_deferred_events();
+        self.generate_history = system_node.has_history();
+        self.generate_regions = system_node.has_regions();
+        self.generate_final_states = system_node.get_final_state_names().len() > 0;
+        self.generate_completions = system_node.has_completions();
+        self.pushed_state_names = system_node.get_pushed_state_names();
+        self.domain_param_names = system_node.get_domain_param_names();
+        self.generate_start_enter = system_node.start_state_enter_params_opt.is_some();
+        self.state_stack_max_opt = system_node.get_state_stack_max();
+        self.state_stack_underflow_opt = system_node.get_state_stack_underflow();
+        if self.generate_event_queue {
+            self.add_code("use std::collections::VecDeque;");
+            self.newline();
+        }
+
         // self.newline();
         // self.add_code("// get include files at https://github.com/frame-lang/frame-ancillary-files");
         // self.newline();
@@ -1263,14 +2643,15 @@ impl AstVisitor for RustVisitor {
         self.newline();
         self.newline();
 
-        self.add_code(&format!("type FrameState = fn(&mut {}, &mut FrameEvent);", &system_node.name));
+        self.add_code(&format!("type FrameState{0} = fn(&mut {1}{0}, &mut FrameEvent{0});", self.type_args, &system_node.name));
 
         self.newline();
         self.newline();
-        self.add_code("enum FrameEventParameter {");
+        self.add_code(&format!("enum FrameEventParameter{} {{", self.type_args));
         self.indent();
         self.newline();
         self.add_code("None,");
+        self.generate_type_params_marker();
 
         let vec = self.arcanium.get_event_names();
         for unparsed_event_name in vec {
@@ -1284,7 +2665,7 @@ impl AstVisitor for RustVisitor {
                             Some(params) => {
                                 for param in params {
                                     let param_type = match &param.param_type_opt {
-                                        Some(param_type) => param_type.get_type_str(),
+                                        Some(param_type) => self.format_type(param_type),
                                         None => "<?>".to_string().clone(),
                                     };
                                     self.newline();
@@ -1308,17 +2689,18 @@ impl AstVisitor for RustVisitor {
         self.newline();
         self.newline();
 
-        self.add_code("enum FrameEventReturn {");
+        self.add_code(&format!("enum FrameEventReturn{} {{", self.type_args));
         self.indent();
         self.newline();
         self.add_code("None,");
+        self.generate_type_params_marker();
 
         if let Some(interface_block_node) = &system_node.interface_block_node_opt {
             for interface_method_node in &interface_block_node.interface_methods {
                 let if_name = interface_method_node.name.clone();
                 if let Some(return_type) = &interface_method_node.return_type_opt {
                     self.newline();
-                    self.add_code(&format!("{} {{return_type:{}}},", RustVisitor::uppercase_first_letter(&if_name), return_type.get_type_str()));
+                    self.add_code(&format!("{} {{return_type:{}}},", RustVisitor::uppercase_first_letter(&if_name), self.format_type(return_type)));
                 }
             }
         }
@@ -1328,7 +2710,7 @@ impl AstVisitor for RustVisitor {
         self.newline();
         self.newline();
 
-        self.add_code("impl FrameEventReturn {");
+        self.add_code(&format!("impl{} FrameEventReturn{} {{", self.impl_generics, self.type_args));
         self.indent();
         self.newline();
         if let Some(interface_block_node) = &system_node.interface_block_node_opt {
@@ -1337,7 +2719,7 @@ impl AstVisitor for RustVisitor {
                 if let Some(return_type) = &interface_method_node.return_type_opt {
                     self.newline();
                     self.newline();
-                    self.add_code(&format!("fn get_{}_ret(&self) -> {} {{",interface_method_node.name,return_type.get_type_str()));
+                    self.add_code(&format!("fn get_{}_ret(&self) -> {} {{",interface_method_node.name,self.format_type(return_type)));
                     self.indent();
                     self.newline();
                     self.add_code(&format!("match self {{"));
@@ -1361,23 +2743,23 @@ impl AstVisitor for RustVisitor {
 
         self.newline();
         self.newline();
-        self.add_code(&format!("struct {} {{",self.config.frame_event_type_name));
+        self.add_code(&format!("struct {}{} {{",self.config.frame_event_type_name, self.type_args));
         self.indent();
         self.newline();
         self.add_code("message: FrameMessage,");
         self.newline();
-        self.add_code("parameters:Option<Box<FrameParameters>>,");
+        self.add_code(&format!("parameters:Option<Box<FrameParameters{}>>,", self.type_args));
         self.newline();
-        self.add_code("ret:FrameEventReturn,");
+        self.add_code(&format!("ret:FrameEventReturn{},", self.type_args));
         self.outdent();
         self.newline();
         self.add_code("}");
         self.newline();
         self.newline();
-        self.add_code(&format!("impl {} {{",self.config.frame_event_type_name));
+        self.add_code(&format!("impl{} {}{} {{", self.impl_generics, self.config.frame_event_type_name, self.type_args));
         self.indent();
         self.newline();
-        self.add_code("fn new(message:FrameMessage, parameters:Option<Box<FrameParameters>>) -> FrameEvent {");
+        self.add_code(&format!("fn new(message:FrameMessage, parameters:Option<Box<FrameParameters{0}>>) -> FrameEvent{0} {{", self.type_args));
         self.indent();
         self.newline();
         self.add_code(&format!("{} {{",self.config.frame_event_type_name));
@@ -1413,17 +2795,23 @@ impl AstVisitor for RustVisitor {
 
                     // generate state parameter declarations
                     let mut has_state_args = false;
+                    let mut state_args_type_args = String::new();
                     match &state_node.params_opt {
                         Some(params) => {
                             has_state_args = true;
+                            if self.arcanium.is_serializable() {
+                                self.add_code("#[cfg_attr(feature = \"serde\", derive(Clone, serde::Serialize, serde::Deserialize))]");
+                                self.newline();
+                            }
                             self.indent();
-                            self.add_code(&format!("struct {}StateArgs {{",state_node.name));
+                            state_args_type_args = self.format_used_type_args(params.iter().filter_map(|param| param.param_type_opt.as_ref()).collect());
+                            self.add_code(&format!("struct {}StateArgs{} {{",state_node.name,state_args_type_args));
 
                             for param in params {
 
                                 let param_type = match &param.param_type_opt {
                                     Some(param_type) => {
-                                        param_type.get_type_str()
+                                        self.format_type(param_type)
                                     },
                                     None => String::from("<?>")
                                 };
@@ -1449,15 +2837,22 @@ impl AstVisitor for RustVisitor {
                     self.newline();
                     self.newline();
                     let mut has_state_vars = false;
+                    let mut state_vars_type_args = String::new();
                     match &state_node.vars_opt {
                         Some(var_decl_nodes) => {
                             has_state_vars = true;
-                            self.add_code(&format!("struct {}StateVars {{", state_node.name));
+                            if self.arcanium.is_serializable() {
+                                self.add_code("#[cfg_attr(feature = \"serde\", derive(Clone, serde::Serialize, serde::Deserialize))]");
+                                self.newline();
+                            }
+                            let var_decl_nodes_ref:Vec<_> = var_decl_nodes.iter().map(|var_decl_node| var_decl_node.borrow()).collect();
+                            state_vars_type_args = self.format_used_type_args(var_decl_nodes_ref.iter().filter_map(|var_decl_node| var_decl_node.type_opt.as_ref()).collect());
+                            self.add_code(&format!("struct {}StateVars{} {{", state_node.name, state_vars_type_args));
                             self.indent();
                             // self.add_code(&format!("{}: (",self.config.enter_arg_prefix));
                             for var_decl_node in var_decl_nodes {
                                 let var_type = match &var_decl_node.borrow().type_opt {
-                                    Some(var_type) => var_type.get_type_str(),
+                                    Some(var_type) => self.format_type(var_type),
                                     None => "<?>".to_string(),
                                 };
                                 self.newline();
@@ -1473,6 +2868,7 @@ impl AstVisitor for RustVisitor {
                     }
 
                     let mut has_enter_event_params = false;
+                    let mut enter_args_type_args = String::new();
                     match &state_node.enter_event_handler_opt {
                         Some(enter_event_handler) => {
                             let eeh_ref = &enter_event_handler.borrow();
@@ -1480,12 +2876,18 @@ impl AstVisitor for RustVisitor {
                             match &event_symbol.params_opt {
                                 Some(params) => {
                                     has_enter_event_params = true;
-                                    self.add_code(&format!("struct {}EnterArgs {{",state_node.name));
+                                    // Enter arguments are not part of a snapshot.
+                                    if self.arcanium.is_serializable() {
+                                        self.add_code("#[cfg_attr(feature = \"serde\", derive(Default))]");
+                                        self.newline();
+                                    }
+                                    enter_args_type_args = self.format_used_type_args(params.iter().filter_map(|param| param.param_type_opt.as_ref()).collect());
+                                    self.add_code(&format!("struct {}EnterArgs{} {{",state_node.name,enter_args_type_args));
                                     self.indent();
                                    // self.add_code(&format!("{}: (",self.config.enter_arg_prefix));
                                     for param in params {
                                         let param_type = match &param.param_type_opt {
-                                            Some(param_type) => param_type.get_type_str(),
+                                            Some(param_type) => self.format_type(param_type),
                                             None => "<?>".to_string(),
                                         };
                                         self.newline();
@@ -1506,25 +2908,25 @@ impl AstVisitor for RustVisitor {
 
 
                     // Generate state context struct per state
-                    self.add_code(&format!("struct {}{} {{",state_node.name,self.config.state_context_struct_name));
+                    self.add_code(&format!("struct {}{}{} {{",state_node.name,self.config.state_context_struct_name,self.type_args));
                     self.indent();
                     self.newline();
-                    self.add_code(&format!("state:{},",self.config.frame_state_type_name));
+                    self.add_code(&format!("state:{}{},",self.config.frame_state_type_name,self.type_args));
                     self.newline();
 
                     if has_state_args {
-                        self.add_code(&format!("{}:{}StateArgs,", self.config.state_args_var, state_node.name));
+                        self.add_code(&format!("{}:{}StateArgs{},", self.config.state_args_var, state_node.name, state_args_type_args));
                         self.newline();
                     }
 
                     if has_state_vars {
-                        self.add_code(&format!("{}:{}StateVars,", self.config.state_vars_var_name, state_node.name));
+                        self.add_code(&format!("{}:{}StateVars{},", self.config.state_vars_var_name, state_node.name, state_vars_type_args));
                         self.newline();
                     }
 
                     // generate enter event parameters
                     if has_enter_event_params {
-                        self.add_code(&format!("{}:{}EnterArgs,", self.config.enter_args_member_name, state_node.name));
+                        self.add_code(&format!("{}:{}EnterArgs{},", self.config.enter_args_member_name, state_node.name, enter_args_type_args));
                         self.newline();
                     }
                         // match &state_node.enter_event_handler_opt {
@@ -1564,12 +2966,12 @@ impl AstVisitor for RustVisitor {
                     self.newline();
                 }
 
-                self.add_code(&format!("enum {} {{",self.config.state_context_name));
+                self.add_code(&format!("enum {}{} {{",self.config.state_context_name,self.type_args));
                 self.indent();
                 for state in &machine_block_node.states {
                     self.newline();
                     let state_node = state.borrow();
-                    self.add_code(&format!("{} {{{}:{}{}}},",state_node.name,state_node.name,state_node.name,self.config.state_context_name))
+                    self.add_code(&format!("{} {{{}:{}{}{}}},",state_node.name,state_node.name,state_node.name,self.config.state_context_name,self.type_args))
                 }
                 self.outdent();
                 self.newline();
@@ -1577,10 +2979,10 @@ impl AstVisitor for RustVisitor {
                 self.newline();
 
                 self.newline();
-                self.add_code(&format!("impl {} {{",self.config.state_context_name));
+                self.add_code(&format!("impl{} {}{} {{",self.impl_generics,self.config.state_context_name,self.type_args));
                 self.indent();
                 self.newline();
-                self.add_code(&format!("fn getState(&self) -> {} {{",self.config.frame_state_type_name));
+                self.add_code(&format!("fn getState(&self) -> {}{} {{",self.config.frame_state_type_name,self.type_args));
                 self.indent();
                 self.newline();
                 self.add_code("match self {");
@@ -1637,35 +3039,89 @@ impl AstVisitor for RustVisitor {
             // self.newline();
         }
 
-
-        if let Some(actions_block_node) = &system_node.actions_block_node_opt {
-            actions_block_node.accept_rust_trait(self);
+
+        if let Some(actions_block_node) = &system_node.actions_block_node_opt {
+            actions_block_node.accept_rust_trait(self);
+        }
+        self.newline();
+
+        if self.generate_timers {
+            self.generate_scheduler_trait();
+        }
+
+        if self.arcanium.is_serializable() {
+            self.generate_snapshot_types(system_node);
+        }
+
+        self.add_code("// System Controller ");
+        self.newline();
+        self.newline();
+        self.add_code(&format!("pub struct {}{} {{", self.system_name, self.type_args));
+        self.indent();
+        self.newline();
+
+        // generate state variable
+        self.add_code(&format!("{}:{}{},",&self.config.state_var_name, self.config.frame_state_type_name, self.type_args));
+
+        if self.generate_timers {
+            self.newline();
+            self.add_code("scheduler:Box<dyn FrameScheduler>,");
+            self.newline();
+            self.add_code("timer_active:bool,");
+        }
+
+        if self.generate_event_queue {
+            self.newline();
+            self.add_code(&format!("event_queue:VecDeque<{}{}>,", self.config.frame_event_type_name, self.type_args));
+            self.newline();
+            self.add_code("processing:bool,");
+        }
+
+        if self.generate_deferred_events {
+            self.newline();
+            self.add_code(&format!("deferred_queue:Vec<{}{}>,", self.config.frame_event_type_name, self.type_args));
+        }
+
+        if self.generate_history {
+            self.newline();
+            self.add_code(&format!("shallow_history:HashMap<String,FrameState{}>,", self.type_args));
+            self.newline();
+            self.add_code(&format!("deep_history:HashMap<String,FrameState{}>,", self.type_args));
+        }
+
+        if self.generate_regions {
+            self.newline();
+            self.add_code(&format!("regions:Vec<FrameState{}>,", self.type_args));
+        }
+
+        if self.generate_final_states {
+            self.newline();
+            self.add_code("pub on_finished:Option<Box<dyn FnMut()>>,");
         }
-        self.newline();
 
-        self.add_code("// System Controller ");
-        self.newline();
-        self.newline();
-        self.add_code(&format!("pub struct {} {{", self.system_name));
-        self.indent();
-        self.newline();
+        if self.generate_completions {
+            self.newline();
+            self.add_code("completing:bool,");
+        }
 
-        // generate state variable
-        self.add_code(&format!("{}:{},",&self.config.state_var_name, self.config.frame_state_type_name));
+        if self.generate_start_enter {
+            self.newline();
+            self.add_code("started:bool,");
+        }
 
         // generate state context variable
 
         if self.generate_state_context {
             self.newline();
-            self.add_code(&format!("{}:Rc<RefCell<{}>>,",self.config.state_context_var_name, self.config.state_context_struct_name));
+            self.add_code(&format!("{}:Rc<RefCell<{}{}>>,",self.config.state_context_var_name, self.config.state_context_struct_name, self.type_args));
             if self.generate_state_stack {
                 self.newline();
-                self.add_code(&format!("{}:Vec<Rc<RefCell<{}>>>,",self.config.state_stack_var_name,self.config.state_context_name));
+                self.add_code(&format!("{}:Vec<Rc<RefCell<{}{}>>>,",self.config.state_stack_var_name,self.config.state_context_name,self.type_args));
             }
         } else {
             if self.generate_state_stack {
                 self.newline();
-                self.add_code(&format!("{}:Vec<Rc<RefCell<{}>>>,",self.config.state_stack_var_name,self.config.frame_state_type_name));
+                self.add_code(&format!("{}:Vec<Rc<RefCell<{}{}>>>,",self.config.state_stack_var_name,self.config.frame_state_type_name,self.type_args));
             }
         }
 
@@ -1681,7 +3137,7 @@ impl AstVisitor for RustVisitor {
         self.newline();
         self.newline();
 
-        self.add_code(&format!("impl {} {{", system_node.name));
+        self.add_code(&format!("impl{} {}{}{} {{", self.impl_generics, system_node.name, self.type_args, self.impl_where_clause));
         self.indent();
         self.newline();
 
@@ -1697,8 +3153,25 @@ impl AstVisitor for RustVisitor {
 
         // generate constructor
 
+        let has_initial_timer = match system_node.get_first_state() {
+            Some(first_state_node_rcref) => first_state_node_rcref.borrow().timeout_opt.is_some(),
+            None => false,
+        };
+
         if self.has_states {
-            self.add_code(&format!("pub fn new() -> {} {{",system_node.name));
+            let mut constructor_params:Vec<String> = system_node.get_system_params().iter()
+                .map(|param| {
+                    let param_type = match &param.param_type_opt {
+                        Some(param_type) => self.format_type(param_type),
+                        None => String::from("<?>"),
+                    };
+                    format!("{}:{}", param.param_name, param_type)
+                })
+                .collect();
+            if self.generate_timers {
+                constructor_params.push(String::from("scheduler:Box<dyn FrameScheduler>"));
+            }
+            self.add_code(&format!("pub fn new({}) -> {}{} {{", constructor_params.join(", "), system_node.name, self.type_args));
             self.indent();
             self.newline();
             if self.generate_state_context {
@@ -1710,14 +3183,25 @@ impl AstVisitor for RustVisitor {
                 self.newline();
                 self.add_code(&format!("state:{}::{},",&self.system_name,self.format_state_name(&self.first_state_name)));
                 //              self.output_string_vec(&enter_arguments);
+                if let Some(params) = &system_node.start_state_state_params_opt {
+                    let state_args:Vec<String> = params.iter().map(|param| format!("{}:{},", param.param_name, param.param_name)).collect();
+                    self.newline();
+                    self.add_code(&format!("state_args:{}StateArgs {{{}}},", self.first_state_name, state_args.join("")));
+                }
+                // The start state is entered with these on the first event.
+                if let Some(params) = &system_node.start_state_enter_params_opt {
+                    let enter_args:Vec<String> = params.iter().map(|param| format!("{}:{},", param.param_name, param.param_name)).collect();
+                    self.newline();
+                    self.add_code(&format!("{}:{}EnterArgs {{{}}},", self.config.enter_args_member_name, self.first_state_name, enter_args.join("")));
+                }
 
                 self.outdent();
                 self.newline();
                 self.add_code("};");
                 self.newline();
                 self.newline();
-                self.add_code(&format!("let state_context:StateContext = StateContext::{} {{"
-                                       , &self.first_state_name));
+                self.add_code(&format!("let state_context:StateContext{} = StateContext::{} {{"
+                                       , self.type_args, &self.first_state_name));
                 self.indent();
                 self.newline();
                 self.add_code(&format!("{}:{}"
@@ -1732,11 +3216,63 @@ impl AstVisitor for RustVisitor {
             }
 
             self.newline();
-            self.add_code(&format!("{} {{", system_node.name));
+            if has_initial_timer {
+                self.add_code(&format!("let mut system = {} {{", system_node.name));
+            } else {
+                self.add_code(&format!("{} {{", system_node.name));
+            }
             self.indent();
             self.newline();
             self.add_code(&format!("{}:{}::{},",&self.config.state_var_name, system_node.name, self.format_state_name(&self.first_state_name)));
 
+            if self.generate_timers {
+                self.newline();
+                self.add_code("scheduler,");
+                self.newline();
+                self.add_code("timer_active:false,");
+            }
+
+            if self.generate_event_queue {
+                self.newline();
+                self.add_code("event_queue:VecDeque::new(),");
+                self.newline();
+                self.add_code("processing:false,");
+            }
+
+            if self.generate_deferred_events {
+                self.newline();
+                self.add_code("deferred_queue:Vec::new(),");
+            }
+
+            if self.generate_history {
+                self.newline();
+                self.add_code("shallow_history:HashMap::new(),");
+                self.newline();
+                self.add_code("deep_history:HashMap::new(),");
+            }
+
+            if self.generate_regions {
+                let region_states:Vec<String> = system_node.get_region_initial_state_names().iter()
+                    .map(|state_name| format!("{}::{}", system_node.name, self.format_state_name(state_name))).collect();
+                self.newline();
+                self.add_code(&format!("regions:vec![{}],", region_states.join(", ")));
+            }
+
+            if self.generate_final_states {
+                self.newline();
+                self.add_code("on_finished:None,");
+            }
+
+            if self.generate_completions {
+                self.newline();
+                self.add_code("completing:false,");
+            }
+
+            if self.generate_start_enter {
+                self.newline();
+                self.add_code("started:false,");
+            }
+
             // generate history mechanism
             if self.generate_state_stack {
                 self.newline();
@@ -1750,7 +3286,11 @@ impl AstVisitor for RustVisitor {
 
                     let var_init_expr = &variable_decl_node.initializer_expr_t_opt.as_ref().unwrap();
                     let mut code = String::new();
-                    var_init_expr.accept_to_string(self, &mut code);
+                    if self.domain_param_names.contains(&variable_decl_node.name) {
+                        code.push_str(&variable_decl_node.name);
+                    } else {
+                        var_init_expr.accept_to_string(self, &mut code);
+                    }
                     self.newline();
                     self.add_code(&format!("{}:{},",variable_decl_node.name,code));
                 }
@@ -1784,7 +3324,15 @@ impl AstVisitor for RustVisitor {
 
             self.outdent();
             self.newline();
-            self.add_code(&format!("}}"));
+            if has_initial_timer {
+                self.add_code("};");
+                self.newline();
+                self.add_code("system.start_initial_timer();");
+                self.newline();
+                self.add_code("system");
+            } else {
+                self.add_code(&format!("}}"));
+            }
             self.outdent();
             self.newline();
             self.add_code(&format!("}}"));
@@ -1793,14 +3341,6 @@ impl AstVisitor for RustVisitor {
 
         // end of generate constructor
 
-        self.serialize.push("".to_string());
-        self.serialize.push("Bag _serialize__do() {".to_string());
-
-        self.deserialize.push("".to_string());
-
-        // @TODO: _do needs to be configurable.
-        self.deserialize.push("void _deserialize__do(Bag data) {".to_string());
-
 
         // self.subclass_code.push("".to_string());
         // self.subclass_code.push("/********************\n".to_string());
@@ -1810,6 +3350,10 @@ impl AstVisitor for RustVisitor {
             interface_block_node.accept(self);
         }
 
+        if let Some(operations_block_node) = &system_node.operations_block_node_opt {
+            operations_block_node.accept(self);
+        }
+
         if let Some(machine_block_node) = &system_node.machine_block_node_opt {
             machine_block_node.accept(self);
         }
@@ -1817,14 +3361,6 @@ impl AstVisitor for RustVisitor {
         // self.subclass_code.push(format!("}}"));
         // self.subclass_code.push("\n********************/".to_string());
 
-        self.serialize.push("".to_string());
-        self.serialize.push("\treturn JSON.stringify(bag);".to_string());
-        self.serialize.push("}".to_string());
-        self.serialize.push("".to_string());
-
-        self.deserialize.push("".to_string());
-        self.deserialize.push("}".to_string());
-
         if self.has_states {
             self.generate_machinery(system_node);
         }
@@ -1858,8 +3394,16 @@ impl AstVisitor for RustVisitor {
         self.add_code("Enter,");
         self.newline();
         self.add_code("Exit,");
+        if self.generate_timers {
+            self.newline();
+            self.add_code("StateTimeout,");
+        }
 
         let events = self.arcanium.get_event_names();
+        for message in self.get_unhandled_state_stack_messages(&events) {
+            self.newline();
+            self.add_code(&format!("{},", message));
+        }
         for event in &events {
         //    ret.push(k.clone());
             if self.isEnterOrExitMessage(&event) {
@@ -1898,6 +3442,14 @@ impl AstVisitor for RustVisitor {
         self.add_code("FrameMessage::Enter => String::from(\"Enter\"),");
         self.newline();
         self.add_code("FrameMessage::Exit => String::from(\"Exit\"),");
+        if self.generate_timers {
+            self.newline();
+            self.add_code("FrameMessage::StateTimeout => String::from(\"StateTimeout\"),");
+        }
+        for message in self.get_unhandled_state_stack_messages(&events) {
+            self.newline();
+            self.add_code(&format!("FrameMessage::{} => String::from(\"{}\"),", message, message));
+        }
         for event in &events {
             //    ret.push(k.clone());
             if self.isEnterOrExitMessage(&event) {
@@ -1935,19 +3487,19 @@ impl AstVisitor for RustVisitor {
     fn visit_interface_parameters(&mut self, _interface_block_node: &InterfaceBlockNode) -> AstVisitorReturnType {
         self.newline();
         self.newline();
-        self.add_code("struct FrameParameters {");
+        self.add_code(&format!("struct FrameParameters{} {{", self.type_args));
         self.indent();
         self.newline();
-        self.add_code("parameters:HashMap<String, FrameEventParameter>");
+        self.add_code(&format!("parameters:HashMap<String, FrameEventParameter{}>", self.type_args));
         self.outdent();
         self.newline();
         self.add_code("}");
         self.newline();
         self.newline();
-        self.add_code("impl FrameParameters {");
+        self.add_code(&format!("impl{} FrameParameters{} {{", self.impl_generics, self.type_args));
         self.indent();
         self.newline();
-        self.add_code("fn new() -> FrameParameters {");
+        self.add_code(&format!("fn new() -> FrameParameters{} {{", self.type_args));
         self.indent();
         self.newline();
         self.add_code("FrameParameters {");
@@ -1971,7 +3523,7 @@ impl AstVisitor for RustVisitor {
                             Some(params) => {
                                 for param in params {
                                     let param_type = match &param.param_type_opt {
-                                        Some(param_type) => param_type.get_type_str(),
+                                        Some(param_type) => self.format_type(param_type),
                                         None => "<?>".to_string().clone(),
                                     };
                                     self.newline();
@@ -2253,6 +3805,13 @@ impl AstVisitor for RustVisitor {
         //     }
         // }
 
+        let alias_name_opt = interface_method_node.alias.as_ref().map(|alias| alias.name.clone());
+        let is_save = alias_name_opt.as_ref() == Some(&self.symbol_config.save_msg_symbol);
+        let is_restore = alias_name_opt.as_ref() == Some(&self.symbol_config.restore_msg_symbol);
+        if is_save || is_restore {
+            self.add_code("#[cfg(feature = \"serde\")]");
+            self.newline();
+        }
         self.add_code(&format!("pub fn {} (&mut self", interface_method_node.name));
 
         match &interface_method_node.params {
@@ -2264,12 +3823,28 @@ impl AstVisitor for RustVisitor {
         self.add_code(")");
         match &interface_method_node.return_type_opt {
             Some(return_type) => {
-                self.add_code(&format!(" -> {}",return_type.get_type_str()));
+                self.add_code(&format!(" -> {}",self.format_type(return_type)));
             },
             None => {}
         }
         self.add_code(" {");
         self.indent();
+        if is_restore {
+            if let Some(params) = &interface_method_node.params {
+                self.newline();
+                self.add_code(&format!("self.deserialize_snapshot(&{});", params[0].param_name));
+            }
+        }
+        if self.generate_start_enter {
+            self.newline();
+            self.add_code("if !self.started {");
+            self.indent();
+            self.newline();
+            self.add_code("self.start();");
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
         let params_param_code;
         // let mut frame_parameters = FrameParameters::new();
         // frame_parameters.set_toggle_msg(msg);
@@ -2300,7 +3875,9 @@ impl AstVisitor for RustVisitor {
 
         // self.indent();
         self.newline();
-        self.add_code(&format!("let mut e = {}::new({}::{},{});"
+        let e_binding = if self.generate_event_queue { "e" } else { "mut e" };
+        self.add_code(&format!("let {} = {}::new({}::{},{});"
+                                ,e_binding
                                 ,self.config.frame_event_type_name
                                 ,self.config.frame_message
                                 ,&interface_method_node.name
@@ -2312,9 +3889,24 @@ impl AstVisitor for RustVisitor {
         // self.newline();
         // self.add_code("};");
         self.newline();
-        self.add_code(&format!("(self.state)(self, &mut e);"));
+        if self.generate_event_queue {
+            // A queued event has not been handled yet so it has no return value.
+            if interface_method_node.return_type_opt.is_some() {
+                self.add_code(&format!("let e = self.dispatch(e).expect(\"{} can not return a value while another event is being handled\");", &interface_method_node.name));
+            } else {
+                self.add_code("self.dispatch(e);");
+            }
+        } else if self.generate_regions {
+            self.add_code("self.dispatch_regions(&mut e);");
+        } else {
+            self.add_code(&format!("(self.state)(self, &mut e);"));
+        }
 
         match &interface_method_node.return_type_opt {
+            Some(_) if is_save => {
+                self.newline();
+                self.add_code("self.serialize_snapshot()");
+            },
             Some(_return_type) => {
                 self.newline();
                 self.add_code("match e.ret {");
@@ -2324,7 +3916,14 @@ impl AstVisitor for RustVisitor {
                     ,self.config.frame_event_return
                     ,RustVisitor::uppercase_first_letter(&interface_method_node.name)));
                 self.newline();
-                self.add_code(&format!("_ => panic!(\"Bad return type for {}\"),",&interface_method_node.name));
+                match &interface_method_node.default_return_expr_opt {
+                    Some(default_return_expr_t) => {
+                        let mut default_return = String::new();
+                        default_return_expr_t.accept_to_string(self, &mut default_return);
+                        self.add_code(&format!("_ => {},", default_return));
+                    },
+                    None => self.add_code(&format!("_ => panic!(\"Bad return type for {}\"),",&interface_method_node.name)),
+                }
                 self.outdent();
                 self.newline();
                 self.add_code("}");
@@ -2348,29 +3947,11 @@ impl AstVisitor for RustVisitor {
         self.newline();
         self.add_code("//===================== Machine Block ===================//");
 
-        self.serialize.push("".to_string());
-        self.serialize.push("\tvar stateName = null;".to_string());
-
-        self.deserialize.push("".to_string());
-        self.deserialize.push("\tconst bag = JSON.parse(data);".to_string());
-        self.deserialize.push("".to_string());
-        self.deserialize.push("\tswitch (bag.state) {".to_string());
-
 
         for state_node_rcref in &machine_block_node.states {
             state_node_rcref.borrow().accept(self);
         }
 
-        self.serialize.push("".to_string());
-        self.serialize.push("\tvar bag = {".to_string());
-        self.serialize.push("\t\tstate : stateName,".to_string());
-        self.serialize.push("\t\tdomain : {}".to_string());
-        self.serialize.push("\t};".to_string());
-        self.serialize.push("".to_string());
-
-        self.deserialize.push("\t}".to_string());
-        self.deserialize.push("".to_string());
-
 
         AstVisitorReturnType::MachineBlockNode {}
     }
@@ -2407,13 +3988,19 @@ impl AstVisitor for RustVisitor {
     //* --------------------------------------------------------------------- *//
 
     fn visit_action_node_rust_trait(&mut self, actions_block_node: &ActionsBlockNode) -> AstVisitorReturnType {
+        if !actions_block_node.actions.iter().any(|action_node_rcref| Self::is_external_action(&action_node_rcref.borrow())) {
+            return AstVisitorReturnType::ActionBlockNode {};
+        }
+
         self.newline();
-        self.add_code(&format!("trait {}Actions {{ ",self.system_name));
+        self.add_code(&format!("trait {}Actions{} {{ ",self.system_name,self.type_args));
         self.indent();
 
         for action_decl_node_rcref in &actions_block_node.actions {
             let action_decl_node = action_decl_node_rcref.borrow();
-            action_decl_node.accept(self);
+            if Self::is_external_action(&action_decl_node) {
+                action_decl_node.accept(self);
+            }
         }
 
         self.outdent();
@@ -2427,19 +4014,47 @@ impl AstVisitor for RustVisitor {
     //* --------------------------------------------------------------------- *//
 
     fn visit_actions_node_rust_impl(&mut self, actions_block_node: &ActionsBlockNode) -> AstVisitorReturnType {
-        self.newline();
+        let (external_actions, implemented_actions):(Vec<_>,Vec<_>) = actions_block_node.actions.iter()
+            .partition(|action_node_rcref| Self::is_external_action(&action_node_rcref.borrow()));
 
-        self.add_code(&format!("impl {}Actions for {} {{ ",self.system_name,self.system_name));
-        self.indent();
-        self.newline();
+        if !implemented_actions.is_empty() {
+            self.newline();
 
-        for action_decl_node_rcref in &actions_block_node.actions {
-            let action_decl_node = action_decl_node_rcref.borrow();
-            action_decl_node.accept_rust_impl(self);
+            self.add_code(&format!("impl{} {}{}{} {{ ",self.impl_generics,self.system_name,self.type_args,self.impl_where_clause));
+            self.indent();
+            self.newline();
+
+            for action_decl_node_rcref in implemented_actions {
+                let action_decl_node = action_decl_node_rcref.borrow();
+                action_decl_node.accept_rust_impl(self);
+            }
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+        }
+
+        if !external_actions.is_empty() {
+            self.subclass_code.push(format!("impl{} {}Actions{} for {}{} {{",self.impl_generics,self.system_name,self.type_args,self.system_name,self.type_args));
+            for action_node_rcref in external_actions {
+                let action_node = action_node_rcref.borrow();
+                let mut params = String::new();
+                if let Some(params_vec) = &action_node.params {
+                    for param in params_vec {
+                        let param_type = match &param.param_type_opt {
+                            Some(param_type) => self.format_type(param_type),
+                            None => String::from("<?>"),
+                        };
+                        params.push_str(&format!(",{}:{}", param.param_name, param_type));
+                    }
+                }
+                let action_name = self.format_action_name(&action_node.name);
+                match &action_node.type_opt {
+                    Some(ret_type) => self.subclass_code.push(format!("    fn {}(&self{}) -> {} {{ todo!() }}", action_name, params, self.format_type(ret_type))),
+                    None => self.subclass_code.push(format!("    fn {}(&self{}) {{}}", action_name, params)),
+                }
+            }
+            self.subclass_code.push(String::from("}"));
         }
-        self.outdent();
-        self.newline();
-        self.add_code("}");
 
         AstVisitorReturnType::ActionBlockNode {}
     }
@@ -2468,7 +4083,7 @@ impl AstVisitor for RustVisitor {
         self.current_state_name_opt = Some(state_node.name.clone());
         self.newline();
         self.newline();
-        self.add_code(&format!("fn {}(&mut self, e:&mut FrameEvent) {{", self.format_state_name(&state_node.name)));
+        self.add_code(&format!("fn {}(&mut self, e:&mut FrameEvent{}) {{", self.format_state_name(&state_node.name), self.type_args));
         self.indent();
         self.newline();
         self.newline();
@@ -2496,6 +4111,19 @@ impl AstVisitor for RustVisitor {
         //
         // self.deserialize.push(format!("\t\tcase \"{}\": _state_ = _s{}_; break;",state_node.name,state_node.name));
 
+        if let Some(timeout_node) = &state_node.timeout_opt {
+            self.newline();
+            self.add_code(&format!("if let FrameMessage::Enter = {}.{} {{"
+                                   ,self.config.frame_event_variable_name
+                                   ,self.config.frame_event_message_attribute_name));
+            self.indent();
+            self.generate_timer_start(timeout_node);
+            self.outdent();
+            self.newline();
+            self.add_code("}");
+            self.newline();
+        }
+
         // this allows for logging and other kinds of calls for each event in the state
         if let Some(calls) = &state_node.calls_opt {
             for call in calls {
@@ -2516,6 +4144,14 @@ impl AstVisitor for RustVisitor {
 
         self.first_event_handler = true; // context for formatting
 
+        if let Some(deferred_events) = &state_node.deferred_events_opt {
+            self.generate_deferred_event_arms(deferred_events);
+        }
+
+        if let Some(timeout_node) = &state_node.timeout_opt {
+            timeout_node.accept(self);
+        }
+
         if state_node.evt_handlers_rcref.len() > 0 {
             for evt_handler_node in &state_node.evt_handlers_rcref {
                 evt_handler_node.as_ref().borrow().accept(self);
@@ -2545,7 +4181,10 @@ impl AstVisitor for RustVisitor {
     //* --------------------------------------------------------------------- *//
 
     fn visit_event_handler_node(&mut self, evt_handler_node: &EventHandlerNode) -> AstVisitorReturnType {
-        self.current_event_ret_type = evt_handler_node.get_event_ret_type();
+        self.current_event_ret_type = match evt_handler_node.get_event_ret_type_node_opt() {
+            Some(ret_type) => self.format_type(&ret_type),
+            None => String::new(),
+        };
         self.newline();
         self.generate_comment(evt_handler_node.line);
 //        let mut generate_final_close_paren = true;
@@ -2788,7 +4427,10 @@ impl AstVisitor for RustVisitor {
         match &transition_statement.target_state_context_t {
             StateContextType::StateRef {..}
                 => self.generate_state_ref_transition(transition_statement),
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..}
+                => self.generate_state_stack_pop_transition(transition_statement),
+            // a history target is restored like a popped state
+            StateContextType::StateHistory {..}
                 => self.generate_state_stack_pop_transition(transition_statement),
         };
 
@@ -2811,7 +4453,7 @@ impl AstVisitor for RustVisitor {
         match &change_state_stmt_node.state_context_t {
             StateContextType::StateRef {..}
             => self.generate_state_ref_change_state(change_state_stmt_node),
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..} | StateContextType::StateHistory {..}
             => self.errors.push(format!("Fatal error - change state stack pop not implemented."),)
         };
 
@@ -2844,6 +4486,25 @@ impl AstVisitor for RustVisitor {
 
     //* --------------------------------------------------------------------- *//
 
+    fn visit_timeout_node(&mut self, timeout_node: &TimeoutNode) -> AstVisitorReturnType {
+        self.newline();
+        self.generate_comment(timeout_node.line);
+        self.add_code("FrameMessage::StateTimeout => {");
+        self.indent();
+        timeout_node.transition_statement_node.accept(self);
+        self.newline();
+        self.add_code("return;");
+        self.outdent();
+        self.newline();
+        self.add_code("},");
+
+        self.first_event_handler = false;
+
+        AstVisitorReturnType::TimeoutNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_test_statement_node(&mut self, test_stmt_node: &TestStatementNode) -> AstVisitorReturnType {
 
         match &test_stmt_node.test_t {
@@ -3363,6 +5024,68 @@ impl AstVisitor for RustVisitor {
 
     //* --------------------------------------------------------------------- *//
 
+    fn visit_list_literal_node(&mut self, list_literal_node: &ListLiteralNode) -> AstVisitorReturnType {
+
+        let mut output = String::new();
+        self.visit_list_literal_node_to_string(list_literal_node, &mut output);
+        self.add_code(&output);
+
+        AstVisitorReturnType::ListLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_list_literal_node_to_string(&mut self, list_literal_node: &ListLiteralNode, output:&mut String) -> AstVisitorReturnType {
+
+        output.push_str("vec![");
+        let mut separator = "";
+        for expr_t in &list_literal_node.exprs_t {
+            output.push_str(separator);
+            expr_t.accept_to_string(self, output);
+            separator = ",";
+        }
+        output.push_str("]");
+
+        AstVisitorReturnType::ListLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_map_literal_node(&mut self, map_literal_node: &MapLiteralNode) -> AstVisitorReturnType {
+
+        let mut output = String::new();
+        self.visit_map_literal_node_to_string(map_literal_node, &mut output);
+        self.add_code(&output);
+
+        AstVisitorReturnType::MapLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_map_literal_node_to_string(&mut self, map_literal_node: &MapLiteralNode, output:&mut String) -> AstVisitorReturnType {
+
+        if map_literal_node.entries.is_empty() {
+            output.push_str("HashMap::new()");
+        } else {
+            output.push_str("HashMap::from([");
+            let mut separator = "";
+            for (key_expr_t, value_expr_t) in &map_literal_node.entries {
+                output.push_str(separator);
+                output.push_str("(");
+                key_expr_t.accept_to_string(self, output);
+                output.push_str(",");
+                value_expr_t.accept_to_string(self, output);
+                output.push_str(")");
+                separator = ",";
+            }
+            output.push_str("])");
+        }
+
+        AstVisitorReturnType::MapLiteralNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_expression_list_node(&mut self, expr_list: &ExprListNode) -> AstVisitorReturnType {
 
         let mut separator = "";
@@ -3508,6 +5231,7 @@ impl AstVisitor for RustVisitor {
         match state_stack_op_statement_node.state_stack_operation_node.operation_t {
             StateStackOperationType::Push => {
                 self.newline();
+                self.generate_state_stack_overflow_check();
                 if self.generate_state_context {
                     self.add_code(&format!("self.state_stack_push(self.state_context_rc.clone());"));
                 } else {
@@ -3527,6 +5251,26 @@ impl AstVisitor for RustVisitor {
     }
     //* --------------------------------------------------------------------- *//
 
+    fn visit_raise_statement_node(&mut self, raise_statement_node:&RaiseStatementNode) -> AstVisitorReturnType {
+        self.newline();
+        self.add_code(&format!("self.{}", raise_statement_node.interface_method_name));
+        raise_statement_node.call_expr_list_node.accept(self);
+        self.add_code(";");
+
+        AstVisitorReturnType::RaiseStatementNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_forward_statement_node(&mut self, forward_statement_node:&ForwardStatementNode) -> AstVisitorReturnType {
+        self.newline();
+        self.add_code(&format!("({}::{})(self,e);", self.system_name, self.format_state_name(&forward_statement_node.target_state_ref.name)));
+
+        AstVisitorReturnType::ForwardStatementNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_state_context_node(&mut self, _state_context_node:&StateContextNode) -> AstVisitorReturnType {
 
         // TODO
@@ -3644,7 +5388,7 @@ impl AstVisitor for RustVisitor {
         self.add_code(")");
         match &action_decl_node.type_opt {
             Some(ret_type) => {
-                self.add_code(&format!(" -> {}", ret_type.get_type_str()));
+                self.add_code(&format!(" -> {}", self.format_type(ret_type)));
             },
             None => {}
         };
@@ -3665,7 +5409,8 @@ impl AstVisitor for RustVisitor {
 
 
         let action_name = self.format_action_name(&action_node.name);
-        self.add_code(&format!("fn {}(&self",action_name));
+        let self_param = if action_node.mutates_domain { "&mut self" } else { "&self" };
+        self.add_code(&format!("fn {}({}",action_name,self_param));
 //        subclass_code.push_str(&format!("fn {}(",action_name));
 
         match &action_node.params {
@@ -3681,7 +5426,7 @@ impl AstVisitor for RustVisitor {
         self.add_code(")");
         match &action_node.type_opt {
             Some(ret_type) => {
-                self.add_code(&format!(" -> {}", ret_type.get_type_str()));
+                self.add_code(&format!(" -> {}", self.format_type(ret_type)));
             },
             None => {}
         };
@@ -3696,6 +5441,12 @@ impl AstVisitor for RustVisitor {
                 self.outdent();
                 self.newline();
             },
+            None if action_node.has_frame_body() => {
+                self.indent();
+                self.generate_frame_body(&action_node.statements, action_node.get_return_expr_opt());
+                self.outdent();
+                self.newline();
+            },
             None => {}
         }
         self.add_code("}");
@@ -3705,10 +5456,61 @@ impl AstVisitor for RustVisitor {
 
     //* --------------------------------------------------------------------- *//
 
+    fn visit_operations_block_node(&mut self, operations_block_node: &OperationsBlockNode) -> AstVisitorReturnType {
+        self.newline();
+        self.add_code("//===================== Operations Block ===================//");
+        self.newline();
+
+        for operation_node_rcref in &operations_block_node.operations {
+            operation_node_rcref.borrow().accept(self);
+        }
+
+        AstVisitorReturnType::OperationsBlockNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_operation_node(&mut self, operation_node: &OperationNode) -> AstVisitorReturnType {
+
+        self.newline();
+        self.add_code(&format!("pub fn {}(&mut self", operation_node.name));
+
+        match &operation_node.params {
+            Some (params)
+                =>  self.format_parameter_list(params),
+            None => {},
+        }
+
+        self.add_code(")");
+        match &operation_node.type_opt {
+            Some(ret_type) => {
+                self.add_code(&format!(" -> {}", self.format_type(ret_type)));
+            },
+            None => {}
+        }
+        self.add_code(" {");
+        self.indent();
+        match &operation_node.code_opt {
+            Some(code) => {
+                self.newline();
+                self.add_code(code);
+            },
+            None => self.generate_frame_body(&operation_node.statements, operation_node.get_return_expr_opt()),
+        }
+        self.outdent();
+        self.newline();
+        self.add_code("}");
+        self.newline();
+
+        AstVisitorReturnType::OperationNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
     fn visit_domain_variable_decl_node(&mut self, variable_decl_node: &VariableDeclNode) -> AstVisitorReturnType {
 
         let var_type = match &variable_decl_node.type_opt {
-            Some(x) => x.get_type_str(),
+            Some(x) => self.format_type(x),
             None => String::from("<?>"),
         };
         let var_name =  &variable_decl_node.name;
@@ -3730,7 +5532,7 @@ impl AstVisitor for RustVisitor {
     fn visit_variable_decl_node(&mut self, variable_decl_node: &VariableDeclNode) -> AstVisitorReturnType {
 
         let var_type = match &variable_decl_node.type_opt {
-            Some(x) => x.get_type_str(),
+            Some(x) => self.format_type(x),
             None => String::from("<?>"),
         };
         let var_name =  &variable_decl_node.name;
@@ -3752,6 +5554,9 @@ impl AstVisitor for RustVisitor {
     fn visit_variable_expr_node(&mut self, variable_node: &VariableNode) -> AstVisitorReturnType {
         let code = self.format_variable_expr(variable_node);
         self.add_code(&code);
+        if self.is_cloned_variable(variable_node) {
+            self.add_code(".clone()");
+        }
 
         AstVisitorReturnType::AssignmentExprNode {}
     }
@@ -3761,6 +5566,9 @@ impl AstVisitor for RustVisitor {
     fn visit_variable_expr_node_to_string(&mut self, variable_node: &VariableNode, output:&mut String) -> AstVisitorReturnType {
         let code = self.format_variable_expr(variable_node);
         output.push_str(&code);
+        if self.is_cloned_variable(variable_node) {
+            output.push_str(".clone()");
+        }
 
         AstVisitorReturnType::AssignmentExprNode {}
     }
@@ -3799,7 +5607,9 @@ impl AstVisitor for RustVisitor {
 
             },
             _ => {
+                self.visiting_lvalue = true;
                 assignment_expr_node.l_value_box.accept(self);
+                self.visiting_lvalue = false;
                 self.add_code(" = ");
                 assignment_expr_node.r_value_box.accept(self);
                 self.add_code(";");
@@ -3817,7 +5627,9 @@ impl AstVisitor for RustVisitor {
         self.generate_comment(assignment_expr_node.line);
         self.newline();
         self.newline_to_string(output);
+        self.visiting_lvalue = true;
         assignment_expr_node.l_value_box.accept_to_string(self, output);
+        self.visiting_lvalue = false;
         output.push_str(" = ");
         assignment_expr_node.r_value_box.accept_to_string(self, output);
         output.push_str(";");
@@ -3839,10 +5651,9 @@ impl AstVisitor for RustVisitor {
 
     fn visit_unary_expr_node(&mut self, unary_expr_node: &UnaryExprNode) -> AstVisitorReturnType {
 
-        // TODO
-        //       self.generate_comment(assignment_expr_node.line);
-        unary_expr_node.operator.accept(self);
-        unary_expr_node.right_rcref.borrow().accept(self);
+        let mut output = String::new();
+        self.visit_unary_expr_node_to_string(unary_expr_node, &mut output);
+        self.add_code(&output);
 
         AstVisitorReturnType::UnaryExprNode {}
     }
@@ -3854,7 +5665,7 @@ impl AstVisitor for RustVisitor {
         // TODO
         //       self.generate_comment(assignment_expr_node.line);
         unary_expr_node.operator.accept_to_string(self, output);
-        unary_expr_node.right_rcref.borrow().accept_to_string(self,output);
+        self.visit_operand_to_string(&unary_expr_node.operator, &unary_expr_node.right_rcref.borrow(), false, output);
 
         AstVisitorReturnType::UnaryExprNode {}
     }
@@ -3863,24 +5674,9 @@ impl AstVisitor for RustVisitor {
 
     fn visit_binary_expr_node(&mut self, binary_expr_node: &BinaryExprNode) -> AstVisitorReturnType {
 
-        // TODO
-        //       self.generate_comment(assignment_expr_node.line);
-
-        if binary_expr_node.operator == OperatorType::LogicalXor {
-            self.add_code("((");
-            binary_expr_node.left_rcref.borrow().accept(self);
-            self.add_code(") && !(");
-            binary_expr_node.right_rcref.borrow().accept(self);
-            self.add_code(")) || (!(");
-            binary_expr_node.left_rcref.borrow().accept(self);
-            self.add_code(") && (");
-            binary_expr_node.right_rcref.borrow().accept(self);
-            self.add_code("))");
-        } else {
-            binary_expr_node.left_rcref.borrow().accept(self);
-            binary_expr_node.operator.accept(self);
-            binary_expr_node.right_rcref.borrow().accept(self);
-        }
+        let mut output = String::new();
+        self.visit_binary_expr_node_to_string(binary_expr_node, &mut output);
+        self.add_code(&output);
 
         AstVisitorReturnType::BinaryExprNode {}
     }
@@ -3889,27 +5685,45 @@ impl AstVisitor for RustVisitor {
 
     fn visit_binary_expr_node_to_string(&mut self, binary_expr_node: &BinaryExprNode, output:&mut String) -> AstVisitorReturnType {
 
-        if binary_expr_node.operator == OperatorType::LogicalXor {
-            output.push_str("((");
-            binary_expr_node.left_rcref.borrow().accept_to_string(self,output);
-            output.push_str(") && !(");
-            binary_expr_node.right_rcref.borrow().accept_to_string(self,output);
-            output.push_str(")) || (!(");
-            binary_expr_node.left_rcref.borrow().accept_to_string(self,output);
-            output.push_str(") && (");
-            binary_expr_node.right_rcref.borrow().accept_to_string(self,output);
-            output.push_str("))");
-
-        } else {
-            binary_expr_node.left_rcref.borrow().accept_to_string(self,output);
-            binary_expr_node.operator.accept_to_string(self, output);
-            binary_expr_node.right_rcref.borrow().accept_to_string(self,output);
-        }
+        let operator_type = &binary_expr_node.operator;
+        self.visit_operand_to_string(operator_type, &binary_expr_node.left_rcref.borrow(), true, output);
+        operator_type.accept_to_string(self, output);
+        self.visit_operand_to_string(operator_type, &binary_expr_node.right_rcref.borrow(), false, output);
 
         AstVisitorReturnType::BinaryExprNode {}
     }
 
 
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_conditional_expr_node(&mut self, conditional_expr_node: &ConditionalExprNode) -> AstVisitorReturnType {
+
+        self.add_code("if ");
+        conditional_expr_node.condition_rcref.borrow().accept(self);
+        self.add_code(" { ");
+        conditional_expr_node.true_expr_rcref.borrow().accept(self);
+        self.add_code(" } else { ");
+        conditional_expr_node.false_expr_rcref.borrow().accept(self);
+        self.add_code(" }");
+
+        AstVisitorReturnType::ConditionalExprNode {}
+    }
+
+    //* --------------------------------------------------------------------- *//
+
+    fn visit_conditional_expr_node_to_string(&mut self, conditional_expr_node: &ConditionalExprNode, output:&mut String) -> AstVisitorReturnType {
+
+        output.push_str("if ");
+        conditional_expr_node.condition_rcref.borrow().accept_to_string(self, output);
+        output.push_str(" { ");
+        conditional_expr_node.true_expr_rcref.borrow().accept_to_string(self, output);
+        output.push_str(" } else { ");
+        conditional_expr_node.false_expr_rcref.borrow().accept_to_string(self, output);
+        output.push_str(" }");
+
+        AstVisitorReturnType::ConditionalExprNode {}
+    }
+
     //* --------------------------------------------------------------------- *//
 
     fn visit_operator_type(&mut self, operator_type: &OperatorType) -> AstVisitorReturnType {
@@ -3929,7 +5743,7 @@ impl AstVisitor for RustVisitor {
             OperatorType::NotEqual => self.add_code(" != "),
             OperatorType::LogicalAnd => self.add_code(" && "),
             OperatorType::LogicalOr => self.add_code(" || "),
-            OperatorType::LogicalXor => self.add_code(""),
+            OperatorType::LogicalXor => self.add_code(" ^ "),
         }
 
         AstVisitorReturnType::BinaryExprNode {}
@@ -3954,7 +5768,7 @@ impl AstVisitor for RustVisitor {
             OperatorType::NotEqual => output.push_str(" != "),
             OperatorType::LogicalAnd => output.push_str(" && "),
             OperatorType::LogicalOr => output.push_str(" || "),
-            OperatorType::LogicalXor => output.push_str(""),
+            OperatorType::LogicalXor => output.push_str(" ^ "),
         }
 
         AstVisitorReturnType::BinaryExprNode {}
@@ -646,7 +646,7 @@ impl XStateVisitor {
         let enter_args_opt = match &transition_statement.target_state_context_t {
             StateContextType::StateRef { state_context_node}
             => &state_context_node.enter_args_opt,
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..}
             => &None,
         };
 
@@ -694,7 +694,7 @@ impl XStateVisitor {
         let target_state_args_opt = match &transition_statement.target_state_context_t {
             StateContextType::StateRef { state_context_node}
             => &state_context_node.state_ref_args_opt,
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..}
             => &Option::None,
         };
 //
@@ -1395,7 +1395,7 @@ impl AstVisitor for XStateVisitor {
         match &transition_statement.target_state_context_t {
             StateContextType::StateRef { state_context_node}
             => self.generate_state_ref_transition(transition_statement),
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..}
             => self.generate_state_stack_pop_transition(transition_statement),
         };
 
@@ -1418,7 +1418,7 @@ impl AstVisitor for XStateVisitor {
         match &change_state_stmt_node.state_context_t {
             StateContextType::StateRef { state_context_node}
             => self.generate_state_ref_change_state(change_state_stmt_node),
-            StateContextType::StateStackPop {}
+            StateContextType::StateStackPop {..}
             => panic!("TODO - not implemented"),
         };
 
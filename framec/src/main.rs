@@ -1,5 +1,6 @@
 use structopt::StructOpt;
 use std::{fs};
+use std::io::{ErrorKind, Write};
 use framec::frame_c::compiler::Exe;
 use framec::frame_c::utils::*;
 
@@ -8,14 +9,28 @@ struct Cli {
     #[structopt(parse(from_os_str))]
     path:std::path::PathBuf,
     language:String,
+    /// Snapshot manifest of a released version of the system. Warns when
+    /// the system can no longer restore its snapshots.
+    #[structopt(long = "snapshot-baseline", parse(from_os_str))]
+    snapshot_baseline:Option<std::path::PathBuf>,
+    /// File to write a stub implementing the system's actions to. An
+    /// existing file is left as it is. Exits with status 2 if the language
+    /// has no actions stub. A Python stub imports the system from a module
+    /// named after the Frame file.
+    #[structopt(long = "actions-stub", parse(from_os_str))]
+    actions_stub:Option<std::path::PathBuf>,
 }
 
 fn main() {
 
     let args = Cli::from_args();
 
-    if let Err(run_error) = run_file(&args.path, &args.language) {
-        eprintln!("Error reading file: {}", run_error.error);
+    if let Err(run_error) = run_file(&args.path, &args.language, &args.snapshot_baseline, &args.actions_stub) {
+        if run_error.code == frame_exitcode::ACTIONS_STUB_ERR {
+            eprintln!("Error: {}", run_error.error);
+        } else {
+            eprintln!("Error reading file: {}", run_error.error);
+        }
         std::process::exit(run_error.code)
     };
     // let args: Vec<String> = env::args().collect();
@@ -34,7 +49,7 @@ fn main() {
 
 /* --------------------------------------------------------------------- */
 
-pub fn run_file(filename:&std::path::PathBuf,output_format:&String) -> Result<(), RunError> {
+pub fn run_file(filename:&std::path::PathBuf,output_format:&String,snapshot_baseline_opt:&Option<std::path::PathBuf>,actions_stub_path_opt:&Option<std::path::PathBuf>) -> Result<(), RunError> {
 
 
     let contents = match fs::read_to_string(filename) {
@@ -46,12 +61,62 @@ pub fn run_file(filename:&std::path::PathBuf,output_format:&String) -> Result<()
     };
     Exe::debug_print(&format!("{}", &contents));
     let frame_c = Exe::new();
-    let run_result = frame_c.run(contents,output_format.clone());
+    let module_name = match filename.file_stem() {
+        Some(file_stem) => file_stem.to_string_lossy().to_string(),
+        None => String::new(),
+    };
+    let run_result = frame_c.run_with_actions_stub(contents.clone(),output_format.clone(),&module_name);
     match run_result {
-        Ok(code) =>  {
+        Ok((code, actions_stub_opt)) =>  {
             println!("{}", code);
-            Ok(())
+            if let Some(actions_stub_path) = actions_stub_path_opt {
+                write_actions_stub(actions_stub_path, actions_stub_opt)?;
+            }
+        },
+        Err(run_err) => return Err(run_err),
+    }
+    if let Some(snapshot_baseline) = snapshot_baseline_opt {
+        let baseline = match fs::read_to_string(snapshot_baseline) {
+            Ok(baseline) => baseline,
+            Err(err) => {
+                let run_err = RunError::new(exitcode::NOINPUT, &*err.to_string());
+                return Err(run_err);
+            }
+        };
+        for warning in frame_c.check_snapshot(contents, &baseline)? {
+            eprintln!("Warning: {}", warning);
+        }
+    }
+    Ok(())
+}
+
+/* --------------------------------------------------------------------- */
+
+// The stub is only written once as the user fills it in with their
+// action implementations, so an existing file is skipped.
+
+fn write_actions_stub(actions_stub_path:&std::path::PathBuf, actions_stub_opt:Option<String>) -> Result<(), RunError> {
+    let actions_stub = match actions_stub_opt {
+        Some(actions_stub) => actions_stub,
+        None => {
+            let run_err = RunError::new(frame_exitcode::ACTIONS_STUB_ERR, "No actions stub is generated for this system and language.");
+            return Err(run_err);
+        }
+    };
+    let mut file = match fs::OpenOptions::new().write(true).create_new(true).open(actions_stub_path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+            eprintln!("Note: {} already exists and was not overwritten.", actions_stub_path.display());
+            return Ok(());
         },
-        Err(run_err) => Err(run_err),
+        Err(err) => {
+            let run_err = RunError::new(exitcode::CANTCREAT, &*err.to_string());
+            return Err(run_err);
+        }
+    };
+    if let Err(err) = file.write_all(actions_stub.as_bytes()) {
+        let run_err = RunError::new(exitcode::CANTCREAT, &*err.to_string());
+        return Err(run_err);
     }
+    Ok(())
 }
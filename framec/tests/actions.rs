@@ -0,0 +1,59 @@
+// Actions with a Frame body only take &mut self in Rust when they assign
+// domain variables, so read-only actions can call each other.
+
+mod common;
+
+use common::*;
+
+const ACCUMULATOR:&str = r#"
+#Accumulator
+
+-interface-
+
+add [n:int]
+
+-machine-
+
+$Running
+    |add| [n:int] addAll(n) report() ^
+
+-actions-
+
+log [total:int]
+total : int {
+    ^(sum)
+}
+report {
+    log(total())
+}
+bump [n:int] {
+    sum = sum + n
+}
+addAll [n:int] {
+    bump(n)
+}
+
+-domain-
+
+var sum:int = 0
+
+##
+"#;
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn read_only_actions_borrow_self_immutably() {
+    let generated = compile(ACCUMULATOR, "rust").unwrap();
+    assert!(generated.contains("fn total(&self) -> i32 {"), "{}", generated);
+    assert!(generated.contains("fn report(&self) {"), "{}", generated);
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn actions_that_assign_domain_borrow_self_mutably() {
+    let generated = compile(ACCUMULATOR, "rust").unwrap();
+    assert!(generated.contains("fn bump(&mut self,n:i32) {"), "{}", generated);
+    assert!(generated.contains("fn addAll(&mut self,n:i32) {"), "{}", generated);
+}
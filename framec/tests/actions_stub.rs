@@ -0,0 +1,92 @@
+// --actions-stub writes a subclass implementing the system's external
+// actions once, and fails with status 2 when the language has no stub.
+
+use framec::frame_c::compiler::Exe;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+const DOOR:&str = r#"
+#Door
+
+-interface-
+
+open
+
+-machine-
+
+$Closed
+    |open| log("open") ^
+
+-actions-
+
+log [msg:string]
+
+##
+"#;
+
+/* --------------------------------------------------------------------- */
+
+// A fresh directory holding door.frm for a test to run framec in.
+
+fn door_dir(test_name:&str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("framec_actions_stub_{}_{}", test_name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("door.frm"), DOOR).unwrap();
+    dir
+}
+
+/* --------------------------------------------------------------------- */
+
+// Returns the exit status and what framec wrote to stderr.
+
+fn run_framec(dir:&PathBuf, language:&str, stub_name:&str) -> (Option<i32>,String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_framec"))
+        .arg(dir.join("door.frm"))
+        .arg(language)
+        .arg("--actions-stub")
+        .arg(dir.join(stub_name))
+        .output()
+        .unwrap();
+    (output.status.code(), String::from_utf8_lossy(&output.stderr).to_string())
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn python_stub_imports_system() {
+    let exe = Exe::new();
+    let (_, actions_stub_opt) = exe.run_with_actions_stub(DOOR.to_string(), "python_3".to_string(), "door")
+        .map_err(|run_error| run_error.error)
+        .unwrap();
+    let actions_stub = actions_stub_opt.unwrap();
+    assert!(actions_stub.starts_with("from door import Door\n"), "{}", actions_stub);
+    assert!(actions_stub.contains("class DoorController(Door):"), "{}", actions_stub);
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn stub_is_written_once() {
+    let dir = door_dir("written_once");
+    assert_eq!(run_framec(&dir, "python_3", "door_actions.py").0, Some(0));
+    fs::write(dir.join("door_actions.py"), "# filled in").unwrap();
+    let (code, stderr) = run_framec(&dir, "python_3", "door_actions.py");
+    assert_eq!(code, Some(0));
+    assert!(stderr.starts_with("Note: "), "{}", stderr);
+    assert_eq!(fs::read_to_string(dir.join("door_actions.py")).unwrap(), "# filled in");
+    let _ = fs::remove_dir_all(&dir);
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn unsupported_language_fails() {
+    let dir = door_dir("unsupported");
+    assert_eq!(run_framec(&dir, "gdscript", "door_actions.gd").0, Some(2));
+    assert!(!dir.join("door_actions.gd").exists());
+    assert_eq!(run_framec(&dir, "c_sharp_bob", "door_actions.cs").0, Some(2));
+    assert!(!dir.join("door_actions.cs").exists());
+    let _ = fs::remove_dir_all(&dir);
+}
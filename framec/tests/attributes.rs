@@ -0,0 +1,89 @@
+// Attributes are checked against the kind of declaration they are on so
+// a misspelled or misplaced attribute isn't silently ignored.
+
+mod common;
+
+use common::*;
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn unknown_attribute_is_rejected() {
+    let error = compile_error(r#"
+#Lamp
+
+-machine-
+
+#[fianl]
+$Off
+
+##
+"#);
+    assert!(error.contains("Unknown attribute #[fianl]."), "{}", error);
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn attribute_on_wrong_declaration_is_rejected() {
+    let error = compile_error(r#"
+#Lamp
+
+-machine-
+
+$Off
+
+-actions-
+
+#[query]
+log [msg:string]
+
+##
+"#);
+    assert!(error.contains("#[query] can't be used on actions."), "{}", error);
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn doc_attributes_are_accepted_on_every_declaration() {
+    let result = compile(r#"
+#Lamp
+#![doc="A lamp"]
+
+-interface-
+
+#[deprecated]
+toggle
+
+-machine-
+
+#[doc="Dark"]
+$Off
+    #[doc="Switch on"]
+    |toggle| -> $On ^
+
+$On
+    |toggle| -> $Off ^
+
+-actions-
+
+#[doc="Writes a message"]
+log [msg:string]
+
+-operations-
+
+#[deprecated]
+reset {
+    count = 0
+}
+
+-domain-
+
+#[doc="Times switched"]
+var count:int = 0
+
+##
+"#, "python_3");
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+}
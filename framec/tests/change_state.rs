@@ -0,0 +1,99 @@
+// Change-state (->>) switches to a state without sending exit or enter
+// events, but still builds the target's state arguments and variables.
+
+mod common;
+
+use common::*;
+
+const DIAL:&str = r#"
+#Dial
+
+-interface-
+
+go
+jump
+show
+
+-machine-
+
+$A
+    |>| log("enter A") ^
+    |<| log("exit A") ^
+    |go| ->> $B(level) ^
+    |jump| -> $B(level) ^
+
+$B [n:int]
+    var doubled:int = level * 2
+    |>| log("enter B") ^
+    |show| log(str(n) + " " + str(doubled)) ^
+
+-actions-
+
+log [msg:string]
+
+-domain-
+
+var level:int = 4
+
+##
+"#;
+
+const CONTROLLER:&str = r#"
+class DialController(Dial):
+    def log_do(self, msg):
+        print(msg)
+
+dial = DialController()
+"#;
+
+/* --------------------------------------------------------------------- */
+
+fn run_dial(driver:&str) -> Option<String> {
+    let generated = compile(DIAL, "python_3").unwrap();
+    run_python(&generated, &format!("{}{}", CONTROLLER, driver))
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn change_state_sets_state_arguments_without_exit_or_enter() {
+    let driver = r#"
+dial.go()
+dial.show()
+print(dial._state_.__name__)
+"#;
+    if let Some(output) = run_dial(driver) {
+        assert_eq!(output, "4 8\n_sB_\n");
+    }
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn transition_to_a_state_with_arguments_runs_exit_and_enter() {
+    let driver = r#"
+dial.jump()
+dial.show()
+"#;
+    if let Some(output) = run_dial(driver) {
+        assert_eq!(output, "exit A\nenter B\n4 8\n");
+    }
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn change_state_builds_the_state_context_in_every_target() {
+    let generated = compile(DIAL, "rust").unwrap();
+    assert!(generated.contains("state_args:BStateArgs {n:self.level,},"), "{}", generated);
+    assert!(generated.contains("state_vars:BStateVars {doubled:self.level * 2,},"), "{}", generated);
+    assert!(generated.contains("self.changeState(Dial::b_state,Rc::new(RefCell::new(next_state_context)));"), "{}", generated);
+
+    let generated = compile(DIAL, "java_8").unwrap();
+    assert!(generated.contains("stateContext.addStateArg(\"n\",this.level);"), "{}", generated);
+    assert!(generated.contains("_changeState_(this::_sB_,stateContext);"), "{}", generated);
+
+    let generated = compile(DIAL, "cpp").unwrap();
+    assert!(generated.contains("pStateContext->addStateArg(string(\"n\"),string(\"int\"),true,new int(this->level));"), "{}", generated);
+    assert!(generated.contains("_changeState_(&Dial::_sB_,pStateContext);"), "{}", generated);
+}
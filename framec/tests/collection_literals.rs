@@ -0,0 +1,47 @@
+// Declarations initialized with a list or map literal and no type take
+// the type inferred from the literal's elements.
+
+mod common;
+
+use common::*;
+
+const INVENTORY:&str = r#"
+#Inventory
+
+-interface-
+
+count
+
+-machine-
+
+$Open
+    |count| var prices = {"tea":2 "cake":3.5} ^
+
+-domain-
+
+var ids = [1 2 3]
+var flags = {"open":true}
+
+##
+"#;
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn untyped_domain_variables_take_inferred_type() {
+    let rust = compile(INVENTORY, "rust").unwrap();
+    assert!(rust.contains("ids:Vec<i32>,"), "{}", rust);
+    assert!(rust.contains("flags:HashMap<String, bool>,"), "{}", rust);
+    let java = compile(INVENTORY, "java_8").unwrap();
+    assert!(java.contains("List<Integer> ids = List.of(1,2,3);"), "{}", java);
+    let cpp = compile(INVENTORY, "cpp").unwrap();
+    assert!(cpp.contains("std::vector<int> ids = std::vector<int>{1,2,3};"), "{}", cpp);
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn untyped_local_variable_takes_widened_type() {
+    let c_sharp = compile(INVENTORY, "c_sharp").unwrap();
+    assert!(c_sharp.contains("Dictionary<string, double> prices = new Dictionary<string, double> {{\"tea\",2.0},{\"cake\",3.5}};"), "{}", c_sharp);
+}
@@ -0,0 +1,115 @@
+// Helpers shared by the integration tests. Generated Python is run against
+// a minimal stand-in for the frame-ancillary-files runtime so tests can
+// drive the state machine and check how it behaves. Generated C++ is only
+// checked to compile against a similar stand-in.
+
+#![allow(dead_code)] // not every test uses every helper
+
+use framec::frame_c::compiler::Exe;
+use std::process::Command;
+
+/* --------------------------------------------------------------------- */
+
+pub fn compile(frame_src:&str, output_format:&str) -> Result<String,String> {
+    let exe = Exe::new();
+    exe.run(frame_src.to_string(), output_format.to_string())
+        .map_err(|run_error| run_error.error)
+}
+
+/* --------------------------------------------------------------------- */
+
+pub fn compile_error(frame_src:&str) -> String {
+    match compile(frame_src, "python_3") {
+        Ok(_) => panic!("Expected a compile error."),
+        Err(error) => error,
+    }
+}
+
+/* --------------------------------------------------------------------- */
+
+const PYTHON_RUNTIME:&str = r#"
+class FrameEvent:
+    def __init__(self, message, parameters):
+        self._message = message
+        self._parameters = parameters
+        self._return = None
+
+class StateContext:
+    def __init__(self, state):
+        self.state = state
+        self._stateArgs = {}
+        self._stateVars = {}
+        self._enterArgs = {}
+
+    def addStateArg(self, name, value):
+        self._stateArgs[name] = value
+
+    def getStateArg(self, name):
+        return self._stateArgs[name]
+
+    def addStateVar(self, name, value):
+        self._stateVars[name] = value
+
+    def setStateVar(self, name, value):
+        self._stateVars[name] = value
+
+    def getStateVar(self, name):
+        return self._stateVars[name]
+
+    def addEnterArg(self, name, value):
+        self._enterArgs[name] = value
+
+    def getEnterArgs(self):
+        return self._enterArgs
+"#;
+
+// Runs the generated code followed by the driver and returns what the
+// driver printed. Returns None when python3 isn't installed.
+
+pub fn run_python(generated:&str, driver:&str) -> Option<String> {
+    let script = format!("{}\n{}\n{}", PYTHON_RUNTIME, generated, driver);
+    let output = match Command::new("python3").arg("-c").arg(&script).output() {
+        Ok(output) => output,
+        Err(_) => {
+            eprintln!("python3 not found - skipping.");
+            return None;
+        }
+    };
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    Some(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/* --------------------------------------------------------------------- */
+
+const CPP_RUNTIME:&str = r#"
+#include <functional>
+#include <map>
+#include <string>
+#include <vector>
+using namespace std;
+
+class FrameEvent {
+public:
+    FrameEvent(string message, map<string,void*>* parameters) : _message(message), _parameters(parameters) {}
+    string _message;
+    map<string,void*>* _parameters;
+    void* _return = nullptr;
+};
+"#;
+
+// Checks that the generated C++ compiles with g++ -fsyntax-only and
+// returns g++'s diagnostics. Returns None when g++ isn't installed.
+
+pub fn check_cpp(generated:&str) -> Option<String> {
+    let source = std::env::temp_dir().join(format!("framec_check_{}_{:?}.cpp", std::process::id(), std::thread::current().id()));
+    std::fs::write(&source, format!("{}\n{}", CPP_RUNTIME, generated)).unwrap();
+    let output = Command::new("g++").arg("-fsyntax-only").arg(&source).output();
+    let _ = std::fs::remove_file(&source);
+    match output {
+        Ok(output) => Some(String::from_utf8_lossy(&output.stderr).to_string()),
+        Err(_) => {
+            eprintln!("g++ not found - skipping.");
+            None
+        }
+    }
+}
@@ -0,0 +1,149 @@
+// Completion transitions are tried in order once a state has been
+// entered, and chains of them run in a loop until no guard holds.
+
+mod common;
+
+use common::*;
+
+const FLOW:&str = r#"
+#Flow
+
+-interface-
+
+go
+
+-machine-
+
+$Idle
+    |>| log("enter Idle") ^
+    |go| -> $Check ^
+
+$Check
+    done(ready) -> $Ready
+    done -> $Wait
+    |>| log("enter Check") ^
+
+$Wait
+    done -> $Idle
+    |>| log("enter Wait") ^
+
+$Ready
+    |>| log("enter Ready") ^
+
+-actions-
+
+log [msg:string]
+
+-domain-
+
+var ready:bool = false
+
+##
+"#;
+
+const CONTROLLER:&str = r#"
+class FlowController(Flow):
+    def log_do(self, msg):
+        print(msg)
+
+flow = FlowController()
+"#;
+
+/* --------------------------------------------------------------------- */
+
+fn run_flow(driver:&str) -> Option<String> {
+    let generated = compile(FLOW, "python_3").unwrap();
+    run_python(&generated, &format!("{}{}", CONTROLLER, driver))
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn completion_chain_runs_until_no_completion_applies() {
+    let driver = r#"
+flow.go()
+print(flow._state_.__name__)
+print(flow._completing_)
+"#;
+    if let Some(output) = run_flow(driver) {
+        assert_eq!(output, "enter Check\nenter Wait\nenter Idle\n_sIdle_\nFalse\n");
+    }
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn first_completion_with_true_guard_is_taken() {
+    let driver = r#"
+flow.ready = True
+flow.go()
+print(flow._state_.__name__)
+"#;
+    if let Some(output) = run_flow(driver) {
+        assert_eq!(output, "enter Check\nenter Ready\n_sReady_\n");
+    }
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn completion_chain_does_not_recurse() {
+    // Every completion is taken from the loop in _complete_, so the
+    // call stack is no deeper after a chain than after a single step.
+    let driver = r#"
+import traceback
+depths = []
+def log_depth(msg):
+    depths.append(len(traceback.extract_stack()))
+flow.log_do = log_depth
+flow.go()
+print(depths[1] == depths[2])
+"#;
+    if let Some(output) = run_flow(driver) {
+        assert_eq!(output, "True\n");
+    }
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn unguarded_completion_cycle_is_rejected() {
+    let error = compile_error(r#"
+#Loop
+
+-machine-
+
+$A
+    done -> $B
+
+$B
+    done -> $A
+
+##
+"#);
+    assert!(error.contains("Completion transitions loop forever: $A -> $B -> $A."), "{}", error);
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn guarded_completion_cycle_is_allowed() {
+    let result = compile(r#"
+#Loop
+
+-machine-
+
+$A
+    done(more) -> $B
+
+$B
+    done -> $A
+
+-domain-
+
+var more:bool = false
+
+##
+"#, "python_3");
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+}
@@ -0,0 +1,126 @@
+// States can defer events, which are replayed once a transition leaves
+// the deferring state.
+
+mod common;
+
+use common::*;
+
+const DOC:&str = r#"
+#Doc
+
+-interface-
+
+start
+done
+save
+close
+print
+
+-machine-
+
+$Idle
+    |start| -> $Busy ^
+    |save| log("save") ^
+    |close| log("close") ^
+    |print| log("print") -> $Busy ^
+
+$Busy
+    defer(|save| |close| |print|)
+    |>| log("enter Busy") ^
+    |done| -> $Idle ^
+
+-actions-
+
+log [msg:string]
+
+##
+"#;
+
+const CONTROLLER:&str = r#"
+class DocController(Doc):
+    def log_do(self, msg):
+        print(msg)
+
+doc = DocController()
+"#;
+
+/* --------------------------------------------------------------------- */
+
+fn run_doc(driver:&str) -> Option<String> {
+    let generated = compile(DOC, "python_3").unwrap();
+    run_python(&generated, &format!("{}{}", CONTROLLER, driver))
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn deferred_events_are_replayed_in_arrival_order() {
+    let driver = r#"
+doc.start()
+doc.save()
+doc.close()
+doc.save()
+doc.done()
+print(len(doc._deferredQueue_))
+"#;
+    if let Some(output) = run_doc(driver) {
+        assert_eq!(output, "enter Busy\nsave\nclose\nsave\n0\n");
+    }
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn events_deferred_again_during_replay_keep_their_order() {
+    // print moves back to $Busy mid replay, so the events after it are
+    // deferred again and wait for the next transition out of $Busy.
+    let driver = r#"
+doc.start()
+doc.save()
+doc.print()
+doc.close()
+doc.save()
+doc.done()
+print([e._message for e in doc._deferredQueue_])
+doc.done()
+"#;
+    if let Some(output) = run_doc(driver) {
+        assert_eq!(output, "enter Busy\nsave\nprint\nenter Busy\n['close', 'save']\nclose\nsave\n");
+    }
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn deferred_events_are_rejected_in_regions() {
+    // Regions would share one queue and replay another region's events.
+    let error = compile_error(r#"
+#Device
+
+-interface-
+
+go
+save
+
+-machine-
+
+-- Net
+$Off
+    |go| -> $On ^
+
+$On
+    defer(|save|)
+    |go| -> $Off ^
+
+-- Ui
+$Idle
+    |save| log("ui save in Idle") ^
+
+-actions-
+
+log [msg:string]
+
+##
+"#);
+    assert!(error.contains("Regions are not supported with state parameters, state variables, enter arguments, the state stack, timeouts or deferred events."), "{}", error);
+}
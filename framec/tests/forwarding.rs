@@ -0,0 +1,88 @@
+// A handler can run its own statements and then forward the event to
+// its parent or another ancestor with => $^ or => $Name.
+
+mod common;
+
+use common::*;
+
+const PANEL:&str = r#"
+#Panel
+
+-interface-
+
+press
+
+-machine-
+
+$Child => $Mid
+    |press| log("child") => $^ ^
+
+$Mid => $Base
+    |press| log("mid") => $Base ^
+
+$Base
+    |press| log("base") -> $Done ^
+
+$Done
+    |>| log("enter Done") ^
+
+-actions-
+
+log [msg:string]
+
+##
+"#;
+
+const CONTROLLER:&str = r#"
+class PanelController(Panel):
+    def log_do(self, msg):
+        print(msg)
+
+panel = PanelController()
+"#;
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn forward_runs_ancestors_after_child() {
+    let driver = r#"
+panel.press()
+print(panel._state_.__name__)
+"#;
+    let generated = compile(PANEL, "python_3").unwrap();
+    if let Some(output) = run_python(&generated, &format!("{}{}", CONTROLLER, driver)) {
+        assert_eq!(output, "child\nmid\nbase\nenter Done\n_sDone_\n");
+    }
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn statement_after_forward_is_rejected() {
+    // The parent may have changed state, so "after" would run in $Q.
+    let error = compile_error(r#"
+#Panel
+
+-interface-
+
+press
+
+-machine-
+
+$P => $Parent
+    |press| => $^ log("after") ^
+
+$Parent
+    |press| -> $Q ^
+
+$Q
+    |>| log("enter Q") ^
+
+-actions-
+
+log [msg:string]
+
+##
+"#);
+    assert!(error.contains("A forward must be the last statement before '^'."), "{}", error);
+}
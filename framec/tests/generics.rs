@@ -0,0 +1,57 @@
+// A generic system's Rust impls only require Clone of their type
+// arguments, and values of a type parameter are cloned when read.
+
+mod common;
+
+use common::*;
+
+const HOLDER:&str = r#"
+#Holder<T> [$[first:T] held:T]
+
+-interface-
+
+put [item:T]
+get : T
+start : T
+
+-machine-
+
+$Empty [first:T]
+    |put| [item:T] held = item -> (item) $Full ^
+    |get| : T ^(held)
+    |start| : T ^(first)
+
+$Full
+    |>| [arrived:T] notify(arrived) ^
+    |get| : T ^(held)
+
+-actions-
+
+notify [item:T]
+
+-domain-
+
+var held:T = held
+
+##
+"#;
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn type_parameters_are_bounded_by_clone() {
+    let generated = compile(HOLDER, "rust").unwrap();
+    assert!(generated.contains("impl<T: Clone> Holder<T> where Self: HolderActions<T> {"), "{}", generated);
+    assert!(!generated.contains("Copy"), "{}", generated);
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn values_of_type_parameters_are_cloned_when_read() {
+    let generated = compile(HOLDER, "rust").unwrap();
+    assert!(generated.contains("self.held = (e.parameters.as_ref().unwrap().get_put_item());"), "{}", generated);
+    assert!(generated.contains("FrameEventReturn::get {return_type:self.held.clone()}"), "{}", generated);
+    assert!(generated.contains("FrameEventReturn::start {return_type:(this_state_context.state_args.first).clone()}"), "{}", generated);
+    assert!(generated.contains("self.notify((this_state_context.enter_args.arrived).clone());"), "{}", generated);
+}
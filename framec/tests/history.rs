@@ -0,0 +1,165 @@
+// History transitions resume the substate a parent was last left in.
+// $Tuning has a state parameter so every transition builds a state
+// context, which history can still do for states that carry no data.
+
+mod common;
+
+use common::*;
+
+const PLAYER:&str = r#"
+#Player
+
+-interface-
+
+play
+pause
+next
+resume
+deep
+tune
+
+-machine-
+
+$Idle
+    |play| -> $Intro ^
+    |resume| -> $Playing[H] ^
+    |deep| -> $Playing[H*] ^
+    |tune| -> $Tuning(level) ^
+
+$Tuning [n:int]
+    |>| log("tuned " + str(n)) ^
+    |play| -> $Idle ^
+
+$Playing
+    |>| log("enter Playing") ^
+    |pause| -> $Idle ^
+
+$Intro => $Playing
+    |>| log("enter Intro") ^
+    |next| -> $Track1 ^
+
+$Album => $Playing
+    |>| log("enter Album") ^
+
+$Track1 => $Album
+    |>| log("enter Track1") ^
+    |next| -> $Track2 ^
+
+$Track2 => $Album
+    |>| log("enter Track2") ^
+
+-actions-
+
+log [msg:string]
+
+-domain-
+
+var level:int = 3
+
+##
+"#;
+
+const CONTROLLER:&str = r#"
+class PlayerController(Player):
+    def log_do(self, msg):
+        print(msg)
+
+player = PlayerController()
+"#;
+
+/* --------------------------------------------------------------------- */
+
+fn run_player(driver:&str) -> Option<String> {
+    let generated = compile(PLAYER, "python_3").unwrap();
+    run_python(&generated, &format!("{}{}", CONTROLLER, driver))
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn shallow_history_resumes_the_last_direct_substate() {
+    let driver = r#"
+player.play()
+player.next()
+player.next()
+player.pause()
+player.resume()
+print(player._state_.__name__)
+print(player._stateContext_.state.__name__)
+"#;
+    if let Some(output) = run_player(driver) {
+        assert_eq!(output, "enter Intro\nenter Track1\nenter Track2\nenter Album\n_sAlbum_\n_sAlbum_\n");
+    }
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn deep_history_resumes_the_last_leaf_state() {
+    let driver = r#"
+player.play()
+player.next()
+player.next()
+player.pause()
+player.tune()
+player.play()
+player.deep()
+print(player._state_.__name__)
+print(player._stateContext_.state.__name__)
+"#;
+    if let Some(output) = run_player(driver) {
+        assert_eq!(output, "enter Intro\nenter Track1\nenter Track2\ntuned 3\nenter Track2\n_sTrack2_\n_sTrack2_\n");
+    }
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn history_without_a_record_enters_the_parent() {
+    let driver = r#"
+player.resume()
+print(player._state_.__name__)
+"#;
+    if let Some(output) = run_player(driver) {
+        assert_eq!(output, "enter Playing\n_sPlaying_\n");
+    }
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn history_builds_a_state_context_in_every_target() {
+    for output_format in ["javascript", "java_8", "c_sharp", "cpp", "gdscript", "rust"] {
+        let result = compile(PLAYER, output_format);
+        assert!(result.is_ok(), "{}: {}", output_format, result.unwrap_err());
+    }
+    let generated = compile(PLAYER, "rust").unwrap();
+    assert!(generated.contains("let state_context_rc = Rc::new(RefCell::new(self.history_state_context(state)));"), "{}", generated);
+    assert!(generated.contains("StateContext::Track2 {Track2:Track2StateContext {state}}"), "{}", generated);
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn history_is_rejected_when_a_substate_carries_data() {
+    let error = compile_error(r#"
+#Player
+
+-interface-
+
+resume
+
+-machine-
+
+$Idle
+    |resume| -> $Playing[H] ^
+
+$Playing
+
+$Track => $Playing
+    var count:int = 0
+
+##
+"#);
+    assert!(error.contains("History transitions are not supported when $Playing or its substates have state parameters, state variables or enter arguments."), "{}", error);
+}
@@ -0,0 +1,107 @@
+// Handlers for #[query] interface methods may only read the system.
+
+mod common;
+
+use common::*;
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn query_handler_can_not_raise() {
+    let error = compile_error(r#"
+#[run_to_completion]
+#Lamp
+
+-interface-
+
+#[query]
+isOn : bool
+toggle
+
+-machine-
+
+$Off
+    |isOn| : bool raise |toggle| ^(false)
+    |toggle| ^
+
+##
+"#);
+    assert!(error.contains("Raised events are not allowed in handlers for #[query] interface methods."), "{}", error);
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn query_handler_can_not_assign_state_parameter() {
+    let error = compile_error(r#"
+#Lamp
+
+-interface-
+
+start
+#[query]
+level : int
+
+-machine-
+
+$Off
+    |start| -> $On(5) ^
+
+$On [brightness:int]
+    |level| : int brightness = 0 ^(brightness)
+
+##
+"#);
+    assert!(error.contains("Handlers for #[query] interface methods can't assign to state parameter brightness."), "{}", error);
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn query_can_not_be_forwarded_to_any_message_handler() {
+    let error = compile_error(r#"
+#Lamp
+
+-interface-
+
+#[query]
+isOn : bool
+
+-machine-
+
+$Off => $Base
+    |isOn| : bool => $^ ^
+
+$Base
+    ||* -> $Off ^
+
+##
+"#);
+    assert!(error.contains("|isOn| is a #[query] but is forwarded to the |*| handler in $Base."), "{}", error);
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn query_can_be_forwarded_to_query_handler() {
+    let result = compile(r#"
+#Lamp
+
+-interface-
+
+#[query]
+isOn : bool
+
+-machine-
+
+$Off => $Base
+    |isOn| : bool => $^ ^
+
+$Base
+    |isOn| : bool ^(false)
+    ||* ^
+
+##
+"#, "python_3");
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+}
@@ -0,0 +1,114 @@
+// Systems marked #[run_to_completion] queue events sent from inside a
+// handler and dispatch them once the current event has been handled.
+
+mod common;
+
+use common::*;
+
+const DOOR:&str = r#"
+#[run_to_completion]
+#Door
+
+-interface-
+
+open
+close
+ping [n:int]
+
+-machine-
+
+$Closed
+    |open|
+        log("open start")
+        raise |ping| (1)
+        log("open end")
+        -> $Open ^
+    |ping| [n:int] log("ping in closed") ^
+
+$Open
+    |>| log("enter open") ^
+    |ping| [n:int] log("ping in open") ^
+    |close| -> $Closed ^
+
+-actions-
+
+log [msg:string]
+
+##
+"#;
+
+const CONTROLLER:&str = r#"
+class DoorController(Door):
+    def __init__(self):
+        super().__init__()
+        self.fail = False
+
+    def log_do(self, msg):
+        if self.fail:
+            self.fail = False
+            raise RuntimeError(msg)
+        print(msg)
+
+door = DoorController()
+"#;
+
+/* --------------------------------------------------------------------- */
+
+fn run_door(driver:&str) -> Option<String> {
+    let generated = compile(DOOR, "python_3").unwrap();
+    run_python(&generated, &format!("{}{}", CONTROLLER, driver))
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn raised_event_waits_for_current_handler() {
+    let driver = r#"
+door.open()
+"#;
+    if let Some(output) = run_door(driver) {
+        assert_eq!(output, "open start\nopen end\nenter open\nping in open\n");
+    }
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn exception_in_handler_does_not_block_later_events() {
+    let driver = r#"
+door.fail = True
+try:
+    door.open()
+except RuntimeError as error:
+    print("failed: " + str(error))
+print(door._processing_)
+door.open()
+"#;
+    if let Some(output) = run_door(driver) {
+        assert_eq!(output, "failed: open start\nFalse\nopen start\nopen end\nenter open\nping in open\n");
+    }
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn raising_method_with_return_value_is_rejected() {
+    let error = compile_error(r#"
+#[run_to_completion]
+#Counter
+
+-interface-
+
+start
+total : int
+
+-machine-
+
+$Idle
+    |start| raise |total| ^
+    |total| : int ^(0)
+
+##
+"#);
+    assert!(error.contains("|total| returns a value and can not be raised."), "{}", error);
+}
@@ -0,0 +1,110 @@
+// Snapshots taken with |>>>| capture the current state, the state stack,
+// state arguments and variables and the domain, and |<<<| restores them
+// into another instance.
+
+mod common;
+
+use common::*;
+
+const COUNTER:&str = r#"
+#Counter
+
+-interface-
+
+save : string @(|>>>|)
+restore [data:string] @(|<<<|)
+start
+inc
+push
+pop
+show
+
+-machine-
+
+$Idle
+    |start| -> $Counting(10) ^
+    |pop| -> $$[-] ^
+    |show| log("idle " + str(total)) ^
+
+$Counting [step:int]
+    var count:int = 5
+    |inc| total = total + step ^
+    |push| $$[+] -> $Idle ^
+    |show| log(str(step) + " " + str(count) + " " + str(total)) ^
+
+-actions-
+
+log [msg:string]
+
+-domain-
+
+var total:int = 0
+
+##
+"#;
+
+const CONTROLLER:&str = r#"
+class CounterController(Counter):
+    def log_do(self, msg):
+        print(msg)
+
+counter = CounterController()
+copy = CounterController()
+"#;
+
+/* --------------------------------------------------------------------- */
+
+fn run_counter(driver:&str) -> Option<String> {
+    let generated = compile(COUNTER, "python_3").unwrap();
+    run_python(&generated, &format!("{}{}", CONTROLLER, driver))
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn restore_returns_a_saved_machine_to_the_same_state() {
+    let driver = r#"
+counter.start()
+counter.inc()
+counter.push()
+copy.restore(counter.save())
+copy.show()
+copy.pop()
+copy.show()
+copy.inc()
+copy.show()
+counter.show()
+"#;
+    if let Some(output) = run_counter(driver) {
+        assert_eq!(output, "idle 10\n10 5 10\n10 5 20\nidle 10\n");
+    }
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn restore_rejects_a_snapshot_of_a_different_system() {
+    let driver = r#"
+import json
+bag = json.loads(counter.save())
+bag["hash"] = "0"
+try:
+    copy.restore(json.dumps(bag))
+except ValueError as error:
+    print(error)
+"#;
+    if let Some(output) = run_counter(driver) {
+        assert_eq!(output, "Snapshot does not match version 1 of Counter\n");
+    }
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn every_target_generates_snapshots() {
+    for output_format in ["javascript", "java_8", "c_sharp", "cpp", "gdscript", "rust"] {
+        let result = compile(COUNTER, output_format);
+        assert!(result.is_ok(), "{}: {}", output_format, result.unwrap_err());
+        assert!(!result.unwrap().contains("Bag"), "{}", output_format);
+    }
+}
@@ -0,0 +1,190 @@
+// A bounded state stack refuses pushes past #![state_stack_max] and
+// applies the #![state_stack_underflow] policy when $$[-] finds it empty.
+
+mod common;
+
+use common::*;
+
+const CONTROLLER:&str = r#"
+class StackController(Stack):
+    def log_do(self, msg):
+        print(msg)
+
+stack = StackController()
+"#;
+
+/* --------------------------------------------------------------------- */
+
+fn stack_src(underflow:&str) -> String {
+    format!(r#"
+#Stack
+    #![state_stack_max="2"]
+    #![state_stack_underflow="{}"]
+
+-interface-
+
+go
+back
+
+-machine-
+
+$A
+    |go| $$[+] -> $B ^
+    |back| -> $$[-] ^
+    |stateStackOverflow| log("overflow in A") ^
+    |stateStackUnderflow| log("underflow in A") ^
+
+$B
+    |>| log("enter B") ^
+    |go| $$[+] -> $A ^
+    |back| -> $$[-] ^
+    |stateStackOverflow| log("overflow in B") ^
+
+$Home
+    |>| log("enter Home") ^
+
+-actions-
+
+log [msg:string]
+
+##
+"#, underflow)
+}
+
+/* --------------------------------------------------------------------- */
+
+fn run_stack(underflow:&str, driver:&str) -> Option<String> {
+    let generated = compile(&stack_src(underflow), "python_3").unwrap();
+    run_python(&generated, &format!("{}{}", CONTROLLER, driver))
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn refused_push_sends_overflow_and_does_not_transition() {
+    let driver = r#"
+stack.go()
+stack.go()
+stack.go()
+print(stack._state_.__name__)
+print(len(stack._stateStack_))
+"#;
+    if let Some(output) = run_stack("error", driver) {
+        assert_eq!(output, "enter B\noverflow in A\n_sA_\n2\n");
+    }
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn underflow_error_sends_underflow_event() {
+    let driver = r#"
+stack.back()
+print(stack._state_.__name__)
+"#;
+    if let Some(output) = run_stack("error", driver) {
+        assert_eq!(output, "underflow in A\n_sA_\n");
+    }
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn underflow_stay_keeps_current_state() {
+    let driver = r#"
+stack.back()
+print(stack._state_.__name__)
+"#;
+    if let Some(output) = run_stack("stay", driver) {
+        assert_eq!(output, "_sA_\n");
+    }
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn underflow_state_transitions_to_named_state() {
+    let driver = r#"
+stack.back()
+print(stack._state_.__name__)
+"#;
+    if let Some(output) = run_stack("$Home", driver) {
+        assert_eq!(output, "enter Home\n_sHome_\n");
+    }
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn pop_returns_to_pushed_state() {
+    let driver = r#"
+stack.go()
+stack.back()
+print(stack._state_.__name__)
+print(len(stack._stateStack_))
+"#;
+    if let Some(output) = run_stack("error", driver) {
+        assert_eq!(output, "enter B\n_sA_\n0\n");
+    }
+}
+
+/* --------------------------------------------------------------------- */
+
+const EDITOR:&str = r#"
+#Editor
+
+-interface-
+
+edit
+open
+ok
+cancel
+
+-machine-
+
+$Start
+    |edit| -> ("opened") $Editing("a.txt") ^
+
+$Editing [doc:string]
+    |>| [result:string] log(doc + ": " + result) ^
+    |open| $$[+] -> $Dialog ^
+
+$Dialog
+    |>| log("enter Dialog") ^
+    |ok| -> ("saved") $$[-] ^
+    |cancel| -> ("cancelled") $$[-] ^
+
+-actions-
+
+log [msg:string]
+
+##
+"#;
+
+#[test]
+fn pop_passes_enter_arguments_to_restored_state() {
+    let generated = compile(EDITOR, "python_3").unwrap();
+    let driver = r#"
+class EditorController(Editor):
+    def log_do(self, msg):
+        print(msg)
+
+editor = EditorController()
+editor.edit()
+editor.open()
+editor.ok()
+editor.open()
+editor.cancel()
+"#;
+    if let Some(output) = run_python(&generated, driver) {
+        assert_eq!(output, "a.txt: opened\nenter Dialog\na.txt: saved\nenter Dialog\na.txt: cancelled\n");
+    }
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn pop_enter_arguments_must_match_restored_state() {
+    let error = compile_error(&EDITOR.replace(r#"|cancel| -> ("cancelled") $$[-] ^"#, "|cancel| -> $$[-] ^"));
+    assert!(error.contains("Enter arguments to $$[-] don't match the enter parameters of $Editing."), "{}", error);
+}
@@ -0,0 +1,97 @@
+// State timeouts are driven through an injected scheduler so these tests
+// decide by hand when a timer fires.
+
+mod common;
+
+use common::*;
+
+const DOOR:&str = r#"
+#Door
+
+-interface-
+
+open
+close
+
+-machine-
+
+$Closed
+    |open| -> $Waiting ^
+
+$Waiting
+    after(5000) -> $TimedOut
+    |close| -> $Closed ^
+
+$TimedOut
+    |close| -> $Closed ^
+
+##
+"#;
+
+const MANUAL_SCHEDULER:&str = r#"
+class ManualScheduler:
+    def __init__(self):
+        self.delay = None
+        self.callback = None
+
+    def schedule(self, delayMs, callback):
+        self.delay = delayMs
+        self.callback = callback
+
+    def cancel(self):
+        print("cancel")
+        self.callback = None
+
+scheduler = ManualScheduler()
+door = Door(scheduler)
+"#;
+
+/* --------------------------------------------------------------------- */
+
+fn run_door(driver:&str) -> Option<String> {
+    let generated = compile(DOOR, "python_3").unwrap();
+    run_python(&generated, &format!("{}{}", MANUAL_SCHEDULER, driver))
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn timeout_fires_after_entering_state() {
+    let driver = r#"
+print(scheduler.callback is None)
+door.open()
+print(scheduler.delay)
+scheduler.callback()
+print(door._state_.__name__)
+"#;
+    if let Some(output) = run_door(driver) {
+        assert_eq!(output, "True\n5000\n_sTimedOut_\n");
+    }
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn timeout_is_cancelled_on_exit() {
+    let driver = r#"
+door.open()
+callback = scheduler.callback
+door.close()
+print(scheduler.callback is None)
+callback()
+print(door._state_.__name__)
+"#;
+    if let Some(output) = run_door(driver) {
+        assert_eq!(output, "cancel\nTrue\n_sClosed_\n");
+    }
+}
+
+/* --------------------------------------------------------------------- */
+
+#[test]
+fn cpp_timers_compile() {
+    let generated = compile(DOOR, "cpp").unwrap();
+    if let Some(diagnostics) = check_cpp(&generated) {
+        assert!(diagnostics.is_empty(), "{}\n{}", diagnostics, generated);
+    }
+}